@@ -1,4 +1,13 @@
-const COMMANDS: &[&str] = &["resolve"];
+const COMMANDS: &[&str] = &[
+    "resolve",
+    "resolve_bytes",
+    "page_unloaded",
+    "rearm",
+    "mock_used",
+    "open_popup",
+    "close_popup",
+    "popup_message",
+];
 
 fn main() {
     tauri_plugin::Builder::new(COMMANDS).build()
@@ -0,0 +1,280 @@
+// auth: answer HTTP authentication challenges with credentials from tests.
+//
+// A page behind basic (or digest) auth would otherwise get the webview's
+// credential prompt, which nothing can fill in. Each webview gets a
+// challenge handler that answers with the credentials set through
+// `/auth/basic`, or cancels the challenge (so the page loads as a 401) when
+// none match. On macOS the handler is added to wry's navigation delegate,
+// on Windows and Linux it's the webview's own authentication event.
+
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use tauri::Runtime;
+
+/// Credentials for the hosts `host` names, or for every host without one.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct Credentials {
+    #[serde(default)]
+    pub host: Option<String>,
+    pub username: String,
+    pub password: String,
+}
+
+/// The credentials tests have supplied. Shared by every webview, and a
+/// static because the macOS handler is a plain function.
+static CREDENTIALS: Mutex<Vec<Credentials>> = Mutex::new(Vec::new());
+
+/// Replace the credentials challenges are answered with.
+pub(crate) fn set(credentials: Vec<Credentials>) {
+    *CREDENTIALS.lock().expect("lock poisoned") = credentials;
+    #[cfg(target_os = "windows")]
+    platform::ANSWERED.lock().expect("lock poisoned").clear();
+}
+
+/// The credentials for `host`: the first entry naming it, else the first
+/// without a host.
+fn credentials_for(host: &str) -> Option<Credentials> {
+    let credentials = CREDENTIALS.lock().expect("lock poisoned");
+    credentials
+        .iter()
+        .find(|c| {
+            c.host
+                .as_deref()
+                .is_some_and(|h| h.eq_ignore_ascii_case(host))
+        })
+        .or_else(|| credentials.iter().find(|c| c.host.is_none()))
+        .cloned()
+}
+
+/// Install the challenge handler in `webview`.
+pub(crate) fn install<R: Runtime>(webview: &tauri::Webview<R>) {
+    let installed = webview.with_webview(|webview| {
+        #[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+        if let Err(e) = platform::install(&webview) {
+            tracing::warn!("failed to install the authentication handler: {e}");
+        }
+        #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+        let _ = webview;
+    });
+    if let Err(e) = installed {
+        tracing::warn!("failed to install the authentication handler: {e}");
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use block2::Block;
+    use objc2::rc::Retained;
+    use objc2::runtime::{AnyObject, Imp, Sel};
+    use objc2::{msg_send, sel};
+    use objc2_foundation::{
+        NSString, NSURLAuthenticationChallenge, NSURLAuthenticationMethodDefault,
+        NSURLAuthenticationMethodHTTPBasic, NSURLAuthenticationMethodHTTPDigest, NSURLCredential,
+        NSURLCredentialPersistence, NSURLSessionAuthChallengeDisposition,
+    };
+
+    type CompletionHandler =
+        Block<dyn Fn(NSURLSessionAuthChallengeDisposition, *mut NSURLCredential)>;
+
+    /// `-[WKNavigationDelegate webView:didReceiveAuthenticationChallenge:completionHandler:]`.
+    extern "C-unwind" fn did_receive_challenge(
+        _this: &AnyObject,
+        _cmd: Sel,
+        _webview: &AnyObject,
+        challenge: &NSURLAuthenticationChallenge,
+        handler: &CompletionHandler,
+    ) {
+        let space = challenge.protectionSpace();
+        let method = space.authenticationMethod();
+        // Server trust and client certificates keep the default handling.
+        let password_based = unsafe {
+            [
+                NSURLAuthenticationMethodDefault,
+                NSURLAuthenticationMethodHTTPBasic,
+                NSURLAuthenticationMethodHTTPDigest,
+            ]
+        }
+        .iter()
+        .any(|m| m.isEqualToString(&method));
+        if !password_based {
+            handler.call((
+                NSURLSessionAuthChallengeDisposition::PerformDefaultHandling,
+                std::ptr::null_mut(),
+            ));
+            return;
+        }
+        // Answer once: after a failure the credentials are wrong, and
+        // answering again would loop.
+        let credentials = (challenge.previousFailureCount() == 0)
+            .then(|| super::credentials_for(&space.host().to_string()))
+            .flatten();
+        match credentials {
+            Some(c) => {
+                let credential = NSURLCredential::credentialWithUser_password_persistence(
+                    &NSString::from_str(&c.username),
+                    &NSString::from_str(&c.password),
+                    NSURLCredentialPersistence::ForSession,
+                );
+                handler.call((
+                    NSURLSessionAuthChallengeDisposition::UseCredential,
+                    Retained::as_ptr(&credential) as *mut NSURLCredential,
+                ));
+            }
+            None => handler.call((
+                NSURLSessionAuthChallengeDisposition::CancelAuthenticationChallenge,
+                std::ptr::null_mut(),
+            )),
+        }
+    }
+
+    pub(super) fn install(webview: &tauri::webview::PlatformWebview) -> Result<(), String> {
+        // SAFETY: `inner` is the live WKWebView, and we're on the main thread.
+        let wk_webview = unsafe { &*(webview.inner() as *const AnyObject) };
+        let delegate: Option<Retained<AnyObject>> =
+            unsafe { msg_send![wk_webview, navigationDelegate] };
+        let delegate = delegate.ok_or("the webview has no navigation delegate")?;
+        let class = delegate.class();
+        let sel = sel!(webView:didReceiveAuthenticationChallenge:completionHandler:);
+        // wry's delegate class doesn't answer challenges, so add the method
+        // to it; later webviews share the class and find it there.
+        if !class.responds_to(sel) {
+            // SAFETY: the function matches the method's signature, and the
+            // type encoding describes it.
+            unsafe {
+                let imp: Imp = std::mem::transmute(
+                    did_receive_challenge
+                        as extern "C-unwind" fn(
+                            &AnyObject,
+                            Sel,
+                            &AnyObject,
+                            &NSURLAuthenticationChallenge,
+                            &CompletionHandler,
+                        ),
+                );
+                objc2::ffi::class_addMethod(
+                    class as *const _ as *mut _,
+                    sel,
+                    imp,
+                    c"v@:@@@?".as_ptr(),
+                );
+            }
+        }
+        // WebKit checks which methods the delegate implements when it's set,
+        // so set it again for the new one to be called.
+        let _: () = unsafe { msg_send![wk_webview, setNavigationDelegate: &*delegate] };
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use webkit2gtk::glib::translate::{mut_override, ToGlibPtr};
+    use webkit2gtk::{
+        AuthenticationRequestExt, AuthenticationScheme, Credential, CredentialPersistence,
+        WebViewExt,
+    };
+
+    pub(super) fn install(webview: &tauri::webview::PlatformWebview) -> Result<(), String> {
+        webview.inner().connect_authenticate(|_, request| {
+            // Anything but a password challenge keeps the default handling.
+            if !matches!(
+                request.scheme(),
+                AuthenticationScheme::Default
+                    | AuthenticationScheme::HttpBasic
+                    | AuthenticationScheme::HttpDigest
+            ) {
+                return false;
+            }
+            let credentials = (!request.is_retry())
+                .then(|| super::credentials_for(&request.host().unwrap_or_default()))
+                .flatten();
+            match credentials {
+                Some(c) => {
+                    let credential = Credential::new(
+                        &c.username,
+                        &c.password,
+                        CredentialPersistence::ForSession,
+                    );
+                    // SAFETY: both pointers are valid for the call, which
+                    // copies the credential.
+                    unsafe {
+                        webkit2gtk::ffi::webkit_authentication_request_authenticate(
+                            request.to_glib_none().0,
+                            mut_override(credential.to_glib_none().0),
+                        );
+                    }
+                }
+                None => request.cancel(),
+            }
+            true
+        });
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::sync::Mutex;
+
+    use webview2_com::Microsoft::Web::WebView2::Win32::ICoreWebView2_10;
+    use webview2_com::{take_pwstr, BasicAuthenticationRequestedEventHandler};
+    use windows_core::{Interface, HSTRING, PWSTR};
+
+    /// URIs whose challenge was answered with credentials. WebView2 doesn't
+    /// say whether a challenge is a retry, so a second one for the same URI
+    /// is taken to mean the credentials were wrong; answering it again would
+    /// loop. Cleared when the credentials change.
+    pub(super) static ANSWERED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    pub(super) fn install(webview: &tauri::webview::PlatformWebview) -> Result<(), String> {
+        let handler = BasicAuthenticationRequestedEventHandler::create(Box::new(|_, args| {
+            let Some(args) = args else {
+                return Ok(());
+            };
+            let uri = unsafe {
+                let mut uri = PWSTR::null();
+                args.Uri(&mut uri)?;
+                take_pwstr(uri)
+            };
+            let host = tauri::Url::parse(&uri)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_default();
+            // Answer once per URI; a retry is cancelled and forgotten, so
+            // the next load of the page is answered again.
+            let mut answered = ANSWERED.lock().expect("lock poisoned");
+            let credentials = match answered.iter().position(|u| *u == uri) {
+                Some(retry) => {
+                    answered.swap_remove(retry);
+                    None
+                }
+                None => super::credentials_for(&host),
+            };
+            if credentials.is_some() {
+                answered.push(uri.clone());
+            }
+            drop(answered);
+            unsafe {
+                match credentials {
+                    Some(c) => {
+                        let response = args.Response()?;
+                        response.SetUserName(&HSTRING::from(c.username))?;
+                        response.SetPassword(&HSTRING::from(c.password))?;
+                    }
+                    None => args.SetCancel(true)?,
+                }
+            }
+            Ok(())
+        }));
+        let mut token = 0;
+        unsafe {
+            webview
+                .controller()
+                .CoreWebView2()
+                .and_then(|core| core.cast::<ICoreWebView2_10>())
+                .and_then(|core| core.add_BasicAuthenticationRequested(&handler, &mut token))
+                .map_err(|e| e.to_string())
+        }
+    }
+}
@@ -0,0 +1,370 @@
+// automation: in-process Rust API over the plugin's routes.
+//
+// `Automation` hands requests straight to the plugin's router, without a
+// socket or token, so integration tests in the app's own crate can drive
+// the UI through the same code paths tauri-wd uses over HTTP.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tauri::{Manager, Runtime};
+use tower::ServiceExt;
+
+use crate::WebDriverState;
+
+/// A failed automation call: the plugin's error message and code, and the
+/// HTTP status the server would have answered with.
+#[derive(Clone, Debug)]
+pub struct Error {
+    status: u16,
+    code: Option<String>,
+    message: String,
+}
+
+impl Error {
+    /// HTTP status of the failure, e.g. `404` for a missing window.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The plugin's error code, e.g. `"noSuchAlert"` or `"javascriptError"`
+    /// (see the error table in SPEC.md). `None` for errors that didn't come
+    /// from a plugin handler, such as a rejected request body.
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// How [`Automation::find_elements`] matches elements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Locator {
+    Css,
+    XPath,
+}
+
+impl Locator {
+    fn as_str(self) -> &'static str {
+        match self {
+            Locator::Css => "css selector",
+            Locator::XPath => "xpath",
+        }
+    }
+}
+
+/// Drives the app's webviews from Rust, inside the app process.
+///
+/// `Automation` shares the server's state: its current window and frame
+/// are tauri-wd's, and scripts it runs wait their turn behind the
+/// session's. The plugin must be registered and enabled (see
+/// [`crate::Builder::enabled`]) so pages load the script bridge.
+///
+/// ```rust,ignore
+/// let automation = Automation::for_app(app.handle());
+/// automation.find_element(Locator::Css, "#save").await?.click().await?;
+/// assert_eq!(automation.title().await?, "Saved");
+/// ```
+#[derive(Clone)]
+pub struct Automation {
+    router: axum::Router,
+}
+
+impl Automation {
+    /// # Panics
+    ///
+    /// Panics if the plugin isn't registered and enabled on `app`.
+    pub fn for_app<R: Runtime>(app: &tauri::AppHandle<R>) -> Self {
+        let state = app
+            .try_state::<WebDriverState>()
+            .expect("webdriver-automation plugin is not registered or not enabled");
+        Self {
+            router: state.router.clone(),
+        }
+    }
+
+    /// Call a plugin endpoint, e.g. `call("/window/rect", json!({}))`. See
+    /// the HTTP API in SPEC.md for paths and bodies.
+    pub async fn call(&self, path: &str, body: Value) -> Result<Value> {
+        let request = axum::http::Request::post(format!("/v{}{path}", crate::server::API_VERSION))
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .map_err(|e| Error {
+                status: 400,
+                code: None,
+                message: e.to_string(),
+            })?;
+        let response = match self.router.clone().oneshot(request).await {
+            Ok(response) => response,
+            Err(never) => match never {},
+        };
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|e| Error {
+                status: 500,
+                code: None,
+                message: e.to_string(),
+            })?;
+        let value: Option<Value> = serde_json::from_slice(&bytes).ok();
+        if status.is_success() {
+            return Ok(value.unwrap_or(Value::Null));
+        }
+        // Handler errors are `{"error": ...}`; body rejections are plain text.
+        let message = value
+            .as_ref()
+            .and_then(|v| v.get("error"))
+            .and_then(|e| e.as_str())
+            .map(String::from)
+            .unwrap_or_else(|| String::from_utf8_lossy(&bytes).into_owned());
+        Err(Error {
+            status: status.as_u16(),
+            code: value
+                .as_ref()
+                .and_then(|v| v.get("code"))
+                .and_then(|c| c.as_str())
+                .map(String::from),
+            message,
+        })
+    }
+
+    // --- Windows ---
+
+    /// Handles of all webview windows: their labels, with a `~n` suffix
+    /// for the nth window to reuse a label.
+    pub async fn window_handles(&self) -> Result<Vec<String>> {
+        decode(self.call("/window/handles", json!({})).await?)
+    }
+
+    /// Handle of the current window.
+    pub async fn window_handle(&self) -> Result<String> {
+        decode(self.call("/window/handle", json!({})).await?)
+    }
+
+    /// Make the window `handle` refers to current and focus it.
+    pub async fn switch_to_window(&self, handle: &str) -> Result<()> {
+        self.call("/window/set-current", json!({"label": handle}))
+            .await
+            .map(drop)
+    }
+
+    // --- Navigation ---
+
+    pub async fn navigate(&self, url: &str) -> Result<()> {
+        self.call("/navigate/url", json!({"url": url}))
+            .await
+            .map(drop)
+    }
+
+    pub async fn current_url(&self) -> Result<String> {
+        let value = self.call("/navigate/current", json!({})).await?;
+        decode(value["url"].clone())
+    }
+
+    pub async fn title(&self) -> Result<String> {
+        let value = self.call("/navigate/title", json!({})).await?;
+        decode(value["title"].clone())
+    }
+
+    pub async fn source(&self) -> Result<String> {
+        let value = self.call("/source", json!({})).await?;
+        decode(value["source"].clone())
+    }
+
+    // --- Scripts ---
+
+    /// Run `script` as a function body with `args` as `arguments`, and
+    /// return what it returns.
+    pub async fn execute(&self, script: &str, args: Vec<Value>) -> Result<Value> {
+        let value = self
+            .call("/script/execute", json!({"script": script, "args": args}))
+            .await?;
+        Ok(value["value"].clone())
+    }
+
+    /// Like [`execute`](Self::execute), but the result is whatever the
+    /// script passes to the callback appended to `arguments`.
+    pub async fn execute_async(&self, script: &str, args: Vec<Value>) -> Result<Value> {
+        let value = self
+            .call(
+                "/script/execute-async",
+                json!({"script": script, "args": args}),
+            )
+            .await?;
+        Ok(value["value"].clone())
+    }
+
+    // --- Elements ---
+
+    /// The first element matching `value`, or a 404 error.
+    pub async fn find_element(&self, using: Locator, value: &str) -> Result<Element> {
+        first(self.find_elements(using, value).await?, value)
+    }
+
+    pub async fn find_elements(&self, using: Locator, value: &str) -> Result<Vec<Element>> {
+        let found = self
+            .call(
+                "/element/find",
+                json!({"using": using.as_str(), "value": value}),
+            )
+            .await?;
+        self.elements(found)
+    }
+
+    fn elements(&self, found: Value) -> Result<Vec<Element>> {
+        let refs: Vec<ElementRef> = decode(found["elements"].clone())?;
+        Ok(refs
+            .into_iter()
+            .map(|reference| Element {
+                automation: self.clone(),
+                reference,
+            })
+            .collect())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ElementRef {
+    selector: String,
+    index: usize,
+    #[serde(default)]
+    using: Option<String>,
+}
+
+/// An element found by [`Automation::find_element`].
+#[derive(Clone)]
+pub struct Element {
+    automation: Automation,
+    reference: ElementRef,
+}
+
+/// Element position and size in CSS pixels, relative to the viewport.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Element {
+    /// Call an element endpoint with this element's reference plus `extra`.
+    async fn call(&self, path: &str, extra: Value) -> Result<Value> {
+        let mut body = json!({
+            "selector": self.reference.selector,
+            "index": self.reference.index,
+            "using": self.reference.using,
+        });
+        if let (Some(body), Value::Object(extra)) = (body.as_object_mut(), extra) {
+            body.extend(extra);
+        }
+        self.automation.call(path, body).await
+    }
+
+    pub async fn click(&self) -> Result<()> {
+        self.call("/element/click", json!({})).await.map(drop)
+    }
+
+    pub async fn clear(&self) -> Result<()> {
+        self.call("/element/clear", json!({})).await.map(drop)
+    }
+
+    /// Append `text` to the element's value.
+    pub async fn send_keys(&self, text: &str) -> Result<()> {
+        self.call("/element/send-keys", json!({"text": text}))
+            .await
+            .map(drop)
+    }
+
+    pub async fn text(&self) -> Result<String> {
+        let value = self.call("/element/text", json!({})).await?;
+        decode(value["text"].clone())
+    }
+
+    pub async fn tag_name(&self) -> Result<String> {
+        let value = self.call("/element/tag", json!({})).await?;
+        decode(value["tag"].clone())
+    }
+
+    pub async fn attribute(&self, name: &str) -> Result<Option<String>> {
+        let value = self
+            .call("/element/attribute", json!({"name": name}))
+            .await?;
+        decode(value["value"].clone())
+    }
+
+    pub async fn property(&self, name: &str) -> Result<Value> {
+        let value = self
+            .call("/element/property", json!({"name": name}))
+            .await?;
+        Ok(value["value"].clone())
+    }
+
+    pub async fn rect(&self) -> Result<Rect> {
+        decode(self.call("/element/rect", json!({})).await?)
+    }
+
+    pub async fn is_displayed(&self) -> Result<bool> {
+        let value = self.call("/element/displayed", json!({})).await?;
+        decode(value["displayed"].clone())
+    }
+
+    pub async fn is_enabled(&self) -> Result<bool> {
+        let value = self.call("/element/enabled", json!({})).await?;
+        decode(value["enabled"].clone())
+    }
+
+    pub async fn is_selected(&self) -> Result<bool> {
+        let value = self.call("/element/selected", json!({})).await?;
+        decode(value["selected"].clone())
+    }
+
+    /// The first descendant matching `value`, or a 404 error.
+    pub async fn find_element(&self, using: Locator, value: &str) -> Result<Element> {
+        first(self.find_elements(using, value).await?, value)
+    }
+
+    pub async fn find_elements(&self, using: Locator, value: &str) -> Result<Vec<Element>> {
+        let found = self
+            .automation
+            .call(
+                "/element/find-from",
+                json!({
+                    "parent_selector": self.reference.selector,
+                    "parent_index": self.reference.index,
+                    "parent_using": self.reference.using,
+                    "using": using.as_str(),
+                    "value": value,
+                }),
+            )
+            .await?;
+        self.automation.elements(found)
+    }
+}
+
+fn first(elements: Vec<Element>, value: &str) -> Result<Element> {
+    elements.into_iter().next().ok_or_else(|| Error {
+        status: 404,
+        code: Some("noSuchElement".into()),
+        message: format!("no element matches {value}"),
+    })
+}
+
+fn decode<T: serde::de::DeserializeOwned>(value: Value) -> Result<T> {
+    serde_json::from_value(value).map_err(|e| Error {
+        status: 500,
+        code: None,
+        message: format!("unexpected plugin response: {e}"),
+    })
+}
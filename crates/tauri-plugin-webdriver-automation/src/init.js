@@ -1,33 +1,121 @@
 // tauri-plugin-webdriver-automation: JavaScript bridge injected into every webview.
 // Provides element finding and async script resolution for the WebDriver server.
+//
+// The bridge object is installed as `window[namespace]`. The plugin fills in
+// the namespace (random per launch unless set with `Builder::namespace`) and
+// binds it as `__WEBDRIVER__` around every script it evaluates.
 
-(function () {
+(function (namespace) {
   "use strict";
 
+  // WebDriver error codes of errors raised by `fail`, kept off the error
+  // objects so app errors with a `code` property of their own (Node-style
+  // "ENOENT" and the like) aren't taken for one.
+  var errorCodes = new WeakMap();
+
+  // Ids of the plugin's scripts still running in this document, reported
+  // when it unloads: their results could never arrive. Each maps to the id
+  // of the tauri-wd request that started it, or null.
+  var inflight = Object.create(null);
+
+  function began(id, requestId) {
+    inflight[id] = requestId || null;
+  }
+
+  // Tell the plugin the script for `id` couldn't be compiled because the
+  // page's CSP forbids it. This goes beside the result, never in it, so
+  // nothing a script returns can pass for it; the plugin then inlines the
+  // script into its wrapper instead.
+  function evalBlocked(id) {
+    delete inflight[id];
+    window.__TAURI_INTERNALS__.invoke("plugin:webdriver-automation|resolve", {
+      id,
+      evalBlocked: true,
+    }).catch(function () {});
+  }
+
   function resolve(id, result) {
+    var requestId = inflight[id];
+    delete inflight[id];
     window.__TAURI_INTERNALS__.invoke("plugin:webdriver-automation|resolve", {
       id,
       result:
         result instanceof Error
           ? {
               error: result.name,
+              code: errorCodes.get(result),
               message: result.message,
               stacktrace: result.stack,
+              requestId: requestId || undefined,
             }
           : result,
+    }).catch(function () {
+      // The request already gave up (or got its result); don't let the
+      // rejection surface as an app error.
+    });
+  }
+
+  // Resolve `id` with raw bytes (a Uint8Array), sent as the IPC body rather
+  // than as base64 inside JSON.
+  function resolveBytes(id, bytes) {
+    delete inflight[id];
+    window.__TAURI_INTERNALS__.invoke(
+      "plugin:webdriver-automation|resolve_bytes",
+      bytes,
+      { headers: { "Webdriver-Result": id } },
+    ).catch(function () {
+      // As in `resolve`: the request already gave up.
     });
   }
 
+  // Resolve `id` with `canvas` encoded as `type` (default PNG), at
+  // `quality` for lossy types.
+  function resolveCanvas(id, canvas, type, quality) {
+    canvas.toBlob(function (blob) {
+      if (!blob) {
+        resolve(id, {
+          error: "ScreenshotError",
+          message: "the canvas could not be encoded",
+          stacktrace: "",
+        });
+        return;
+      }
+      blob.arrayBuffer().then(
+        function (buffer) {
+          resolveBytes(id, new Uint8Array(buffer));
+        },
+        function (e) {
+          resolve(id, e);
+        },
+      );
+    }, type || "image/png", quality);
+  }
+
+  // Fail a plugin script with a WebDriver error code (e.g. "noSuchAlert")
+  // that tauri-wd maps to the matching W3C error.
+  function fail(code, message) {
+    var e = new Error(message);
+    errorCodes.set(e, code);
+    throw e;
+  }
+
+  // Elements kept in the find cache; past it the least recently used go.
+  var MAX_CACHED = 1000;
+  var cachedCount = 0;
+
   function findElement(selector, index) {
-    // Check cache first
+    var cache = __WEBDRIVER__.cache;
     var cacheKey = selector + ":" + index;
-    if (__WEBDRIVER__.cache[cacheKey] !== undefined) {
-      var cached = __WEBDRIVER__.cache[cacheKey];
+    if (cache[cacheKey] !== undefined) {
+      var cached = cache[cacheKey];
+      // Re-inserted, so the keys stay in order of last use.
+      delete cache[cacheKey];
       // Verify the cached element is still in the DOM
       if (cached.isConnected) {
+        cache[cacheKey] = cached;
         return cached;
       }
-      delete __WEBDRIVER__.cache[cacheKey];
+      cachedCount--;
     }
 
     var elements = document.querySelectorAll(selector);
@@ -36,29 +124,24 @@
     }
 
     var element = elements[index];
-    __WEBDRIVER__.cache[cacheKey] = element;
+    if (cachedCount >= MAX_CACHED) {
+      for (var oldest in cache) {
+        delete cache[oldest];
+        cachedCount--;
+        break;
+      }
+    }
+    cache[cacheKey] = element;
+    cachedCount++;
     return element;
   }
 
-  var __wdIdCounter = 0;
-
   function getActiveElement() {
     var el = document.activeElement;
     if (!el || el === document.body || el === document.documentElement) {
       return null;
     }
-    var id = "wd-" + (++__wdIdCounter);
-    el.setAttribute("data-wd-id", id);
-    return { selector: '[data-wd-id="' + id + '"]', index: 0 };
-  }
-
-  // Shadow DOM element cache: holds direct references to elements inside shadow roots,
-  // since document.querySelectorAll cannot reach into shadow DOM.
-  function findElementInShadow(id) {
-    var el = __WEBDRIVER__.__shadowCache[id];
-    if (el && el.isConnected) return el;
-    delete __WEBDRIVER__.__shadowCache[id];
-    return null;
+    return elementRef(el);
   }
 
   function findElementByXPath(xpath, index) {
@@ -75,44 +158,1866 @@
     return result.snapshotItem(index);
   }
 
-  Object.defineProperty(window, "__WEBDRIVER__", {
-    value: Object.create(null),
+  // Viewport emulation: override window.devicePixelRatio. The native
+  // descriptor is saved on first use so clearing the override restores it.
+  var __wdNativeDpr = null;
+
+  function setDevicePixelRatio(ratio) {
+    if (__wdNativeDpr === null) {
+      __wdNativeDpr =
+        Object.getOwnPropertyDescriptor(window, "devicePixelRatio") || false;
+    }
+    if (ratio === null || ratio === undefined) {
+      if (__wdNativeDpr) {
+        Object.defineProperty(window, "devicePixelRatio", __wdNativeDpr);
+      } else {
+        delete window.devicePixelRatio;
+      }
+    } else {
+      Object.defineProperty(window, "devicePixelRatio", {
+        get: function () {
+          return ratio;
+        },
+        configurable: true,
+      });
+    }
+    window.dispatchEvent(new Event("resize"));
+  }
+
+  // Modifier keys held down by key actions. Keyboard, mouse, pointer and
+  // touch events synthesized for later actions carry them, so shift-click
+  // and friends work across input sources.
+  var modifiers = {
+    shiftKey: false,
+    ctrlKey: false,
+    altKey: false,
+    metaKey: false,
+  };
+  var modifierKeys = {
+    "\uE008": "shiftKey",
+    "\uE050": "shiftKey",
+    "\uE009": "ctrlKey",
+    "\uE051": "ctrlKey",
+    "\uE00A": "altKey",
+    "\uE052": "altKey",
+    "\uE03D": "metaKey",
+    "\uE053": "metaKey",
+  };
+
+  function setModifier(key, down) {
+    if (modifierKeys[key]) modifiers[modifierKeys[key]] = down;
+  }
+
+  function clearModifiers() {
+    Object.keys(modifiers).forEach(function (m) {
+      modifiers[m] = false;
+    });
+  }
+
+  function withModifiers(init) {
+    return Object.assign({}, init, modifiers);
+  }
+
+  // Typing for Element Send Keys: each character is a keystroke (keydown,
+  // keypress, beforeinput, the edit, input, keyup), so masks, counters and
+  // per-key validation run. Special keys in the U+E000 block send their
+  // named key; modifiers stay down until typed again or the Null key.
+  var namedKeys = {
+    "\uE003": "Backspace",
+    "\uE004": "Tab",
+    "\uE006": "Enter",
+    "\uE007": "Enter",
+    "\uE008": "Shift",
+    "\uE009": "Control",
+    "\uE00A": "Alt",
+    "\uE00C": "Escape",
+    "\uE00D": " ",
+    "\uE00E": "PageUp",
+    "\uE00F": "PageDown",
+    "\uE010": "End",
+    "\uE011": "Home",
+    "\uE012": "ArrowLeft",
+    "\uE013": "ArrowUp",
+    "\uE014": "ArrowRight",
+    "\uE015": "ArrowDown",
+    "\uE017": "Delete",
+    "\uE03D": "Meta",
+    "\uE050": "Shift",
+    "\uE051": "Control",
+    "\uE052": "Alt",
+    "\uE053": "Meta",
+  };
+
+  function keyCodeName(key) {
+    if (/^[a-z]$/i.test(key)) return "Key" + key.toUpperCase();
+    if (/^[0-9]$/.test(key)) return "Digit" + key;
+    if (key === " ") return "Space";
+    return key.length > 1 ? key : "";
+  }
+
+  // Set a form control's value through the prototype setter, so frameworks
+  // that track the value (React) see the change.
+  function setValue(el, value) {
+    var proto = Object.getPrototypeOf(el);
+    var desc = Object.getOwnPropertyDescriptor(proto, "value");
+    if (desc && desc.set) desc.set.call(el, value);
+    else el.value = value;
+  }
+
+  // Apply one edit to `el` as typing would, unless beforeinput is canceled.
+  function editText(el, inputType, data) {
+    if (el.isContentEditable) {
+      // execCommand fires beforeinput and input itself.
+      var commands = {
+        insertText: "insertText",
+        insertLineBreak: "insertParagraph",
+        deleteContentBackward: "delete",
+        deleteContentForward: "forwardDelete",
+      };
+      el.ownerDocument.execCommand(commands[inputType], false, data);
+      return;
+    }
+    if (!("value" in el)) return;
+    var init = {
+      inputType: inputType,
+      data: data,
+      bubbles: true,
+      cancelable: true,
+      composed: true,
+    };
+    if (!el.dispatchEvent(new InputEvent("beforeinput", init))) return;
+    var value = String(el.value);
+    var start = el.selectionStart;
+    var end = el.selectionEnd;
+    // Inputs like email and number have no selection; edit at the end.
+    if (typeof start !== "number") start = end = value.length;
+    if (inputType === "deleteContentBackward" && start === end) {
+      start = Math.max(0, start - 1);
+    } else if (inputType === "deleteContentForward" && start === end) {
+      end = Math.min(value.length, end + 1);
+    }
+    var inserted = data || "";
+    var next = value.slice(0, start) + inserted + value.slice(end);
+    if (inserted && el.maxLength >= 0 && next.length > el.maxLength) return;
+    setValue(el, next);
+    try {
+      el.setSelectionRange(start + inserted.length, start + inserted.length);
+    } catch (e) {
+      // Not a text control with a selection.
+    }
+    init.cancelable = false;
+    el.dispatchEvent(new InputEvent("input", init));
+  }
+
+  // Focus `el` to type into it. As in Element Send Keys, an element that
+  // didn't have focus gets the caret after its text, so typing appends.
+  function focusToType(el) {
+    var doc = el.ownerDocument;
+    if (doc.activeElement === el) return;
+    el.focus();
+    if (hasSelectionApi(el)) {
+      var length = String(el.value).length;
+      el.setSelectionRange(length, length);
+    } else if (el.isContentEditable) {
+      var selection = doc.getSelection();
+      selection.selectAllChildren(el);
+      selection.collapseToEnd();
+    }
+  }
+
+  function typeText(el, text) {
+    var held = { shiftKey: false, ctrlKey: false, altKey: false, metaKey: false };
+    var chars =
+      typeof Intl === "object" && Intl.Segmenter
+        ? Array.from(new Intl.Segmenter().segment(text), function (s) {
+            return s.segment;
+          })
+        : Array.from(text);
+    var keyEvent = function (type, key) {
+      return el.dispatchEvent(
+        new KeyboardEvent(
+          type,
+          Object.assign(
+            {
+              key: key,
+              code: keyCodeName(key),
+              bubbles: true,
+              cancelable: true,
+              composed: true,
+            },
+            held,
+          ),
+        ),
+      );
+    };
+    var release = function () {
+      var names = {
+        shiftKey: "Shift",
+        ctrlKey: "Control",
+        altKey: "Alt",
+        metaKey: "Meta",
+      };
+      Object.keys(held).forEach(function (m) {
+        if (!held[m]) return;
+        held[m] = false;
+        keyEvent("keyup", names[m]);
+      });
+    };
+    chars.forEach(function (ch) {
+      if (ch === "\uE000") {
+        release();
+        return;
+      }
+      var key = namedKeys[ch] || ch;
+      if (held.shiftKey && key.length === 1) key = key.toUpperCase();
+      var modifier = modifierKeys[ch];
+      if (modifier) {
+        held[modifier] = !held[modifier];
+        keyEvent(held[modifier] ? "keydown" : "keyup", key);
+        return;
+      }
+      if (keyEvent("keydown", key)) {
+        // Printable keys and Enter also fire keypress; with Control or
+        // Meta down they're shortcuts and don't type.
+        var printable = key.length === 1 || key === "Enter";
+        var shortcut = held.ctrlKey || held.metaKey;
+        if (printable && !shortcut && keyEvent("keypress", key)) {
+          if (key !== "Enter") {
+            editText(el, "insertText", key);
+          } else if (el.tagName === "TEXTAREA" || el.isContentEditable) {
+            editText(el, "insertLineBreak", "\n");
+          } else if (el.form && el.form.requestSubmit) {
+            el.form.requestSubmit();
+          }
+        } else if (key === "Backspace") {
+          editText(el, "deleteContentBackward", null);
+        } else if (key === "Delete") {
+          editText(el, "deleteContentForward", null);
+        }
+      }
+      keyEvent("keyup", key);
+    });
+    release();
+    if ("value" in el && !el.isContentEditable) {
+      el.dispatchEvent(new Event("change", { bubbles: true }));
+    }
+  }
+
+  // The pointer event that goes with a synthesized mouse event. Returns
+  // false if the page canceled it.
+  function mousePointerEvent(type, target, init) {
+    var buttons = { 0: 1, 1: 4, 2: 2 };
+    return target.dispatchEvent(
+      new PointerEvent(
+        type,
+        Object.assign({}, init, {
+          pointerId: 1,
+          pointerType: "mouse",
+          isPrimary: true,
+          composed: true,
+          buttons: type === "pointerdown" ? buttons[init.button] || 0 : 0,
+        }),
+      ),
+    );
+  }
+
+  // A press moves focus to its target's nearest focusable ancestor, or
+  // away from the focused element if there's none.
+  function focusFor(target) {
+    for (var el = target; el; el = el.parentElement || el.getRootNode().host) {
+      if (el.tabIndex >= 0 || el.hasAttribute("tabindex") || el.isContentEditable) {
+        el.focus();
+        return;
+      }
+    }
+    var active = target.ownerDocument.activeElement;
+    if (active && active.blur) active.blur();
+  }
+
+  // Hover: the element under the mouse pointer, so a move onto another
+  // element fires mouseout/mouseleave on the old one and
+  // mouseover/mouseenter on the new one, as a real pointer would.
+  var hovered = null;
+
+  function ancestors(el) {
+    var out = [];
+    for (; el; el = el.parentNode || el.host) {
+      if (el.nodeType === 1) out.push(el);
+    }
+    return out;
+  }
+
+  function hover(target, x, y) {
+    var prev = hovered;
+    if (target === prev) return;
+    hovered = target;
+    var fire = function (type, el, related, bubbles) {
+      el.dispatchEvent(
+        new MouseEvent(
+          type,
+          withModifiers({
+            bubbles: bubbles,
+            cancelable: bubbles,
+            composed: bubbles,
+            clientX: x,
+            clientY: y,
+            view: window,
+            relatedTarget: related,
+          }),
+        ),
+      );
+    };
+    var left = ancestors(prev);
+    var entered = ancestors(target);
+    // Enter and leave only go to elements the pointer really entered or
+    // left: leave innermost first, enter outermost first.
+    if (prev && prev.isConnected) {
+      fire("mouseout", prev, target, true);
+      left.forEach(function (el) {
+        if (entered.indexOf(el) < 0) fire("mouseleave", el, target, false);
+      });
+    }
+    if (target) {
+      fire("mouseover", target, prev, true);
+      entered.reverse().forEach(function (el) {
+        if (left.indexOf(el) < 0) fire("mouseenter", el, prev, false);
+      });
+    }
+  }
+
+  // Drag and drop: synthetic mouse events never start a native drag, so a
+  // pointer drag from a draggable element runs the HTML5 sequence here
+  // (dragstart, drag, dragenter/dragover/dragleave, drop, dragend) with one
+  // DataTransfer shared by all its events.
+  var drag = null;
+
+  function dragEvent(type, target, x, y, data) {
+    var ev = new DragEvent(
+      type,
+      withModifiers({
+        bubbles: true,
+        cancelable: true,
+        composed: true,
+        clientX: x,
+        clientY: y,
+        view: window,
+        dataTransfer: data,
+      }),
+    );
+    target.dispatchEvent(ev);
+    return ev;
+  }
+
+  // Primary button pressed on `target`: arm a drag if it's (inside) a
+  // draggable element. Images and links are draggable by default.
+  function dragPress(target, x, y) {
+    var source = target;
+    while (source && !(source.nodeType === 1 && source.draggable)) {
+      source = source.parentNode || source.host;
+    }
+    drag = source
+      ? { source: source, x: x, y: y, data: null, over: null, accepted: false }
+      : null;
+  }
+
+  // Pointer moved over `target` with the button down. Starts the drag on
+  // the first move away from the press; returns whether a drag is running,
+  // in which case the page gets drag events instead of mouse events.
+  function dragMove(target, x, y) {
+    if (!drag) return false;
+    if (!drag.data) {
+      if (x === drag.x && y === drag.y) return false;
+      drag.data = new DataTransfer();
+      var start = dragEvent("dragstart", drag.source, drag.x, drag.y, drag.data);
+      if (start.defaultPrevented) {
+        drag = null;
+        return false;
+      }
+    }
+    dragEvent("drag", drag.source, x, y, drag.data);
+    if (target !== drag.over) {
+      if (target) dragEvent("dragenter", target, x, y, drag.data);
+      if (drag.over) dragEvent("dragleave", drag.over, x, y, drag.data);
+      drag.over = target;
+    }
+    // Like a real drag, only a target that cancels dragover accepts a drop.
+    drag.accepted =
+      !!target &&
+      dragEvent("dragover", target, x, y, drag.data).defaultPrevented;
+    return true;
+  }
+
+  // Pointer released over `target`: drop if the target accepted it and end
+  // the drag. Returns whether a drag was running.
+  function dragRelease(target, x, y) {
+    var d = drag;
+    drag = null;
+    if (!d || !d.data) return false;
+    if (d.accepted && target === d.over) {
+      dragEvent("drop", target, x, y, d.data);
+    } else if (d.over) {
+      dragEvent("dragleave", d.over, x, y, d.data);
+    }
+    dragEvent("dragend", d.source, x, y, d.data);
+    return true;
+  }
+
+  // Waits: test a condition on the elements a locator finds whenever the
+  // document changes, rather than the client polling over HTTP. A slow poll
+  // backs the observer up for changes no mutation reports (e.g. a
+  // transition ending).
+  var WAIT_POLL_MS = 250;
+
+  function locate(doc, using, value) {
+    if (using !== "xpath") {
+      return Array.prototype.slice.call(doc.querySelectorAll(value));
+    }
+    var r = doc.evaluate(
+      value,
+      doc,
+      null,
+      XPathResult.ORDERED_NODE_SNAPSHOT_TYPE,
+      null,
+    );
+    var found = [];
+    for (var i = 0; i < r.snapshotLength; i++) found.push(r.snapshotItem(i));
+    return found;
+  }
+
+  function waitCondition(spec) {
+    switch (spec.condition) {
+      case "present":
+        return function () {
+          return true;
+        };
+      case "displayed":
+        return function (el) {
+          var s = window.getComputedStyle(el);
+          return (
+            s.display !== "none" &&
+            s.visibility !== "hidden" &&
+            s.opacity !== "0"
+          );
+        };
+      case "enabled":
+        return function (el) {
+          return !el.disabled;
+        };
+      case "text-matches":
+        var pattern;
+        try {
+          pattern = new RegExp(spec.pattern);
+        } catch (e) {
+          fail("invalidArgument", "invalid pattern: " + e.message);
+        }
+        return function (el) {
+          return pattern.test(el.textContent || "");
+        };
+      case "attribute-equals":
+        return function (el) {
+          return el.getAttribute(spec.name) === spec.expected;
+        };
+    }
+    fail("invalidArgument", "unknown condition: " + spec.condition);
+  }
+
+  // Call `done` with `{element}` for the first element `spec` locates that
+  // meets its condition, or with a timeout error after `spec.timeout` ms.
+  function waitForElement(doc, spec, done) {
+    var test = waitCondition(spec);
+    function check() {
+      var found = locate(doc, spec.using, spec.value);
+      for (var i = 0; i < found.length; i++) {
+        if (test(found[i])) {
+          return { selector: spec.value, index: i, using: spec.using };
+        }
+      }
+      return null;
+    }
+    var match = check();
+    if (match) {
+      done({ element: match });
+      return;
+    }
+    var observer = new MutationObserver(recheck);
+    var poll = setInterval(recheck, WAIT_POLL_MS);
+    var timer = setTimeout(function () {
+      stop();
+      var e = new Error(
+        "waited " + spec.timeout + "ms for " + spec.value + " to be " +
+          spec.condition,
+      );
+      errorCodes.set(e, "timeout");
+      done(e);
+    }, spec.timeout);
+    function stop() {
+      observer.disconnect();
+      clearInterval(poll);
+      clearTimeout(timer);
+    }
+    function recheck() {
+      var match = check();
+      if (match) {
+        stop();
+        done({ element: match });
+      }
+    }
+    observer.observe(doc, {
+      subtree: true,
+      childList: true,
+      attributes: true,
+      characterData: true,
+    });
+  }
+
+  // Call `done` once the document has loaded and no fetch/XHR has been in
+  // flight for `quiet` ms, or with a timeout error after `timeout` ms.
+  function waitForIdle(quiet, timeout, done) {
+    var deadline = Date.now() + timeout;
+    (function check() {
+      var now = Date.now();
+      if (
+        document.readyState === "complete" &&
+        requestsInFlight === 0 &&
+        now - lastNetworkActivity >= quiet
+      ) {
+        done(null);
+        return;
+      }
+      if (now >= deadline) {
+        var e = new Error(
+          "the page wasn't idle after " + timeout + "ms (readyState " +
+            document.readyState + ", " + requestsInFlight +
+            " requests in flight)",
+        );
+        errorCodes.set(e, "timeout");
+        done(e);
+        return;
+      }
+      setTimeout(check, WAIT_POLL_MS / 5);
+    })();
+  }
+
+  // Call `done` with `{value}` once `predicate` returns (or resolves to) a
+  // truthy value, evaluating it every `interval` ms, or with a timeout error
+  // after `timeout` ms. A string predicate is compiled like `run` compiles
+  // scripts, and `blocked` is called instead if the page's CSP forbids that.
+  function waitForScript(
+    predicate,
+    args,
+    interval,
+    timeout,
+    doc,
+    done,
+    blocked,
+  ) {
+    var fn = predicate;
+    if (typeof predicate === "string") {
+      try {
+        fn = new Function(
+          "document",
+          "__WEBDRIVER__",
+          "return function () {\n" + predicate + "\n};",
+        )(doc, __WEBDRIVER__);
+      } catch (e) {
+        if (e instanceof EvalError) blocked();
+        else done(scriptError(e));
+        return;
+      }
+    }
+    var deadline = Date.now() + timeout;
+    function settle(result) {
+      if (result) {
+        done({ value: result });
+      } else if (Date.now() >= deadline) {
+        var e = new Error(
+          "the predicate wasn't truthy after " + timeout + "ms",
+        );
+        errorCodes.set(e, "timeout");
+        done(e);
+      } else {
+        setTimeout(check, interval);
+      }
+    }
+    function check() {
+      var result;
+      try {
+        result = fn.apply(null, args);
+      } catch (e) {
+        done(scriptError(e));
+        return;
+      }
+      if (result && typeof result.then === "function") {
+        result.then(settle, function (e) {
+          done(scriptError(e));
+        });
+      } else {
+        settle(result);
+      }
+    }
+    check();
+  }
+
+  // Selection: offsets into a text control's value, or into the text of any
+  // other element (e.g. a contenteditable editor), where the document
+  // selection is read and set through its text nodes.
+  function hasSelectionApi(el) {
+    try {
+      return typeof el.selectionStart === "number";
+    } catch (e) {
+      return false;
+    }
+  }
+
+  function textOffset(root, node, offset) {
+    var range = root.ownerDocument.createRange();
+    range.selectNodeContents(root);
+    range.setEnd(node, offset);
+    return range.toString().length;
+  }
+
+  function textPosition(root, offset) {
+    var walker = root.ownerDocument.createTreeWalker(root, NodeFilter.SHOW_TEXT);
+    var node;
+    var last = null;
+    while ((node = walker.nextNode())) {
+      if (offset <= node.data.length) return [node, offset];
+      offset -= node.data.length;
+      last = node;
+    }
+    return last ? [last, last.data.length] : [root, 0];
+  }
+
+  function getSelectionOf(el) {
+    if (hasSelectionApi(el)) {
+      return {
+        start: el.selectionStart,
+        end: el.selectionEnd,
+        direction: el.selectionDirection || "none",
+        text: el.value.substring(el.selectionStart, el.selectionEnd),
+      };
+    }
+    var sel = el.ownerDocument.getSelection();
+    var range = sel && sel.rangeCount ? sel.getRangeAt(0) : null;
+    if (
+      !range ||
+      !el.contains(range.startContainer) ||
+      !el.contains(range.endContainer)
+    ) {
+      return { start: null, end: null, direction: "none", text: "" };
+    }
+    var backward =
+      !range.collapsed &&
+      sel.anchorNode === range.endContainer &&
+      sel.anchorOffset === range.endOffset;
+    return {
+      start: textOffset(el, range.startContainer, range.startOffset),
+      end: textOffset(el, range.endContainer, range.endOffset),
+      direction: range.collapsed ? "none" : backward ? "backward" : "forward",
+      text: range.toString(),
+    };
+  }
+
+  function setSelectionOf(el, start, end, direction) {
+    if (hasSelectionApi(el)) {
+      el.focus();
+      el.setSelectionRange(start, end, direction);
+      return getSelectionOf(el);
+    }
+    if (el.tagName === "INPUT" || el.tagName === "TEXTAREA") {
+      fail("invalidArgument", "the element's type has no text selection");
+    }
+    // Focusing an editor can move its caret, so focus first.
+    if (el.isContentEditable) el.focus();
+    var from = textPosition(el, start);
+    var to = textPosition(el, end);
+    var sel = el.ownerDocument.getSelection();
+    if (direction === "backward") {
+      sel.setBaseAndExtent(to[0], to[1], from[0], from[1]);
+    } else {
+      sel.setBaseAndExtent(from[0], from[1], to[0], to[1]);
+    }
+    return getSelectionOf(el);
+  }
+
+  function pageSelection(doc) {
+    var sel = doc.getSelection();
+    return sel
+      ? { text: sel.toString(), type: sel.type }
+      : { text: "", type: "None" };
+  }
+
+  // File uploads: base64 file content, inline or chunk by chunk, as bytes
+  // for a File.
+  function decodeBase64(data) {
+    var raw = atob(data);
+    var bytes = new Uint8Array(raw.length);
+    for (var i = 0; i < raw.length; i++) bytes[i] = raw.charCodeAt(i);
+    return bytes;
+  }
+
+  // Touch input: pointer actions of "touch" sources become pointer events
+  // and, where the engine can construct them, touch events listing every
+  // active touch. Like a finger, a touch keeps targeting the element it went
+  // down on, and one that goes up without moving is a tap and clicks.
+  var touchPoints = Object.create(null);
+  var nextTouchId = 2;
+
+  function touchPosition(source) {
+    var t = touchPoints[source];
+    return t ? [t.x, t.y] : [0, 0];
+  }
+
+  function touch(type, source, x, y, target) {
+    var t = touchPoints[source];
+    if (!t) {
+      t = touchPoints[source] = { id: nextTouchId++, x: 0, y: 0, target: null };
+    }
+    if (type === "move" && t.target && (x !== t.x || y !== t.y)) t.moved = true;
+    t.x = x;
+    t.y = y;
+    if (type === "down") {
+      t.primary = !Object.keys(touchPoints).some(function (k) {
+        return touchPoints[k].target;
+      });
+      t.target = target;
+      t.moved = false;
+    }
+    // A touch that isn't down has nothing to send events to.
+    var tgt = t.target;
+    if (!tgt) return;
+    var init = withModifiers({
+      clientX: x,
+      clientY: y,
+      bubbles: true,
+      cancelable: true,
+      composed: true,
+      view: window,
+    });
+    tgt.dispatchEvent(
+      new PointerEvent(
+        "pointer" + type,
+        Object.assign({}, init, {
+          pointerId: t.id,
+          pointerType: "touch",
+          isPrimary: t.primary,
+          button: type === "move" ? -1 : 0,
+          buttons: type === "up" ? 0 : 1,
+          pressure: type === "up" ? 0 : 0.5,
+          width: 1,
+          height: 1,
+        }),
+      ),
+    );
+    if (type === "up") t.target = null;
+    if (typeof Touch === "function" && typeof TouchEvent === "function") {
+      var toTouch = function (p) {
+        return new Touch({
+          identifier: p.id,
+          target: p.target || tgt,
+          clientX: p.x,
+          clientY: p.y,
+        });
+      };
+      var active = Object.keys(touchPoints)
+        .filter(function (k) {
+          return touchPoints[k].target;
+        })
+        .map(function (k) {
+          return toTouch(touchPoints[k]);
+        });
+      var name = { down: "touchstart", move: "touchmove", up: "touchend" };
+      tgt.dispatchEvent(
+        new TouchEvent(
+          name[type],
+          Object.assign({}, init, {
+            touches: active,
+            targetTouches: active.filter(function (p) {
+              return p.target === tgt;
+            }),
+            changedTouches: [toTouch(t)],
+          }),
+        ),
+      );
+    }
+    if (type === "up" && !t.moved) {
+      tgt.dispatchEvent(new MouseEvent("click", Object.assign({ detail: 1 }, init)));
+    }
+  }
+
+  // Console capture: buffer console output and uncaught errors so the
+  // WebDriver server can serve them as browser logs. The buffer is capped
+  // to avoid unbounded growth in long-running pages.
+  var CONSOLE_LIMIT = 1000;
+
+  // Entries carry a sequence number so readers can poll for new entries
+  // without draining the buffer; the document id tells them when a
+  // navigation has restarted the sequence.
+  var consoleSeq = 0;
+  var documentId =
+    Date.now().toString(36) + Math.random().toString(36).slice(2, 8);
+
+  function formatLogArg(arg) {
+    if (arg instanceof Error) return arg.stack || arg.name + ": " + arg.message;
+    if (typeof arg === "string") return arg;
+    if (arg === undefined) return "undefined";
+    if (typeof arg === "function") return String(arg);
+    try {
+      return JSON.stringify(arg);
+    } catch (e) {
+      return String(arg);
+    }
+  }
+
+  function pushConsoleEntry(level, source, args) {
+    var buf = __WEBDRIVER__.__console;
+    var parts = [];
+    for (var i = 0; i < args.length; i++) parts.push(formatLogArg(args[i]));
+    buf.push({
+      seq: ++consoleSeq,
+      level: level,
+      source: source,
+      message: parts.join(" "),
+      args: parts,
+      timestamp: Date.now(),
+    });
+    if (buf.length > CONSOLE_LIMIT) buf.splice(0, buf.length - CONSOLE_LIMIT);
+  }
+
+  ["log", "info", "warn", "error", "debug"].forEach(function (level) {
+    var original = console[level];
+    console[level] = function () {
+      try {
+        pushConsoleEntry(level, "console-api", arguments);
+      } catch (e) {}
+      return original.apply(console, arguments);
+    };
+  });
+
+  // Page errors: uncaught exceptions and unhandled promise rejections are
+  // recorded separately (with stack and source) in addition to the console.
+  function pushPageError(type, message, stack, source) {
+    var buf = __WEBDRIVER__.__errors;
+    buf.push({
+      type: type,
+      message: message,
+      stack: stack || "",
+      source: source || "",
+      timestamp: Date.now(),
+    });
+    if (buf.length > CONSOLE_LIMIT) buf.splice(0, buf.length - CONSOLE_LIMIT);
+  }
+
+  window.addEventListener("error", function (ev) {
+    // Resource load failures (img, script) bubble here without a message.
+    if (!ev.message && !ev.error) return;
+    var source = ev.filename
+      ? ev.filename + ":" + ev.lineno + ":" + ev.colno
+      : "";
+    var stack = (ev.error && ev.error.stack) || "";
+    pushPageError("error", String(ev.message), stack, source);
+    pushConsoleEntry("error", "javascript", [
+      stack || String(ev.message) + (source ? " (" + source + ")" : ""),
+    ]);
+  });
+
+  window.addEventListener("unhandledrejection", function (ev) {
+    var reason = ev.reason;
+    var message =
+      reason instanceof Error
+        ? reason.name + ": " + reason.message
+        : "Unhandled rejection: " + formatLogArg(reason);
+    var stack = (reason && reason.stack) || "";
+    pushPageError("unhandledrejection", message, stack, "");
+    pushConsoleEntry("error", "javascript", [stack || message]);
+  });
+
+  // window.print() would open the native print dialog and block automation,
+  // so it only records the request for tests to assert on.
+  window.print = function () {
+    var buf = __WEBDRIVER__.__prints;
+    buf.push({
+      url: String(location.href),
+      title: String(document.title),
+      timestamp: Date.now(),
+    });
+    if (buf.length > CONSOLE_LIMIT) buf.splice(0, buf.length - CONSOLE_LIMIT);
+  };
+
+  // Network capture: wrap fetch and XHR to record method, URL, status,
+  // timings and sizes for each request. Sizes are -1 when unknown.
+  var networkCounter = 0;
+
+  // Requests in flight, and when one last started or settled, so waits can
+  // tell when the page has gone quiet.
+  var requestsInFlight = 0;
+  var lastNetworkActivity = Date.now();
+
+  function networkActivity(delta) {
+    requestsInFlight += delta;
+    lastNetworkActivity = Date.now();
+  }
+
+  function pushNetworkEntry(entry) {
+    var buf = __WEBDRIVER__.__network;
+    buf.push(entry);
+    if (buf.length > CONSOLE_LIMIT) buf.splice(0, buf.length - CONSOLE_LIMIT);
+  }
+
+  function newNetworkEntry(type, method, url) {
+    var abs;
+    try {
+      abs = new URL(url, document.baseURI).href;
+    } catch (e) {
+      abs = String(url);
+    }
+    return {
+      id: "net-" + ++networkCounter,
+      type: type,
+      method: String(method || "GET").toUpperCase(),
+      url: abs,
+      status: 0,
+      statusText: "",
+      requestHeaders: {},
+      responseHeaders: {},
+      mimeType: "",
+      requestSize: -1,
+      responseSize: -1,
+      startedAt: Date.now(),
+      duration: 0,
+      error: null,
+      mocked: false,
+    };
+  }
+
+  function bodySize(body) {
+    if (body == null) return 0;
+    if (typeof body === "string") return new Blob([body]).size;
+    if (body instanceof Blob) return body.size;
+    if (body instanceof ArrayBuffer) return body.byteLength;
+    if (ArrayBuffer.isView(body)) return body.byteLength;
+    if (body instanceof URLSearchParams) return new Blob([String(body)]).size;
+    return -1;
+  }
+
+  function headersToObject(headers) {
+    var out = {};
+    if (!headers) return out;
+    if (typeof headers.forEach === "function" && !Array.isArray(headers)) {
+      headers.forEach(function (value, name) {
+        out[name] = value;
+      });
+    } else if (Array.isArray(headers)) {
+      headers.forEach(function (pair) {
+        out[String(pair[0]).toLowerCase()] = String(pair[1]);
+      });
+    } else {
+      Object.keys(headers).forEach(function (name) {
+        out[name.toLowerCase()] = String(headers[name]);
+      });
+    }
+    return out;
+  }
+
+  // Request mocking: rules registered by the WebDriver server are matched
+  // against fetch/XHR requests before they reach the network. A rule either
+  // fails the request (`error`) or answers it with a canned response.
+  var mockRules = [];
+
+  function setMocks(rules) {
+    mockRules = Array.isArray(rules) ? rules.slice() : [];
+  }
+
+  function globToRegExp(pattern) {
+    var escaped = String(pattern).replace(/[.+?^${}()|[\]\\]/g, "\\$&");
+    return new RegExp("^" + escaped.replace(/\*/g, ".*") + "$");
+  }
+
+  function findMock(method, url) {
+    for (var i = 0; i < mockRules.length; i++) {
+      var rule = mockRules[i];
+      if (rule.method && String(rule.method).toUpperCase() !== method) continue;
+      if (!globToRegExp(rule.urlPattern || "*").test(url)) continue;
+      if (typeof rule.times === "number") {
+        if (rule.times <= 0) continue;
+        rule.times -= 1;
+        // The count dies with this document; the plugin keeps the one the
+        // next page load starts from.
+        if (rule.id) {
+          window.__TAURI_INTERNALS__.invoke(
+            "plugin:webdriver-automation|mock_used",
+            { id: String(rule.id) }
+          ).catch(function () {});
+        }
+      }
+      return rule;
+    }
+    return null;
+  }
+
+  function mockBody(rule) {
+    var body = rule.body;
+    if (body == null) return "";
+    return typeof body === "string" ? body : JSON.stringify(body);
+  }
+
+  function mockHeaders(rule) {
+    var headers = headersToObject(rule.headers);
+    if (!headers["content-type"] && rule.body != null) {
+      headers["content-type"] =
+        typeof rule.body === "string" ? "text/plain" : "application/json";
+    }
+    return headers;
+  }
+
+  function mockDelay(rule) {
+    return new Promise(function (done) {
+      setTimeout(done, rule.delayMs || 0);
+    });
+  }
+
+  function mockXhr(xhr, rule) {
+    var headers = mockHeaders(rule);
+    var text = mockBody(rule);
+    var failed = !!rule.error;
+    var url = xhr.__wdNetwork.url;
+    function define(name, value) {
+      Object.defineProperty(xhr, name, { value: value, configurable: true });
+    }
+    mockDelay(rule).then(function () {
+      define("readyState", 4);
+      define("status", failed ? 0 : rule.status || 200);
+      define("statusText", failed ? "" : rule.statusText || "");
+      define("responseURL", url);
+      define("responseText", failed ? "" : text);
+      var response = failed ? null : text;
+      if (!failed && xhr.responseType === "json") {
+        try {
+          response = JSON.parse(text);
+        } catch (e) {
+          response = null;
+        }
+      }
+      define("response", response);
+      define("getResponseHeader", function (name) {
+        var value = headers[String(name).toLowerCase()];
+        return failed || value === undefined ? null : value;
+      });
+      define("getAllResponseHeaders", function () {
+        if (failed) return "";
+        return Object.keys(headers)
+          .map(function (name) {
+            return name + ": " + headers[name];
+          })
+          .join("\r\n");
+      });
+      xhr.dispatchEvent(new Event("readystatechange"));
+      xhr.dispatchEvent(new ProgressEvent(failed ? "error" : "load"));
+      xhr.dispatchEvent(new ProgressEvent("loadend"));
+    });
+  }
+
+  var nativeFetch = window.fetch;
+  if (nativeFetch) {
+    window.fetch = function (input, init) {
+      var request = input instanceof Request ? input : null;
+      var method = (init && init.method) || (request && request.method);
+      var entry = newNetworkEntry(
+        "fetch",
+        method,
+        request ? request.url : String(input),
+      );
+      entry.requestHeaders = headersToObject(
+        (init && init.headers) || (request && request.headers),
+      );
+      entry.requestSize = bodySize(init && init.body);
+      var start = performance.now();
+      var rule = findMock(entry.method, entry.url);
+      var pending;
+      networkActivity(1);
+      if (rule) {
+        entry.mocked = true;
+        pending = mockDelay(rule).then(function () {
+          if (rule.error) throw new TypeError(String(rule.error));
+          return new Response(mockBody(rule), {
+            status: rule.status || 200,
+            statusText: rule.statusText || "",
+            headers: mockHeaders(rule),
+          });
+        });
+      } else {
+        pending = nativeFetch.apply(this, arguments);
+      }
+      return pending.then(
+        function (response) {
+          entry.duration = performance.now() - start;
+          entry.status = response.status;
+          entry.statusText = response.statusText;
+          entry.responseHeaders = headersToObject(response.headers);
+          entry.mimeType = response.headers.get("content-type") || "";
+          var length = response.headers.get("content-length");
+          entry.responseSize = length !== null ? parseInt(length, 10) : -1;
+          pushNetworkEntry(entry);
+          networkActivity(-1);
+          return response;
+        },
+        function (err) {
+          entry.duration = performance.now() - start;
+          entry.error = String((err && err.message) || err);
+          pushNetworkEntry(entry);
+          networkActivity(-1);
+          throw err;
+        },
+      );
+    };
+  }
+
+  var nativeXhrOpen = XMLHttpRequest.prototype.open;
+  var nativeXhrSend = XMLHttpRequest.prototype.send;
+  var nativeXhrSetHeader = XMLHttpRequest.prototype.setRequestHeader;
+  XMLHttpRequest.prototype.open = function (method, url) {
+    this.__wdNetwork = newNetworkEntry("xhr", method, url);
+    return nativeXhrOpen.apply(this, arguments);
+  };
+  XMLHttpRequest.prototype.setRequestHeader = function (name, value) {
+    if (this.__wdNetwork) {
+      this.__wdNetwork.requestHeaders[String(name).toLowerCase()] =
+        String(value);
+    }
+    return nativeXhrSetHeader.apply(this, arguments);
+  };
+  XMLHttpRequest.prototype.send = function (body) {
+    var xhr = this;
+    var entry = xhr.__wdNetwork;
+    if (entry) {
+      entry.requestSize = bodySize(body);
+      entry.startedAt = Date.now();
+      var start = performance.now();
+      networkActivity(1);
+      xhr.addEventListener("loadend", function () {
+        networkActivity(-1);
+        entry.duration = performance.now() - start;
+        entry.status = xhr.status;
+        entry.statusText = xhr.statusText;
+        var raw = xhr.getAllResponseHeaders() || "";
+        raw.split(/\r?\n/).forEach(function (line) {
+          var idx = line.indexOf(":");
+          if (idx > 0) {
+            entry.responseHeaders[line.slice(0, idx).trim().toLowerCase()] =
+              line.slice(idx + 1).trim();
+          }
+        });
+        entry.mimeType = xhr.getResponseHeader("content-type") || "";
+        var length = xhr.getResponseHeader("content-length");
+        if (length !== null) {
+          entry.responseSize = parseInt(length, 10);
+        } else if (xhr.responseType === "" || xhr.responseType === "text") {
+          entry.responseSize = new Blob([xhr.responseText]).size;
+        }
+        if (xhr.status === 0) entry.error = "network error";
+        pushNetworkEntry(entry);
+      });
+      var rule = findMock(entry.method, entry.url);
+      if (rule) {
+        entry.mocked = true;
+        mockXhr(xhr, rule);
+        return;
+      }
+    }
+    try {
+      return nativeXhrSend.apply(this, arguments);
+    } catch (e) {
+      // Not sent, so no loadend.
+      if (entry) networkActivity(-1);
+      throw e;
+    }
+  };
+
+  // The document request itself, from the Navigation Timing API.
+  window.addEventListener("load", function () {
+    var nav = performance.getEntriesByType("navigation")[0];
+    if (!nav) return;
+    var entry = newNetworkEntry("navigation", "GET", nav.name);
+    entry.status = nav.responseStatus || 200;
+    entry.mimeType = "text/html";
+    entry.responseSize = nav.transferSize || nav.encodedBodySize || -1;
+    entry.startedAt = Math.round(performance.timeOrigin + nav.startTime);
+    entry.duration = nav.responseEnd - nav.startTime;
+    pushNetworkEntry(entry);
+  });
+
+  // BiDi value serialization: page values become WebDriver BiDi
+  // RemoteValues, client LocalValues become page values. Nodes get stable
+  // shared ids so they can be passed back into later calls.
+  var nodeIds = new WeakMap();
+  var nodesById = new Map();
+  var nodeCounter = 0;
+  // Ids kept before collected nodes, then the oldest, are dropped.
+  var MAX_NODE_IDS = 10000;
+
+  function nodeSharedId(node) {
+    var id = nodeIds.get(node);
+    if (!id) {
+      id = documentId + "." + ++nodeCounter;
+      nodeIds.set(node, id);
+      nodesById.set(id, new WeakRef(node));
+      if (nodesById.size > MAX_NODE_IDS) pruneNodeIds();
+    }
+    return id;
+  }
+
+  function forgetNodeId(id) {
+    var ref = nodesById.get(id);
+    var node = ref && ref.deref();
+    if (node && nodeIds.get(node) === id) nodeIds.delete(node);
+    return nodesById.delete(id);
+  }
+
+  function pruneNodeIds() {
+    nodesById.forEach(function (ref, id) {
+      if (!ref.deref()) nodesById.delete(id);
+    });
+    // Map keys iterate oldest first.
+    var excess = nodesById.size - Math.floor(MAX_NODE_IDS * 0.9);
+    if (excess <= 0) return;
+    var oldest = [];
+    var ids = nodesById.keys();
+    while (oldest.length < excess) oldest.push(ids.next().value);
+    oldest.forEach(forgetNodeId);
+  }
+
+  // Drop the ids tauri-wd released; the nodes get new ones if found again.
+  function releaseRefs(ids) {
+    return ids.filter(forgetNodeId).length;
+  }
+
+  // Element references that can't be expressed as a selector (scoped,
+  // shadow DOM and active-element finds) use the same registry, so the
+  // app's DOM is never tagged.
+  function elementRef(node) {
+    return { selector: nodeSharedId(node), index: 0, using: "ref" };
+  }
+
+  function elementById(id) {
+    var ref = nodesById.get(id);
+    var node = ref && ref.deref();
+    if (!node) {
+      // Collected: the id can never resolve again.
+      nodesById.delete(id);
+      return null;
+    }
+    return node.isConnected ? node : null;
+  }
+
+  function currentContext() {
+    try {
+      return window.__TAURI_INTERNALS__.metadata.currentWindow.label;
+    } catch (e) {
+      return null;
+    }
+  }
+
+  function toRemoteValue(value, depth, seen) {
+    if (depth == null) depth = Infinity;
+    seen = seen || [];
+    switch (typeof value) {
+      case "undefined":
+        return { type: "undefined" };
+      case "string":
+        return { type: "string", value: value };
+      case "boolean":
+        return { type: "boolean", value: value };
+      case "bigint":
+        return { type: "bigint", value: String(value) };
+      case "symbol":
+        return { type: "symbol" };
+      case "function":
+        return { type: "function" };
+      case "number":
+        if (Number.isNaN(value)) return { type: "number", value: "NaN" };
+        if (Object.is(value, -0)) return { type: "number", value: "-0" };
+        if (value === Infinity) return { type: "number", value: "Infinity" };
+        if (value === -Infinity) return { type: "number", value: "-Infinity" };
+        return { type: "number", value: value };
+    }
+    if (value === null) return { type: "null" };
+    if (value === window) {
+      return { type: "window", value: { context: currentContext() } };
+    }
+    if (value instanceof Node) {
+      var node = {
+        type: "node",
+        sharedId: nodeSharedId(value),
+        value: {
+          nodeType: value.nodeType,
+          childNodeCount: value.childNodes.length,
+        },
+      };
+      if (value.nodeType === Node.ELEMENT_NODE) {
+        var attributes = {};
+        for (var i = 0; i < value.attributes.length; i++) {
+          attributes[value.attributes[i].name] = value.attributes[i].value;
+        }
+        node.value.localName = value.localName;
+        node.value.namespaceURI = value.namespaceURI;
+        node.value.attributes = attributes;
+        if (value.shadowRoot) {
+          node.value.shadowRoot = toRemoteValue(value.shadowRoot, 0, seen);
+        }
+      } else if (value.nodeValue !== null) {
+        node.value.nodeValue = value.nodeValue;
+      }
+      return node;
+    }
+    if (value instanceof Date) return { type: "date", value: value.toISOString() };
+    if (value instanceof RegExp) {
+      return {
+        type: "regexp",
+        value: { pattern: value.source, flags: value.flags },
+      };
+    }
+    if (value instanceof Error) return { type: "error" };
+    if (value instanceof Promise) return { type: "promise" };
+    if (value instanceof WeakMap) return { type: "weakmap" };
+    if (value instanceof WeakSet) return { type: "weakset" };
+    if (value instanceof ArrayBuffer) return { type: "arraybuffer" };
+    if (ArrayBuffer.isView(value)) return { type: "typedarray" };
+
+    var type = "object";
+    if (Array.isArray(value)) type = "array";
+    else if (value instanceof Map) type = "map";
+    else if (value instanceof Set) type = "set";
+    else if (value instanceof NodeList) type = "nodelist";
+    else if (value instanceof HTMLCollection) type = "htmlcollection";
+    var result = { type: type };
+    // Past the depth limit, or on a cycle, report the type only.
+    if (depth <= 0 || seen.indexOf(value) !== -1) return result;
+    seen.push(value);
+    if (type === "object") {
+      result.value = Object.keys(value).map(function (key) {
+        return [key, toRemoteValue(value[key], depth - 1, seen)];
+      });
+    } else if (type === "map") {
+      result.value = Array.from(value.entries()).map(function (entry) {
+        var key =
+          typeof entry[0] === "string"
+            ? entry[0]
+            : toRemoteValue(entry[0], depth - 1, seen);
+        return [key, toRemoteValue(entry[1], depth - 1, seen)];
+      });
+    } else {
+      result.value = Array.from(value).map(function (item) {
+        return toRemoteValue(item, depth - 1, seen);
+      });
+    }
+    seen.pop();
+    return result;
+  }
+
+  function fromLocalValue(v) {
+    if (v && v.sharedId !== undefined) {
+      var ref = nodesById.get(v.sharedId);
+      var node = ref && ref.deref();
+      if (!node || !node.isConnected) {
+        throw new Error("no such node: " + v.sharedId);
+      }
+      return node;
+    }
+    function pairs(list) {
+      return (list || []).map(function (pair) {
+        var key = typeof pair[0] === "string" ? pair[0] : fromLocalValue(pair[0]);
+        return [key, fromLocalValue(pair[1])];
+      });
+    }
+    switch (v && v.type) {
+      case "undefined":
+        return undefined;
+      case "null":
+        return null;
+      case "string":
+      case "boolean":
+        return v.value;
+      case "number":
+        if (v.value === "NaN") return NaN;
+        if (v.value === "-0") return -0;
+        if (v.value === "Infinity") return Infinity;
+        if (v.value === "-Infinity") return -Infinity;
+        return v.value;
+      case "bigint":
+        return BigInt(v.value);
+      case "date":
+        return new Date(v.value);
+      case "regexp":
+        return new RegExp(v.value.pattern, v.value.flags || "");
+      case "array":
+        return (v.value || []).map(fromLocalValue);
+      case "set":
+        return new Set((v.value || []).map(fromLocalValue));
+      case "map":
+        return new Map(pairs(v.value));
+      case "object":
+        var obj = {};
+        pairs(v.value).forEach(function (pair) {
+          obj[pair[0]] = pair[1];
+        });
+        return obj;
+    }
+    throw new Error("unsupported LocalValue type: " + (v && v.type));
+  }
+
+  function exceptionDetails(e, depth) {
+    var text =
+      e instanceof Error ? e.name + ": " + e.message : "Uncaught " + formatLogArg(e);
+    return {
+      columnNumber: 0,
+      lineNumber: 0,
+      exception: toRemoteValue(e, depth),
+      stackTrace: { callFrames: [] },
+      text: text,
+    };
+  }
+
+  // Run `fn` for a BiDi script command and report a script.EvaluateResult
+  // through `done`, awaiting a returned promise if asked to.
+  function bidiRun(fn, awaitPromise, depth, done) {
+    function ok(v) {
+      done({ type: "success", result: toRemoteValue(v, depth) });
+    }
+    function fail(e) {
+      done({ type: "exception", exceptionDetails: exceptionDetails(e, depth) });
+    }
+    var result;
+    try {
+      result = fn();
+    } catch (e) {
+      fail(e);
+      return;
+    }
+    if (awaitPromise && result && typeof result.then === "function") {
+      result.then(ok, fail);
+    } else {
+      ok(result);
+    }
+  }
+
+  function scriptError(e) {
+    return e instanceof Error
+      ? e
+      : { error: "Error", message: formatLogArg(e), stacktrace: "" };
+  }
+
+  // W3C JSON clone for WebDriver classic scripts. tauri-wd sends element and
+  // shadow root references as `{key: {selector, index, using}}`, the plugin
+  // reference behind its id; they become the nodes again here. Nodes in a
+  // result go back the same way, as `ref` references tauri-wd hands out ids
+  // for.
+  var ELEMENT_KEY = "element-6066-11e4-a52e-4f735466cecf";
+  var SHADOW_KEY = "shadow-6066-11e4-a52e-4f735466cecf";
+  var WINDOW_KEY = "window-fcc6-11e5-b4f8-330a88ab9d7f";
+
+  function referencedNode(doc, ref) {
+    if (!ref || typeof ref.selector !== "string") return null;
+    if (ref.using === "ref") return elementById(ref.selector);
+    var index = ref.index || 0;
+    if (ref.using === "xpath") {
+      return doc
+        .evaluate(
+          ref.selector,
+          doc,
+          null,
+          XPathResult.ORDERED_NODE_SNAPSHOT_TYPE,
+          null,
+        )
+        .snapshotItem(index);
+    }
+    return doc.querySelectorAll(ref.selector)[index] || null;
+  }
+
+  function fromWebDriverJson(value, doc) {
+    if (value === null || typeof value !== "object") return value;
+    if (Array.isArray(value)) {
+      return value.map(function (item) {
+        return fromWebDriverJson(item, doc);
+      });
+    }
+    if (typeof value[ELEMENT_KEY] === "object") {
+      var el = referencedNode(doc, value[ELEMENT_KEY]);
+      if (!el) {
+        fail(
+          "staleElement",
+          "a script argument refers to an element no longer in the document",
+        );
+      }
+      return el;
+    }
+    if (typeof value[SHADOW_KEY] === "object") {
+      var host = referencedNode(doc, value[SHADOW_KEY]);
+      if (!host || !host.shadowRoot) {
+        fail(
+          "noSuchShadowRoot",
+          "a script argument refers to a shadow root no longer in the document",
+        );
+      }
+      return host.shadowRoot;
+    }
+    var result = {};
+    Object.keys(value).forEach(function (key) {
+      result[key] = fromWebDriverJson(value[key], doc);
+    });
+    return result;
+  }
+
+  function toWebDriverJson(value, seen) {
+    if (value === undefined || value === null) return null;
+    if (typeof value !== "object" && typeof value !== "function") return value;
+    if (value instanceof Element) {
+      if (!value.isConnected) {
+        fail(
+          "staleElement",
+          "the script returned an element no longer in the document",
+        );
+      }
+      var ref = {};
+      ref[ELEMENT_KEY] = elementRef(value);
+      return ref;
+    }
+    if (value instanceof ShadowRoot) {
+      if (!value.host.isConnected) {
+        fail(
+          "noSuchShadowRoot",
+          "the script returned a shadow root no longer in the document",
+        );
+      }
+      var shadow = {};
+      shadow[SHADOW_KEY] = elementRef(value.host);
+      return shadow;
+    }
+    if (value === window) {
+      var win = {};
+      win[WINDOW_KEY] = currentContext();
+      return win;
+    }
+    if (typeof value.toJSON === "function") return value.toJSON();
+    if (seen.indexOf(value) !== -1) {
+      fail("javascriptError", "the script returned a cyclic object value");
+    }
+    seen.push(value);
+    var result;
+    if (
+      Array.isArray(value) ||
+      value instanceof NodeList ||
+      value instanceof HTMLCollection ||
+      value instanceof FileList ||
+      Object.prototype.toString.call(value) === "[object Arguments]"
+    ) {
+      result = Array.from(value, function (item) {
+        return toWebDriverJson(item, seen);
+      });
+    } else {
+      result = {};
+      Object.keys(value).forEach(function (key) {
+        result[key] = toWebDriverJson(value[key], seen);
+      });
+    }
+    seen.pop();
+    return result;
+  }
+
+  // Run a WebDriver script for the plugin. The source arrives as a JSON
+  // string and is compiled here, so nothing in it can break out of the
+  // plugin's wrapper. `doc` is bound as `document` (the current frame's).
+  // With `callback`, the result is whatever the script passes to the
+  // function appended to `args` rather than its return value. Arguments and
+  // results go through the W3C JSON clone above.
+  function run(id, source, args, doc, callback) {
+    var fn = source;
+    if (typeof source === "string") {
+      try {
+        fn = new Function(
+          "document",
+          "__WEBDRIVER__",
+          "return function () {\n" + source + "\n};",
+        )(doc, __WEBDRIVER__);
+      } catch (e) {
+        // A CSP without 'unsafe-eval' forbids compiling strings; the plugin
+        // retries with the script inlined into its wrapper and passed here
+        // as a function.
+        if (e instanceof EvalError) evalBlocked(id);
+        else resolve(id, scriptError(e));
+        return;
+      }
+    }
+    function settle(result) {
+      try {
+        resolve(id, toWebDriverJson(result, []));
+      } catch (e) {
+        resolve(id, scriptError(e));
+      }
+    }
+    try {
+      args = fromWebDriverJson(args, doc);
+      if (callback) {
+        var resolved = false;
+        args.push(function (r) {
+          if (resolved) return;
+          resolved = true;
+          settle(r);
+        });
+        fn.apply(null, args);
+      } else {
+        settle(fn.apply(null, args));
+      }
+    } catch (e) {
+      resolve(id, scriptError(e));
+    }
+  }
+
+  var __WEBDRIVER__ = Object.create(null);
+  Object.defineProperty(window, namespace, {
+    value: __WEBDRIVER__,
     writable: false,
     configurable: false,
   });
 
   // Intercept native dialogs for WebDriver alert handling.
-  // These must be set up before page scripts run.
+  // These must be set up before page scripts run. The page isn't blocked,
+  // so dialogs opened back to back queue up and the alert commands answer
+  // them oldest first. Until the queue is empty, other commands fail with
+  // "unexpected alert open" (see checkDialog).
+  var dialogs = [];
+  var noDialog = { open: false, type: null, text: null, response: null };
+
+  function openDialog(dialog) {
+    dialog.open = true;
+    dialog.text = String(dialog.text || "");
+    dialogs.push(dialog);
+    return dialog.response;
+  }
+
+  function closeDialog() {
+    var d = dialogs.shift();
+    if (d) d.open = false;
+    return d || null;
+  }
+
+  function checkDialog() {
+    if (dialogs.length) {
+      fail("unexpectedAlertOpen", "unexpected alert open: " + dialogs[0].text);
+    }
+  }
+
+  // Dialogs the previous document left open, passed back by the plugin.
+  function restoreDialogs(list) {
+    list.forEach(openDialog);
+  }
+
   window.alert = function (msg) {
-    window.__WEBDRIVER__.__dialog = {
-      open: true,
-      type: "alert",
-      text: String(msg || ""),
-      response: null,
-    };
+    openDialog({ type: "alert", text: msg, response: null });
   };
   window.confirm = function (msg) {
-    window.__WEBDRIVER__.__dialog = {
-      open: true,
-      type: "confirm",
-      text: String(msg || ""),
-      response: false,
-    };
-    return window.__WEBDRIVER__.__dialog.response;
+    return openDialog({ type: "confirm", text: msg, response: false });
   };
   window.prompt = function (msg, defaultVal) {
-    window.__WEBDRIVER__.__dialog = {
-      open: true,
+    return openDialog({
       type: "prompt",
-      text: String(msg || ""),
+      text: msg,
       defaultValue: defaultVal || "",
       response: null,
-    };
-    return window.__WEBDRIVER__.__dialog.response;
+    });
   };
 
-  Object.defineProperties(window.__WEBDRIVER__, {
+  // window.open() would fail, or open a webview nothing can reach, so popups
+  // become Tauri windows the plugin opens: they get window handles and can be
+  // switched to. The page gets a stand-in for the popup's WindowProxy that
+  // can close it and post messages to it (the popup's `opener` can post
+  // back); it can't reach into the popup's document. The session's
+  // popupBehavior can instead load popups in this window or block them.
+  var nativeOpen = window.open;
+  var popupBehavior = "open-window";
+
+  function setPopupBehavior(behavior) {
+    popupBehavior = behavior;
+  }
+
+  function popupSize(features, name) {
+    var m = new RegExp("(?:^|,)\\s*" + name + "\\s*=\\s*(\\d+)").exec(features);
+    return m ? Number(m[1]) : null;
+  }
+
+  function isWindow(w) {
+    try {
+      return !!w && w.window === w;
+    } catch (e) {
+      return false;
+    }
+  }
+
+  // Whether `w` or a frame nested in it has a child frame called `name`.
+  // Named access to child frames works across origins too.
+  function hasFrameNamed(w, name) {
+    var child;
+    try {
+      child = w.frames[name];
+    } catch (e) {}
+    if (isWindow(child)) return true;
+    for (var i = 0; i < w.frames.length; i++) {
+      if (hasFrameNamed(w.frames[i], name)) return true;
+    }
+    return false;
+  }
+
+  // Whether `target` opens a new browsing context rather than navigating one
+  // the webview already has: `_blank`, or a name that's neither this window
+  // or one of its ancestors, nor a frame anywhere in the page.
+  function opensPopup(target) {
+    var keyword = target.toLowerCase();
+    if (keyword === "" || keyword === "_blank") return true;
+    if (keyword === "_self" || keyword === "_parent" || keyword === "_top") {
+      return false;
+    }
+    for (var w = window; ; w = w.parent) {
+      try {
+        if (w.name === target) return false;
+      } catch (e) {}
+      if (w === w.parent) break;
+    }
+    return !hasFrameNamed(window.top, target);
+  }
+
+  function openPopup(url, target, features) {
+    target = target == null ? "" : String(target);
+    if (!opensPopup(target)) return nativeOpen.apply(window, arguments);
+    var href = new URL(url ? String(url) : "about:blank", location.href).href;
+    if (popupBehavior === "block") return null;
+    if (popupBehavior === "same-window") {
+      location.href = href;
+      return window;
+    }
+    features = features == null ? "" : String(features);
+    var name = target.toLowerCase() === "_blank" ? "" : target;
+    var label = null;
+    var opened = window.__TAURI_INTERNALS__.invoke(
+      "plugin:webdriver-automation|open_popup",
+      {
+        url: href,
+        name: name,
+        width: popupSize(features, "width"),
+        height: popupSize(features, "height"),
+      },
+    );
+    var popup = {
+      closed: false,
+      opener: window,
+      name: name,
+      location: { href: href },
+      focus: function () {},
+      blur: function () {},
+      close: function () {
+        if (popup.closed) return;
+        popup.closed = true;
+        if (label) closePopup(label);
+      },
+      postMessage: function (message, targetOrigin) {
+        opened.then(function (to) {
+          if (popup.closed) return;
+          window.__TAURI_INTERNALS__.invoke(
+            "plugin:webdriver-automation|popup_message",
+            {
+              to: to,
+              data: message === undefined ? null : message,
+              targetOrigin: targetOrigin == null ? "/" : String(targetOrigin),
+            },
+          ).catch(function () {});
+        }, function () {});
+      },
+    };
+    opened.then(
+      function (result) {
+        label = result;
+        if (popup.closed) closePopup(label);
+      },
+      function () {
+        popup.closed = true;
+      },
+    );
+    return popup;
+  }
+
+  window.open = openPopup;
+
+  function closePopup(label) {
+    window.__TAURI_INTERNALS__.invoke("plugin:webdriver-automation|close_popup", {
+      label: label,
+    }).catch(function () {});
+  }
+
+  // Links that open a new browsing context go nowhere in a webview, so once
+  // the page's own handlers have had their say they follow the popup
+  // behavior too. Links targeting a frame or window the page has navigate
+  // it as usual.
+  window.addEventListener("click", function (ev) {
+    if (ev.defaultPrevented || ev.button !== 0) return;
+    var link = ev.target instanceof Element && ev.target.closest("a[href], area[href]");
+    if (!link || link.hasAttribute("download")) return;
+    var target = link.target;
+    if (!target || !opensPopup(target)) return;
+    ev.preventDefault();
+    openPopup(link.href, target);
+  });
+
+  window.addEventListener("pagehide", function () {
+    var ids = Object.keys(inflight);
+    if (!ids.length && !dialogs.length) return;
+    var open = dialogs.map(function (d) {
+      return {
+        type: d.type,
+        text: d.text,
+        defaultValue: d.defaultValue,
+        response: d.response,
+      };
+    });
+    window.__TAURI_INTERNALS__.invoke(
+      "plugin:webdriver-automation|page_unloaded",
+      { ids: ids, dialogs: open },
+    ).catch(function () {
+      // Too late to deliver; the requests time out instead.
+    });
+  });
+
+  // In-memory cookie store (WKWebView has no `document.cookie` on tauri://
+  // URLs). The plugin keeps the cookies it added and passes back those
+  // that aren't expired into each new document; only the ones for this
+  // host are kept. Cookies are keyed by domain, path and name, so ones that
+  // share a name but not a path don't replace each other.
+  var cookies = Object.create(null);
+
+  function cookieKey(c) {
+    return JSON.stringify([c.domain, c.path, c.name]);
+  }
+
+  function restoreCookies(list) {
+    var host = location.hostname;
+    var now = Date.now() / 1000;
+    list.forEach(function (c) {
+      var domain = String(c.domain || "").replace(/^\./, "");
+      var matches = !domain || host === domain || host.endsWith("." + domain);
+      if (matches && (c.expiry == null || c.expiry > now)) {
+        cookies[cookieKey(c)] = c;
+      }
+    });
+  }
+
+  Object.defineProperties(__WEBDRIVER__, {
+    began: { value: began, writable: false, configurable: false },
     resolve: { value: resolve, writable: false, configurable: false },
+    evalBlocked: { value: evalBlocked, writable: false, configurable: false },
+    resolveBytes: { value: resolveBytes, writable: false, configurable: false },
+    resolveCanvas: {
+      value: resolveCanvas,
+      writable: false,
+      configurable: false,
+    },
+    fail: { value: fail, writable: false, configurable: false },
     findElement: { value: findElement, writable: false, configurable: false },
     findElementByXPath: {
       value: findElementByXPath,
@@ -124,30 +2029,143 @@
       writable: false,
       configurable: false,
     },
-    findElementInShadow: {
-      value: findElementInShadow,
+    elementRef: { value: elementRef, writable: false, configurable: false },
+    releaseRefs: { value: releaseRefs, writable: false, configurable: false },
+    elementById: {
+      value: elementById,
       writable: false,
       configurable: false,
     },
-    __shadowCache: {
-      value: Object.create(null),
+    setDevicePixelRatio: {
+      value: setDevicePixelRatio,
+      writable: false,
+      configurable: false,
+    },
+    setMocks: { value: setMocks, writable: false, configurable: false },
+    setModifier: { value: setModifier, writable: false, configurable: false },
+    clearModifiers: {
+      value: clearModifiers,
       writable: false,
       configurable: false,
     },
+    withModifiers: {
+      value: withModifiers,
+      writable: false,
+      configurable: false,
+    },
+    focusToType: { value: focusToType, writable: false, configurable: false },
+    typeText: { value: typeText, writable: false, configurable: false },
+    mousePointerEvent: {
+      value: mousePointerEvent,
+      writable: false,
+      configurable: false,
+    },
+    focusFor: { value: focusFor, writable: false, configurable: false },
+    hover: { value: hover, writable: false, configurable: false },
+    dragPress: { value: dragPress, writable: false, configurable: false },
+    dragMove: { value: dragMove, writable: false, configurable: false },
+    dragRelease: { value: dragRelease, writable: false, configurable: false },
+    touchPosition: {
+      value: touchPosition,
+      writable: false,
+      configurable: false,
+    },
+    touch: { value: touch, writable: false, configurable: false },
+    setPopupBehavior: {
+      value: setPopupBehavior,
+      writable: false,
+      configurable: false,
+    },
+    decodeBase64: { value: decodeBase64, writable: false, configurable: false },
+    waitForElement: {
+      value: waitForElement,
+      writable: false,
+      configurable: false,
+    },
+    waitForIdle: { value: waitForIdle, writable: false, configurable: false },
+    waitForScript: {
+      value: waitForScript,
+      writable: false,
+      configurable: false,
+    },
+    getSelectionOf: {
+      value: getSelectionOf,
+      writable: false,
+      configurable: false,
+    },
+    setSelectionOf: {
+      value: setSelectionOf,
+      writable: false,
+      configurable: false,
+    },
+    pageSelection: {
+      value: pageSelection,
+      writable: false,
+      configurable: false,
+    },
+    toRemoteValue: {
+      value: toRemoteValue,
+      writable: false,
+      configurable: false,
+    },
+    fromLocalValue: {
+      value: fromLocalValue,
+      writable: false,
+      configurable: false,
+    },
+    bidiRun: { value: bidiRun, writable: false, configurable: false },
+    run: { value: run, writable: false, configurable: false },
+    scriptError: { value: scriptError, writable: false, configurable: false },
     cache: {
       value: Object.create(null),
       writable: false,
       configurable: false,
     },
-    cookies: {
-      value: Object.create(null),
+    cookies: { value: cookies, writable: false, configurable: false },
+    cookieKey: { value: cookieKey, writable: false, configurable: false },
+    restoreCookies: {
+      value: restoreCookies,
+      writable: false,
+      configurable: false,
+    },
+    __console: {
+      value: [],
+      writable: false,
+      configurable: false,
+    },
+    __documentId: {
+      value: documentId,
+      writable: false,
+      configurable: false,
+    },
+    __errors: {
+      value: [],
+      writable: false,
+      configurable: false,
+    },
+    __prints: {
+      value: [],
+      writable: false,
+      configurable: false,
+    },
+    __network: {
+      value: [],
       writable: false,
       configurable: false,
     },
     __dialog: {
-      value: { open: false, type: null, text: null, response: null },
-      writable: true,
+      get: function () {
+        return dialogs[0] || noDialog;
+      },
+      configurable: false,
+    },
+    __dialogs: { value: dialogs, writable: false, configurable: false },
+    closeDialog: { value: closeDialog, writable: false, configurable: false },
+    restoreDialogs: {
+      value: restoreDialogs,
+      writable: false,
       configurable: false,
     },
+    checkDialog: { value: checkDialog, writable: false, configurable: false },
   });
-})();
+})(/* namespace */ "__WEBDRIVER__");
@@ -4,48 +4,884 @@
 // allows an external WebDriver server to interact with the webview: find elements,
 // click buttons, read text, manage windows, and execute JavaScript.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Mutex;
 
 use tauri::{Manager, Runtime, State};
 
+mod auth;
+pub mod automation;
+mod native;
+mod pdf;
+mod peer;
 mod server;
+mod shortcut;
+mod snapshot;
+
+pub use automation::Automation;
 
 // --- Tauri IPC command: receives script results from the JS bridge ---
 
+/// With `eval_blocked`, the bridge couldn't compile the script because the
+/// page's CSP forbids it. That comes as a flag rather than a result, so no
+/// value a script returns can be mistaken for it; the window is marked so
+/// its next scripts are inlined straight away.
 #[tauri::command]
 async fn resolve<R: Runtime>(
-    _app: tauri::AppHandle<R>,
+    webview: tauri::Webview<R>,
     webdriver: State<'_, WebDriverState>,
     id: String,
     result: Option<serde_json::Value>,
-) -> Result<(), ()> {
-    webdriver
-        .pending_scripts
-        .lock()
-        .expect("failed to lock pending scripts")
-        .remove(&id)
-        .expect("no pending script with that id")
-        .send(result.unwrap_or_default())
-        .expect("failed to send script result");
+    eval_blocked: Option<bool>,
+) -> Result<(), String> {
+    let script = webdriver.take_pending(&id, webview.label())?;
+    if eval_blocked == Some(true) {
+        webdriver
+            .csp_blocked
+            .lock()
+            .expect("lock poisoned")
+            .insert(webview.label().to_string());
+        let _ = script.tx.send(Err(Cancelled::EvalBlocked));
+        return Ok(());
+    }
+    // The request may have been dropped since; then nobody wants the result.
+    let _ = script.tx.send(Ok(result.unwrap_or_default()));
+    Ok(())
+}
+
+/// Header of a `resolve_bytes` call naming the script it answers.
+const RESULT_ID_HEADER: &str = "webdriver-result";
+
+/// Receives binary results (screenshots, PDFs) as the raw IPC body, so they
+/// don't cross the bridge as base64 inside JSON. The script resolves to
+/// `{"bytes": length}`, and the bytes wait in `binary_results` for the
+/// request to take them.
+#[tauri::command]
+async fn resolve_bytes<R: Runtime>(
+    webview: tauri::Webview<R>,
+    webdriver: State<'_, WebDriverState>,
+    request: tauri::ipc::Request<'_>,
+) -> Result<(), String> {
+    let id = request
+        .headers()
+        .get(RESULT_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .ok_or("missing result id")?;
+    let tauri::ipc::InvokeBody::Raw(bytes) = request.body() else {
+        return Err("expected a binary body".into());
+    };
+    let script = webdriver.take_pending(id, webview.label())?;
+    let mut results = webdriver.binary_results.lock().expect("lock poisoned");
+    if script
+        .tx
+        .send(Ok(serde_json::json!({"bytes": bytes.len()})))
+        .is_ok()
+    {
+        results.insert(id.to_string(), bytes.clone());
+    }
+    Ok(())
+}
+
+/// Sent by the bridge as its document unloads (a navigation or reload),
+/// with the ids of the scripts it was still running and the dialogs still
+/// open. The scripts can never resolve now, so their requests fail at once
+/// instead of timing out; the dialogs are opened again in the next document.
+#[tauri::command]
+async fn page_unloaded<R: Runtime>(
+    webview: tauri::Webview<R>,
+    webdriver: State<'_, WebDriverState>,
+    ids: Vec<String>,
+    dialogs: Option<Vec<serde_json::Value>>,
+) -> Result<(), String> {
+    for id in ids {
+        if let Ok(script) = webdriver.take_pending(&id, webview.label()) {
+            let _ = script.tx.send(Err(Cancelled::Unloaded));
+        }
+    }
+    if let Some(dialogs) = dialogs.filter(|dialogs| !dialogs.is_empty()) {
+        webdriver
+            .open_dialogs
+            .lock()
+            .expect("lock poisoned")
+            .insert(webview.label().to_string(), dialogs);
+    }
     Ok(())
 }
 
+/// Sent by a plugin script that found no bridge in its document, which can
+/// happen when a navigation replaced the document without the init script
+/// running. Installs the bridge again with the driver's state; the script
+/// runs once it's there (see [`WebDriverState::bridged`]). A script that
+/// still finds no bridge sends its result id as `failed`, which fails it.
+#[tauri::command]
+async fn rearm<R: Runtime>(
+    webview: tauri::Webview<R>,
+    webdriver: State<'_, WebDriverState>,
+    failed: Option<String>,
+) -> Result<(), String> {
+    if let Some(id) = failed {
+        let script = webdriver.take_pending(&id, webview.label())?;
+        let _ = script.tx.send(Err(Cancelled::NoBridge));
+        return Ok(());
+    }
+    tracing::debug!("re-installing the webdriver bridge in {}", webview.label());
+    let namespace = serde_json::to_string(&webdriver.namespace).unwrap();
+    webview
+        .eval(format!(
+            "if(!window[{namespace}]){{{}\n}}",
+            webdriver.init_script
+        ))
+        .map_err(|e| e.to_string())?;
+    webdriver.push_popup_behavior(&webview);
+    webdriver.restore(&webview);
+    Ok(())
+}
+
+/// Sent by the bridge when a mock rule limited by `times` answered a
+/// request. The count the bridge decremented dies with its document, so the
+/// plugin keeps the remaining one: it's what the next page load gets, and
+/// the other windows are told at once.
+#[tauri::command]
+async fn mock_used<R: Runtime>(
+    webview: tauri::Webview<R>,
+    webdriver: State<'_, WebDriverState>,
+    id: String,
+) -> Result<(), String> {
+    let rules = {
+        let mut rules = webdriver.mock_rules.lock().expect("lock poisoned");
+        let Some(rule) = rules
+            .iter_mut()
+            .find(|rule| rule.get("id").and_then(|v| v.as_str()) == Some(id.as_str()))
+        else {
+            return Ok(());
+        };
+        if let Some(times) = rule.get("times").and_then(|v| v.as_u64()) {
+            rule["times"] = serde_json::json!(times.saturating_sub(1));
+        }
+        serde_json::Value::Array(rules.clone())
+    };
+    let script = webdriver.bridged(&format!("__WEBDRIVER__.setMocks({rules})"));
+    for other in webview.webview_windows().values() {
+        if other.label() != webview.label() {
+            let _ = other.eval(&script);
+        }
+    }
+    Ok(())
+}
+
+// --- Tauri IPC commands: window.open() popups ---
+
+/// Label prefix of windows opened for `window.open()`.
+const POPUP_PREFIX: &str = "wd-popup-";
+
+/// What popups do until a session picks something else.
+const DEFAULT_POPUP_BEHAVIOR: &str = "open-window";
+
+/// Initialization script of a popup: a stand-in `window.opener` whose
+/// `postMessage` reaches the window that opened it through
+/// [`popup_message`]. `{opener}` is that window's label as a JSON string.
+const POPUP_OPENER_SCRIPT: &str = r#"Object.defineProperty(window, "opener", {
+  configurable: true,
+  value: {
+    closed: false,
+    postMessage: function (message, targetOrigin) {
+      window.__TAURI_INTERNALS__.invoke("plugin:webdriver-automation|popup_message", {
+        to: {opener},
+        data: message === undefined ? null : message,
+        targetOrigin: targetOrigin == null ? "/" : String(targetOrigin),
+      }).catch(function () {});
+    },
+  },
+});"#;
+
+/// Open a `window.open()` popup as a Tauri window, so it gets a window handle
+/// and can be switched to. Returns the window's label.
+///
+/// Only http(s) URLs, `about:blank` and URLs of the opener's own origin are
+/// opened: a popup is a window of the app, so a `file:`, `data:` or
+/// app-internal URL would get the app's capabilities. Only the app's own
+/// pages may call this (see the capabilities in [`build_plugin`]), and only
+/// while popups open windows at all.
+///
+/// A popup with a `name` is reused: opening the same name again from the
+/// same window loads `url` in the popup already open.
+#[tauri::command]
+async fn open_popup<R: Runtime>(
+    webview: tauri::Webview<R>,
+    webdriver: State<'_, WebDriverState>,
+    url: String,
+    name: Option<String>,
+    width: Option<f64>,
+    height: Option<f64>,
+) -> Result<String, String> {
+    if *webdriver.popup_behavior.lock().expect("lock poisoned") != DEFAULT_POPUP_BEHAVIOR {
+        return Err("popups don't open windows".into());
+    }
+    let url: tauri::Url = url.parse().map_err(|e| format!("invalid popup URL: {e}"))?;
+    let opener = webview.url().map_err(|e| e.to_string())?;
+    let web = matches!(url.scheme(), "http" | "https") || url.as_str() == "about:blank";
+    if !web && !same_origin(&url, &opener) {
+        return Err(format!("popups can't open {url}"));
+    }
+    let name = name.unwrap_or_default();
+    let named = (!name.is_empty())
+        .then(|| {
+            let popups = webdriver.popups.lock().expect("lock poisoned");
+            popups
+                .iter()
+                .find(|(_, p)| p.opener == webview.label() && p.name == name)
+                .map(|(label, _)| label.clone())
+        })
+        .flatten()
+        .and_then(|label| webview.app_handle().get_webview_window(&label));
+    if let Some(window) = named {
+        window.navigate(url).map_err(|e| e.to_string())?;
+        return Ok(window.label().to_string());
+    }
+    let label = format!("{POPUP_PREFIX}{}", uuid::Uuid::new_v4().simple());
+    let opener_label = serde_json::to_string(webview.label()).unwrap();
+    tauri::WebviewWindowBuilder::new(
+        webview.app_handle(),
+        &label,
+        tauri::WebviewUrl::External(url),
+    )
+    .inner_size(width.unwrap_or(800.0), height.unwrap_or(600.0))
+    .initialization_script(POPUP_OPENER_SCRIPT.replace("{opener}", &opener_label))
+    .build()
+    .map_err(|e| format!("failed to open popup: {e}"))?;
+    webdriver.popups.lock().expect("lock poisoned").insert(
+        label.clone(),
+        Popup {
+            opener: webview.label().to_string(),
+            name,
+        },
+    );
+    Ok(label)
+}
+
+/// Close a popup opened by [`open_popup`], for the stand-in `window.open()`
+/// returns. Only the window that opened it may.
+#[tauri::command]
+async fn close_popup<R: Runtime>(
+    webview: tauri::Webview<R>,
+    webdriver: State<'_, WebDriverState>,
+    label: String,
+) -> Result<(), String> {
+    if !webdriver.is_opener(webview.label(), &label) {
+        return Err(format!("{label} is not a popup of {}", webview.label()));
+    }
+    if let Some(window) = webview.app_handle().get_webview_window(&label) {
+        window.close().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Deliver a `postMessage` between a popup and the window that opened it,
+/// for the stand-ins on either side; no other window can be reached. As in
+/// a browser, the message is dropped unless `target_origin` is `*`, or the
+/// receiver's origin (`/` for the sender's). It arrives as a `message` event
+/// carrying a JSON copy of `data` and the sender's origin, without `source`.
+#[tauri::command]
+async fn popup_message<R: Runtime>(
+    webview: tauri::Webview<R>,
+    webdriver: State<'_, WebDriverState>,
+    to: String,
+    data: serde_json::Value,
+    target_origin: String,
+) -> Result<(), String> {
+    if !webdriver.is_opener(webview.label(), &to) && !webdriver.is_opener(&to, webview.label()) {
+        return Err(format!("{} can't post messages to {to}", webview.label()));
+    }
+    let Some(receiver) = webview.app_handle().get_webview_window(&to) else {
+        return Ok(());
+    };
+    let sender_url = webview.url().map_err(|e| e.to_string())?;
+    let receiver_url = receiver.url().map_err(|e| e.to_string())?;
+    let allowed = match target_origin.as_str() {
+        "*" => true,
+        "/" => same_origin(&receiver_url, &sender_url),
+        target => tauri::Url::parse(target).is_ok_and(|url| same_origin(&url, &receiver_url)),
+    };
+    if !allowed {
+        return Ok(());
+    }
+    receiver
+        .eval(format!(
+            "window.dispatchEvent(new MessageEvent(\"message\",{{data:{data},origin:{}}}))",
+            serde_json::Value::String(serialize_origin(&sender_url))
+        ))
+        .map_err(|e| e.to_string())
+}
+
+/// A URL's origin as `(scheme, host, port)`, or `None` for a URL without a
+/// host (`about:`, `data:`, `file:`). Unlike [`tauri::Url::origin`], which is
+/// opaque for every scheme but the special ones, this also gives the app's
+/// own `tauri://localhost` pages an origin equal to itself.
+fn tuple_origin(url: &tauri::Url) -> Option<(&str, &str, Option<u16>)> {
+    let host = url.host_str().filter(|host| !host.is_empty())?;
+    Some((url.scheme(), host, url.port_or_known_default()))
+}
+
+/// Whether two URLs share a (non-opaque) origin.
+fn same_origin(a: &tauri::Url, b: &tauri::Url) -> bool {
+    tuple_origin(a).is_some_and(|origin| Some(origin) == tuple_origin(b))
+}
+
+/// The origin a `message` event reports: `scheme://host[:port]`, the port
+/// only if it isn't the scheme's default, or `"null"` without a host.
+fn serialize_origin(url: &tauri::Url) -> String {
+    match (tuple_origin(url), url.port()) {
+        (Some((scheme, host, _)), Some(port)) => format!("{scheme}://{host}:{port}"),
+        (Some((scheme, host, _)), None) => format!("{scheme}://{host}"),
+        (None, _) => "null".into(),
+    }
+}
+
 // --- Internal types ---
 
 pub(crate) struct WebDriverState {
-    pub pending_scripts: Mutex<HashMap<String, tokio::sync::oneshot::Sender<serde_json::Value>>>,
+    /// Scripts waiting for the bridge to `resolve` them, by result id.
+    pub pending_scripts: Mutex<HashMap<String, PendingScript>>,
+    /// Bytes sent with `resolve_bytes`, by result id, until taken.
+    pub binary_results: Mutex<HashMap<String, Vec<u8>>>,
+    /// Emulated `devicePixelRatio` per window label, re-applied after each page load.
+    pub device_scale_overrides: Mutex<HashMap<String, f64>>,
+    /// Request mock rules, shared by all webviews and re-applied after each page load.
+    pub mock_rules: Mutex<Vec<serde_json::Value>>,
+    /// What `target="_blank"` links and `window.open()` do: "open-window",
+    /// "same-window" or "block". Pushed into each new document.
+    pub popup_behavior: Mutex<String>,
+    /// BiDi preload scripts, evaluated as each new document is committed.
+    pub preload_scripts: Mutex<Vec<PreloadScript>>,
+    /// Cookies added through the driver, by domain, path and name. The
+    /// bridge's store lives in the document, so they're put back into each
+    /// new one.
+    pub cookies: Mutex<HashMap<(String, String, String), serde_json::Value>>,
+    /// Dialogs that were still open when a window's document unloaded, by
+    /// window label, until the next document opens them again.
+    pub open_dialogs: Mutex<HashMap<String, Vec<serde_json::Value>>>,
+    /// Set once any webview has finished loading its first page.
+    pub page_loaded: tokio::sync::watch::Sender<bool>,
+    /// Labels of the windows whose page is loading, from the navigation
+    /// starting until the new document has finished loading.
+    pub loading: tokio::sync::watch::Sender<HashSet<String>>,
+    /// Name of the `window` property holding the JS bridge.
+    pub namespace: String,
+    /// `init.js` for that namespace, for [`rearm`].
+    pub init_script: String,
+    /// How many webviews have been created with each label.
+    pub generations: Mutex<HashMap<String, u64>>,
+    /// Labels of the windows whose current document's CSP forbids compiling
+    /// scripts, until their next page load.
+    pub csp_blocked: Mutex<HashSet<String>>,
+    /// Windows opened by [`open_popup`], by label.
+    pub popups: Mutex<HashMap<String, Popup>>,
+    /// The plugin's routes, served over HTTP by the server and called
+    /// directly by [`Automation`], which share its window and frame tracking.
+    pub router: axum::Router,
+}
+
+/// A window opened for `window.open()`.
+pub(crate) struct Popup {
+    /// Label of the window that opened it.
+    pub opener: String,
+    /// The target name it was opened with; empty for `_blank`.
+    pub name: String,
+}
+
+/// How long a pending script may wait for its result before it's dropped
+/// from the map, well past the server's own evaluation timeout.
+const PENDING_SCRIPT_TTL: std::time::Duration = std::time::Duration::from_secs(120);
+
+impl WebDriverState {
+    /// WebDriver handle of the window `label`: the label itself for the
+    /// first window with that label, `{label}~{n}` for the nth, so a handle
+    /// to a closed window never reaches a new one that reuses its label.
+    /// Tauri labels can't contain `~`.
+    pub fn handle(&self, label: &str) -> String {
+        match self.generation(label) {
+            1 => label.to_string(),
+            n => format!("{label}~{n}"),
+        }
+    }
+
+    /// The label of the window `handle` was issued for, or `None` if that
+    /// label now belongs to a newer window. Bare labels name the first
+    /// window created with them.
+    pub fn label_of<'a>(&self, handle: &'a str) -> Option<&'a str> {
+        let (label, generation) = match handle.split_once('~') {
+            Some((label, n)) => (label, n.parse().ok()?),
+            None => (handle, 1),
+        };
+        (self.generation(label) == generation).then_some(label)
+    }
+
+    fn generation(&self, label: &str) -> u64 {
+        self.generations
+            .lock()
+            .expect("lock poisoned")
+            .get(label)
+            .copied()
+            .unwrap_or(1)
+    }
+
+    /// Register a script evaluated in `window`, whose result goes to `tx`.
+    /// Entries nobody waits for any more, or that have waited past
+    /// [`PENDING_SCRIPT_TTL`], are dropped first, so the map stays bounded
+    /// even when requests are abandoned mid-evaluation.
+    pub fn add_pending(
+        &self,
+        id: String,
+        window: &str,
+        tx: tokio::sync::oneshot::Sender<Result<serde_json::Value, Cancelled>>,
+    ) {
+        let mut pending = self.pending_scripts.lock().expect("lock poisoned");
+        pending.retain(|_, script| {
+            !script.tx.is_closed() && script.created.elapsed() < PENDING_SCRIPT_TTL
+        });
+        pending.insert(
+            id,
+            PendingScript {
+                window: window.to_string(),
+                created: std::time::Instant::now(),
+                tx,
+            },
+        );
+    }
+
+    /// Remove the pending script `id` for its result, which `label`'s
+    /// webview sent.
+    fn take_pending(&self, id: &str, label: &str) -> Result<PendingScript, String> {
+        let mut pending = self.pending_scripts.lock().expect("lock poisoned");
+        // Unknown ids come from pages guessing, scripts resolving twice, or
+        // results arriving after their request gave up. None of them is ours
+        // to crash over.
+        let origin = pending
+            .get(id)
+            .map(|script| script.window.as_str())
+            .ok_or_else(|| format!("no pending script {id}"))?;
+        // Only the webview the script was evaluated in may answer it;
+        // another one (say, remote content in a second window) could
+        // otherwise spoof the result of a guessed id.
+        if origin != label {
+            return Err(format!("script {id} was not evaluated in this webview"));
+        }
+        Ok(pending.remove(id).expect("checked above"))
+    }
+
+    /// Take the bytes sent with `resolve_bytes` for the result `id`.
+    pub fn take_bytes(&self, id: &str) -> Option<Vec<u8>> {
+        self.binary_results
+            .lock()
+            .expect("lock poisoned")
+            .remove(id)
+    }
+
+    /// Fail the pending scripts `which` selects with `reason`, so their
+    /// requests return now instead of at the evaluation timeout.
+    pub fn cancel_scripts(&self, reason: Cancelled, which: impl Fn(&PendingScript) -> bool) {
+        let mut pending = self.pending_scripts.lock().expect("lock poisoned");
+        let ids: Vec<String> = pending
+            .iter()
+            .filter(|(_, script)| which(script))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in ids {
+            if let Some(script) = pending.remove(&id) {
+                let _ = script.tx.send(Err(reason));
+            }
+        }
+    }
+
+    /// Wait up to `limit` for `label`'s page to finish loading, if it's
+    /// loading at all.
+    pub async fn wait_for_page(&self, label: &str, limit: std::time::Duration) {
+        let mut loading = self.loading.subscribe();
+        let _ =
+            tokio::time::timeout(limit, loading.wait_for(|loading| !loading.contains(label))).await;
+    }
+
+    /// Wrap `script` so the bridge is reachable as `__WEBDRIVER__` inside it,
+    /// whatever the namespace. Every script the plugin evaluates goes
+    /// through this. Where the bridge is missing, the script has it
+    /// installed again ([`rearm`]) and runs once it's there, rather than
+    /// failing with a `TypeError` nobody hears about.
+    pub fn bridged(&self, script: &str) -> String {
+        self.wrap(script, "")
+    }
+
+    /// Like [`bridged`](Self::bridged), for a script whose result `id_json`
+    /// waits on: if the bridge still isn't there after a second, the
+    /// script fails with [`Cancelled::NoBridge`] instead of timing out.
+    pub fn bridged_with_id(&self, id_json: &str, script: &str) -> String {
+        self.wrap(
+            script,
+            &format!(
+                "else window.__TAURI_INTERNALS__.invoke(\
+                 \"plugin:webdriver-automation|rearm\",{{failed:{id_json}}});"
+            ),
+        )
+    }
+
+    fn wrap(&self, script: &str, give_up: &str) -> String {
+        let namespace = serde_json::to_string(&self.namespace).unwrap();
+        format!(
+            "(function(run){{var b=window[{namespace}];if(b)return run(b);\
+             var tries=0;\
+             window.__TAURI_INTERNALS__.invoke(\"plugin:webdriver-automation|rearm\")\
+             .then(function again(){{var b=window[{namespace}];\
+             if(b)run(b);else if(++tries<50)setTimeout(again,20);{give_up}}});\
+             }})(function(__WEBDRIVER__){{{script}\n}});"
+        )
+    }
+
+    /// Whether the window `opener` opened the popup `popup`.
+    fn is_opener(&self, opener: &str, popup: &str) -> bool {
+        self.popups
+            .lock()
+            .expect("lock poisoned")
+            .get(popup)
+            .is_some_and(|p| p.opener == opener)
+    }
+
+    /// Push a non-default popup behavior into `webview`'s document.
+    fn push_popup_behavior<R: Runtime>(&self, webview: &tauri::Webview<R>) {
+        let behavior = self.popup_behavior.lock().expect("lock poisoned").clone();
+        if behavior != DEFAULT_POPUP_BEHAVIOR {
+            let _ = webview.eval(self.bridged(&format!(
+                "__WEBDRIVER__.setPopupBehavior({})",
+                serde_json::Value::String(behavior)
+            )));
+        }
+    }
+
+    /// Put the driver's state back into `webview`'s new document: init.js
+    /// runs fresh on every navigation, so viewport emulation, mocks,
+    /// cookies and dialogs left open by the previous document would be
+    /// lost.
+    fn restore<R: Runtime>(&self, webview: &tauri::Webview<R>) {
+        let ratio = self
+            .device_scale_overrides
+            .lock()
+            .expect("lock poisoned")
+            .get(webview.label())
+            .copied();
+        if let Some(ratio) = ratio {
+            let _ =
+                webview.eval(self.bridged(&format!("__WEBDRIVER__.setDevicePixelRatio({ratio})")));
+        }
+        let rules = self.mock_rules.lock().expect("lock poisoned").clone();
+        if !rules.is_empty() {
+            let _ = webview.eval(self.bridged(&format!(
+                "__WEBDRIVER__.setMocks({})",
+                serde_json::Value::Array(rules)
+            )));
+        }
+        let cookies: Vec<_> = self
+            .cookies
+            .lock()
+            .expect("lock poisoned")
+            .values()
+            .cloned()
+            .collect();
+        if !cookies.is_empty() {
+            let _ = webview.eval(self.bridged(&format!(
+                "__WEBDRIVER__.restoreCookies({})",
+                serde_json::Value::Array(cookies)
+            )));
+        }
+        let dialogs = self
+            .open_dialogs
+            .lock()
+            .expect("lock poisoned")
+            .remove(webview.label());
+        if let Some(dialogs) = dialogs {
+            let _ = webview.eval(self.bridged(&format!(
+                "__WEBDRIVER__.restoreDialogs({})",
+                serde_json::Value::Array(dialogs)
+            )));
+        }
+    }
+}
+
+pub(crate) struct PendingScript {
+    /// Label of the window the script was evaluated in.
+    pub window: String,
+    pub created: std::time::Instant,
+    pub tx: tokio::sync::oneshot::Sender<Result<serde_json::Value, Cancelled>>,
+}
+
+/// Why a pending script was given up before its result arrived.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Cancelled {
+    WindowClosed,
+    SessionDeleted,
+    /// The document the script ran in was unloaded, see [`page_unloaded`].
+    Unloaded,
+    /// The page's CSP forbids compiling the script's source, see [`resolve`].
+    EvalBlocked,
+    /// The bridge couldn't be installed again, see [`rearm`].
+    NoBridge,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub(crate) struct PreloadScript {
+    /// Script source, already wrapped so it can be evaluated as-is.
+    pub source: String,
+    /// Window labels to run in; all windows when absent.
+    pub contexts: Option<Vec<String>>,
 }
 
 // --- Plugin entry point ---
 
+/// How the plugin reports its port on stdout once the server is listening.
+/// The port file passed by tauri-wd is written regardless.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Announce {
+    /// `[webdriver] listening on port {N}`, the line tauri-wd looks for,
+    /// followed by ` host {H}` when the advertised host isn't `127.0.0.1`.
+    #[default]
+    Text,
+    /// `{"webdriver":{"host":"127.0.0.1","port":N}}` with the advertised
+    /// host, for other tooling.
+    Json,
+    /// Print nothing.
+    Silent,
+}
+
+/// Configures the plugin. `init()` is `Builder::new().build()`.
+///
+/// ```rust,ignore
+/// builder = builder.plugin(
+///     tauri_plugin_webdriver_automation::Builder::new()
+///         .port(4445)
+///         .build(),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct Builder {
+    enabled: Option<bool>,
+    host: std::net::IpAddr,
+    advertise_host: Option<String>,
+    port: u16,
+    announce: Announce,
+    namespace: Option<String>,
+    body_limit: usize,
+    peer_check: bool,
+    extensions: axum::Router,
+}
+
+impl std::fmt::Debug for Builder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Builder")
+            .field("enabled", &self.enabled)
+            .field("host", &self.host)
+            .field("advertise_host", &self.advertise_host)
+            .field("port", &self.port)
+            .field("announce", &self.announce)
+            .field("namespace", &self.namespace)
+            .field("body_limit", &self.body_limit)
+            .field("peer_check", &self.peer_check)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            enabled: None,
+            host: std::net::Ipv4Addr::LOCALHOST.into(),
+            advertise_host: None,
+            port: 0,
+            announce: Announce::Text,
+            namespace: None,
+            body_limit: 64 * 1024 * 1024,
+            peer_check: false,
+            extensions: axum::Router::new(),
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to run the automation server. By default it only runs when
+    /// the app is launched with `TAURI_WEBVIEW_AUTOMATION=true`, as tauri-wd
+    /// does, so a normal debug run doesn't open a port. `true` always starts
+    /// it; `false` never does. A disabled plugin registers nothing, so it can
+    /// stay in the builder chain.
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    /// Address to bind (default `127.0.0.1`). tauri-wd connects over
+    /// `127.0.0.1`, so keep the loopback interface reachable.
+    pub fn host(mut self, host: std::net::IpAddr) -> Self {
+        self.host = host;
+        self
+    }
+
+    /// Host tauri-wd should connect to (default: the bound address, or
+    /// `127.0.0.1` when bound to all interfaces), for when it reaches the app
+    /// across a container or machine boundary. `TAURI_WEBDRIVER_ADVERTISE_HOST`
+    /// overrides it.
+    pub fn advertise_host(mut self, host: impl Into<String>) -> Self {
+        self.advertise_host = Some(host.into());
+        self
+    }
+
+    /// Fixed port to bind (default `0`, a random free port).
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// How to report the port on stdout (default [`Announce::Text`]).
+    pub fn announce(mut self, announce: Announce) -> Self {
+        self.announce = announce;
+        self
+    }
+
+    /// Name of the `window` property that holds the JS bridge. By default
+    /// it's random per launch (`__wd_` plus hex digits), so app code can't
+    /// collide with it and pages can't look for a fixed name. tauri-wd
+    /// learns it from `/api/version`.
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Largest request body the server accepts, in bytes (default 64 MiB).
+    /// Bigger ones, e.g. a huge `execute` script or file upload, fail with
+    /// an `invalid argument` error saying the payload is too large.
+    pub fn body_limit(mut self, bytes: usize) -> Self {
+        self.body_limit = bytes;
+        self
+    }
+
+    /// Also check the process behind each connection (default `false`): it
+    /// must run as the app's user and, on macOS when tauri-wd launched the
+    /// app, be that tauri-wd. Supported on Linux and macOS; elsewhere it
+    /// logs a warning and only the token is checked. Leave it off when
+    /// connections are forwarded, as with `adb forward`.
+    pub fn peer_check(mut self, enabled: bool) -> Self {
+        self.peer_check = enabled;
+        self
+    }
+
+    /// Serve extra app-specific routes under `/v1/ext`, e.g. a `/reset-db`
+    /// route becomes `POST /v1/ext/reset-db`, which tests reach through
+    /// tauri-wd as `POST /session/{id}/tauri/ext/reset-db`. They sit behind
+    /// the same token check as the built-in endpoints. May be called more
+    /// than once.
+    ///
+    /// ```rust,ignore
+    /// Builder::new().routes(
+    ///     axum::Router::new().route("/reset-db", axum::routing::post(reset_db)),
+    /// )
+    /// ```
+    pub fn routes(mut self, router: axum::Router) -> Self {
+        self.extensions = self.extensions.merge(router);
+        self
+    }
+
+    /// Serve `handler` as `POST /v1/ext/{name}`: it gets the JSON request body
+    /// and its `Ok` value is the JSON response. An `Err` is returned as
+    /// `{"error": ...}` with status 500, like the built-in endpoints.
+    ///
+    /// ```rust,ignore
+    /// Builder::new().command("seed", |body| async move {
+    ///     seed_fixtures(&body).await.map_err(|e| e.to_string())?;
+    ///     Ok(serde_json::json!(null))
+    /// })
+    /// ```
+    pub fn command<F, Fut>(self, name: &str, handler: F) -> Self
+    where
+        F: Fn(serde_json::Value) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<serde_json::Value, String>> + Send + 'static,
+    {
+        let route = axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+            let handler = handler.clone();
+            async move {
+                handler(body).await.map(axum::Json).map_err(|e| {
+                    (
+                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        axum::Json(serde_json::json!({"error": e})),
+                    )
+                })
+            }
+        });
+        self.routes(axum::Router::new().route(&format!("/{name}"), route))
+    }
+
+    pub fn build<R: Runtime>(self) -> tauri::plugin::TauriPlugin<R> {
+        // Release builds need the `release` feature as well as a launch
+        // token at runtime (checked when the server starts).
+        let allowed = cfg!(debug_assertions) || cfg!(feature = "release");
+        // Android apps can't be launched with environment variables, so
+        // there the server runs whenever the plugin is registered.
+        let enabled = self.enabled.unwrap_or_else(|| {
+            cfg!(target_os = "android")
+                || std::env::var("TAURI_WEBVIEW_AUTOMATION").is_ok_and(|v| v == "true")
+        });
+        if !enabled || !allowed {
+            return tauri::plugin::Builder::new("webdriver-automation").build();
+        }
+        let namespace = self.namespace.unwrap_or_else(|| {
+            format!("__wd_{}", &uuid::Uuid::new_v4().simple().to_string()[..12])
+        });
+        build_plugin(
+            server::ServerConfig {
+                addr: std::net::SocketAddr::new(self.host, self.port),
+                advertise_host: self.advertise_host,
+                announce: self.announce,
+                body_limit: self.body_limit,
+                peer_check: self.peer_check,
+                extensions: self.extensions,
+            },
+            namespace,
+        )
+    }
+}
+
 pub fn init<R: Runtime>() -> tauri::plugin::TauriPlugin<R> {
+    Builder::new().build()
+}
+
+fn build_plugin<R: Runtime>(
+    config: server::ServerConfig,
+    namespace: String,
+) -> tauri::plugin::TauriPlugin<R> {
     let (webview_created_tx, webview_created_rx) = tokio::sync::broadcast::channel(16);
+    let init_script = include_str!("init.js").replace(
+        "/* namespace */ \"__WEBDRIVER__\"",
+        &serde_json::to_string(&namespace).unwrap(),
+    );
 
     tauri::plugin::Builder::new("webdriver-automation")
-        .invoke_handler(tauri::generate_handler![resolve])
-        .js_init_script(include_str!("init.js").to_string())
+        .invoke_handler(tauri::generate_handler![
+            resolve,
+            resolve_bytes,
+            page_unloaded,
+            rearm,
+            mock_used,
+            open_popup,
+            close_popup,
+            popup_message
+        ])
+        .js_init_script(init_script.clone())
         .on_webview_ready(move |webview| {
+            auth::install(&webview);
+            if let Some(ws) = webview.try_state::<WebDriverState>() {
+                *ws.generations
+                    .lock()
+                    .expect("lock poisoned")
+                    .entry(webview.label().to_string())
+                    .or_default() += 1;
+            }
             webview_created_tx
                 .send(
                     webview
@@ -56,9 +892,75 @@ pub fn init<R: Runtime>() -> tauri::plugin::TauriPlugin<R> {
                 )
                 .unwrap_or_default();
         })
+        .on_page_load(|webview, payload| {
+            let Some(ws) = webview.try_state::<WebDriverState>() else {
+                return;
+            };
+            // Preload scripts should run as early as possible in the new
+            // document, i.e. as soon as the navigation commits.
+            if payload.event() == tauri::webview::PageLoadEvent::Started {
+                ws.loading.send_modify(|loading| {
+                    loading.insert(webview.label().to_string());
+                });
+                ws.csp_blocked
+                    .lock()
+                    .expect("lock poisoned")
+                    .remove(webview.label());
+                ws.push_popup_behavior(webview);
+                let scripts = ws.preload_scripts.lock().expect("lock poisoned").clone();
+                for script in scripts {
+                    let targeted = script.contexts.as_ref().map_or(true, |c| {
+                        c.iter()
+                            .any(|handle| ws.label_of(handle) == Some(webview.label()))
+                    });
+                    if targeted {
+                        let _ = webview.eval(ws.bridged(&script.source));
+                    }
+                }
+                return;
+            }
+            ws.page_loaded.send_replace(true);
+            ws.loading.send_modify(|loading| {
+                loading.remove(webview.label());
+            });
+            ws.restore(webview);
+        })
+        .on_event(|app, event| {
+            // A script in a window that's gone will never resolve.
+            if let tauri::RunEvent::WindowEvent {
+                label,
+                event: tauri::WindowEvent::Destroyed,
+                ..
+            } = event
+            {
+                if let Some(ws) = app.try_state::<WebDriverState>() {
+                    ws.cancel_scripts(Cancelled::WindowClosed, |s| &s.window == label);
+                    ws.csp_blocked.lock().expect("lock poisoned").remove(label);
+                    ws.popups
+                        .lock()
+                        .expect("lock poisoned")
+                        .retain(|popup, p| popup != label && &p.opener != label);
+                }
+            }
+        })
         .setup(move |app, _api| {
             app.manage(WebDriverState {
                 pending_scripts: Mutex::new(HashMap::new()),
+                binary_results: Mutex::new(HashMap::new()),
+                device_scale_overrides: Mutex::new(HashMap::new()),
+                mock_rules: Mutex::new(Vec::new()),
+                popup_behavior: Mutex::new(DEFAULT_POPUP_BEHAVIOR.to_string()),
+                preload_scripts: Mutex::new(Vec::new()),
+                cookies: Mutex::new(HashMap::new()),
+                open_dialogs: Mutex::new(HashMap::new()),
+                page_loaded: tokio::sync::watch::channel(false).0,
+                loading: tokio::sync::watch::channel(HashSet::new()).0,
+                namespace,
+                init_script,
+                generations: Mutex::new(HashMap::new()),
+                csp_blocked: Mutex::new(HashSet::new()),
+                popups: Mutex::new(HashMap::new()),
+                router: server::router(app.clone()),
             });
 
             app.add_capability(
@@ -69,12 +971,22 @@ pub fn init<R: Runtime>() -> tauri::plugin::TauriPlugin<R> {
                     .remote("https://*".into())
                     .permission("webdriver-automation:default"),
             )?;
+            // Popups are opened as windows of the app, so only the app's own
+            // pages may open them, not remote content it loads.
+            app.add_capability(
+                tauri::ipc::CapabilityBuilder::new("webdriver-automation-popups")
+                    .local(true)
+                    .window("*")
+                    .permission("webdriver-automation:allow-open-popup")
+                    .permission("webdriver-automation:allow-close-popup")
+                    .permission("webdriver-automation:allow-popup-message"),
+            )?;
 
             // Start the HTTP server that the external WebDriver CLI connects to.
             let app_handle = app.clone();
             let rx = webview_created_rx.resubscribe();
             tauri::async_runtime::spawn(async move {
-                server::start(app_handle, rx).await;
+                server::start(app_handle, rx, config).await;
             });
 
             Ok(())
@@ -82,16 +994,64 @@ pub fn init<R: Runtime>() -> tauri::plugin::TauriPlugin<R> {
         .build()
 }
 
-// --- Helper: resolve a window by label ---
+// --- Helper: resolve a window by handle ---
 
+/// The window a WebDriver handle (see [`WebDriverState::handle`]) refers to,
+/// or the default window when `handle` is `None`.
 pub(crate) fn window_by_label<R: Runtime>(
     app: &tauri::AppHandle<R>,
-    label: Option<&str>,
+    handle: Option<&str>,
 ) -> Option<tauri::WebviewWindow<R>> {
-    if let Some(label) = label {
+    if let Some(handle) = handle {
+        let label = match app.try_state::<WebDriverState>() {
+            Some(ws) => ws.label_of(handle)?,
+            None => handle,
+        };
         app.get_webview_window(label)
     } else {
         app.get_webview_window("main")
             .or_else(|| app.webview_windows().into_values().next())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> tauri::Url {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn app_pages_share_an_origin() {
+        let opener = url("tauri://localhost/index.html");
+        assert!(same_origin(&url("tauri://localhost/popup.html"), &opener));
+        assert!(!same_origin(&url("tauri://other/popup.html"), &opener));
+        assert!(!same_origin(&url("asset://localhost/popup.html"), &opener));
+        assert_eq!(serialize_origin(&opener), "tauri://localhost");
+    }
+
+    #[test]
+    fn ports_are_part_of_the_origin() {
+        let opener = url("http://localhost:1420/");
+        assert!(same_origin(&url("http://localhost:1420/a"), &opener));
+        assert!(!same_origin(&url("http://localhost:1421/a"), &opener));
+        assert!(same_origin(
+            &url("https://example.com:443/"),
+            &url("https://example.com/")
+        ));
+        assert_eq!(serialize_origin(&opener), "http://localhost:1420");
+        assert_eq!(
+            serialize_origin(&url("https://example.com:443/")),
+            "https://example.com"
+        );
+    }
+
+    #[test]
+    fn urls_without_a_host_are_opaque() {
+        let blank = url("about:blank");
+        assert!(!same_origin(&blank, &blank));
+        assert!(!same_origin(&url("file:///a"), &url("file:///b")));
+        assert_eq!(serialize_origin(&blank), "null");
+    }
+}
@@ -0,0 +1,242 @@
+// native: OS-level input events for `/actions/perform` and
+// `/element/send-keys` with `native: true`.
+//
+// Events dispatched from JS are `isTrusted: false` and skip the OS (hit
+// testing, text selection, native context menus). On macOS these helpers
+// post real Quartz events (CGEvent) at screen coordinates instead. The
+// process running the app needs the Accessibility permission to post them.
+
+/// What a native mouse event does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MouseAction {
+    Move,
+    Down,
+    Up,
+}
+
+/// WebDriver modifier keys, in `Modifiers` order: Shift, Control, Alt,
+/// Meta. The right-hand variants count as the left.
+const MODIFIER_KEYS: [char; 4] = ['\u{E008}', '\u{E009}', '\u{E00A}', '\u{E03D}'];
+
+/// Modifier keys held down while native key events are posted.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Modifiers([bool; 4]);
+
+impl Modifiers {
+    fn index(key: char) -> Option<usize> {
+        match key {
+            '\u{E008}' | '\u{E050}' => Some(0),
+            '\u{E009}' | '\u{E051}' => Some(1),
+            '\u{E00A}' | '\u{E052}' => Some(2),
+            '\u{E03D}' | '\u{E053}' => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Whether `key` is held, if it's a modifier key.
+    pub fn is_held(&self, key: char) -> Option<bool> {
+        Self::index(key).map(|i| self.0[i])
+    }
+
+    /// Record `key` going down or up if it's a modifier key.
+    pub fn update(&mut self, key: char, down: bool) {
+        if let Some(i) = Self::index(key) {
+            self.0[i] = down;
+        }
+    }
+
+    /// The modifier keys currently held, for releasing them.
+    pub fn held(&self) -> Vec<char> {
+        MODIFIER_KEYS
+            .into_iter()
+            .zip(self.0)
+            .filter_map(|(key, held)| held.then_some(key))
+            .collect()
+    }
+}
+
+/// Whether this platform can post native input.
+pub(crate) const SUPPORTED: bool = cfg!(target_os = "macos");
+
+/// Post a mouse event at screen point (`x`, `y`), in points from the top
+/// left of the main display. `button` is the WebDriver button (0 left,
+/// 1 middle, 2 right); for `Move` it's the button held down, if any, which
+/// makes the move a drag. `clicks` is the click count (2 for the second
+/// press of a double click).
+#[cfg(target_os = "macos")]
+pub(crate) fn post_mouse(
+    action: MouseAction,
+    x: f64,
+    y: f64,
+    button: Option<u64>,
+    clicks: i64,
+) -> Result<(), String> {
+    use core_graphics::event::{
+        CGEvent, CGEventTapLocation, CGEventType, CGMouseButton, EventField,
+    };
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+    use core_graphics::geometry::CGPoint;
+
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+        .map_err(|_| "failed to create a CGEvent source".to_string())?;
+    let (event_type, cg_button) = match (action, button) {
+        (MouseAction::Move, None) => (CGEventType::MouseMoved, CGMouseButton::Left),
+        (MouseAction::Move, Some(0)) => (CGEventType::LeftMouseDragged, CGMouseButton::Left),
+        (MouseAction::Move, Some(2)) => (CGEventType::RightMouseDragged, CGMouseButton::Right),
+        (MouseAction::Move, Some(_)) => (CGEventType::OtherMouseDragged, CGMouseButton::Center),
+        (MouseAction::Down, Some(2)) => (CGEventType::RightMouseDown, CGMouseButton::Right),
+        (MouseAction::Down, Some(1)) => (CGEventType::OtherMouseDown, CGMouseButton::Center),
+        (MouseAction::Down, _) => (CGEventType::LeftMouseDown, CGMouseButton::Left),
+        (MouseAction::Up, Some(2)) => (CGEventType::RightMouseUp, CGMouseButton::Right),
+        (MouseAction::Up, Some(1)) => (CGEventType::OtherMouseUp, CGMouseButton::Center),
+        (MouseAction::Up, _) => (CGEventType::LeftMouseUp, CGMouseButton::Left),
+    };
+    let event = CGEvent::new_mouse_event(source, event_type, CGPoint::new(x, y), cg_button)
+        .map_err(|_| "failed to create a CGEvent mouse event".to_string())?;
+    if action != MouseAction::Move {
+        event.set_integer_value_field(EventField::MOUSE_EVENT_CLICK_STATE, clicks);
+    }
+    event.post(CGEventTapLocation::HID);
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn post_mouse(
+    _action: MouseAction,
+    _x: f64,
+    _y: f64,
+    _button: Option<u64>,
+    _clicks: i64,
+) -> Result<(), String> {
+    Err("native input is only supported on macOS".into())
+}
+
+/// Post a press (`down`) or release of the WebDriver key `key`: a
+/// character, or one of the special keys in the `U+E000` block. `modifiers`
+/// are the modifier keys held at the time, including `key` itself once
+/// it's down.
+#[cfg(target_os = "macos")]
+pub(crate) fn post_key(key: char, down: bool, modifiers: Modifiers) -> Result<(), String> {
+    use core_graphics::event::{CGEvent, CGEventFlags, CGEventTapLocation};
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+    let code = key_code(key)?;
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+        .map_err(|_| "failed to create a CGEvent source".to_string())?;
+    let special = special_key_code(key);
+    let event = CGEvent::new_keyboard_event(source, code, down)
+        .map_err(|_| "failed to create a CGEvent key event".to_string())?;
+    // Characters carry their own text, so input doesn't depend on the
+    // keyboard layout; the key code only matters for shortcuts.
+    if special.is_none() {
+        event.set_string(&key.to_string());
+    }
+    let mut flags = CGEventFlags::CGEventFlagNull;
+    for (held, flag) in modifiers.0.into_iter().zip([
+        CGEventFlags::CGEventFlagShift,
+        CGEventFlags::CGEventFlagControl,
+        CGEventFlags::CGEventFlagAlternate,
+        CGEventFlags::CGEventFlagCommand,
+    ]) {
+        if held {
+            flags |= flag;
+        }
+    }
+    event.set_flags(flags);
+    event.post(CGEventTapLocation::HID);
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn post_key(_key: char, _down: bool, _modifiers: Modifiers) -> Result<(), String> {
+    Err("native input is only supported on macOS".into())
+}
+
+/// The macOS virtual key code [`post_key`] posts `key` with, or an error
+/// if no key on the US keyboard types it. Check every key first, so a
+/// sequence isn't left half posted with modifier keys down.
+#[cfg(target_os = "macos")]
+pub(crate) fn key_code(key: char) -> Result<u16, String> {
+    special_key_code(key)
+        .or_else(|| ansi_key_code(key))
+        .ok_or_else(|| format!("U+{:04X} has no key on the US keyboard to post", key as u32))
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn key_code(_key: char) -> Result<u16, String> {
+    Err("native input is only supported on macOS".into())
+}
+
+/// macOS virtual key code of a WebDriver special key.
+#[cfg(target_os = "macos")]
+fn special_key_code(key: char) -> Option<u16> {
+    Some(match key {
+        '\u{E002}' | '\u{E016}' => 0x72, // Help, Insert
+        '\u{E003}' => 0x33,              // Backspace
+        '\u{E004}' => 0x30,              // Tab
+        '\u{E006}' | '\u{E007}' => 0x24, // Return, Enter
+        '\u{E008}' | '\u{E050}' => 0x38, // Shift
+        '\u{E009}' | '\u{E051}' => 0x3B, // Control
+        '\u{E00A}' | '\u{E052}' => 0x3A, // Alt (Option)
+        '\u{E00C}' => 0x35,              // Escape
+        '\u{E00E}' => 0x74,              // PageUp
+        '\u{E00F}' => 0x79,              // PageDown
+        '\u{E010}' => 0x77,              // End
+        '\u{E011}' => 0x73,              // Home
+        '\u{E012}' => 0x7B,              // ArrowLeft
+        '\u{E013}' => 0x7E,              // ArrowUp
+        '\u{E014}' => 0x7C,              // ArrowRight
+        '\u{E015}' => 0x7D,              // ArrowDown
+        '\u{E005}' => 0x47,              // Clear
+        '\u{E017}' => 0x75,              // Delete
+        '\u{E018}' => 0x29,              // Semicolon
+        '\u{E019}' => 0x18,              // Equals
+        '\u{E01A}' => 0x52,              // Numpad0
+        '\u{E01B}' => 0x53,
+        '\u{E01C}' => 0x54,
+        '\u{E01D}' => 0x55,
+        '\u{E01E}' => 0x56,
+        '\u{E01F}' => 0x57,
+        '\u{E020}' => 0x58,
+        '\u{E021}' => 0x59,
+        '\u{E022}' => 0x5B,
+        '\u{E023}' => 0x5C, // Numpad9
+        '\u{E024}' => 0x43, // Multiply
+        '\u{E025}' => 0x45, // Add
+        '\u{E027}' => 0x4E, // Subtract
+        '\u{E028}' => 0x41, // Decimal
+        '\u{E029}' => 0x4B, // Divide
+        '\u{E031}' => 0x7A, // F1
+        '\u{E032}' => 0x78,
+        '\u{E033}' => 0x63,
+        '\u{E034}' => 0x76,
+        '\u{E035}' => 0x60,
+        '\u{E036}' => 0x61,
+        '\u{E037}' => 0x62,
+        '\u{E038}' => 0x64,
+        '\u{E039}' => 0x65,
+        '\u{E03A}' => 0x6D,
+        '\u{E03B}' => 0x67,
+        '\u{E03C}' => 0x6F,              // F12
+        '\u{E03D}' | '\u{E053}' => 0x37, // Meta (Command)
+        _ => return None,
+    })
+}
+
+/// macOS virtual key code of a character on the US (ANSI) layout, so
+/// shortcuts like Command+S reach the app as that key.
+#[cfg(target_os = "macos")]
+fn ansi_key_code(key: char) -> Option<u16> {
+    const KEYS: &str = "asdfhgzxcv\0bqweryt123465=97-80]ou[ip\0lj'k;\\,/nm.";
+    if key == ' ' || key == '\u{E00D}' {
+        return Some(0x31);
+    }
+    if key == '`' {
+        return Some(0x32);
+    }
+    let key = key.to_ascii_lowercase();
+    if key == '\0' {
+        return None;
+    }
+    KEYS.chars().position(|c| c == key).map(|i| i as u16)
+}
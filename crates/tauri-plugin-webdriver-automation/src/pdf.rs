@@ -0,0 +1,110 @@
+// pdf: the one-page PDF `/print` returns.
+//
+// The page is rendered to a canvas in the webview and sent here as a JPEG,
+// which PDF embeds as-is (DCTDecode): no decoding, and the PDF is barely
+// larger than the image.
+
+/// US Letter, in points.
+const PAGE_WIDTH: f64 = 612.0;
+const PAGE_HEIGHT: f64 = 792.0;
+
+/// A PDF with `jpeg` scaled to fit the page, anchored at its top left.
+pub(crate) fn from_jpeg(jpeg: &[u8]) -> Result<Vec<u8>, String> {
+    let (width, height, components) = jpeg_info(jpeg).ok_or("the page image is not a JPEG")?;
+    if width == 0 || height == 0 {
+        return Err("the page image is empty".into());
+    }
+    let color_space = match components {
+        1 => "DeviceGray",
+        3 => "DeviceRGB",
+        4 => "DeviceCMYK",
+        n => return Err(format!("unsupported JPEG with {n} color components")),
+    };
+    let scale = (PAGE_WIDTH / f64::from(width)).min(PAGE_HEIGHT / f64::from(height));
+    let (draw_width, draw_height) = (
+        (f64::from(width) * scale).round(),
+        (f64::from(height) * scale).round(),
+    );
+    let content = format!(
+        "q {draw_width} 0 0 {draw_height} 0 {} cm /Img Do Q",
+        PAGE_HEIGHT - draw_height
+    );
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    let mut object = |pdf: &mut Vec<u8>, body: &[u8]| {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+        pdf.extend_from_slice(body);
+        pdf.extend_from_slice(b"\nendobj\n");
+    };
+    object(&mut pdf, b"<< /Type /Catalog /Pages 2 0 R >>");
+    object(&mut pdf, b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>");
+    object(
+        &mut pdf,
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+             /Contents 5 0 R /Resources << /XObject << /Img 4 0 R >> >> >>"
+        )
+        .as_bytes(),
+    );
+    let mut image = format!(
+        "<< /Type /XObject /Subtype /Image /Width {width} /Height {height} \
+         /ColorSpace /{color_space} /BitsPerComponent 8 /Filter /DCTDecode \
+         /Length {} >>\nstream\n",
+        jpeg.len()
+    )
+    .into_bytes();
+    image.extend_from_slice(jpeg);
+    image.extend_from_slice(b"\nendstream");
+    object(&mut pdf, &image);
+    object(
+        &mut pdf,
+        format!(
+            "<< /Length {} >>\nstream\n{content}\nendstream",
+            content.len()
+        )
+        .as_bytes(),
+    );
+
+    let xref = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1);
+    for offset in &offsets {
+        trailer.push_str(&format!("{offset:010} 00000 n \n"));
+    }
+    trailer.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        offsets.len() + 1
+    ));
+    pdf.extend_from_slice(trailer.as_bytes());
+    Ok(pdf)
+}
+
+/// Width, height and color component count from a JPEG's frame header.
+fn jpeg_info(jpeg: &[u8]) -> Option<(u16, u16, u8)> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut at = 2;
+    while at + 4 <= jpeg.len() {
+        if jpeg[at] != 0xFF {
+            return None;
+        }
+        let marker = jpeg[at + 1];
+        // Fill bytes before a marker.
+        if marker == 0xFF {
+            at += 1;
+            continue;
+        }
+        let length = usize::from(u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]));
+        // SOF0 to SOF15, but not DHT (C4), JPG (C8) or DAC (CC).
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let frame = jpeg.get(at + 4..at + 2 + length)?;
+            let height = u16::from_be_bytes([*frame.get(1)?, *frame.get(2)?]);
+            let width = u16::from_be_bytes([*frame.get(3)?, *frame.get(4)?]);
+            return Some((width, height, *frame.get(5)?));
+        }
+        at += 2 + length;
+    }
+    None
+}
@@ -0,0 +1,234 @@
+// peer: who may talk to the automation server.
+//
+// The server binds to loopback and requires the launch token, but any local
+// process can still connect, and a web page in any browser on the machine
+// can send it requests. Requests a browser would have sent are always
+// turned away; `Builder::peer_check` also looks up the process behind each
+// connection and requires it to run as the app's user and, when tauri-wd
+// launched the app, to be tauri-wd itself.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::extract::connect_info::Connected;
+use axum::http::HeaderMap;
+use axum::serve::IncomingStream;
+use tokio::net::TcpListener;
+
+/// Environment variable tauri-wd sets to its own PID when it launches the
+/// app, so the peer check can require connections to come from it.
+const PEER_PID_ENV: &str = "TAURI_WEBDRIVER_PEER_PID";
+
+/// A connection to the server, attached to each request it carries. The
+/// peer check's verdict lives here, so it's reached once per connection and
+/// goes away with it: a later connection from the same address is checked
+/// again.
+#[derive(Clone)]
+pub(crate) struct Connection {
+    /// The client's end.
+    pub peer: SocketAddr,
+    /// The server's end.
+    local: Option<SocketAddr>,
+    verdict: Arc<tokio::sync::OnceCell<Result<(), String>>>,
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for Connection {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self {
+            peer: *stream.remote_addr(),
+            local: stream.io().local_addr().ok(),
+            verdict: Arc::default(),
+        }
+    }
+}
+
+/// Whether `headers` mark a request made by a web page: browsers send
+/// `Origin` with every cross-origin and every non-GET request, and
+/// `Sec-Fetch-*` with all of them. HTTP clients send neither.
+pub(crate) fn from_browser(headers: &HeaderMap) -> bool {
+    headers.contains_key(axum::http::header::ORIGIN)
+        || headers
+            .keys()
+            .any(|name| name.as_str().starts_with("sec-fetch-"))
+}
+
+/// Whether the `Host` header value `host` names a loopback address, so a
+/// page on a rebound DNS name can't reach the server.
+pub(crate) fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        // `[::1]:4444`
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        None => host.split(':').next().unwrap_or(host),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// The process on the other end of a connection.
+struct Owner {
+    uid: u32,
+    /// Only known on macOS.
+    pid: Option<u32>,
+}
+
+pub(crate) struct PeerCheck {
+    /// tauri-wd's PID, when it launched the app.
+    expected_pid: Option<u32>,
+}
+
+impl PeerCheck {
+    /// `None` where processes behind connections can't be looked up.
+    pub fn new() -> Option<Self> {
+        if !cfg!(any(target_os = "linux", target_os = "macos")) {
+            tracing::warn!("webdriver peer checks are not supported on this platform");
+            return None;
+        }
+        let expected_pid = std::env::var(PEER_PID_ENV)
+            .ok()
+            .and_then(|pid| pid.parse().ok());
+        Some(Self { expected_pid })
+    }
+
+    /// `Err` saying why `connection` isn't allowed. Keep-alive connections
+    /// carry many requests, so the process behind one is only looked up for
+    /// the first.
+    pub async fn check(&self, connection: &Connection) -> Result<(), String> {
+        connection
+            .verdict
+            .get_or_init(|| self.verify(connection))
+            .await
+            .clone()
+    }
+
+    async fn verify(&self, connection: &Connection) -> Result<(), String> {
+        let local = connection
+            .local
+            .ok_or("the server's end of the connection is unknown")?;
+        let owner = owner(connection.peer, local).await?;
+        let uid = own_uid();
+        if owner.uid != uid {
+            return Err(format!(
+                "connection from a process of another user (uid {}, not {uid})",
+                owner.uid
+            ));
+        }
+        if let (Some(expected), Some(pid)) = (self.expected_pid, owner.pid) {
+            if pid != expected {
+                return Err(format!(
+                    "connection from process {pid}, not the tauri-wd that launched the app ({expected})"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn own_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail.
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn own_uid() -> u32 {
+    0
+}
+
+/// An address as the kernel's TCP tables print it: v4-mapped IPv6
+/// addresses as the IPv4 address the client socket has.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn unmapped(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), addr.port()),
+            None => addr,
+        },
+        IpAddr::V4(_) => addr,
+    }
+}
+
+/// Parse a `/proc/net/tcp{,6}` address: the IP in hex, as 32-bit words in
+/// host byte order, then `:` and the port in hex.
+#[cfg(target_os = "linux")]
+fn proc_address(field: &str) -> Option<SocketAddr> {
+    let (ip, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let mut bytes = Vec::with_capacity(16);
+    for word in 0..ip.len() / 8 {
+        let word = u32::from_str_radix(ip.get(word * 8..word * 8 + 8)?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+    let ip = match bytes.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?),
+        16 => IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// The owner of the client socket `peer` connected to the server at
+/// `server`, from the kernel's TCP table.
+#[cfg(target_os = "linux")]
+async fn owner(peer: SocketAddr, server: SocketAddr) -> Result<Owner, String> {
+    // On a dual-stack listener an IPv4 client shows up v4-mapped, but its
+    // socket is an IPv4 one.
+    let (peer, server) = (unmapped(peer), unmapped(server));
+    let table = if peer.is_ipv4() {
+        "/proc/net/tcp"
+    } else {
+        "/proc/net/tcp6"
+    };
+    let text = tokio::fs::read_to_string(table)
+        .await
+        .map_err(|e| format!("can't read {table}: {e}"))?;
+    // `sl local_address rem_address st tx_queue:rx_queue tr:tm->when
+    // retrnsmt uid ...`
+    for line in text.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (Some(local), Some(remote), Some(uid)) = (fields.get(1), fields.get(2), fields.get(7))
+        else {
+            continue;
+        };
+        if proc_address(local) == Some(peer) && proc_address(remote) == Some(server) {
+            let uid = uid
+                .parse()
+                .map_err(|_| format!("bad uid {uid:?} in {table}"))?;
+            return Ok(Owner { uid, pid: None });
+        }
+    }
+    Err(format!("no socket for {peer} in {table}"))
+}
+
+/// The process holding the client socket `peer`, from `lsof`.
+#[cfg(target_os = "macos")]
+async fn owner(peer: SocketAddr, _server: SocketAddr) -> Result<Owner, String> {
+    let peer = unmapped(peer);
+    // Both ends of the connection match; ours is the server's socket.
+    let output = tokio::process::Command::new("lsof")
+        .args(["-nP", &format!("-iTCP@{peer}"), "-sTCP:ESTABLISHED", "-Fpu"])
+        .output()
+        .await
+        .map_err(|e| format!("can't run lsof: {e}"))?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let own_pid = std::process::id();
+    let mut pid = None;
+    // One `p<pid>` line per process, then `u<uid>` and a line per file.
+    for line in text.lines() {
+        if let Some(value) = line.strip_prefix('p') {
+            pid = value.parse::<u32>().ok();
+        } else if let Some(value) = line.strip_prefix('u') {
+            if let (Some(pid), Ok(uid)) = (pid.filter(|&p| p != own_pid), value.parse()) {
+                return Ok(Owner {
+                    uid,
+                    pid: Some(pid),
+                });
+            }
+        }
+    }
+    Err(format!("lsof found no process for {peer}"))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+async fn owner(_peer: SocketAddr, _server: SocketAddr) -> Result<Owner, String> {
+    Err("peer checks are not supported on this platform".into())
+}
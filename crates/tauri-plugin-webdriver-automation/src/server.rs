@@ -2,31 +2,89 @@
 // Binds to 127.0.0.1 on a random port and exposes endpoints for
 // window management, element interaction, script execution, and navigation.
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
 use axum::extract::State as AxumState;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::{Json, Router};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tauri::{Manager, Runtime};
 
-use crate::{window_by_label, WebDriverState};
+use crate::native::{self, Modifiers, MouseAction};
+use crate::{pdf, snapshot};
+use crate::{window_by_label, Cancelled, PreloadScript, WebDriverState};
 
 // --- Server state ---
 
 struct FrameRef {
     selector: String,
     index: usize,
+    using: Option<String>,
+}
+
+/// The window commands go to unless they name one.
+#[derive(Clone)]
+enum CurrentWindow {
+    /// `main`, else any window, until the client switches.
+    Default,
+    /// The window the client switched to, by handle.
+    Handle(String),
+    /// The current window was closed: commands fail with `noSuchWindow`
+    /// until the client switches to another one.
+    Closed,
 }
 
 struct ServerState<R: Runtime> {
     app: tauri::AppHandle<R>,
-    current_window_label: std::sync::Mutex<Option<String>>,
+    /// Random id of this server, reported by `/info` so tauri-wd can tell a
+    /// reconnect to the same app from a restarted one.
+    instance: String,
+    current_window: std::sync::Mutex<CurrentWindow>,
     frame_stack: std::sync::Mutex<Vec<FrameRef>>,
+    /// One lock per window label. Scripts share page globals (`__wdPointerX`,
+    /// `__wdPointerY`) so evaluations in the same webview run one at
+    /// a time; different windows still evaluate in parallel.
+    eval_locks: std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// Modifier keys held down by native key input, released by
+    /// `/actions/release`.
+    native_modifiers: std::sync::Mutex<Modifiers>,
+}
+
+impl<R: Runtime> ServerState<R> {
+    /// The window `handle` refers to, or the current window without one.
+    fn window(&self, handle: Option<&str>) -> Result<tauri::WebviewWindow<R>, ApiError> {
+        let current = self.current_window.lock().expect("lock poisoned").clone();
+        let handle = match (handle, &current) {
+            (Some(handle), _) => Some(handle),
+            (None, CurrentWindow::Default) => None,
+            (None, CurrentWindow::Handle(handle)) => Some(handle.as_str()),
+            (None, CurrentWindow::Closed) => {
+                return Err(ApiError::new(
+                    ErrorCode::NoSuchWindow,
+                    "the current window was closed; switch to another window",
+                ))
+            }
+        };
+        window_by_label(&self.app, handle).ok_or_else(ApiError::no_window)
+    }
+
+    /// Wait for exclusive use of `label`'s webview. Hold the guard until the
+    /// script's result has come back.
+    async fn lock_window(&self, label: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self
+            .eval_locks
+            .lock()
+            .expect("lock poisoned")
+            .entry(label.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
 }
 
 type SharedState<R> = Arc<ServerState<R>>;
@@ -41,127 +99,339 @@ fn build_frame_prefix<R: Runtime>(state: &SharedState<R>) -> String {
     }
     let mut js = "var __doc=document;".to_string();
     for fr in stack.iter() {
-        let sel_json = serde_json::to_string(&fr.selector).unwrap();
+        let frame = element_expr("__doc", &fr.selector, fr.index, fr.using.as_deref());
         js.push_str(&format!(
-            "var __f=__doc.querySelectorAll({sel_json})[{idx}];\
-             if(!__f)throw new Error('frame not found');\
+            "var __f={frame};\
+             if(!__f)__WEBDRIVER__.fail('noSuchFrame','frame not found');\
              __doc=__f.contentDocument;\
-             if(!__doc)throw new Error('cannot access frame document');",
-            sel_json = sel_json,
-            idx = fr.index,
+             if(!__doc)__WEBDRIVER__.fail('noSuchFrame','cannot access frame document');"
         ));
     }
     js
 }
 
-/// Returns true if the frame stack is non-empty.
-fn in_frame<R: Runtime>(state: &SharedState<R>) -> bool {
-    !state.frame_stack.lock().expect("lock poisoned").is_empty()
+/// JS expression for the element a plugin element reference points at, or
+/// `null`/`undefined` if it's gone. CSS and XPath references are looked up
+/// in `doc`; `ref` references (returned for scoped, shadow and active-element
+/// finds) are opaque ids held by the bridge's node registry, so nothing is
+/// written into the app's DOM to find them again.
+fn element_expr(doc: &str, selector: &str, index: usize, using: Option<&str>) -> String {
+    let sel_json = serde_json::to_string(selector).unwrap();
+    match using {
+        Some("ref") => format!("__WEBDRIVER__.elementById({sel_json})"),
+        Some("xpath") => format!(
+            "{doc}.evaluate({sel_json},{doc},null,\
+             XPathResult.ORDERED_NODE_SNAPSHOT_TYPE,null).snapshotItem({index})"
+        ),
+        _ => format!("{doc}.querySelectorAll({sel_json})[{index}]"),
+    }
 }
 
 // --- Error handling ---
 
-enum ApiError {
-    NotFound(String),
-    Internal(String),
+/// Why a request failed. Sent as `code` next to the message so tauri-wd can
+/// report the matching W3C error without parsing messages. Plugin scripts
+/// raise one with `__WEBDRIVER__.fail(code, message)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ErrorCode {
+    NoSuchElement,
+    /// The element a reference pointed at is gone from the document.
+    StaleElement,
+    NoSuchWindow,
+    NoSuchFrame,
+    NoSuchAlert,
+    NoSuchShadowRoot,
+    /// Another element is on top of the one to click.
+    ElementClickIntercepted,
+    /// The element can't be clicked or typed into, e.g. it has no size.
+    ElementNotInteractable,
+    /// A dialog is open, so the command can't interact with the page.
+    UnexpectedAlertOpen,
+    /// The document was unloaded (navigated away from or reloaded) before
+    /// the script finished.
+    DocumentUnloaded,
+    /// A script threw.
+    JavascriptError,
+    Timeout,
+    InvalidArgument,
+    UnknownCommand,
+    UnknownError,
+}
+
+struct ApiError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::UnknownError, message)
+    }
+
+    fn no_window() -> Self {
+        Self::new(ErrorCode::NoSuchWindow, "no such window")
+    }
+
+    /// The error a script resolved with, `{error, message, code?}`. Without
+    /// a code it's the script's own exception.
+    fn from_script(obj: &serde_json::Map<String, Value>) -> Self {
+        let code = obj
+            .get("code")
+            .and_then(|c| ErrorCode::deserialize(c).ok())
+            .unwrap_or(ErrorCode::JavascriptError);
+        let message = obj
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("script error");
+        Self::new(code, message)
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        let (status, msg) = match self {
-            ApiError::NotFound(m) => (StatusCode::NOT_FOUND, m),
-            ApiError::Internal(m) => (StatusCode::INTERNAL_SERVER_ERROR, m),
+        let status = match self.code {
+            ErrorCode::NoSuchElement
+            | ErrorCode::StaleElement
+            | ErrorCode::NoSuchWindow
+            | ErrorCode::NoSuchFrame
+            | ErrorCode::NoSuchAlert
+            | ErrorCode::NoSuchShadowRoot
+            | ErrorCode::UnknownCommand => StatusCode::NOT_FOUND,
+            ErrorCode::InvalidArgument
+            | ErrorCode::ElementClickIntercepted
+            | ErrorCode::ElementNotInteractable => StatusCode::BAD_REQUEST,
+            ErrorCode::JavascriptError
+            | ErrorCode::UnexpectedAlertOpen
+            | ErrorCode::DocumentUnloaded
+            | ErrorCode::Timeout
+            | ErrorCode::UnknownError => StatusCode::INTERNAL_SERVER_ERROR,
         };
-        (status, Json(json!({"error": msg}))).into_response()
+        let mut body = json!({"error": self.message, "code": self.code});
+        if let Ok(id) = REQUEST_ID.try_with(Clone::clone) {
+            body["requestId"] = json!(id);
+        }
+        (status, Json(body)).into_response()
     }
 }
 
 type ApiResult = Result<Json<Value>, ApiError>;
 
-// --- JS evaluation helpers ---
+/// Header tauri-wd sends with the id of the W3C command a request serves.
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
-async fn eval_js<R: Runtime>(state: &SharedState<R>, script: &str) -> Result<Value, ApiError> {
-    let label = state
-        .current_window_label
-        .lock()
-        .expect("lock poisoned")
-        .clone();
-    let window = window_by_label(&state.app, label.as_deref())
-        .ok_or_else(|| ApiError::NotFound("no such window".into()))?;
+tokio::task_local! {
+    /// The `X-Request-Id` of the request being handled, if it carried one.
+    static REQUEST_ID: String;
+}
 
-    let id = uuid::Uuid::new_v4().to_string();
-    let (tx, rx) = tokio::sync::oneshot::channel();
+/// Version of the HTTP API below. Routes are served under `/v{API_VERSION}`;
+/// bump it when a change would break an older tauri-wd.
+pub(crate) const API_VERSION: u32 = 1;
 
-    {
-        let ws = state.app.state::<WebDriverState>();
-        ws.pending_scripts
-            .lock()
-            .expect("lock poisoned")
-            .insert(id.clone(), tx);
-    }
+// --- JS evaluation helpers ---
 
-    // Build frame prefix to navigate into current iframe context.
+async fn eval_js<R: Runtime>(state: &SharedState<R>, script: &str) -> Result<Value, ApiError> {
+    let window = state.window(None)?;
+    let _guard = state.lock_window(window.label()).await;
     let frame_prefix = build_frame_prefix(state);
-    let is_framed = in_frame(state);
+    run_script(state, &window, &frame_prefix, script, &[], false, true).await
+}
 
-    // Wrap user script: execute it, send result back via IPC.
-    // When inside a frame, pass the frame document as a `document` parameter
-    // to the inner function, which shadows the global `document` without
-    // hoisting issues that `var document=...` would cause.
-    let wrapped = if is_framed {
-        format!(
-            concat!(
-                "(function(){{try{{{frame_prefix}",
-                "var __r=(function(document){{{script}}}).call(null,__doc);",
-                "window.__WEBDRIVER__.resolve(\"{id}\",__r)",
-                "}}catch(__e){{window.__WEBDRIVER__.resolve(\"{id}\",",
-                "{{error:__e.name,message:__e.message,stacktrace:__e.stack||\"\"}})",
-                "}}}})()"
-            ),
-            frame_prefix = frame_prefix,
-            script = script,
-            id = id,
-        )
+/// Like `eval_js`, but also runs while a dialog is open: for the alert
+/// commands themselves and for reading logs.
+async fn eval_js_during_dialog<R: Runtime>(
+    state: &SharedState<R>,
+    script: &str,
+) -> Result<Value, ApiError> {
+    let window = state.window(None)?;
+    let _guard = state.lock_window(window.label()).await;
+    let frame_prefix = build_frame_prefix(state);
+    run_script(state, &window, &frame_prefix, script, &[], false, false).await
+}
+
+/// Run `source` as a function body in `window` with `args` as its
+/// arguments, inside the frame `frame_prefix` selects (empty for the top
+/// document), and return its result. With `callback`, the result is what the
+/// script passes to a callback appended to `args`. With `guard`, the script
+/// doesn't run while a dialog is open and fails with `unexpectedAlertOpen`
+/// instead. The caller holds the window's eval lock, except with `callback`:
+/// an async script can run for long, so it takes the lock only while it's
+/// dispatched (see [`eval_with_id`]).
+///
+/// The source and arguments are embedded as JSON literals and compiled by
+/// the bridge, so script content can't break out of the wrapper (see
+/// [`with_source`] for pages whose CSP forbids that).
+async fn run_script<R: Runtime>(
+    state: &SharedState<R>,
+    window: &tauri::WebviewWindow<R>,
+    frame_prefix: &str,
+    source: &str,
+    args: &[Value],
+    callback: bool,
+    guard: bool,
+) -> Result<Value, ApiError> {
+    let check = if guard {
+        "__WEBDRIVER__.checkDialog();"
     } else {
-        format!(
-            concat!(
-                "(function(){{try{{var __r=(function(){{{script}}})();",
-                "window.__WEBDRIVER__.resolve(\"{id}\",__r)",
-                "}}catch(__e){{window.__WEBDRIVER__.resolve(\"{id}\",",
-                "{{error:__e.name,message:__e.message,stacktrace:__e.stack||\"\"}})",
-                "}}}})()"
-            ),
-            script = script,
-            id = id,
+        ""
+    };
+    let frame_prefix = match frame_prefix {
+        "" => "var __doc=document;",
+        prefix => prefix,
+    };
+    let args = serde_json::to_string(args).unwrap();
+    let timeout_msg = if callback {
+        "async script timed out"
+    } else {
+        "script timed out"
+    };
+    let value = with_source(state, window, source, |function| {
+        let args = &args;
+        eval_with_id(
+            state,
+            window,
+            timeout_msg,
+            SCRIPT_TIMEOUT,
+            callback,
+            move |id| {
+                format!(
+                    "(function(){{try{{{check}{frame_prefix}}}catch(__e){{\
+                 __WEBDRIVER__.resolve({id},__WEBDRIVER__.scriptError(__e));return}}\
+                 __WEBDRIVER__.run({id},{function},{args},__doc,{callback})}})()"
+                )
+            },
         )
+    })
+    .await?;
+    // If the JS threw, it comes back as {error, message, stacktrace}.
+    if let Some(obj) = value.as_object() {
+        if obj.contains_key("error") && obj.contains_key("message") {
+            return Err(ApiError::from_script(obj));
+        }
+    }
+    Ok(value)
+}
+
+/// Evaluate a script taking the user script `source` as a function for the
+/// bridge, `eval` building and running it around that function's
+/// expression. That is first `source` as a JSON string, which the bridge
+/// compiles itself, so nothing in it can break out of the wrapper. Where the
+/// page's CSP forbids compiling strings, the bridge says so (see
+/// [`crate::resolve`]) and `source` is inlined as a function expression
+/// instead, on lines of its own so a trailing `//` comment can't swallow the
+/// closing brace; the window is remembered until its next page load, so
+/// later scripts go straight to that. The expression binds `__doc`, which
+/// the wrapper must define, as `document`.
+async fn with_source<R: Runtime, F: std::future::Future<Output = Result<Value, ApiError>>>(
+    state: &SharedState<R>,
+    window: &tauri::WebviewWindow<R>,
+    source: &str,
+    eval: impl Fn(String) -> F,
+) -> Result<Value, ApiError> {
+    let ws = state.app.state::<WebDriverState>();
+    let blocked = || {
+        ws.csp_blocked
+            .lock()
+            .expect("lock poisoned")
+            .contains(window.label())
     };
-
+    if !blocked() {
+        match eval(serde_json::to_string(source).unwrap()).await {
+            Err(_) if blocked() => {}
+            result => return result,
+        }
+    }
+    eval(format!(
+        "(function(document){{return function(){{\n{source}\n}}}})(__doc)"
+    ))
+    .await
+}
+
+/// How long a script has to resolve its result.
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a script lost to a navigation waits for the new document to
+/// load before reporting it, so a retry runs against the new page.
+const NEW_DOCUMENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Evaluate the script `build` returns for a fresh result id (passed as a
+/// JSON string) and wait up to `limit` for the bridge to resolve that id.
+/// The bridge reports the id if its document unloads first.
+///
+/// With `lock_dispatch`, the window's eval lock is taken here and let go
+/// once the script is handed to the webview, for async scripts and waits,
+/// which go on in later ticks: the webview runs evaluations in order, so the
+/// script's synchronous part still runs alone, and the window's other
+/// commands don't queue behind the wait. Otherwise the caller holds it.
+async fn eval_with_id<R: Runtime>(
+    state: &SharedState<R>,
+    window: &tauri::WebviewWindow<R>,
+    timeout_msg: &str,
+    limit: Duration,
+    lock_dispatch: bool,
+    build: impl FnOnce(&str) -> String,
+) -> Result<Value, ApiError> {
+    let guard = if lock_dispatch {
+        Some(state.lock_window(window.label()).await)
+    } else {
+        None
+    };
+    let id = uuid::Uuid::new_v4().to_string();
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let ws = state.app.state::<WebDriverState>();
+    ws.add_pending(id.clone(), window.label(), tx);
+
+    let id_json = serde_json::to_string(&id).unwrap();
+    let request_id = REQUEST_ID.try_with(|r| json!(r)).unwrap_or(Value::Null);
+    let script = ws.bridged_with_id(
+        &id_json,
+        &format!(
+            "__WEBDRIVER__.began({id_json},{request_id});{}",
+            build(&id_json)
+        ),
+    );
     window
-        .eval(&wrapped)
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
-
-    match tokio::time::timeout(Duration::from_secs(30), rx).await {
-        Ok(Ok(value)) => {
-            // If the JS threw, it comes back as {error, message, stacktrace}.
-            if let Some(obj) = value.as_object() {
-                if obj.contains_key("error") && obj.contains_key("message") {
-                    let msg = obj
-                        .get("message")
-                        .and_then(|m| m.as_str())
-                        .unwrap_or("script error");
-                    return Err(ApiError::Internal(msg.to_string()));
-                }
-            }
-            Ok(value)
+        .eval(&script)
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    drop(guard);
+
+    match tokio::time::timeout(limit, rx).await {
+        Ok(Ok(Ok(value))) => Ok(value),
+        Ok(Ok(Err(Cancelled::WindowClosed))) => Err(ApiError::new(
+            ErrorCode::NoSuchWindow,
+            "window closed while the script was running",
+        )),
+        Ok(Ok(Err(Cancelled::SessionDeleted))) => Err(ApiError::internal("session deleted")),
+        Ok(Ok(Err(Cancelled::EvalBlocked))) => Err(ApiError::new(
+            ErrorCode::JavascriptError,
+            "the page's Content-Security-Policy forbids compiling the script",
+        )),
+        Ok(Ok(Err(Cancelled::NoBridge))) => Err(ApiError::new(
+            ErrorCode::JavascriptError,
+            "the script bridge couldn't be installed in the page",
+        )),
+        Ok(Ok(Err(Cancelled::Unloaded))) => {
+            ws.wait_for_page(window.label(), NEW_DOCUMENT_TIMEOUT).await;
+            Err(ApiError::new(
+                ErrorCode::DocumentUnloaded,
+                "the page was unloaded (navigated away from or reloaded) before the script finished",
+            ))
         }
-        Ok(Err(_)) => Err(ApiError::Internal("result channel closed".into())),
+        Ok(Err(_)) => Err(ApiError::internal("result channel closed")),
         Err(_) => {
-            let ws = state.app.state::<WebDriverState>();
             ws.pending_scripts
                 .lock()
                 .expect("lock poisoned")
                 .remove(&id);
-            Err(ApiError::Internal("script timed out".into()))
+            Err(ApiError::new(ErrorCode::Timeout, timeout_msg))
         }
     }
 }
@@ -174,35 +444,14 @@ async fn eval_on_element<R: Runtime>(
     using: Option<&str>,
     body: &str,
 ) -> Result<Value, ApiError> {
-    let script = if using == Some("shadow") {
-        // Shadow DOM element: look up from the shadow cache by ID
-        let sel_json = serde_json::to_string(selector).unwrap();
-        format!(
-            "var el=window.__WEBDRIVER__.findElementInShadow({sel_json});\
-             if(!el)throw new Error(\"shadow element not found or stale\");\
-             {body}"
-        )
-    } else {
-        let sel_json = serde_json::to_string(selector).unwrap();
-        // When inside a frame context, eval_js passes the frame document as
-        // the `document` parameter, so we use document.querySelectorAll directly
-        // instead of the findElement helper (which uses the top-level document).
-        if using == Some("xpath") {
-            format!(
-                "var __xr=document.evaluate({sel_json},document,null,\
-                 XPathResult.ORDERED_NODE_SNAPSHOT_TYPE,null);\
-                 var el=__xr.snapshotItem({index});\
-                 if(!el)throw new Error(\"element not found\");\
-                 {body}"
-            )
-        } else {
-            format!(
-                "var el=document.querySelectorAll({sel_json})[{index}];\
-                 if(!el)throw new Error(\"element not found\");\
-                 {body}"
-            )
-        }
-    };
+    // When inside a frame context, eval_js passes the frame document as the
+    // `document` parameter, so lookups start from `document`.
+    let el = element_expr("document", selector, index, using);
+    let script = format!(
+        "var el={el};\
+         if(!el)__WEBDRIVER__.fail(\"staleElement\",\"element not found\");\
+         {body}"
+    );
     eval_js(state, &script).await
 }
 
@@ -241,6 +490,19 @@ struct ElemReq {
     using: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct ScrollIntoViewReq {
+    selector: String,
+    index: usize,
+    #[serde(default)]
+    using: Option<String>,
+    /// `scrollIntoView` alignments; both default to "center".
+    #[serde(default)]
+    block: Option<String>,
+    #[serde(default)]
+    inline: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct ElemAttrReq {
     selector: String,
@@ -257,20 +519,44 @@ struct SendKeysReq {
     text: String,
     #[serde(default)]
     using: Option<String>,
+    /// Type the text as native key events instead of setting the value.
+    #[serde(default)]
+    native: bool,
+    /// Insert the text in one edit instead of typing it key by key.
+    #[serde(default)]
+    fast: bool,
 }
 
+/// Texts longer than this many characters are inserted in one edit, since
+/// typing them key by key would take too long.
+const FAST_SEND_KEYS_LENGTH: usize = 10_000;
+
 #[derive(Deserialize)]
 struct FileInfo {
     name: String,
-    data: String, // base64-encoded file content
+    /// Base64-encoded file content, for files sent inline.
+    #[serde(default)]
+    data: String,
+    /// Id of a file sent in chunks through `/file/chunk`, instead of `data`.
+    #[serde(default)]
+    upload: Option<String>,
     #[serde(default = "default_mime")]
     mime: String,
+    /// Path within an uploaded directory, e.g. `photos/2024/a.jpg`.
+    #[serde(default)]
+    path: Option<String>,
 }
 
 fn default_mime() -> String {
     "application/octet-stream".to_string()
 }
 
+#[derive(Deserialize)]
+struct FileChunkReq {
+    upload: String,
+    data: String, // base64-encoded
+}
+
 #[derive(Deserialize)]
 struct SetFilesReq {
     selector: String,
@@ -285,6 +571,9 @@ struct ScriptReq {
     script: String,
     #[serde(default)]
     args: Vec<Value>,
+    /// Run in this window instead of the current one (async scripts only).
+    #[serde(default)]
+    window: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -328,42 +617,152 @@ async fn window_handle<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(_body): Json<Value>,
 ) -> ApiResult {
-    let label = state
-        .current_window_label
-        .lock()
-        .expect("lock poisoned")
-        .clone();
-    let window = window_by_label(&state.app, label.as_deref())
-        .ok_or(ApiError::NotFound("no window".into()))?;
-    Ok(Json(json!(window.label())))
+    let window = state.window(None)?;
+    let ws = state.app.state::<WebDriverState>();
+    Ok(Json(json!(ws.handle(window.label()))))
+}
+
+// --- Diagnostics ---
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadyReq {
+    #[serde(default = "default_ready_timeout")]
+    timeout_ms: u64,
+}
+
+fn default_ready_timeout() -> u64 {
+    30000
+}
+
+/// Wait until a webview has finished loading its first page, so the first
+/// command of a session never races window creation.
+async fn ready<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<ReadyReq>,
+) -> ApiResult {
+    let mut loaded = state.app.state::<WebDriverState>().page_loaded.subscribe();
+    let waited = tokio::time::timeout(
+        Duration::from_millis(body.timeout_ms),
+        loaded.wait_for(|loaded| *loaded),
+    )
+    .await;
+    if waited.is_err() {
+        return Err(ApiError::new(
+            ErrorCode::Timeout,
+            "no webview finished loading in time",
+        ));
+    }
+    let windows: Vec<String> = state.app.webview_windows().keys().cloned().collect();
+    Ok(Json(json!({"ready": true, "windows": windows})))
+}
+
+/// How long `/healthz` waits for the bridge to answer.
+const HEALTH_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether the app can take commands: a webview exists, has loaded a page,
+/// and the bridge in it answers a script. `503` until then, so tauri-wd can
+/// poll it right after the port is reported.
+async fn healthz<R: Runtime>(AxumState(state): AxumState<SharedState<R>>) -> Response {
+    let windows: Vec<String> = state.app.webview_windows().keys().cloned().collect();
+    let loaded = *state.app.state::<WebDriverState>().page_loaded.borrow();
+    let bridge = match window_by_label(&state.app, None).filter(|_| loaded) {
+        // Doesn't take the window's eval lock: the probe touches no page
+        // globals, and a long-running command shouldn't make the app look
+        // unhealthy.
+        Some(window) => eval_with_id(
+            &state,
+            &window,
+            "the bridge did not answer",
+            HEALTH_PROBE_TIMEOUT,
+            false,
+            |id| format!("__WEBDRIVER__.resolve({id},true)"),
+        )
+        .await
+        .is_ok_and(|answer| answer == json!(true)),
+        None => false,
+    };
+    let ready = loaded && bridge;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "ready": ready,
+        "webview": !windows.is_empty(),
+        "loaded": loaded,
+        "bridge": bridge,
+        "windows": windows,
+    });
+    (status, Json(body)).into_response()
+}
+
+async fn api_version<R: Runtime>(AxumState(state): AxumState<SharedState<R>>) -> Json<Value> {
+    Json(json!({
+        "api": API_VERSION,
+        "plugin": env!("CARGO_PKG_VERSION"),
+        "bridge": state.app.state::<WebDriverState>().namespace,
+    }))
+}
+
+async fn info<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(_body): Json<Value>,
+) -> ApiResult {
+    let windows: Vec<String> = state.app.webview_windows().keys().cloned().collect();
+    Ok(Json(json!({
+        "plugin": env!("CARGO_PKG_VERSION"),
+        "tauri": tauri::VERSION,
+        "webview": tauri::webview_version().ok(),
+        "debugBuild": cfg!(debug_assertions),
+        "identifier": state.app.config().identifier,
+        "instance": state.instance,
+        "windows": windows,
+    })))
 }
 
 async fn window_handles<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(_body): Json<Value>,
 ) -> ApiResult {
-    let labels: Vec<String> = state.app.webview_windows().keys().cloned().collect();
-    Ok(Json(json!(labels)))
+    let ws = state.app.state::<WebDriverState>();
+    let handles: Vec<String> = state
+        .app
+        .webview_windows()
+        .keys()
+        .map(|label| ws.handle(label))
+        .collect();
+    Ok(Json(json!(handles)))
 }
 
 async fn window_close<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<CloseReq>,
 ) -> ApiResult {
-    let window = state
-        .app
-        .get_webview_window(&body.label)
-        .ok_or_else(|| ApiError::NotFound(format!("window '{}' not found", body.label)))?;
+    let window = window_by_label(&state.app, Some(&body.label)).ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::NoSuchWindow,
+            format!("window '{}' not found", body.label),
+        )
+    })?;
+    let was_current = state
+        .window(None)
+        .is_ok_and(|current| current.label() == window.label());
     window
         .close()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
-    // Clear current_window_label if it matches the closed window
-    let mut label = state.current_window_label.lock().expect("lock poisoned");
-    if label.as_deref() == Some(&body.label) {
-        *label = None;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    state
+        .eval_locks
+        .lock()
+        .expect("lock poisoned")
+        .remove(window.label());
+    // Per the spec there's no current window now, rather than some other
+    // one, until the client switches to another handle.
+    if was_current {
+        *state.current_window.lock().expect("lock poisoned") = CurrentWindow::Closed;
+        state.frame_stack.lock().expect("lock poisoned").clear();
     }
-    // Reset frame stack since we may have been in a frame of the closed window
-    state.frame_stack.lock().expect("lock poisoned").clear();
     Ok(Json(json!(true)))
 }
 
@@ -371,18 +770,17 @@ async fn window_rect<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<LabelReq>,
 ) -> ApiResult {
-    let window = window_by_label(&state.app, body.label.as_deref())
-        .ok_or(ApiError::NotFound("no window".into()))?;
+    let window = state.window(body.label.as_deref())?;
 
     let scale = window
         .scale_factor()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
     let pos = window
         .outer_position()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
     let size = window
         .outer_size()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
 
     Ok(Json(json!({
         "x": pos.x as f64 / scale,
@@ -396,32 +794,63 @@ async fn window_set_rect<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<SetRectReq>,
 ) -> ApiResult {
-    let window = window_by_label(&state.app, body.label.as_deref())
-        .ok_or(ApiError::NotFound("no window".into()))?;
+    let window = state.window(body.label.as_deref())?;
 
     if let (Some(x), Some(y)) = (body.x, body.y) {
         window
             .set_position(tauri::LogicalPosition::new(x, y))
-            .map_err(|e| ApiError::Internal(e.to_string()))?;
+            .map_err(|e| ApiError::internal(e.to_string()))?;
     }
     if let (Some(w), Some(h)) = (body.width, body.height) {
         window
             .set_size(tauri::LogicalSize::new(w, h))
-            .map_err(|e| ApiError::Internal(e.to_string()))?;
+            .map_err(|e| ApiError::internal(e.to_string()))?;
     }
+    #[cfg(target_os = "linux")]
+    wait_for_geometry(&window, &body).await;
 
     Ok(Json(json!(true)))
 }
 
+/// GTK applies move and resize requests asynchronously, so on Linux the
+/// window keeps reporting its old geometry for a moment after `set_position`
+/// / `set_size`. Wait (up to 1s, window managers may clamp the request) for
+/// the requested geometry so the rect read back by the client is the new one.
+#[cfg(target_os = "linux")]
+async fn wait_for_geometry<R: Runtime>(window: &tauri::WebviewWindow<R>, body: &SetRectReq) {
+    let near = |a: f64, b: f64| (a - b).abs() < 1.0;
+    let settled = || {
+        let Ok(scale) = window.scale_factor() else {
+            return true;
+        };
+        let position = match (body.x, body.y) {
+            (Some(x), Some(y)) => window.outer_position().map_or(true, |p| {
+                near(p.x as f64 / scale, x) && near(p.y as f64 / scale, y)
+            }),
+            _ => true,
+        };
+        let size = match (body.width, body.height) {
+            (Some(w), Some(h)) => window.inner_size().map_or(true, |s| {
+                near(s.width as f64 / scale, w) && near(s.height as f64 / scale, h)
+            }),
+            _ => true,
+        };
+        position && size
+    };
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+    while !settled() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
 async fn window_fullscreen<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<LabelReq>,
 ) -> ApiResult {
-    let window = window_by_label(&state.app, body.label.as_deref())
-        .ok_or(ApiError::NotFound("no window".into()))?;
+    let window = state.window(body.label.as_deref())?;
     window
         .set_fullscreen(true)
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
     Ok(Json(json!(true)))
 }
 
@@ -429,11 +858,10 @@ async fn window_minimize<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<LabelReq>,
 ) -> ApiResult {
-    let window = window_by_label(&state.app, body.label.as_deref())
-        .ok_or(ApiError::NotFound("no window".into()))?;
+    let window = state.window(body.label.as_deref())?;
     window
         .minimize()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
     Ok(Json(json!(true)))
 }
 
@@ -441,11 +869,10 @@ async fn window_maximize<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<LabelReq>,
 ) -> ApiResult {
-    let window = window_by_label(&state.app, body.label.as_deref())
-        .ok_or(ApiError::NotFound("no window".into()))?;
+    let window = state.window(body.label.as_deref())?;
     window
         .maximize()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
     Ok(Json(json!(true)))
 }
 
@@ -453,18 +880,17 @@ async fn window_insets<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<LabelReq>,
 ) -> ApiResult {
-    let window = window_by_label(&state.app, body.label.as_deref())
-        .ok_or(ApiError::NotFound("no window".into()))?;
+    let window = state.window(body.label.as_deref())?;
 
     let scale = window
         .scale_factor()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
     let outer_pos = window
         .outer_position()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
     let inner_pos = window
         .inner_position()
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::internal(e.to_string()))?;
 
     let top = (inner_pos.y - outer_pos.y) as f64 / scale;
     let left = (inner_pos.x - outer_pos.x) as f64 / scale;
@@ -477,6 +903,97 @@ async fn window_insets<R: Runtime>(
     })))
 }
 
+// --- Viewport emulation handlers ---
+
+#[derive(Deserialize)]
+struct ViewportReq {
+    width: f64,
+    height: f64,
+    #[serde(rename = "deviceScaleFactor", default)]
+    device_scale_factor: Option<f64>,
+}
+
+/// Read back the viewport as seen by the page.
+async fn viewport_metrics<R: Runtime>(state: &SharedState<R>) -> ApiResult {
+    let result = eval_js(
+        state,
+        "return{width:window.innerWidth,height:window.innerHeight,\
+         deviceScaleFactor:window.devicePixelRatio}",
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+async fn window_set_viewport<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<ViewportReq>,
+) -> ApiResult {
+    // Callers other than tauri-wd (`Automation`, the app's own tests) don't
+    // have its checks in front of them.
+    let fields = [
+        ("width", Some(body.width)),
+        ("height", Some(body.height)),
+        ("deviceScaleFactor", body.device_scale_factor),
+    ];
+    for (name, value) in fields {
+        if value.is_some_and(|v| !v.is_finite() || v <= 0.0) {
+            return Err(ApiError::new(
+                ErrorCode::InvalidArgument,
+                format!("'{name}' must be a positive number"),
+            ));
+        }
+    }
+    let window = state.window(None)?;
+
+    // set_size sizes the content area, which is exactly the webview viewport,
+    // so title bar and insets don't need to be accounted for.
+    window
+        .set_size(tauri::LogicalSize::new(body.width, body.height))
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+
+    {
+        let ws = state.app.state::<WebDriverState>();
+        let mut overrides = ws.device_scale_overrides.lock().expect("lock poisoned");
+        match body.device_scale_factor {
+            Some(ratio) => overrides.insert(window.label().to_string(), ratio),
+            None => overrides.remove(window.label()),
+        };
+    }
+    let ratio_js = match body.device_scale_factor {
+        Some(ratio) => format!("{ratio}"),
+        None => "null".to_string(),
+    };
+    eval_js(
+        &state,
+        &format!("__WEBDRIVER__.setDevicePixelRatio({ratio_js});return null"),
+    )
+    .await?;
+
+    // Give the webview a moment to lay out at the new size.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    viewport_metrics(&state).await
+}
+
+async fn window_reset_viewport<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(_body): Json<Value>,
+) -> ApiResult {
+    let window = state.window(None)?;
+    {
+        let ws = state.app.state::<WebDriverState>();
+        ws.device_scale_overrides
+            .lock()
+            .expect("lock poisoned")
+            .remove(window.label());
+    }
+    eval_js(
+        &state,
+        "__WEBDRIVER__.setDevicePixelRatio(null);return null",
+    )
+    .await?;
+    viewport_metrics(&state).await
+}
+
 // --- New window handler ---
 
 #[derive(Deserialize)]
@@ -495,7 +1012,7 @@ async fn window_new<R: Runtime>(
     let window = tauri::WebviewWindowBuilder::new(&state.app, &label, tauri::WebviewUrl::default())
         .inner_size(800.0, 600.0)
         .build()
-        .map_err(|e| ApiError::Internal(format!("failed to create window: {e}")))?;
+        .map_err(|e| ApiError::internal(format!("failed to create window: {e}")))?;
 
     // Wait briefly for the window to initialize
     tokio::time::sleep(Duration::from_millis(200)).await;
@@ -612,19 +1129,79 @@ async fn element_rect<R: Runtime>(
     Ok(Json(result))
 }
 
+async fn element_scroll_into_view<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<ScrollIntoViewReq>,
+) -> ApiResult {
+    let mut align = Vec::new();
+    for (name, value) in [("block", &body.block), ("inline", &body.inline)] {
+        let value = value.as_deref().unwrap_or("center");
+        if !["start", "center", "end", "nearest"].contains(&value) {
+            return Err(ApiError::new(
+                ErrorCode::InvalidArgument,
+                format!("'{name}' must be one of start, center, end, nearest"),
+            ));
+        }
+        align.push(format!("{name}:'{value}'"));
+    }
+    let align = align.join(",");
+    let result = eval_on_element(
+        &state,
+        &body.selector,
+        body.index,
+        body.using.as_deref(),
+        &format!(
+            "el.scrollIntoView({{{align}}});var r=el.getBoundingClientRect();\
+             return{{x:r.x,y:r.y,width:r.width,height:r.height}}"
+        ),
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+/// Element Click: scroll the element's center into view, check nothing
+/// covers it, and click there with a pointer move, press and release, so
+/// the page sees the same pointer and mouse events as a real click.
 async fn element_click<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<ElemReq>,
 ) -> ApiResult {
-    eval_on_element(
+    let center = eval_on_element(
         &state,
         &body.selector,
         body.index,
         body.using.as_deref(),
-        "el.scrollIntoView({block:'center',inline:'center'});el.focus();el.click();return null",
+        "if(el.tagName==='OPTION'){var s=el.closest('select');el.selected=true;\
+         if(s){s.dispatchEvent(new Event('input',{bubbles:true}));\
+         s.dispatchEvent(new Event('change',{bubbles:true}))}return null}\
+         var r=el.getBoundingClientRect(),de=document.documentElement;\
+         var x=r.x+r.width/2,y=r.y+r.height/2;\
+         if(x<0||y<0||x>=de.clientWidth||y>=de.clientHeight){\
+         el.scrollIntoView({block:'center',inline:'center'});r=el.getBoundingClientRect();\
+         x=r.x+r.width/2;y=r.y+r.height/2}\
+         if(!r.width||!r.height)__WEBDRIVER__.fail('elementNotInteractable','element has no size');\
+         var root=el.getRootNode(),hit=(root.elementFromPoint?root:document).elementFromPoint(x,y);\
+         if(!hit||(hit!==el&&!el.contains(hit)))__WEBDRIVER__.fail('elementClickIntercepted',\
+         'element click intercepted: '+(hit?'<'+hit.tagName.toLowerCase()+'>':'nothing')+\
+         ' would receive the click');\
+         delete (window.__wdClicks||{})['element-click'];return [x,y]",
     )
     .await?;
-    Ok(Json(json!(null)))
+    // Options are selected directly, as in the spec.
+    let Some(center) = center.as_array() else {
+        return Ok(Json(json!(null)));
+    };
+    let actions = json!({"actions": [{
+        "type": "pointer",
+        "id": "element-click",
+        "parameters": {"pointerType": "mouse"},
+        "actions": [
+            {"type": "pointerMove", "origin": "viewport", "x": center[0], "y": center[1]},
+            {"type": "pointerDown", "button": 0},
+            {"type": "pointerUp", "button": 0},
+        ],
+    }]});
+    actions_perform(AxumState(state), Json(actions)).await
 }
 
 async fn element_clear<R: Runtime>(
@@ -647,69 +1224,247 @@ async fn element_send_keys<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<SendKeysReq>,
 ) -> ApiResult {
-    let text_json = serde_json::to_string(&body.text).unwrap();
-    let js = format!(
-        "el.focus();el.value+={text_json};\
-         el.dispatchEvent(new Event('input',{{bubbles:true}}));\
-         el.dispatchEvent(new Event('change',{{bubbles:true}}));return null"
-    );
-    eval_on_element(
-        &state,
-        &body.selector,
-        body.index,
-        body.using.as_deref(),
-        &js,
-    )
-    .await?;
+    send_keys(&state, &body, "").await?;
     Ok(Json(json!(null)))
 }
 
-async fn element_set_files<R: Runtime>(
+/// Skips typing into file inputs, whose text is file paths to upload.
+const SKIP_FILE_INPUT: &str = "if(el.tagName==='INPUT'&&el.type==='file')return false;";
+
+/// Element Send Keys in a single round trip: the element's tag and type are
+/// checked in the same script that types. A file input is left alone and
+/// `{"file":true}` returned, since only the caller can read the files its
+/// text names; they're sent through `/element/set-files` instead.
+async fn element_type<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
-    Json(body): Json<SetFilesReq>,
+    Json(body): Json<SendKeysReq>,
 ) -> ApiResult {
-    // Build a JS array of {name, data, mime} objects to pass into the webview.
-    let files_json = serde_json::to_string(
-        &body
-            .files
-            .iter()
-            .map(|f| json!({"name": f.name, "data": f.data, "mime": f.mime}))
-            .collect::<Vec<_>>(),
-    )
-    .unwrap();
+    let typed = send_keys(&state, &body, SKIP_FILE_INPUT).await?;
+    Ok(Json(json!({"file": !typed})))
+}
 
-    let js = format!(
-        "if(el.tagName!=='INPUT'||el.type!=='file')throw new Error('element is not a file input');\
-         var _files={files_json};\
-         var dt=new DataTransfer();\
-         for(var i=0;i<_files.length;i++){{\
-           var raw=atob(_files[i].data);\
-           var bytes=new Uint8Array(raw.length);\
-           for(var j=0;j<raw.length;j++)bytes[j]=raw.charCodeAt(j);\
-           dt.items.add(new File([bytes],_files[i].name,{{type:_files[i].mime}}));\
-         }}\
-         el.files=dt.files;\
-         el.dispatchEvent(new Event('input',{{bubbles:true}}));\
-         el.dispatchEvent(new Event('change',{{bubbles:true}}));\
-         return null"
-    );
-    eval_on_element(
-        &state,
+/// Type `body.text` into the element, unless `guard` (run first, with `el`
+/// bound) returns false. Returns whether the text was typed.
+async fn send_keys<R: Runtime>(
+    state: &SharedState<R>,
+    body: &SendKeysReq,
+    guard: &str,
+) -> Result<bool, ApiError> {
+    if body.native {
+        return native_send_keys(state, body, guard).await;
+    }
+    let text_json = serde_json::to_string(&body.text).unwrap();
+    let js = if body.fast || body.text.chars().count() > FAST_SEND_KEYS_LENGTH {
+        format!(
+            "{guard}el.focus();el.value+={text_json};\
+             el.dispatchEvent(new Event('input',{{bubbles:true}}));\
+             el.dispatchEvent(new Event('change',{{bubbles:true}}));return true"
+        )
+    } else {
+        format!(
+            "{guard}__WEBDRIVER__.focusToType(el);\
+             __WEBDRIVER__.typeText(el,{text_json});return true"
+        )
+    };
+    let typed = eval_on_element(
+        state,
         &body.selector,
         body.index,
         body.using.as_deref(),
         &js,
     )
     .await?;
-    Ok(Json(json!(null)))
+    Ok(typed.as_bool() == Some(true))
 }
 
-async fn element_displayed<R: Runtime>(
-    AxumState(state): AxumState<SharedState<R>>,
-    Json(body): Json<ElemReq>,
-) -> ApiResult {
-    let result = eval_on_element(
-        &state,
+/// Focus the element and type `body.text` as native key events. As in
+/// Element Send Keys, modifier keys stay down until typed again or until the
+/// Null key (U+E000), and are released at the end.
+async fn native_send_keys<R: Runtime>(
+    state: &SharedState<R>,
+    body: &SendKeysReq,
+    guard: &str,
+) -> Result<bool, ApiError> {
+    if !native::SUPPORTED {
+        return Err(ApiError::new(
+            ErrorCode::InvalidArgument,
+            "native input is only supported on macOS",
+        ));
+    }
+    body.text
+        .chars()
+        .filter(|&key| key != '\u{E000}')
+        .try_for_each(check_native_key)?;
+    let focused = eval_on_element(
+        state,
+        &body.selector,
+        body.index,
+        body.using.as_deref(),
+        &format!("{guard}__WEBDRIVER__.focusToType(el);return true"),
+    )
+    .await?;
+    if focused.as_bool() != Some(true) {
+        return Ok(false);
+    }
+    let _ = state.window(None)?.set_focus();
+    let mut modifiers = Modifiers::default();
+    for key in body.text.chars() {
+        if key == '\u{E000}' {
+            release_modifiers(&mut modifiers)?;
+            continue;
+        }
+        match modifiers.is_held(key) {
+            Some(held) => post_key(&mut modifiers, key, !held)?,
+            None => {
+                post_key(&mut modifiers, key, true)?;
+                post_key(&mut modifiers, key, false)?;
+            }
+        }
+    }
+    release_modifiers(&mut modifiers)?;
+    Ok(true)
+}
+
+/// Post a native press or release of `key`, tracking it in `modifiers` if
+/// it's a modifier key.
+fn post_key(modifiers: &mut Modifiers, key: char, down: bool) -> Result<(), ApiError> {
+    modifiers.update(key, down);
+    native::post_key(key, down, *modifiers).map_err(ApiError::internal)
+}
+
+/// Refuse a key [`native::post_key`] has no key code for, so a sequence
+/// isn't left half typed.
+fn check_native_key(key: char) -> Result<(), ApiError> {
+    native::key_code(key)
+        .map(drop)
+        .map_err(|e| ApiError::new(ErrorCode::InvalidArgument, e))
+}
+
+/// Post a native release of every modifier key held in `modifiers`.
+fn release_modifiers(modifiers: &mut Modifiers) -> Result<(), ApiError> {
+    for key in modifiers.held() {
+        post_key(modifiers, key, false)?;
+    }
+    Ok(())
+}
+
+/// Append a chunk to the upload `upload`, creating it on the first chunk.
+/// `/element/set-files` turns the chunks into one File, so large files never
+/// have to pass through a single script.
+async fn file_chunk<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<FileChunkReq>,
+) -> ApiResult {
+    let upload = serde_json::to_string(&body.upload).unwrap();
+    let data = serde_json::to_string(&body.data).unwrap();
+    let js = format!(
+        "var u=window.__wdUploads=window.__wdUploads||{{}};\
+         (u[{upload}]=u[{upload}]||[]).push(__WEBDRIVER__.decodeBase64({data}));\
+         return null"
+    );
+    eval_js(&state, &js).await?;
+    Ok(Json(json!(null)))
+}
+
+#[derive(Deserialize)]
+struct DiscardReq {
+    uploads: Vec<String>,
+}
+
+/// Drop chunked uploads that won't be claimed, e.g. because sending a later
+/// chunk failed.
+async fn file_discard<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<DiscardReq>,
+) -> ApiResult {
+    let uploads = serde_json::to_string(&body.uploads).unwrap();
+    let js = format!(
+        "var u=window.__wdUploads||{{}};\
+         {uploads}.forEach(function(id){{delete u[id]}});\
+         return null"
+    );
+    eval_js(&state, &js).await?;
+    Ok(Json(json!(null)))
+}
+
+async fn element_set_files<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<SetFilesReq>,
+) -> ApiResult {
+    // Build a JS array of {name, data, upload, mime} objects to pass into
+    // the webview. Chunked uploads are claimed before anything can fail, so
+    // a rejected request doesn't leave their bytes behind.
+    let files_json = serde_json::to_string(
+        &body
+            .files
+            .iter()
+            .map(|f| {
+                json!({"name": f.name, "data": f.data, "upload": f.upload, "mime": f.mime, "path": f.path})
+            })
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+
+    let js = format!(
+        "var _files={files_json};\
+         var _uploads=window.__wdUploads||{{}};\
+         for(var u=0;u<_files.length;u++)if(_files[u].upload){{\
+           _files[u].parts=_uploads[_files[u].upload];\
+           delete _uploads[_files[u].upload];\
+           if(!_files[u].parts)__WEBDRIVER__.fail('invalidArgument','unknown upload: '+_files[u].upload);\
+         }}\
+         if(el.tagName!=='INPUT'||el.type!=='file')__WEBDRIVER__.fail('invalidArgument','element is not a file input');\
+         var dir=_files.some(function(f){{return f.path}});\
+         if(dir&&!el.webkitdirectory)__WEBDRIVER__.fail('invalidArgument','the file input does not accept directories');\
+         if(!dir&&el.webkitdirectory)__WEBDRIVER__.fail('invalidArgument','the file input only accepts directories');\
+         if(_files.length>1&&!el.multiple&&!el.webkitdirectory)\
+           __WEBDRIVER__.fail('invalidArgument','the file input does not accept multiple files');\
+         var dt=new DataTransfer();\
+         if(el.multiple&&!dir)for(var k=0;k<el.files.length;k++)dt.items.add(el.files[k]);\
+         for(var i=0;i<_files.length;i++){{\
+           var parts=_files[i].parts||[__WEBDRIVER__.decodeBase64(_files[i].data)];\
+           var file=new File(parts,_files[i].name,{{type:_files[i].mime}});\
+           if(_files[i].path)Object.defineProperty(file,'webkitRelativePath',{{value:_files[i].path}});\
+           dt.items.add(file);\
+         }}\
+         el.files=dt.files;\
+         el.dispatchEvent(new Event('input',{{bubbles:true}}));\
+         el.dispatchEvent(new Event('change',{{bubbles:true}}));\
+         return null"
+    );
+    eval_on_element(
+        &state,
+        &body.selector,
+        body.index,
+        body.using.as_deref(),
+        &js,
+    )
+    .await?;
+    Ok(Json(json!(null)))
+}
+
+#[derive(Deserialize)]
+struct ReleaseReq {
+    ids: Vec<String>,
+}
+
+/// Forget node ids (`"using":"ref"` selectors) tauri-wd no longer
+/// references, so the bridge's registry doesn't grow with the session.
+async fn element_release<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<ReleaseReq>,
+) -> ApiResult {
+    let ids = serde_json::to_string(&body.ids).unwrap();
+    let released = eval_js(&state, &format!("return __WEBDRIVER__.releaseRefs({ids})")).await?;
+    Ok(Json(json!({"released": released})))
+}
+
+async fn element_displayed<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<ElemReq>,
+) -> ApiResult {
+    let result = eval_on_element(
+        &state,
         &body.selector,
         body.index,
         body.using.as_deref(),
@@ -756,12 +1511,19 @@ async fn script_execute<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<ScriptReq>,
 ) -> ApiResult {
-    let args_json = serde_json::to_string(&body.args).unwrap();
-    let script = format!(
-        "var __args={args_json};return (function(){{{}}}).apply(null,__args)",
-        body.script
-    );
-    let result = eval_js(&state, &script).await?;
+    let window = state.window(None)?;
+    let _guard = state.lock_window(window.label()).await;
+    let frame_prefix = build_frame_prefix(&state);
+    let result = run_script(
+        &state,
+        &window,
+        &frame_prefix,
+        &body.script,
+        &body.args,
+        false,
+        true,
+    )
+    .await?;
     Ok(Json(json!({"value": result})))
 }
 
@@ -769,64 +1531,22 @@ async fn script_execute_async<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<ScriptReq>,
 ) -> ApiResult {
-    let label = state
-        .current_window_label
-        .lock()
-        .expect("lock poisoned")
-        .clone();
-    let window = window_by_label(&state.app, label.as_deref())
-        .ok_or(ApiError::NotFound("no window".into()))?;
-
-    let id = uuid::Uuid::new_v4().to_string();
-    let (tx, rx) = tokio::sync::oneshot::channel();
-
-    {
-        let ws = state.app.state::<WebDriverState>();
-        ws.pending_scripts
-            .lock()
-            .expect("lock poisoned")
-            .insert(id.clone(), tx);
-    }
-
-    let args_json = serde_json::to_string(&body.args).unwrap();
-    let script = format!(
-        "(function(){{var __args={args_json};\
-         var __done=function(r){{window.__WEBDRIVER__.resolve(\"{id}\",r)}};\
-         __args.push(__done);\
-         try{{(function(){{{user_script}}}).apply(null,__args)}}\
-         catch(__e){{window.__WEBDRIVER__.resolve(\"{id}\",\
-         {{error:__e.name,message:__e.message,stacktrace:__e.stack||\"\"}})}}}})();",
-        user_script = body.script,
-        id = id,
-    );
+    let window = state.window(body.window.as_deref())?;
+    let result = run_script(&state, &window, "", &body.script, &body.args, true, true).await?;
+    Ok(Json(json!({"value": result})))
+}
 
-    window
-        .eval(&script)
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
-
-    match tokio::time::timeout(Duration::from_secs(30), rx).await {
-        Ok(Ok(value)) => {
-            if let Some(obj) = value.as_object() {
-                if obj.contains_key("error") && obj.contains_key("message") {
-                    let msg = obj
-                        .get("message")
-                        .and_then(|m| m.as_str())
-                        .unwrap_or("script error");
-                    return Err(ApiError::Internal(msg.to_string()));
-                }
-            }
-            Ok(Json(json!({"value": value})))
-        }
-        Ok(Err(_)) => Err(ApiError::Internal("result channel closed".into())),
-        Err(_) => {
-            let ws = state.app.state::<WebDriverState>();
-            ws.pending_scripts
-                .lock()
-                .expect("lock poisoned")
-                .remove(&id);
-            Err(ApiError::Internal("async script timed out".into()))
-        }
-    }
+/// Fail every script still waiting for a result, e.g. when tauri-wd deletes
+/// a session whose app keeps running.
+async fn script_cancel<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(_body): Json<Value>,
+) -> ApiResult {
+    state
+        .app
+        .state::<WebDriverState>()
+        .cancel_scripts(Cancelled::SessionDeleted, |_| true);
+    Ok(Json(json!(null)))
 }
 
 // --- Navigation handlers ---
@@ -893,10 +1613,10 @@ async fn alert_get_text<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(_body): Json<Value>,
 ) -> ApiResult {
-    let result = eval_js(
+    let result = eval_js_during_dialog(
         &state,
-        "var d=window.__WEBDRIVER__.__dialog;\
-         if(!d.open)throw new Error('no such alert');\
+        "var d=__WEBDRIVER__.__dialog;\
+         if(!d.open)__WEBDRIVER__.fail('noSuchAlert','no such alert');\
          return d.text",
     )
     .await?;
@@ -907,13 +1627,13 @@ async fn alert_dismiss<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(_body): Json<Value>,
 ) -> ApiResult {
-    eval_js(
+    eval_js_during_dialog(
         &state,
-        "var d=window.__WEBDRIVER__.__dialog;\
-         if(!d.open)throw new Error('no such alert');\
+        "var d=__WEBDRIVER__.__dialog;\
+         if(!d.open)__WEBDRIVER__.fail('noSuchAlert','no such alert');\
          if(d.type==='confirm')d.response=false;\
          if(d.type==='prompt')d.response=null;\
-         d.open=false;\
+         __WEBDRIVER__.closeDialog();\
          return null",
     )
     .await?;
@@ -924,19 +1644,32 @@ async fn alert_accept<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(_body): Json<Value>,
 ) -> ApiResult {
-    eval_js(
+    eval_js_during_dialog(
         &state,
-        "var d=window.__WEBDRIVER__.__dialog;\
-         if(!d.open)throw new Error('no such alert');\
+        "var d=__WEBDRIVER__.__dialog;\
+         if(!d.open)__WEBDRIVER__.fail('noSuchAlert','no such alert');\
          if(d.type==='confirm')d.response=true;\
          if(d.type==='prompt'&&d.response===null)d.response=d.defaultValue||'';\
-         d.open=false;\
+         __WEBDRIVER__.closeDialog();\
          return null",
     )
     .await?;
     Ok(Json(json!(null)))
 }
 
+/// The open dialogs, oldest (the one the alert commands act on) first.
+async fn alert_queue<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(_body): Json<Value>,
+) -> ApiResult {
+    let result = eval_js_during_dialog(
+        &state,
+        "return __WEBDRIVER__.__dialogs.map(function(d){return {type:d.type,text:d.text}})",
+    )
+    .await?;
+    Ok(Json(json!({"dialogs": result})))
+}
+
 #[derive(Deserialize)]
 struct AlertTextReq {
     text: String,
@@ -948,78 +1681,335 @@ async fn alert_send_text<R: Runtime>(
 ) -> ApiResult {
     let text_json = serde_json::to_string(&body.text).unwrap();
     let script = format!(
-        "var d=window.__WEBDRIVER__.__dialog;\
-         if(!d.open)throw new Error('no such alert');\
-         if(d.type!=='prompt')throw new Error('no such alert');\
+        "var d=__WEBDRIVER__.__dialog;\
+         if(!d.open)__WEBDRIVER__.fail('noSuchAlert','no such alert');\
+         if(d.type!=='prompt')__WEBDRIVER__.fail('noSuchAlert','no such alert');\
          d.response={text_json};\
          return null"
     );
-    eval_js(&state, &script).await?;
+    eval_js_during_dialog(&state, &script).await?;
     Ok(Json(json!(null)))
 }
 
-// --- Screenshot handlers ---
+// --- Console log handlers ---
 
-/// Helper: run raw JS that manually calls __WEBDRIVER__.resolve(id, result).
-/// Unlike eval_js, the script is NOT wrapped — the caller must call resolve().
-async fn eval_js_callback<R: Runtime>(
-    state: &SharedState<R>,
-    script: &str,
-) -> Result<Value, ApiError> {
-    let label = state
-        .current_window_label
+#[derive(Deserialize)]
+struct LogReq {
+    #[serde(default)]
+    clear: bool,
+    /// Only return entries after this sequence number (console only).
+    since: Option<u64>,
+    /// Document the `since` cursor belongs to; ignored after a navigation.
+    document: Option<String>,
+}
+
+async fn log_console<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<LogReq>,
+) -> ApiResult {
+    if let Some(since) = body.since {
+        let document = serde_json::to_string(&body.document).unwrap_or_default();
+        let script = format!(
+            "var w=__WEBDRIVER__;\
+             var since=w.__documentId==={document}?{since}:0;\
+             return {{document:w.__documentId,entries:w.__console.filter(function(e){{return e.seq>since}})}}"
+        );
+        let result = eval_js_during_dialog(&state, &script).await?;
+        return Ok(Json(result));
+    }
+    let script = if body.clear {
+        "var b=__WEBDRIVER__.__console;return b.splice(0,b.length)"
+    } else {
+        "return __WEBDRIVER__.__console.slice()"
+    };
+    let result = eval_js_during_dialog(&state, script).await?;
+    Ok(Json(json!({"entries": result})))
+}
+
+async fn log_errors<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<LogReq>,
+) -> ApiResult {
+    let script = if body.clear {
+        "var b=__WEBDRIVER__.__errors;return b.splice(0,b.length)"
+    } else {
+        "return __WEBDRIVER__.__errors.slice()"
+    };
+    let result = eval_js_during_dialog(&state, script).await?;
+    Ok(Json(json!({"errors": result})))
+}
+
+#[derive(Deserialize)]
+struct PreloadReq {
+    scripts: Vec<PreloadScript>,
+}
+
+async fn script_preload<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<PreloadReq>,
+) -> ApiResult {
+    let count = body.scripts.len();
+    *state
+        .app
+        .state::<WebDriverState>()
+        .preload_scripts
         .lock()
-        .expect("lock poisoned")
-        .clone();
-    let window = window_by_label(&state.app, label.as_deref())
-        .ok_or_else(|| ApiError::NotFound("no such window".into()))?;
+        .expect("lock poisoned") = body.scripts;
+    Ok(Json(json!({"count": count})))
+}
 
-    let id = uuid::Uuid::new_v4().to_string();
+#[derive(Deserialize)]
+struct BasicAuthReq {
+    credentials: Vec<crate::auth::Credentials>,
+}
+
+/// Set the credentials HTTP authentication challenges are answered with in
+/// every webview; an empty list cancels them all.
+async fn auth_basic(Json(body): Json<BasicAuthReq>) -> ApiResult {
+    let count = body.credentials.len();
+    crate::auth::set(body.credentials);
+    Ok(Json(json!({"count": count})))
+}
+
+#[derive(Deserialize)]
+struct ShortcutReq {
+    accelerator: String,
+    #[serde(default)]
+    global: bool,
+}
+
+/// Press a menu accelerator in the current window, or with `global`, post
+/// it as OS-level key events for shortcuts registered with the OS.
+/// `handled` is whether a menu item took it, or `null` when nothing reports
+/// back.
+async fn shortcut_press<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<ShortcutReq>,
+) -> ApiResult {
+    let accelerator = crate::shortcut::Accelerator::parse(&body.accelerator)
+        .map_err(|e| ApiError::new(ErrorCode::InvalidArgument, e))?;
+    if body.global {
+        crate::shortcut::post_keys(&accelerator)
+            .map_err(|e| ApiError::new(ErrorCode::InvalidArgument, e))?;
+        return Ok(Json(json!({"handled": null})));
+    }
+    let window = state.window(None)?;
     let (tx, rx) = tokio::sync::oneshot::channel();
+    let target = window.clone();
+    window
+        .run_on_main_thread(move || {
+            let _ = tx.send(crate::shortcut::activate_menu(&target, &accelerator));
+        })
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let handled = rx
+        .await
+        .map_err(|_| ApiError::internal("the main thread dropped the shortcut"))?
+        .map_err(ApiError::internal)?;
+    Ok(Json(json!({ "handled": handled })))
+}
 
-    {
-        let ws = state.app.state::<WebDriverState>();
-        ws.pending_scripts
-            .lock()
-            .expect("lock poisoned")
-            .insert(id.clone(), tx);
+#[derive(Deserialize)]
+struct PopupBehaviorReq {
+    behavior: String,
+}
+
+/// Set what `target="_blank"` links and `window.open()` do in every window,
+/// now and after each navigation.
+async fn popup_behavior<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<PopupBehaviorReq>,
+) -> ApiResult {
+    if !["open-window", "same-window", "block"].contains(&body.behavior.as_str()) {
+        return Err(ApiError::new(
+            ErrorCode::InvalidArgument,
+            format!("unknown popup behavior: {}", body.behavior),
+        ));
     }
+    let ws = state.app.state::<WebDriverState>();
+    let script = ws.bridged(&format!(
+        "__WEBDRIVER__.setPopupBehavior({})",
+        Value::String(body.behavior.clone())
+    ));
+    *ws.popup_behavior.lock().expect("lock poisoned") = body.behavior;
+    for window in state.app.webview_windows().values() {
+        let _guard = state.lock_window(window.label()).await;
+        window
+            .eval(&script)
+            .map_err(|e| ApiError::internal(e.to_string()))?;
+    }
+    Ok(Json(json!(null)))
+}
 
-    let final_script = script.replace("__CALLBACK_ID__", &id);
+#[derive(Deserialize)]
+struct MockSetReq {
+    rules: Vec<Value>,
+}
 
-    window
-        .eval(&final_script)
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
-
-    match tokio::time::timeout(Duration::from_secs(30), rx).await {
-        Ok(Ok(value)) => {
-            if let Some(obj) = value.as_object() {
-                if obj.contains_key("error") && obj.contains_key("message") {
-                    let msg = obj
-                        .get("message")
-                        .and_then(|m| m.as_str())
-                        .unwrap_or("script error");
-                    return Err(ApiError::Internal(msg.to_string()));
+async fn mock_set<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<MockSetReq>,
+) -> ApiResult {
+    let count = body.rules.len();
+    let webdriver = state.app.state::<WebDriverState>();
+    let rules = {
+        let mut current = webdriver.mock_rules.lock().expect("lock poisoned");
+        let mut rules = body.rules;
+        for rule in rules.iter_mut().filter_map(Value::as_object_mut) {
+            // The bridge reports uses of a `times` rule by id.
+            let id = match rule.get("id").and_then(Value::as_str) {
+                Some(id) => id.to_string(),
+                None => {
+                    let id = format!("mock-{}", uuid::Uuid::new_v4().simple());
+                    rule.insert("id".into(), json!(id));
+                    id
                 }
+            };
+            // A rule the plugin already has keeps the uses it has left, so
+            // setting the rules again doesn't start its count over.
+            let remaining = current
+                .iter()
+                .find(|old| old.get("id").and_then(Value::as_str) == Some(id.as_str()))
+                .and_then(|old| old.get("times"))
+                .filter(|times| times.is_number());
+            if let Some(remaining) = remaining.filter(|_| rule.contains_key("times")) {
+                rule.insert("times".into(), remaining.clone());
             }
-            Ok(value)
         }
-        Ok(Err(_)) => Err(ApiError::Internal("result channel closed".into())),
-        Err(_) => {
-            let ws = state.app.state::<WebDriverState>();
-            ws.pending_scripts
-                .lock()
-                .expect("lock poisoned")
-                .remove(&id);
-            Err(ApiError::Internal("screenshot timed out".into()))
+        *current = rules.clone();
+        Value::Array(rules)
+    };
+    let script = webdriver.bridged(&format!("__WEBDRIVER__.setMocks({rules})"));
+    // Mocks apply to every window, not just the current one.
+    for window in state.app.webview_windows().values() {
+        let _guard = state.lock_window(window.label()).await;
+        window
+            .eval(&script)
+            .map_err(|e| ApiError::internal(e.to_string()))?;
+    }
+    Ok(Json(json!({"count": count})))
+}
+
+async fn log_prints<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<LogReq>,
+) -> ApiResult {
+    let script = if body.clear {
+        "var b=__WEBDRIVER__.__prints;return b.splice(0,b.length)"
+    } else {
+        "return __WEBDRIVER__.__prints.slice()"
+    };
+    let result = eval_js_during_dialog(&state, script).await?;
+    Ok(Json(json!({"prints": result})))
+}
+
+async fn log_network<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<LogReq>,
+) -> ApiResult {
+    let script = if body.clear {
+        "var b=__WEBDRIVER__.__network;return b.splice(0,b.length)"
+    } else {
+        "return __WEBDRIVER__.__network.slice()"
+    };
+    let result = eval_js_during_dialog(&state, script).await?;
+    Ok(Json(json!({"entries": result})))
+}
+
+// --- Screenshot handlers ---
+
+/// Helper: run raw JS that sends its result with
+/// __WEBDRIVER__.resolveBytes(id, bytes) (or resolveCanvas), and return the
+/// bytes. Unlike eval_js, the script is NOT wrapped — the caller must
+/// resolve, with `resolve` only for errors.
+async fn eval_js_bytes<R: Runtime>(
+    state: &SharedState<R>,
+    script: &str,
+) -> Result<Vec<u8>, ApiError> {
+    let window = state.window(None)?;
+    let _guard = state.lock_window(window.label()).await;
+
+    let mut result_id = String::new();
+    let value = eval_with_id(
+        state,
+        &window,
+        "screenshot timed out",
+        SCRIPT_TIMEOUT,
+        false,
+        |id| {
+            result_id = serde_json::from_str(id).unwrap_or_default();
+            script.replace("\"__CALLBACK_ID__\"", id)
+        },
+    )
+    .await?;
+    if let Some(obj) = value.as_object() {
+        if obj.contains_key("error") && obj.contains_key("message") {
+            return Err(ApiError::from_script(obj));
+        }
+    }
+    state
+        .app
+        .state::<WebDriverState>()
+        .take_bytes(&result_id)
+        .ok_or_else(|| ApiError::internal("the bridge resolved without bytes"))
+}
+
+#[derive(Deserialize)]
+struct BinaryReq {
+    /// Answer with the bytes themselves instead of `{"data": base64}`.
+    #[serde(default)]
+    raw: bool,
+}
+
+#[derive(Deserialize)]
+struct ElemScreenshotReq {
+    selector: String,
+    index: usize,
+    #[serde(default)]
+    using: Option<String>,
+    #[serde(default)]
+    raw: bool,
+}
+
+/// Answer with `bytes`: as a `content_type` body if `raw`, else
+/// base64-encoded in `{"data"}`.
+fn binary_response(bytes: Vec<u8>, raw: bool, content_type: &'static str) -> Response {
+    use base64::Engine as _;
+
+    if raw {
+        return ([(axum::http::header::CONTENT_TYPE, content_type)], bytes).into_response();
+    }
+    let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+    Json(json!({ "data": data })).into_response()
+}
+
+/// A native snapshot of the current window's viewport, or of `rect` in it.
+/// `None` if the platform can't take one or it failed, for the caller to
+/// render the page itself instead.
+async fn native_snapshot<R: Runtime>(
+    state: &SharedState<R>,
+    rect: Option<snapshot::Rect>,
+) -> Result<Option<Vec<u8>>, ApiError> {
+    if !snapshot::SUPPORTED {
+        return Ok(None);
+    }
+    let window = state.window(None)?;
+    let _guard = state.lock_window(window.label()).await;
+    match snapshot::capture(&window, rect).await {
+        Ok(png) => Ok(Some(png)),
+        Err(e) => {
+            tracing::warn!("native snapshot failed, rendering the page instead: {e}");
+            Ok(None)
         }
     }
 }
 
 async fn screenshot<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
-    Json(_body): Json<Value>,
-) -> ApiResult {
+    Json(body): Json<BinaryReq>,
+) -> Result<Response, ApiError> {
+    if let Some(png) = native_snapshot(&state, None).await? {
+        return Ok(binary_response(png, body.raw, "image/png"));
+    }
     let script = r#"(function(){try{
 var el=document.documentElement;
 var w=Math.max(el.scrollWidth,el.clientWidth);
@@ -1030,35 +2020,65 @@ var svg='<svg xmlns="http://www.w3.org/2000/svg" width="'+w+'" height="'+h+'">'
 var c=document.createElement('canvas');c.width=w;c.height=h;
 var ctx=c.getContext('2d');var img=new Image();
 img.onload=function(){try{ctx.drawImage(img,0,0);
-var d=c.toDataURL('image/png').split(',')[1];
-window.__WEBDRIVER__.resolve("__CALLBACK_ID__",d)}
-catch(e){window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+__WEBDRIVER__.resolveCanvas("__CALLBACK_ID__",c)}
+catch(e){__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {error:"SecurityError",message:e.message,stacktrace:""})}};
-img.onerror=function(){window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+img.onerror=function(){__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {error:"ScreenshotError",message:"SVG render failed",stacktrace:""})};
 img.src='data:image/svg+xml;charset=utf-8,'+encodeURIComponent(svg)
-}catch(e){window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+}catch(e){__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {error:e.name,message:e.message,stacktrace:e.stack||""})}})()"#;
 
-    let result = eval_js_callback(&state, script).await?;
-    Ok(Json(json!({"data": result})))
+    let png = eval_js_bytes(&state, script).await?;
+    Ok(binary_response(png, body.raw, "image/png"))
 }
 
 async fn screenshot_element<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
-    Json(body): Json<ElemReq>,
-) -> ApiResult {
-    let find_fn = if body.using.as_deref() == Some("xpath") {
-        "findElementByXPath"
-    } else {
-        "findElement"
-    };
-    let sel_json = serde_json::to_string(&body.selector).unwrap();
+    Json(body): Json<ElemScreenshotReq>,
+) -> Result<Response, ApiError> {
+    if snapshot::SUPPORTED {
+        // The element's rect in the top-level viewport, once scrolled into
+        // view, counting the offsets of the frames it's in.
+        let rect = eval_on_element(
+            &state,
+            &body.selector,
+            body.index,
+            body.using.as_deref(),
+            "el.scrollIntoView({block:'nearest',inline:'nearest'});\
+             var r=el.getBoundingClientRect(),x=r.x,y=r.y,w=el.ownerDocument.defaultView;\
+             while(w&&w!==w.top){var f=null;try{f=w.frameElement}catch(e){}if(!f)break;\
+             var fr=f.getBoundingClientRect();x+=fr.x+f.clientLeft;y+=fr.y+f.clientTop;w=w.parent}\
+             return [x,y,r.width,r.height]",
+        )
+        .await?;
+        let rect: Vec<f64> = serde_json::from_value(rect).unwrap_or_default();
+        // An empty rect would snapshot the whole viewport.
+        if let [x, y, width, height] = rect[..] {
+            if width >= 1.0 && height >= 1.0 {
+                let rect = snapshot::Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                };
+                if let Some(png) = native_snapshot(&state, Some(rect)).await? {
+                    return Ok(binary_response(png, body.raw, "image/png"));
+                }
+            }
+        }
+    }
+    let target = element_expr(
+        "document",
+        &body.selector,
+        body.index,
+        body.using.as_deref(),
+    );
     let script = format!(
         r#"(function(){{try{{
-var tgt=window.__WEBDRIVER__.{find_fn}({sel_json},{index});
-if(!tgt){{window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
-{{error:"NoSuchElement",message:"element not found",stacktrace:""}});return}}
+var tgt={target};
+if(!tgt){{__WEBDRIVER__.resolve("__CALLBACK_ID__",
+{{error:"NoSuchElement",code:"staleElement",message:"element not found",stacktrace:""}});return}}
 var rect=tgt.getBoundingClientRect();
 var el=document.documentElement;
 var w=Math.max(el.scrollWidth,el.clientWidth);
@@ -1073,33 +2093,30 @@ var c=document.createElement('canvas');
 c.width=Math.ceil(rect.width);c.height=Math.ceil(rect.height);
 var ctx=c.getContext('2d');
 ctx.drawImage(fc,rect.x,rect.y,rect.width,rect.height,0,0,rect.width,rect.height);
-var d=c.toDataURL('image/png').split(',')[1];
-window.__WEBDRIVER__.resolve("__CALLBACK_ID__",d)}}
-catch(e){{window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+__WEBDRIVER__.resolveCanvas("__CALLBACK_ID__",c)}}
+catch(e){{__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {{error:"SecurityError",message:e.message,stacktrace:""}})}}}};
-img.onerror=function(){{window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+img.onerror=function(){{__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {{error:"ScreenshotError",message:"SVG render failed",stacktrace:""}})}};
 img.src='data:image/svg+xml;charset=utf-8,'+encodeURIComponent(svg)
-}}catch(e){{window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+}}catch(e){{__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {{error:e.name,message:e.message,stacktrace:e.stack||""}})}}}})()
-"#,
-        find_fn = find_fn,
-        sel_json = sel_json,
-        index = body.index,
+"#
     );
 
-    let result = eval_js_callback(&state, &script).await?;
-    Ok(Json(json!({"data": result})))
+    let png = eval_js_bytes(&state, &script).await?;
+    Ok(binary_response(png, body.raw, "image/png"))
 }
 
 // --- Print to PDF handler ---
 
 async fn print_page<R: Runtime>(
-    AxumState(state): AxumState<SharedState<R>>,
-    Json(_body): Json<Value>,
-) -> ApiResult {
-    // Render the page to a canvas (same SVG foreignObject approach as screenshots),
-    // then wrap the PNG image data in a minimal PDF 1.4 structure.
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<BinaryReq>,
+) -> Result<Response, ApiError> {
+    // Render the page to a canvas (same SVG foreignObject approach as
+    // screenshots) on white, since JPEG has no transparency, and wrap the
+    // JPEG in a one-page PDF here.
     let script = r#"(function(){try{
 var el=document.documentElement;
 var w=Math.max(el.scrollWidth,el.clientWidth);
@@ -1109,48 +2126,19 @@ var svg='<svg xmlns="http://www.w3.org/2000/svg" width="'+w+'" height="'+h+'">'
 +'<foreignObject width="100%" height="100%">'+xml+'</foreignObject></svg>';
 var c=document.createElement('canvas');c.width=w;c.height=h;
 var ctx=c.getContext('2d');var img=new Image();
-img.onload=function(){try{ctx.drawImage(img,0,0);
-var pngDataUrl=c.toDataURL('image/png');
-var pngB64=pngDataUrl.split(',')[1];
-var bin=atob(pngB64);var len=bin.length;
-var imgW=w;var imgH=h;
-var pageW=612;var pageH=792;
-var scaleX=pageW/imgW;var scaleY=pageH/imgH;
-var sc=Math.min(scaleX,scaleY);
-var dw=Math.round(imgW*sc);var dh=Math.round(imgH*sc);
-var objs=[];var offsets=[];
-function addObj(s){offsets.push(objs.join('').length);objs.push(s)}
-addObj('%PDF-1.4\n');
-addObj('1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n');
-addObj('2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 >>\nendobj\n');
-addObj('3 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 '+pageW+' '+pageH+'] /Contents 5 0 R /Resources << /XObject << /Img 4 0 R >> >> >>\nendobj\n');
-var imgStream='4 0 obj\n<< /Type /XObject /Subtype /Image /Width '+imgW+' /Height '+imgH+' /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /ASCIIHexDecode /Length '+(len*6+1)+' >>\nstream\n';
-var hexParts=[];for(var i=0;i<len;i++){
-var byte=bin.charCodeAt(i);
-hexParts.push(('0'+byte.toString(16)).slice(-2))}
-imgStream+=hexParts.join('')+'>\nendstream\nendobj\n';
-addObj(imgStream);
-var contentStr='q '+dw+' 0 0 '+dh+' 0 '+(pageH-dh)+' cm /Img Do Q';
-addObj('5 0 obj\n<< /Length '+contentStr.length+' >>\nstream\n'+contentStr+'\nendstream\nendobj\n');
-var body=objs.join('');
-var xrefOff=body.length;
-var xref='xref\n0 6\n0000000000 65535 f \n';
-for(var j=1;j<offsets.length;j++){
-xref+=('0000000000'+offsets[j]).slice(-10)+' 00000 n \n'}
-xref+='trailer\n<< /Size 6 /Root 1 0 R >>\nstartxref\n'+xrefOff+'\n%%EOF';
-var pdf=body+xref;
-var pdfB64=btoa(pdf);
-window.__WEBDRIVER__.resolve("__CALLBACK_ID__",pdfB64)}
-catch(e){window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+img.onload=function(){try{ctx.fillStyle='#fff';ctx.fillRect(0,0,w,h);ctx.drawImage(img,0,0);
+__WEBDRIVER__.resolveCanvas("__CALLBACK_ID__",c,'image/jpeg',0.92)}
+catch(e){__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {error:e.name,message:e.message,stacktrace:e.stack||""})}};
-img.onerror=function(){window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+img.onerror=function(){__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {error:"PrintError",message:"SVG render failed",stacktrace:""})};
 img.src='data:image/svg+xml;charset=utf-8,'+encodeURIComponent(svg)
-}catch(e){window.__WEBDRIVER__.resolve("__CALLBACK_ID__",
+}catch(e){__WEBDRIVER__.resolve("__CALLBACK_ID__",
 {error:e.name,message:e.message,stacktrace:e.stack||""})}})()"#;
 
-    let result = eval_js_callback(&state, script).await?;
-    Ok(Json(json!({"data": result})))
+    let jpeg = eval_js_bytes(&state, script).await?;
+    let pdf = pdf::from_jpeg(&jpeg).map_err(ApiError::internal)?;
+    Ok(binary_response(pdf, body.raw, "application/pdf"))
 }
 
 // --- Cookie handlers ---
@@ -1160,7 +2148,7 @@ async fn cookie_get_all<R: Runtime>(
     Json(_body): Json<Value>,
 ) -> ApiResult {
     let script = r#"
-var store = window.__WEBDRIVER__.cookies;
+var store = __WEBDRIVER__.cookies;
 var cookies = [];
 var keys = Object.keys(store);
 for (var i = 0; i < keys.length; i++) {
@@ -1178,8 +2166,9 @@ async fn cookie_get<R: Runtime>(
 ) -> ApiResult {
     let name_json = serde_json::to_string(&body.name).unwrap();
     let script = format!(
-        "var c=window.__WEBDRIVER__.cookies[{name_json}];\
-         return c||null"
+        "var s=__WEBDRIVER__.cookies;\
+         for(var k in s)if(s[k].name==={name_json})return s[k];\
+         return null"
     );
     let result = eval_js(&state, &script).await?;
     Ok(Json(json!({"cookie": result})))
@@ -1205,24 +2194,54 @@ async fn cookie_add<R: Runtime>(
     };
 
     let script = format!(
-        "window.__WEBDRIVER__.cookies[{name_json}]={{\
+        "var c={{\
          name:{name_json},value:{value_json},path:{path_json},\
          domain:{domain_json},secure:{secure},httpOnly:{http_only},\
          expiry:{expiry_js},sameSite:\"Lax\"\
-         }};return null"
+         }};\
+         return __WEBDRIVER__.cookies[__WEBDRIVER__.cookieKey(c)]=c"
     );
 
-    eval_js(&state, &script).await?;
+    let cookie = eval_js(&state, &script).await?;
+    // Kept for the documents that come after this one.
+    state
+        .app
+        .state::<WebDriverState>()
+        .cookies
+        .lock()
+        .expect("lock poisoned")
+        .insert(cookie_key(&cookie), cookie);
     Ok(Json(json!(null)))
 }
 
+/// Where a cookie is kept: its domain, path and name.
+fn cookie_key(cookie: &Value) -> (String, String, String) {
+    let field = |name: &str| cookie[name].as_str().unwrap_or_default().to_string();
+    (field("domain"), field("path"), field("name"))
+}
+
+/// Forget the cookies the page deleted, out of the `deleted` array a
+/// delete script returned, so later documents don't get them back.
+fn forget_cookies<R: Runtime>(state: &SharedState<R>, deleted: &Value) {
+    let ws = state.app.state::<WebDriverState>();
+    let mut cookies = ws.cookies.lock().expect("lock poisoned");
+    for cookie in deleted.as_array().into_iter().flatten() {
+        cookies.remove(&cookie_key(cookie));
+    }
+}
+
 async fn cookie_delete<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<CookieNameReq>,
 ) -> ApiResult {
     let name_json = serde_json::to_string(&body.name).unwrap();
-    let script = format!("delete window.__WEBDRIVER__.cookies[{name_json}];return null");
-    eval_js(&state, &script).await?;
+    let script = format!(
+        "var s=__WEBDRIVER__.cookies,d=[];\
+         for(var k in s)if(s[k].name==={name_json}){{d.push(s[k]);delete s[k];}}\
+         return d"
+    );
+    let deleted = eval_js(&state, &script).await?;
+    forget_cookies(&state, &deleted);
     Ok(Json(json!(null)))
 }
 
@@ -1230,15 +2249,25 @@ async fn cookie_delete_all<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(_body): Json<Value>,
 ) -> ApiResult {
-    let script = "var s=window.__WEBDRIVER__.cookies;\
-         var k=Object.keys(s);for(var i=0;i<k.length;i++)delete s[k[i]];\
-         return null";
-    eval_js(&state, script).await?;
+    let script = "var s=__WEBDRIVER__.cookies,d=[];\
+         for(var k in s){d.push(s[k]);delete s[k];}\
+         return d";
+    let deleted = eval_js(&state, script).await?;
+    forget_cookies(&state, &deleted);
     Ok(Json(json!(null)))
 }
 
 // --- Action handlers ---
 
+/// Interval, in ms, between the steps of pointer moves with a duration.
+const MOVE_STEP_MS: u64 = 16;
+
+/// Longest gap, in ms, between presses that still counts as a double click.
+const DOUBLE_CLICK_MS: u64 = 500;
+/// Farthest the pointer can move, in CSS pixels, between the presses of a
+/// double click.
+const DOUBLE_CLICK_SLOP: u64 = 4;
+
 async fn actions_perform<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<Value>,
@@ -1246,7 +2275,39 @@ async fn actions_perform<R: Runtime>(
     let action_sequences = body
         .get("actions")
         .and_then(|a| a.as_array())
-        .ok_or_else(|| ApiError::Internal("Missing 'actions' array".into()))?;
+        .ok_or_else(|| ApiError::new(ErrorCode::InvalidArgument, "Missing 'actions' array"))?;
+    // With `native`, pointer input is posted as OS events at the pointer's
+    // screen position (the page still tracks the position) and key input
+    // as OS key events to the focused window.
+    let native = body
+        .get("native")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if native {
+        if !native::SUPPORTED {
+            return Err(ApiError::new(
+                ErrorCode::InvalidArgument,
+                "native input is only supported on macOS",
+            ));
+        }
+        // Refuse keys with no key code before posting anything.
+        for seq in action_sequences.iter() {
+            if seq.get("type").and_then(|t| t.as_str()) != Some("key") {
+                continue;
+            }
+            let actions = seq.get("actions").and_then(|a| a.as_array());
+            for action in actions.into_iter().flatten() {
+                if let Some(keys) = action.get("value").and_then(|v| v.as_str()) {
+                    keys.chars().try_for_each(check_native_key)?;
+                }
+            }
+        }
+        // OS events go to whatever is under the pointer, so bring the
+        // window to the front first.
+        let _ = state.window(None)?.set_focus();
+    }
+    // Button held down by native input, which turns moves into drags.
+    let mut pressed: Option<u64> = None;
 
     // Determine the number of ticks (max length across all action sequences).
     let tick_count = action_sequences
@@ -1259,164 +2320,366 @@ async fn actions_perform<R: Runtime>(
         .max()
         .unwrap_or(0);
 
-    // Process each tick across all input sources.
+    // Process each tick across all input sources. A tick lasts as long as
+    // its longest action; pointer moves with a duration are spread over it
+    // in steps of about a frame.
     for tick_idx in 0..tick_count {
-        let mut js_parts: Vec<String> = Vec::new();
-        let mut pause_ms: u64 = 0;
-
-        for seq in action_sequences {
-            let source_type = seq.get("type").and_then(|t| t.as_str()).unwrap_or("null");
-            let actions_arr = match seq.get("actions").and_then(|a| a.as_array()) {
-                Some(a) => a,
-                None => continue,
-            };
-            let action = match actions_arr.get(tick_idx) {
-                Some(a) => a,
-                None => continue,
-            };
-            let action_type = action
-                .get("type")
-                .and_then(|t| t.as_str())
-                .unwrap_or("pause");
-
-            match (source_type, action_type) {
-                ("key", "keyDown") => {
-                    let key = action.get("value").and_then(|v| v.as_str()).unwrap_or("");
-                    let key_json = serde_json::to_string(key).unwrap();
-                    js_parts.push(format!(
-                        "(function(){{var k={key_json};\
-                         var code=k.length===1?'Key'+k.toUpperCase():k;\
-                         var tgt=document.activeElement||document.body;\
-                         tgt.dispatchEvent(new KeyboardEvent('keydown',\
-                         {{key:k,code:code,bubbles:true,cancelable:true}}))}})();"
-                    ));
-                }
-                ("key", "keyUp") => {
-                    let key = action.get("value").and_then(|v| v.as_str()).unwrap_or("");
-                    let key_json = serde_json::to_string(key).unwrap();
-                    js_parts.push(format!(
-                        "(function(){{var k={key_json};\
-                         var code=k.length===1?'Key'+k.toUpperCase():k;\
-                         var tgt=document.activeElement||document.body;\
-                         tgt.dispatchEvent(new KeyboardEvent('keyup',\
-                         {{key:k,code:code,bubbles:true,cancelable:true}}))}})();"
-                    ));
+        let tick_actions = || {
+            action_sequences
+                .iter()
+                .filter_map(move |seq| seq.get("actions")?.as_array()?.get(tick_idx))
+        };
+        let duration_of = |action: &Value| action.get("duration").and_then(|d| d.as_u64());
+        let tick_ms = tick_actions().filter_map(duration_of).max().unwrap_or(0);
+        let timed_move = tick_actions().any(|a| {
+            a.get("type").and_then(|t| t.as_str()) == Some("pointerMove")
+                && duration_of(a).unwrap_or(0) > 0
+        });
+        let steps = if timed_move {
+            (tick_ms / MOVE_STEP_MS).max(1)
+        } else {
+            1
+        };
+        let started = std::time::Instant::now();
+        for step in 1..=steps {
+            let mut js_parts: Vec<String> = Vec::new();
+            let mut native_events: Vec<(MouseAction, u64)> = Vec::new();
+
+            for seq in action_sequences {
+                let source_type = seq.get("type").and_then(|t| t.as_str()).unwrap_or("null");
+                let source_id = seq.get("id").and_then(|t| t.as_str()).unwrap_or("");
+                let source_json = serde_json::to_string(source_id).unwrap();
+                let pointer_type = seq
+                    .pointer("/parameters/pointerType")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or("mouse");
+                let actions_arr = match seq.get("actions").and_then(|a| a.as_array()) {
+                    Some(a) => a,
+                    None => continue,
+                };
+                let action = match actions_arr.get(tick_idx) {
+                    Some(a) => a,
+                    None => continue,
+                };
+                let action_type = action
+                    .get("type")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or("pause");
+                // A timed move's progress (0 to 1) by the end of `step`.
+                let duration = duration_of(action).unwrap_or(0);
+                let timed = action_type == "pointerMove" && duration > 0;
+                let progress = |step: u64| (tick_ms * step / steps) as f64 / duration as f64;
+                // Everything but timed moves happens at the start of the tick.
+                if step > 1 && !(timed && progress(step - 1) < 1.0) {
+                    continue;
                 }
-                ("pointer", "pointerMove") => {
-                    let x = action.get("x").and_then(|v| v.as_f64()).unwrap_or(0.0);
-                    let y = action.get("y").and_then(|v| v.as_f64()).unwrap_or(0.0);
-                    let origin = action
-                        .get("origin")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("viewport");
-
-                    // If origin is an element object, resolve its center.
-                    if let Some(origin_obj) = action.get("origin").and_then(|v| v.as_object()) {
-                        if let Some(elem) = origin_obj.values().next().and_then(|v| v.as_object()) {
-                            let sel = elem.get("selector").and_then(|s| s.as_str()).unwrap_or("");
-                            let idx = elem.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
-                            let sel_json = serde_json::to_string(sel).unwrap();
-                            js_parts.push(format!(
-                                "(function(){{var el=document.querySelectorAll({sel_json})[{idx}];\
-                                 if(el){{var r=el.getBoundingClientRect();\
-                                 window.__wdPointerX=r.x+r.width/2+{x};\
-                                 window.__wdPointerY=r.y+r.height/2+{y};}}}})();"
-                            ));
-                        }
-                    } else {
-                        match origin {
-                            "pointer" => {
-                                js_parts.push(format!(
-                                    "window.__wdPointerX=(window.__wdPointerX||0)+{x};\
-                                     window.__wdPointerY=(window.__wdPointerY||0)+{y};"
-                                ));
+                let motion = timed.then(|| (progress(step).min(1.0), step == 1));
+
+                match (source_type, action_type) {
+                    ("key", "keyDown") => {
+                        let key = action.get("value").and_then(|v| v.as_str()).unwrap_or("");
+                        if native {
+                            let mut modifiers =
+                                state.native_modifiers.lock().expect("lock poisoned");
+                            for key in key.chars() {
+                                post_key(&mut modifiers, key, true)?;
                             }
-                            _ => {
-                                // "viewport" or any other value
-                                js_parts.push(format!(
-                                    "window.__wdPointerX={x};window.__wdPointerY={y};"
-                                ));
+                            continue;
+                        }
+                        let key_json = serde_json::to_string(key).unwrap();
+                        js_parts.push(format!(
+                            "(function(){{var k={key_json};\
+                             var code=k.length===1?'Key'+k.toUpperCase():k;\
+                             var tgt=document.activeElement||document.body;\
+                             __WEBDRIVER__.setModifier(k,true);\
+                             tgt.dispatchEvent(new KeyboardEvent('keydown',__WEBDRIVER__.withModifiers(\
+                             {{key:k,code:code,bubbles:true,cancelable:true}})))}})();"
+                        ));
+                    }
+                    ("key", "keyUp") => {
+                        let key = action.get("value").and_then(|v| v.as_str()).unwrap_or("");
+                        if native {
+                            let mut modifiers =
+                                state.native_modifiers.lock().expect("lock poisoned");
+                            for key in key.chars() {
+                                post_key(&mut modifiers, key, false)?;
                             }
+                            continue;
                         }
+                        let key_json = serde_json::to_string(key).unwrap();
+                        js_parts.push(format!(
+                            "(function(){{var k={key_json};\
+                             var code=k.length===1?'Key'+k.toUpperCase():k;\
+                             var tgt=document.activeElement||document.body;\
+                             __WEBDRIVER__.setModifier(k,false);\
+                             tgt.dispatchEvent(new KeyboardEvent('keyup',__WEBDRIVER__.withModifiers(\
+                             {{key:k,code:code,bubbles:true,cancelable:true}})))}})();"
+                        ));
                     }
-
-                    // Dispatch mousemove event.
-                    js_parts.push(
-                        "(function(){var tgt=document.elementFromPoint(\
-                         window.__wdPointerX||0,window.__wdPointerY||0)||document.body;\
-                         tgt.dispatchEvent(new MouseEvent('mousemove',\
-                         {clientX:window.__wdPointerX||0,clientY:window.__wdPointerY||0,\
-                         bubbles:true,cancelable:true}))})();"
-                            .to_string(),
-                    );
-                }
-                ("pointer", "pointerDown") => {
-                    let button = action.get("button").and_then(|v| v.as_u64()).unwrap_or(0);
-                    js_parts.push(format!(
-                        "(function(){{var tgt=document.elementFromPoint(\
-                         window.__wdPointerX||0,window.__wdPointerY||0)||document.body;\
-                         tgt.dispatchEvent(new MouseEvent('mousedown',\
-                         {{clientX:window.__wdPointerX||0,clientY:window.__wdPointerY||0,\
-                         button:{button},bubbles:true,cancelable:true}}))}})();"
-                    ));
-                }
-                ("pointer", "pointerUp") => {
-                    let button = action.get("button").and_then(|v| v.as_u64()).unwrap_or(0);
-                    js_parts.push(format!(
-                        "(function(){{var tgt=document.elementFromPoint(\
-                         window.__wdPointerX||0,window.__wdPointerY||0)||document.body;\
-                         tgt.dispatchEvent(new MouseEvent('mouseup',\
-                         {{clientX:window.__wdPointerX||0,clientY:window.__wdPointerY||0,\
-                         button:{button},bubbles:true,cancelable:true}}));\
-                         tgt.dispatchEvent(new MouseEvent('click',\
-                         {{clientX:window.__wdPointerX||0,clientY:window.__wdPointerY||0,\
-                         button:{button},bubbles:true,cancelable:true}}))}})();"
-                    ));
-                }
-                ("wheel", "scroll") => {
-                    let x = action.get("x").and_then(|v| v.as_f64()).unwrap_or(0.0);
-                    let y = action.get("y").and_then(|v| v.as_f64()).unwrap_or(0.0);
-                    let delta_x = action.get("deltaX").and_then(|v| v.as_f64()).unwrap_or(0.0);
-                    let delta_y = action.get("deltaY").and_then(|v| v.as_f64()).unwrap_or(0.0);
-                    js_parts.push(format!(
-                        "(function(){{var tgt=document.elementFromPoint({x},{y})||document.body;\
-                         tgt.dispatchEvent(new WheelEvent('wheel',\
-                         {{clientX:{x},clientY:{y},deltaX:{delta_x},deltaY:{delta_y},\
-                         bubbles:true,cancelable:true}}))}})();"
-                    ));
-                }
-                (_, "pause") => {
-                    let d = action.get("duration").and_then(|v| v.as_u64()).unwrap_or(0);
-                    if d > pause_ms {
-                        pause_ms = d;
+                    ("pointer", "pointerMove" | "pointerDown" | "pointerUp")
+                        if pointer_type == "touch" && !native =>
+                    {
+                        js_parts.push(touch_action_js(&source_json, action_type, action, motion));
+                    }
+                    ("pointer", "pointerMove") => {
+                        let position = move_position_js(
+                            action,
+                            "[window.__wdPointerX||0,window.__wdPointerY||0]",
+                            &source_json,
+                            motion,
+                        );
+                        js_parts.push(format!(
+                            "(function(){{var p={position};\
+                             window.__wdPointerX=p[0];window.__wdPointerY=p[1]}})();"
+                        ));
+
+                        if native {
+                            native_events.push((MouseAction::Move, 0));
+                            continue;
+                        }
+                        // Dispatch hover and mousemove events, or continue an
+                        // HTML5 drag.
+                        js_parts.push(
+                            "(function(){var x=window.__wdPointerX||0,y=window.__wdPointerY||0;\
+                             var tgt=document.elementFromPoint(x,y)||document.body;\
+                             if(__WEBDRIVER__.dragMove(tgt,x,y))return;\
+                             __WEBDRIVER__.hover(tgt,x,y);\
+                             var o=__WEBDRIVER__.withModifiers(\
+                             {clientX:x,clientY:y,bubbles:true,cancelable:true,view:window});\
+                             __WEBDRIVER__.mousePointerEvent('pointermove',tgt,o);\
+                             tgt.dispatchEvent(new MouseEvent('mousemove',o))})();"
+                                .to_string(),
+                        );
+                    }
+                    ("pointer", "pointerDown") => {
+                        let button = action.get("button").and_then(|v| v.as_u64()).unwrap_or(0);
+                        // Count clicks per pointer source: a press of the same
+                        // button close in time and place to the last one
+                        // continues a double (or triple) click.
+                        js_parts.push(format!(
+                            "(function(){{var p=window.__wdClicks=window.__wdClicks||{{}};\
+                             var s=p[{source_json}]||{{n:0}},now=Date.now(),\
+                             x=window.__wdPointerX||0,y=window.__wdPointerY||0;\
+                             s.n=s.b==={button}&&now-s.t<={DOUBLE_CLICK_MS}\
+                             &&Math.abs(s.x-x)<={DOUBLE_CLICK_SLOP}&&Math.abs(s.y-y)<={DOUBLE_CLICK_SLOP}\
+                             ?s.n+1:1;\
+                             s.t=now;s.x=x;s.y=y;s.b={button};p[{source_json}]=s;\
+                             window.__wdClickCount=s.n}})();"
+                        ));
+                        if native {
+                            native_events.push((MouseAction::Down, button));
+                            continue;
+                        }
+                        // Pointer, mouse and focus as for a real press; a
+                        // canceled pointerdown suppresses mousedown. Secondary
+                        // presses open the context menu, as on macOS and Linux.
+                        js_parts.push(format!(
+                            "(function(){{var tgt=document.elementFromPoint(\
+                             window.__wdPointerX||0,window.__wdPointerY||0)||document.body;\
+                             var o=__WEBDRIVER__.withModifiers({{clientX:window.__wdPointerX||0,\
+                             clientY:window.__wdPointerY||0,button:{button},\
+                             detail:window.__wdClickCount,bubbles:true,cancelable:true,view:window}});\
+                             var armed=__WEBDRIVER__.mousePointerEvent('pointerdown',tgt,o)\
+                             &&tgt.dispatchEvent(new MouseEvent('mousedown',o));\
+                             if(armed)__WEBDRIVER__.focusFor(tgt);\
+                             if({button}===0&&armed)__WEBDRIVER__.dragPress(tgt,o.clientX,o.clientY);\
+                             if({button}===2)tgt.dispatchEvent(new MouseEvent('contextmenu',o))}})();"
+                        ));
+                    }
+                    ("pointer", "pointerUp") => {
+                        let button = action.get("button").and_then(|v| v.as_u64()).unwrap_or(0);
+                        js_parts.push(format!(
+                            "window.__wdClickCount=((window.__wdClicks||{{}})[{source_json}]||{{n:1}}).n;"
+                        ));
+                        if native {
+                            native_events.push((MouseAction::Up, button));
+                            continue;
+                        }
+                        // Only the primary button clicks; others fire auxclick.
+                        // Every second click of a run is a double click.
+                        js_parts.push(format!(
+                            "(function(){{var tgt=document.elementFromPoint(\
+                             window.__wdPointerX||0,window.__wdPointerY||0)||document.body;\
+                             var n=window.__wdClickCount;\
+                             if({button}===0&&__WEBDRIVER__.dragRelease(tgt,\
+                             window.__wdPointerX||0,window.__wdPointerY||0))return;\
+                             var o=__WEBDRIVER__.withModifiers({{clientX:window.__wdPointerX||0,\
+                             clientY:window.__wdPointerY||0,button:{button},\
+                             detail:n,bubbles:true,cancelable:true,view:window}});\
+                             __WEBDRIVER__.mousePointerEvent('pointerup',tgt,o);\
+                             tgt.dispatchEvent(new MouseEvent('mouseup',o));\
+                             tgt.dispatchEvent(new MouseEvent({button}===0?'click':'auxclick',o));\
+                             if({button}===0&&n%2===0)tgt.dispatchEvent(new MouseEvent('dblclick',o))}})();"
+                        ));
+                    }
+                    ("wheel", "scroll") => {
+                        let x = action.get("x").and_then(|v| v.as_f64()).unwrap_or(0.0);
+                        let y = action.get("y").and_then(|v| v.as_f64()).unwrap_or(0.0);
+                        let delta_x = action.get("deltaX").and_then(|v| v.as_f64()).unwrap_or(0.0);
+                        let delta_y = action.get("deltaY").and_then(|v| v.as_f64()).unwrap_or(0.0);
+                        js_parts.push(format!(
+                            "(function(){{var tgt=document.elementFromPoint({x},{y})||document.body;\
+                             tgt.dispatchEvent(new WheelEvent('wheel',__WEBDRIVER__.withModifiers(\
+                             {{clientX:{x},clientY:{y},deltaX:{delta_x},deltaY:{delta_y},\
+                             bubbles:true,cancelable:true}})))}})();"
+                        ));
                     }
+                    _ => {}
                 }
-                _ => {}
             }
-        }
 
-        // Execute the JS for this tick.
-        if !js_parts.is_empty() {
-            let combined = js_parts.join("");
-            let script = format!("{combined}return null");
-            eval_js(&state, &script).await?;
-        }
+            // Execute the JS for this step of the tick.
+            if !js_parts.is_empty() || !native_events.is_empty() {
+                let combined = js_parts.join("");
+                let script = format!(
+                    "{combined}return [window.__wdPointerX||0,window.__wdPointerY||0,\
+                     window.__wdClickCount||1]"
+                );
+                let pointer = eval_js(&state, &script).await?;
+                if !native_events.is_empty() {
+                    let window = state.window(None)?;
+                    let (x, y) = viewport_to_screen(
+                        &window,
+                        pointer[0].as_f64().unwrap_or(0.0),
+                        pointer[1].as_f64().unwrap_or(0.0),
+                    )?;
+                    for (action, button) in native_events {
+                        let held = match action {
+                            MouseAction::Move => pressed,
+                            MouseAction::Down | MouseAction::Up => Some(button),
+                        };
+                        let clicks = pointer[2].as_i64().unwrap_or(1);
+                        native::post_mouse(action, x, y, held, clicks)
+                            .map_err(ApiError::internal)?;
+                        match action {
+                            MouseAction::Down => pressed = Some(button),
+                            MouseAction::Up => pressed = None,
+                            MouseAction::Move => {}
+                        }
+                    }
+                }
+            }
 
-        // Apply pause duration for this tick.
-        if pause_ms > 0 {
-            tokio::time::sleep(Duration::from_millis(pause_ms)).await;
+            // Keep to the tick's schedule: step k ends k/steps of the way
+            // through it.
+            let due = Duration::from_millis(tick_ms * step / steps);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                tokio::time::sleep(wait).await;
+            }
         }
     }
 
     Ok(Json(json!(null)))
 }
 
+/// JS expression for the `[x, y]` viewport position a pointerMove `action`
+/// moves to, given the pointer's `current` position as a JS expression.
+fn pointer_position_js(action: &Value, current: &str) -> String {
+    let x = action.get("x").and_then(|v| v.as_f64()).unwrap_or(0.0);
+    let y = action.get("y").and_then(|v| v.as_f64()).unwrap_or(0.0);
+    // An element origin is relative to the element's center, scrolled into
+    // view first if the center is off screen; the pointer stays put if the
+    // element is gone.
+    if let Some(origin_obj) = action.get("origin").and_then(|v| v.as_object()) {
+        let Some(elem) = origin_obj.values().next().and_then(|v| v.as_object()) else {
+            return current.to_string();
+        };
+        let sel = elem.get("selector").and_then(|s| s.as_str()).unwrap_or("");
+        let idx = elem.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+        let using = elem.get("using").and_then(|u| u.as_str());
+        let el = element_expr("document", sel, idx as usize, using);
+        return format!(
+            "(function(){{var el={el};if(!el)return {current};\
+             var r=el.getBoundingClientRect(),de=document.documentElement;\
+             var cx=r.x+r.width/2,cy=r.y+r.height/2;\
+             if(cx<0||cy<0||cx>=de.clientWidth||cy>=de.clientHeight){{\
+             el.scrollIntoView({{block:'center',inline:'center'}});\
+             r=el.getBoundingClientRect()}}\
+             return [r.x+r.width/2+{x},r.y+r.height/2+{y}]}})()"
+        );
+    }
+    match action.get("origin").and_then(|v| v.as_str()) {
+        Some("pointer") => format!("(function(p){{return [p[0]+{x},p[1]+{y}]}})({current})"),
+        // "viewport" or any other value
+        _ => format!("[{x},{y}]"),
+    }
+}
+
+/// JS expression for the `[x, y]` position of a pointerMove `action` of
+/// the source `source_json`. `motion` is how far along a timed move is and
+/// whether this is its first step, or `None` for a move without duration.
+fn move_position_js(
+    action: &Value,
+    current: &str,
+    source_json: &str,
+    motion: Option<(f64, bool)>,
+) -> String {
+    let end = pointer_position_js(action, current);
+    let Some((t, first)) = motion else {
+        return end;
+    };
+    // The first step records where the move starts and ends, as the spec
+    // fixes both when the move begins; each step interpolates between them.
+    let record = if first {
+        format!("m[{source_json}]={{a:{current},b:{end}}};")
+    } else {
+        String::new()
+    };
+    format!(
+        "(function(){{var m=window.__wdMoves=window.__wdMoves||{{}};{record}\
+         var s=m[{source_json}];\
+         return [s.a[0]+(s.b[0]-s.a[0])*{t},s.a[1]+(s.b[1]-s.a[1])*{t}]}})()"
+    )
+}
+
+/// Script for a pointer action of the touch source `source_json`, which
+/// the bridge turns into pointer and touch events.
+fn touch_action_js(
+    source_json: &str,
+    action_type: &str,
+    action: &Value,
+    motion: Option<(f64, bool)>,
+) -> String {
+    let current = format!("__WEBDRIVER__.touchPosition({source_json})");
+    let (kind, position) = match action_type {
+        "pointerMove" => (
+            "move",
+            move_position_js(action, &current, source_json, motion),
+        ),
+        "pointerDown" => ("down", current),
+        _ => ("up", current),
+    };
+    format!(
+        "(function(){{var p={position};\
+         __WEBDRIVER__.touch('{kind}',{source_json},p[0],p[1],\
+         document.elementFromPoint(p[0],p[1])||document.body)}})();"
+    )
+}
+
+/// Screen point, in points, of viewport coordinates (`x`, `y`) in `window`.
+fn viewport_to_screen<R: Runtime>(
+    window: &tauri::WebviewWindow<R>,
+    x: f64,
+    y: f64,
+) -> Result<(f64, f64), ApiError> {
+    let scale = window
+        .scale_factor()
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let origin = window
+        .inner_position()
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .to_logical::<f64>(scale);
+    Ok((origin.x + x, origin.y + y))
+}
+
 async fn actions_release<R: Runtime>(
-    AxumState(_state): AxumState<SharedState<R>>,
+    AxumState(state): AxumState<SharedState<R>>,
     Json(_body): Json<Value>,
 ) -> ApiResult {
-    // Release all held keys and pointer buttons. Currently returns null
-    // as the plugin does not track pressed state across requests.
+    // Release modifier keys held by key actions. Other keys and pointer
+    // buttons aren't tracked across requests.
+    release_modifiers(&mut state.native_modifiers.lock().expect("lock poisoned"))?;
+    eval_js(&state, "__WEBDRIVER__.clearModifiers();return null").await?;
     Ok(Json(json!(null)))
 }
 
@@ -1452,30 +2715,22 @@ async fn shadow_find<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<ShadowFindReq>,
 ) -> ApiResult {
-    let host_find_fn = if body.host_using.as_deref() == Some("xpath") {
-        "findElementByXPath"
-    } else {
-        "findElement"
-    };
-    let host_sel_json = serde_json::to_string(&body.host_selector).unwrap();
+    let host = element_expr(
+        "document",
+        &body.host_selector,
+        body.host_index,
+        body.host_using.as_deref(),
+    );
     let val_json = serde_json::to_string(&body.value).unwrap();
 
     let script = format!(
-        "if(!window.__wdShadowCtr)window.__wdShadowCtr=0;\
-         var host=window.__WEBDRIVER__.{host_find_fn}({host_sel_json},{host_index});\
-         if(!host)throw new Error('host element not found');\
+        "var host={host};\
+         if(!host)__WEBDRIVER__.fail('staleElement','host element not found');\
          var sr=host.shadowRoot;\
-         if(!sr)throw new Error('no shadow root');\
+         if(!sr)__WEBDRIVER__.fail('noSuchShadowRoot','no shadow root');\
          var els=sr.querySelectorAll({val_json});\
-         var a=[];for(var i=0;i<els.length;i++){{\
-         var id='wds-'+(++window.__wdShadowCtr);\
-         window.__WEBDRIVER__.__shadowCache[id]=els[i];\
-         a.push({{selector:id,index:0,using:'shadow'}})}}\
-         return a",
-        host_find_fn = host_find_fn,
-        host_sel_json = host_sel_json,
-        host_index = body.host_index,
-        val_json = val_json,
+         var a=[];for(var i=0;i<els.length;i++)a.push(__WEBDRIVER__.elementRef(els[i]));\
+         return a"
     );
 
     let result = eval_js(&state, &script).await?;
@@ -1494,15 +2749,18 @@ async fn window_set_current<R: Runtime>(
     Json(body): Json<SwitchWindowReq>,
 ) -> ApiResult {
     // Validate window exists
-    let window = state
-        .app
-        .get_webview_window(&body.label)
-        .ok_or_else(|| ApiError::NotFound(format!("window '{}' not found", body.label)))?;
+    let window = window_by_label(&state.app, Some(&body.label)).ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::NoSuchWindow,
+            format!("window '{}' not found", body.label),
+        )
+    })?;
     // Focus the window (W3C spec: Switch To Window brings window to foreground)
     let _ = window.set_focus();
     // Reset frame stack (W3C spec: switching windows resets to top-level context)
     state.frame_stack.lock().expect("lock poisoned").clear();
-    *state.current_window_label.lock().expect("lock poisoned") = Some(body.label.clone());
+    let handle = state.app.state::<WebDriverState>().handle(window.label());
+    *state.current_window.lock().expect("lock poisoned") = CurrentWindow::Handle(handle);
     Ok(Json(json!(true)))
 }
 
@@ -1522,54 +2780,29 @@ async fn element_find_from<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(body): Json<FindFromReq>,
 ) -> ApiResult {
-    let parent_sel_json = serde_json::to_string(&body.parent_selector).unwrap();
     let val_json = serde_json::to_string(&body.value).unwrap();
-
-    // Find parent using document.querySelectorAll/evaluate directly
-    // (works in both frame and top-level contexts since eval_js shadows document).
-    let parent_js = if body.parent_using.as_deref() == Some("xpath") {
-        format!(
-            "var __xr=document.evaluate({sel},document,null,\
-             XPathResult.ORDERED_NODE_SNAPSHOT_TYPE,null);\
-             var parent=__xr.snapshotItem({idx});\
-             if(!parent)throw new Error('parent element not found');",
-            sel = parent_sel_json,
-            idx = body.parent_index,
-        )
-    } else {
-        format!(
-            "var parent=document.querySelectorAll({sel})[{idx}];\
-             if(!parent)throw new Error('parent element not found');",
-            sel = parent_sel_json,
-            idx = body.parent_index,
-        )
-    };
-
-    let child_js = if body.using == "xpath" {
+    let parent = element_expr(
+        "document",
+        &body.parent_selector,
+        body.parent_index,
+        body.parent_using.as_deref(),
+    );
+    // Matches are registered with the bridge rather than tagged in the DOM.
+    let matches = if body.using == "xpath" {
         format!(
-            "var r=document.evaluate({v},parent,null,XPathResult.ORDERED_NODE_SNAPSHOT_TYPE,null);\
-             var a=[];for(var i=0;i<r.snapshotLength;i++){{\
-             var e=r.snapshotItem(i);var id='wd-'+(++window.__wdFindFromCtr);\
-             e.setAttribute('data-wd-id',id);\
-             a.push({{selector:'[data-wd-id=\"'+id+'\"]',index:0}})}}\
-             return a",
-            v = val_json,
+            "var r=document.evaluate({val_json},parent,null,XPathResult.ORDERED_NODE_SNAPSHOT_TYPE,null);\
+             for(var i=0;i<r.snapshotLength;i++)a.push(__WEBDRIVER__.elementRef(r.snapshotItem(i)));"
         )
     } else {
         format!(
-            "var els=parent.querySelectorAll({v});\
-             var a=[];for(var i=0;i<els.length;i++){{\
-             var id='wd-'+(++window.__wdFindFromCtr);\
-             els[i].setAttribute('data-wd-id',id);\
-             a.push({{selector:'[data-wd-id=\"'+id+'\"]',index:0}})}}\
-             return a",
-            v = val_json,
+            "var els=parent.querySelectorAll({val_json});\
+             for(var i=0;i<els.length;i++)a.push(__WEBDRIVER__.elementRef(els[i]));"
         )
     };
-
     let script = format!(
-        "if(!window.__wdFindFromCtr)window.__wdFindFromCtr=0;\
-         {parent_js}{child_js}"
+        "var parent={parent};\
+         if(!parent)__WEBDRIVER__.fail('staleElement','parent element not found');\
+         var a=[];{matches}return a"
     );
 
     let result = eval_js(&state, &script).await?;
@@ -1633,13 +2866,264 @@ return ''"#;
     Ok(Json(json!({"label": result})))
 }
 
+// --- Wait handlers ---
+
+/// Extra time the plugin gives a wait to report its own timeout.
+const WAIT_MARGIN: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct WaitElementReq {
+    using: String,
+    value: String,
+    #[serde(default = "default_wait_condition")]
+    condition: String,
+    /// Regular expression for `text-matches`.
+    #[serde(default)]
+    pattern: Option<String>,
+    /// Attribute name and value for `attribute-equals`; a `null` value
+    /// waits for the attribute to be absent.
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    expected: Option<String>,
+    /// Milliseconds.
+    timeout: u64,
+}
+
+fn default_wait_condition() -> String {
+    "present".to_string()
+}
+
+/// Wait in the page for an element the locator finds to meet `condition`,
+/// re-checking as the document changes, and return its reference.
+async fn wait_element<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<WaitElementReq>,
+) -> ApiResult {
+    let missing = match body.condition.as_str() {
+        "present" | "displayed" | "enabled" => None,
+        "text-matches" => body.pattern.is_none().then_some("pattern"),
+        "attribute-equals" => body.name.is_none().then_some("name"),
+        other => {
+            return Err(ApiError::new(
+                ErrorCode::InvalidArgument,
+                format!("unknown condition: {other}"),
+            ))
+        }
+    };
+    if let Some(field) = missing {
+        return Err(ApiError::new(
+            ErrorCode::InvalidArgument,
+            format!("'{}' needs '{field}'", body.condition),
+        ));
+    }
+    let spec = json!({
+        "using": if body.using == "xpath" { "xpath" } else { "css" },
+        "value": body.value,
+        "condition": body.condition,
+        "pattern": body.pattern,
+        "name": body.name,
+        "expected": body.expected,
+        "timeout": body.timeout,
+    });
+    let value = run_wait(&state, body.timeout, None, |_| {
+        format!("__WEBDRIVER__.waitForElement(__doc,{spec},__done)")
+    })
+    .await?;
+    Ok(Json(value))
+}
+
+#[derive(Deserialize)]
+struct WaitIdleReq {
+    /// How long no fetch/XHR may have been in flight, in ms.
+    #[serde(default = "default_quiet_ms")]
+    quiet: u64,
+    /// Milliseconds.
+    timeout: u64,
+}
+
+fn default_quiet_ms() -> u64 {
+    500
+}
+
+/// Wait until the current window's document has loaded and its network has
+/// been quiet for `quiet` ms.
+async fn wait_idle<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<WaitIdleReq>,
+) -> ApiResult {
+    let value = run_wait(&state, body.timeout, None, |_| {
+        format!(
+            "__WEBDRIVER__.waitForIdle({},{},__done)",
+            body.quiet, body.timeout
+        )
+    })
+    .await?;
+    Ok(Json(value))
+}
+
+#[derive(Deserialize)]
+struct WaitScriptReq {
+    /// A function body, as for `/script/execute`.
+    script: String,
+    #[serde(default)]
+    args: Vec<Value>,
+    /// Milliseconds between evaluations.
+    #[serde(default = "default_wait_interval_ms")]
+    interval: u64,
+    /// Milliseconds.
+    timeout: u64,
+}
+
+fn default_wait_interval_ms() -> u64 {
+    100
+}
+
+/// Evaluate `script` every `interval` ms in the current window until it
+/// returns something truthy, and return that as `value`.
+async fn wait_script<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<WaitScriptReq>,
+) -> ApiResult {
+    let args = Value::Array(body.args);
+    let value = run_wait(&state, body.timeout, Some(&body.script), |function| {
+        format!(
+            "__WEBDRIVER__.waitForScript({function},{args},{},{},__doc,__done,__blocked)",
+            body.interval.max(1),
+            body.timeout
+        )
+    })
+    .await?;
+    Ok(Json(value))
+}
+
+/// Run the bridge wait `call` returns in the current window and return what
+/// it passes `__done`. `__doc` is the current frame's document. With
+/// `source`, `call` gets it as a function expression (see [`with_source`])
+/// and passes `__blocked` on to the bridge. The wait reports its own
+/// timeout; the plugin gives up `WAIT_MARGIN` after that. The window's eval
+/// lock is only held while the wait is dispatched.
+async fn run_wait<R: Runtime>(
+    state: &SharedState<R>,
+    timeout_ms: u64,
+    source: Option<&str>,
+    call: impl Fn(&str) -> String,
+) -> Result<Value, ApiError> {
+    let window = state.window(None)?;
+    let frame_prefix = match build_frame_prefix(state) {
+        prefix if prefix.is_empty() => "var __doc=document;".to_string(),
+        prefix => prefix,
+    };
+    let limit = Duration::from_millis(timeout_ms) + WAIT_MARGIN;
+    let wait = |function: String| {
+        let call = call(&function);
+        let frame_prefix = &frame_prefix;
+        eval_with_id(state, &window, "wait timed out", limit, true, move |id| {
+            format!(
+                "(function(){{try{{__WEBDRIVER__.checkDialog();{frame_prefix}\
+                 var __done=function(r){{__WEBDRIVER__.resolve({id},r)}};\
+                 var __blocked=function(){{__WEBDRIVER__.evalBlocked({id})}};{call}\
+                 }}catch(__e){{__WEBDRIVER__.resolve({id},__WEBDRIVER__.scriptError(__e))}}}})()"
+            )
+        })
+    };
+    let value = match source {
+        Some(source) => with_source(state, &window, source, wait).await?,
+        None => wait(String::new()).await?,
+    };
+    if let Some(obj) = value.as_object() {
+        if obj.contains_key("error") && obj.contains_key("message") {
+            return Err(ApiError::from_script(obj));
+        }
+    }
+    Ok(value)
+}
+
+// --- Selection handlers ---
+
+/// The element's selection: `{start, end, direction, text}`, as offsets
+/// into its value or text, or `null` offsets if the selection is elsewhere.
+async fn element_selection<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<ElemReq>,
+) -> ApiResult {
+    let result = eval_on_element(
+        &state,
+        &body.selector,
+        body.index,
+        body.using.as_deref(),
+        "return __WEBDRIVER__.getSelectionOf(el)",
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+#[derive(Deserialize)]
+struct SetSelectionReq {
+    selector: String,
+    index: usize,
+    #[serde(default)]
+    using: Option<String>,
+    start: u32,
+    end: u32,
+    /// `forward`, `backward` or `none` (the default).
+    #[serde(default)]
+    direction: Option<String>,
+}
+
+/// Focus the element and select `start..end` of its value or text; equal
+/// offsets place the caret.
+async fn element_set_selection<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(body): Json<SetSelectionReq>,
+) -> ApiResult {
+    let direction = body.direction.as_deref().unwrap_or("none");
+    if !["forward", "backward", "none"].contains(&direction) {
+        return Err(ApiError::new(
+            ErrorCode::InvalidArgument,
+            format!("unknown selection direction: {direction}"),
+        ));
+    }
+    if body.start > body.end {
+        return Err(ApiError::new(
+            ErrorCode::InvalidArgument,
+            "the selection's start is after its end",
+        ));
+    }
+    let js = format!(
+        "return __WEBDRIVER__.setSelectionOf(el,{},{},{})",
+        body.start,
+        body.end,
+        Value::String(direction.to_string())
+    );
+    let result = eval_on_element(
+        &state,
+        &body.selector,
+        body.index,
+        body.using.as_deref(),
+        &js,
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+/// The document selection (`getSelection()`) of the current frame:
+/// `{text, type}`.
+async fn selection<R: Runtime>(
+    AxumState(state): AxumState<SharedState<R>>,
+    Json(_body): Json<Value>,
+) -> ApiResult {
+    let result = eval_js(&state, "return __WEBDRIVER__.pageSelection(document)").await?;
+    Ok(Json(result))
+}
+
 // --- Active element handler ---
 
 async fn element_active<R: Runtime>(
     AxumState(state): AxumState<SharedState<R>>,
     Json(_body): Json<Value>,
 ) -> ApiResult {
-    let result = eval_js(&state, "return window.__WEBDRIVER__.getActiveElement()").await?;
+    let result = eval_js(&state, "return __WEBDRIVER__.getActiveElement()").await?;
     Ok(Json(json!({"element": result})))
 }
 
@@ -1679,6 +3163,7 @@ async fn frame_switch<R: Runtime>(
             .push(FrameRef {
                 selector: "iframe".to_string(),
                 index: index as usize,
+                using: None,
             });
         return Ok(Json(json!(null)));
     }
@@ -1688,18 +3173,28 @@ async fn frame_switch<R: Runtime>(
         let selector = obj
             .get("selector")
             .and_then(|s| s.as_str())
-            .ok_or_else(|| ApiError::Internal("frame element missing selector".into()))?
+            .ok_or_else(|| {
+                ApiError::new(ErrorCode::InvalidArgument, "frame element missing selector")
+            })?
             .to_string();
         let index = obj.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
+        let using = obj.get("using").and_then(|u| u.as_str()).map(String::from);
         state
             .frame_stack
             .lock()
             .expect("lock poisoned")
-            .push(FrameRef { selector, index });
+            .push(FrameRef {
+                selector,
+                index,
+                using,
+            });
         return Ok(Json(json!(null)));
     }
 
-    Err(ApiError::Internal("invalid frame id".into()))
+    Err(ApiError::new(
+        ErrorCode::InvalidArgument,
+        "invalid frame id",
+    ))
 }
 
 async fn frame_parent<R: Runtime>(
@@ -1713,17 +3208,141 @@ async fn frame_parent<R: Runtime>(
 
 // --- Server entry point ---
 
-pub(crate) async fn start<R: Runtime>(
-    app: tauri::AppHandle<R>,
-    _webview_created_rx: tokio::sync::broadcast::Receiver<tauri::WebviewWindow<R>>,
-) {
+/// Report a body over the limit as an error tauri-wd understands; axum's
+/// own rejection is a bare 413 with a plain-text body.
+fn payload_too_large(response: Response, limit: usize) -> Response {
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE {
+        return response;
+    }
+    ApiError::new(
+        ErrorCode::InvalidArgument,
+        format!("payload too large: request bodies are limited to {limit} bytes"),
+    )
+    .into_response()
+}
+
+/// Handle the request within its `X-Request-Id`, so errors and log lines
+/// can be matched with the tauri-wd command that caused them.
+async fn with_request_id(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    use tracing::Instrument as _;
+
+    let Some(id) = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(str::to_owned)
+    else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path().to_owned();
+    let span = tracing::info_span!("request", request_id = %id);
+    REQUEST_ID
+        .scope(id, async move {
+            let response = next.run(request).await;
+            if !response.status().is_success() {
+                tracing::debug!("{path} failed with status {}", response.status());
+            }
+            response
+        })
+        .instrument(span)
+        .await
+}
+
+/// Turn away requests a web page could have sent: any with an `Origin` or
+/// `Sec-Fetch-*` header and, on a loopback address, any from another host
+/// or naming one in `Host` (DNS rebinding).
+async fn check_origin(
+    loopback: bool,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<axum::extract::ConnectInfo<crate::peer::Connection>>()
+        .map(|info| info.0.peer);
+    let host = request
+        .headers()
+        .get(axum::http::header::HOST)
+        .map(|v| v.to_str().unwrap_or(""));
+    let refusal = if crate::peer::from_browser(request.headers()) {
+        Some("browser requests are not allowed")
+    } else if loopback && peer.is_some_and(|peer| !peer.ip().is_loopback()) {
+        Some("connections must come from this machine")
+    } else if loopback && host.is_some_and(|host| !crate::peer::is_loopback_host(host)) {
+        Some("the Host header must name a loopback address")
+    } else {
+        None
+    };
+    match refusal {
+        Some(message) => (StatusCode::FORBIDDEN, Json(json!({"error": message}))).into_response(),
+        None => next.run(request).await,
+    }
+}
+
+/// Reject connections from processes `Builder::peer_check` doesn't allow.
+async fn check_peer(
+    check: &crate::peer::PeerCheck,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let Some(connection) = request
+        .extensions()
+        .get::<axum::extract::ConnectInfo<crate::peer::Connection>>()
+        .map(|info| info.0.clone())
+    else {
+        return next.run(request).await;
+    };
+    match check.check(&connection).await {
+        Ok(()) => next.run(request).await,
+        Err(e) => {
+            tracing::warn!("webdriver request from {} refused: {e}", connection.peer);
+            (StatusCode::FORBIDDEN, Json(json!({"error": e}))).into_response()
+        }
+    }
+}
+
+/// Reject requests that don't carry the launch token.
+async fn check_token(
+    token: &str,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let sent = request
+        .headers()
+        .get("x-tauri-webdriver-token")
+        .and_then(|v| v.to_str().ok());
+    if sent == Some(token) {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "missing or invalid x-tauri-webdriver-token"})),
+        )
+            .into_response()
+    }
+}
+
+/// All plugin routes, with fresh window and frame tracking. Built once per
+/// app (see [`WebDriverState::router`]) and shared by the HTTP server and,
+/// without a socket, [`crate::Automation`].
+pub(crate) fn router<R: Runtime>(app: tauri::AppHandle<R>) -> Router {
     let state: SharedState<R> = Arc::new(ServerState {
         app,
-        current_window_label: std::sync::Mutex::new(None),
+        instance: uuid::Uuid::new_v4().to_string(),
+        current_window: std::sync::Mutex::new(CurrentWindow::Default),
         frame_stack: std::sync::Mutex::new(Vec::new()),
+        eval_locks: std::sync::Mutex::new(HashMap::new()),
+        native_modifiers: std::sync::Mutex::new(Modifiers::default()),
     });
 
-    let router = Router::new()
+    let api = Router::new()
+        // Diagnostics
+        .route("/info", post(info::<R>))
+        .route("/ready", post(ready::<R>))
         // Window
         .route("/window/handle", post(window_handle::<R>))
         .route("/window/handles", post(window_handles::<R>))
@@ -1736,6 +3355,8 @@ pub(crate) async fn start<R: Runtime>(
         .route("/window/insets", post(window_insets::<R>))
         .route("/window/set-current", post(window_set_current::<R>))
         .route("/window/new", post(window_new::<R>))
+        .route("/window/set-viewport", post(window_set_viewport::<R>))
+        .route("/window/reset-viewport", post(window_reset_viewport::<R>))
         // Elements
         .route("/element/find", post(element_find::<R>))
         .route("/element/text", post(element_text::<R>))
@@ -1744,21 +3365,36 @@ pub(crate) async fn start<R: Runtime>(
         .route("/element/tag", post(element_tag::<R>))
         .route("/element/rect", post(element_rect::<R>))
         .route("/element/click", post(element_click::<R>))
+        .route(
+            "/element/scroll-into-view",
+            post(element_scroll_into_view::<R>),
+        )
         .route("/element/clear", post(element_clear::<R>))
         .route("/element/send-keys", post(element_send_keys::<R>))
+        .route("/element/type", post(element_type::<R>))
         .route("/element/set-files", post(element_set_files::<R>))
+        .route("/file/chunk", post(file_chunk::<R>))
+        .route("/file/discard", post(file_discard::<R>))
         .route("/element/displayed", post(element_displayed::<R>))
         .route("/element/enabled", post(element_enabled::<R>))
         .route("/element/selected", post(element_selected::<R>))
         .route("/element/active", post(element_active::<R>))
         .route("/element/find-from", post(element_find_from::<R>))
         .route("/element/shadow", post(element_shadow::<R>))
+        .route("/element/release", post(element_release::<R>))
         .route("/shadow/find", post(shadow_find::<R>))
         .route("/element/computed-role", post(element_computed_role::<R>))
         .route("/element/computed-label", post(element_computed_label::<R>))
+        .route("/element/selection", post(element_selection::<R>))
+        .route("/element/set-selection", post(element_set_selection::<R>))
+        .route("/selection", post(selection::<R>))
+        .route("/wait/element", post(wait_element::<R>))
+        .route("/wait/idle", post(wait_idle::<R>))
+        .route("/wait/script", post(wait_script::<R>))
         // Scripts
         .route("/script/execute", post(script_execute::<R>))
         .route("/script/execute-async", post(script_execute_async::<R>))
+        .route("/script/cancel", post(script_cancel::<R>))
         // Navigation
         .route("/navigate/url", post(navigate_url::<R>))
         .route("/navigate/current", post(navigate_current::<R>))
@@ -1780,8 +3416,20 @@ pub(crate) async fn start<R: Runtime>(
         .route("/alert/dismiss", post(alert_dismiss::<R>))
         .route("/alert/accept", post(alert_accept::<R>))
         .route("/alert/send-text", post(alert_send_text::<R>))
+        .route("/alert/queue", post(alert_queue::<R>))
         // Page source
         .route("/source", post(get_source::<R>))
+        // Logs
+        .route("/log/console", post(log_console::<R>))
+        .route("/log/errors", post(log_errors::<R>))
+        .route("/log/network", post(log_network::<R>))
+        .route("/log/prints", post(log_prints::<R>))
+        .route("/script/preload", post(script_preload::<R>))
+        // Mocks
+        .route("/mock/set", post(mock_set::<R>))
+        .route("/popup/behavior", post(popup_behavior::<R>))
+        .route("/auth/basic", post(auth_basic))
+        .route("/shortcut/press", post(shortcut_press::<R>))
         // Print
         .route("/print", post(print_page::<R>))
         // Actions
@@ -1789,16 +3437,184 @@ pub(crate) async fn start<R: Runtime>(
         .route("/actions/release", post(actions_release::<R>))
         // Frames
         .route("/frame/switch", post(frame_switch::<R>))
-        .route("/frame/parent", post(frame_parent::<R>))
-        .with_state(state);
+        .route("/frame/parent", post(frame_parent::<R>));
+    Router::new()
+        .route("/api/version", get(api_version::<R>).post(api_version::<R>))
+        .route("/healthz", get(healthz::<R>))
+        .nest(&format!("/v{API_VERSION}"), api)
+        .with_state(state)
+}
+
+/// Where the server listens and how it reports its port (see `Builder`).
+pub(crate) struct ServerConfig {
+    /// Address to bind (`Builder::host`/`port`); `TAURI_WEBDRIVER_HOST`
+    /// overrides the host.
+    pub addr: std::net::SocketAddr,
+    /// Host reported to tauri-wd (`Builder::advertise_host`).
+    pub advertise_host: Option<String>,
+    pub announce: crate::Announce,
+    /// Largest request body, in bytes (`Builder::body_limit`).
+    pub body_limit: usize,
+    /// Check the process behind each connection (`Builder::peer_check`).
+    pub peer_check: bool,
+    /// App routes from `Builder::routes` / `Builder::command`.
+    pub extensions: Router,
+}
+
+pub(crate) async fn start<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    _webview_created_rx: tokio::sync::broadcast::Receiver<tauri::WebviewWindow<R>>,
+    config: ServerConfig,
+) {
+    // tauri-wd passes a random token per launch; requests must carry it.
+    let token = std::env::var("TAURI_WEBDRIVER_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    if !cfg!(debug_assertions) && token.is_none() {
+        tracing::warn!(
+            "webdriver plugin not started: release builds require TAURI_WEBDRIVER_TOKEN"
+        );
+        return;
+    }
+
+    // tauri-wd passes a file path so it doesn't depend on stdout alone, which
+    // may be buffered or interleaved with other app output.
+    let port_file = std::env::var_os("TAURI_WEBDRIVER_PORT_FILE").map(std::path::PathBuf::from);
+    // Android apps can't be launched with environment variables; tauri-wd
+    // reads the port from the cache directory with `adb shell run-as`.
+    #[cfg(target_os = "android")]
+    let port_file = port_file.or_else(|| {
+        app.path()
+            .app_cache_dir()
+            .ok()
+            .map(|dir| dir.join("tauri-webdriver-port"))
+    });
+
+    // In a container or on a remote CI host the server has to listen on
+    // another interface, and tell tauri-wd where to reach it.
+    let addr = match std::env::var("TAURI_WEBDRIVER_HOST") {
+        Ok(host) if !host.is_empty() => match host.parse() {
+            Ok(ip) => std::net::SocketAddr::new(ip, config.addr.port()),
+            Err(_) => {
+                tracing::warn!("ignoring TAURI_WEBDRIVER_HOST={host:?}: not an IP address");
+                config.addr
+            }
+        },
+        _ => config.addr,
+    };
+    // Off loopback, the Host and peer checks don't apply and anyone who can
+    // reach the port could run scripts in the app; only the token stops them.
+    if !addr.ip().is_loopback() && token.is_none() {
+        tracing::error!(
+            "webdriver plugin not started: listening on {} requires TAURI_WEBDRIVER_TOKEN",
+            addr.ip()
+        );
+        return;
+    }
+    let advertised = std::env::var("TAURI_WEBDRIVER_ADVERTISE_HOST")
+        .ok()
+        .filter(|host| !host.is_empty())
+        .or(config.advertise_host)
+        .unwrap_or_else(|| {
+            if addr.ip().is_unspecified() {
+                std::net::Ipv4Addr::LOCALHOST.to_string()
+            } else {
+                addr.ip().to_string()
+            }
+        });
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+    let router = app.state::<WebDriverState>().router.clone();
+    let extensions = config.extensions.fallback(|| async {
+        ApiError::new(
+            ErrorCode::UnknownCommand,
+            "no such app route; see Builder::routes",
+        )
+    });
+    let body_limit = config.body_limit;
+    let router = router
+        .nest(&format!("/v{API_VERSION}/ext"), extensions)
+        .layer(axum::middleware::map_response(
+            move |response: Response| async move { payload_too_large(response, body_limit) },
+        ))
+        .layer(axum::extract::DefaultBodyLimit::max(body_limit));
+    let router = match token {
+        Some(token) => {
+            let token: Arc<str> = token.into();
+            router.layer(axum::middleware::from_fn(
+                move |request: axum::extract::Request, next: axum::middleware::Next| {
+                    let token = token.clone();
+                    async move { check_token(&token, request, next).await }
+                },
+            ))
+        }
+        None => router,
+    };
+    let loopback = addr.ip().is_loopback();
+    let router = router
+        .layer(axum::middleware::from_fn(
+            move |request: axum::extract::Request, next: axum::middleware::Next| async move {
+                check_origin(loopback, request, next).await
+            },
+        ))
+        .layer(axum::middleware::from_fn(with_request_id));
+
+    let listener = tokio::net::TcpListener::bind(addr)
         .await
-        .expect("failed to bind webdriver plugin server");
+        .unwrap_or_else(|e| panic!("failed to bind webdriver plugin server to {}: {e}", addr));
     let port = listener.local_addr().unwrap().port();
-    println!("[webdriver] listening on port {}", port);
+    let router = match config
+        .peer_check
+        .then(crate::peer::PeerCheck::new)
+        .flatten()
+    {
+        Some(check) => {
+            let check = Arc::new(check);
+            router.layer(axum::middleware::from_fn(
+                move |request: axum::extract::Request, next: axum::middleware::Next| {
+                    let check = check.clone();
+                    async move { check_peer(&check, request, next).await }
+                },
+            ))
+        }
+        None => router,
+    };
+    // Older tauri-wd versions only understand a bare port, so the host is
+    // only added when it isn't the loopback address they assume.
+    let remote = advertised != std::net::Ipv4Addr::LOCALHOST.to_string();
+    match config.announce {
+        crate::Announce::Text if remote => {
+            println!("[webdriver] listening on port {port} host {advertised}")
+        }
+        crate::Announce::Text => println!("[webdriver] listening on port {}", port),
+        crate::Announce::Json => println!(
+            "{}",
+            json!({"webdriver": {"host": advertised, "port": port}})
+        ),
+        crate::Announce::Silent => {}
+    }
+    let report = if remote {
+        format!("{port}\n{advertised}")
+    } else {
+        port.to_string()
+    };
+    if let Some(path) = port_file {
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let written = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(&tmp, &report))
+            .and_then(|()| std::fs::rename(&tmp, &path));
+        if let Err(e) = written {
+            tracing::warn!("failed to write port file {}: {e}", path.display());
+        }
+    }
 
-    axum::serve(listener, router)
-        .await
-        .expect("webdriver plugin server error");
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<crate::peer::Connection>(),
+    )
+    .await
+    .expect("webdriver plugin server error");
 }
@@ -0,0 +1,419 @@
+// shortcut: menu accelerators and global shortcuts for `/shortcut/press`.
+//
+// DOM KeyboardEvents never leave the webview, so a test typing Cmd+N into
+// the page can't reach the native menu. Menu accelerators are fired through
+// the platform's own key-equivalent lookup instead: AppKit's
+// `performKeyEquivalent:` on the main menu on macOS, the window's GTK accel
+// groups on Linux. Windows menus (and, on every platform that can post
+// them, shortcuts registered with the OS) get real key events.
+
+// Mobile builds only parse accelerators to reject them.
+#![cfg_attr(
+    not(any(target_os = "macos", target_os = "linux", target_os = "windows")),
+    allow(dead_code)
+)]
+
+/// A key as accelerators name it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    /// A printable key, lowercased.
+    Char(char),
+    Enter,
+    Tab,
+    Escape,
+    Backspace,
+    Delete,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    ArrowUp,
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+    /// F1 to F24.
+    F(u8),
+}
+
+/// An accelerator in Tauri's format, e.g. `CmdOrCtrl+Shift+N`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Accelerator {
+    shift: bool,
+    control: bool,
+    alt: bool,
+    meta: bool,
+    key: Key,
+}
+
+impl Accelerator {
+    /// Parse `accelerator`: modifiers (`Shift`, `Ctrl`/`Control`,
+    /// `Alt`/`Option`, `Cmd`/`Command`/`Super`/`Meta`, `CmdOrCtrl`) and one
+    /// key, joined by `+`.
+    pub fn parse(accelerator: &str) -> Result<Self, String> {
+        let (modifiers, key) = match accelerator.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None => accelerator.rsplit_once('+').unwrap_or(("", accelerator)),
+        };
+        let mut parsed = Self {
+            shift: false,
+            control: false,
+            alt: false,
+            meta: false,
+            key: parse_key(key.trim())?,
+        };
+        for modifier in modifiers.split('+').filter(|m| !m.trim().is_empty()) {
+            match modifier.trim().to_ascii_lowercase().as_str() {
+                "shift" => parsed.shift = true,
+                "ctrl" | "control" => parsed.control = true,
+                "alt" | "option" => parsed.alt = true,
+                "cmd" | "command" | "super" | "meta" => parsed.meta = true,
+                "cmdorctrl" | "cmdorcontrol" | "commandorctrl" | "commandorcontrol" => {
+                    if cfg!(target_os = "macos") {
+                        parsed.meta = true;
+                    } else {
+                        parsed.control = true;
+                    }
+                }
+                _ => return Err(format!("unknown modifier '{modifier}' in '{accelerator}'")),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// The WebDriver keys (`U+E008` Shift and so on) of the modifiers.
+    #[cfg(target_os = "macos")]
+    fn modifier_keys(&self) -> Vec<char> {
+        [
+            (self.shift, '\u{E008}'),
+            (self.control, '\u{E009}'),
+            (self.alt, '\u{E00A}'),
+            (self.meta, '\u{E03D}'),
+        ]
+        .into_iter()
+        .filter_map(|(held, key)| held.then_some(key))
+        .collect()
+    }
+
+    /// The key as a WebDriver key, for posting it as a native key event.
+    #[cfg(target_os = "macos")]
+    fn webdriver_key(&self) -> Option<char> {
+        Some(match self.key {
+            Key::Char(c) => c,
+            Key::Enter => '\u{E007}',
+            Key::Tab => '\u{E004}',
+            Key::Escape => '\u{E00C}',
+            Key::Backspace => '\u{E003}',
+            Key::Delete => '\u{E017}',
+            Key::Home => '\u{E011}',
+            Key::End => '\u{E010}',
+            Key::PageUp => '\u{E00E}',
+            Key::PageDown => '\u{E00F}',
+            Key::ArrowLeft => '\u{E012}',
+            Key::ArrowUp => '\u{E013}',
+            Key::ArrowRight => '\u{E014}',
+            Key::ArrowDown => '\u{E015}',
+            Key::F(n @ 1..=12) => char::from_u32(0xE031 + u32::from(n) - 1)?,
+            Key::Insert => '\u{E016}',
+            Key::F(_) => return None,
+        })
+    }
+}
+
+fn parse_key(key: &str) -> Result<Key, String> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_graphic() {
+            return Ok(Key::Char(c.to_ascii_lowercase()));
+        }
+    }
+    let lower = key.to_ascii_lowercase();
+    Ok(match lower.as_str() {
+        "space" => Key::Char(' '),
+        "plus" => Key::Char('+'),
+        "enter" | "return" => Key::Enter,
+        "tab" => Key::Tab,
+        "escape" | "esc" => Key::Escape,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "up" | "arrowup" => Key::ArrowUp,
+        "down" | "arrowdown" => Key::ArrowDown,
+        "left" | "arrowleft" => Key::ArrowLeft,
+        "right" | "arrowright" => Key::ArrowRight,
+        _ => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            Some(n @ 1..=24) => Key::F(n),
+            _ if key.is_empty() => return Err("the accelerator has no key".into()),
+            _ => return Err(format!("unknown key '{key}'")),
+        },
+    })
+}
+
+/// Fire the menu item `accelerator` is the shortcut of in `window`'s menu
+/// (or the app menu). Returns whether one took it. Runs on the main thread.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub(crate) fn activate_menu<R: tauri::Runtime>(
+    window: &tauri::WebviewWindow<R>,
+    accelerator: &Accelerator,
+) -> Result<Option<bool>, String> {
+    platform::activate_menu(window, accelerator).map(Some)
+}
+
+/// On Windows the menu gets real key events, so nothing reports whether an
+/// item took them.
+#[cfg(target_os = "windows")]
+pub(crate) fn activate_menu<R: tauri::Runtime>(
+    window: &tauri::WebviewWindow<R>,
+    accelerator: &Accelerator,
+) -> Result<Option<bool>, String> {
+    window.set_focus().map_err(|e| e.to_string())?;
+    platform::send_input(accelerator).map(|()| None)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub(crate) fn activate_menu<R: tauri::Runtime>(
+    _window: &tauri::WebviewWindow<R>,
+    _accelerator: &Accelerator,
+) -> Result<Option<bool>, String> {
+    Err("menu accelerators are only supported on desktop".into())
+}
+
+/// Press `accelerator` as OS-level key events, which reach shortcuts
+/// registered with the OS (e.g. through the global-shortcut plugin).
+#[cfg(target_os = "macos")]
+pub(crate) fn post_keys(accelerator: &Accelerator) -> Result<(), String> {
+    use crate::native::{self, Modifiers};
+
+    let key = accelerator
+        .webdriver_key()
+        .ok_or("the key can't be posted on macOS")?;
+    // Refuse a key with no key code before any modifier goes down.
+    native::key_code(key)?;
+    let modifier_keys = accelerator.modifier_keys();
+    let mut modifiers = Modifiers::default();
+    for &m in &modifier_keys {
+        modifiers.update(m, true);
+        native::post_key(m, true, modifiers)?;
+    }
+    native::post_key(key, true, modifiers)?;
+    native::post_key(key, false, modifiers)?;
+    for &m in modifier_keys.iter().rev() {
+        modifiers.update(m, false);
+        native::post_key(m, false, modifiers)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn post_keys(accelerator: &Accelerator) -> Result<(), String> {
+    platform::send_input(accelerator)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub(crate) fn post_keys(_accelerator: &Accelerator) -> Result<(), String> {
+    Err("global shortcuts can only be pressed on macOS and Windows".into())
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use objc2::rc::Retained;
+    use objc2::runtime::AnyObject;
+    use objc2::{class, msg_send};
+    use objc2_foundation::{NSPoint, NSString};
+
+    use super::{Accelerator, Key};
+
+    /// `NSEventTypeKeyDown`.
+    const KEY_DOWN: usize = 10;
+
+    /// The menu key equivalent muda gives `key`.
+    fn key_equivalent(key: Key) -> String {
+        let c = match key {
+            Key::Char(c) => c,
+            Key::Enter => '\u{0003}',
+            Key::Tab => '⇥',
+            Key::Escape => '\u{001b}',
+            Key::Backspace => '\u{0008}',
+            Key::Delete => '\u{007f}',
+            Key::Insert => '\u{F727}',
+            Key::Home => '\u{F729}',
+            Key::End => '\u{F72B}',
+            Key::PageUp => '\u{F72C}',
+            Key::PageDown => '\u{F72D}',
+            Key::ArrowUp => '\u{F700}',
+            Key::ArrowDown => '\u{F701}',
+            Key::ArrowLeft => '\u{F702}',
+            Key::ArrowRight => '\u{F703}',
+            Key::F(n) => char::from_u32(0xF704 + u32::from(n) - 1).unwrap_or_default(),
+        };
+        c.to_string()
+    }
+
+    pub(super) fn activate_menu<R: tauri::Runtime>(
+        window: &tauri::WebviewWindow<R>,
+        accelerator: &Accelerator,
+    ) -> Result<bool, String> {
+        let ns_window = window.ns_window().map_err(|e| e.to_string())? as *const AnyObject;
+        let mut flags = 0usize;
+        for (held, flag) in [
+            (accelerator.shift, 1 << 17),
+            (accelerator.control, 1 << 18),
+            (accelerator.alt, 1 << 19),
+            (accelerator.meta, 1 << 20),
+        ] {
+            if held {
+                flags |= flag;
+            }
+        }
+        let characters = NSString::from_str(&key_equivalent(accelerator.key));
+        // SAFETY: we're on the main thread, `ns_window` is the live
+        // NSWindow, and the messages match AppKit's signatures.
+        unsafe {
+            let window_number: isize = msg_send![&*ns_window, windowNumber];
+            let event: Option<Retained<AnyObject>> = msg_send![
+                class!(NSEvent),
+                keyEventWithType: KEY_DOWN,
+                location: NSPoint::new(0.0, 0.0),
+                modifierFlags: flags,
+                timestamp: 0.0f64,
+                windowNumber: window_number,
+                context: std::ptr::null::<AnyObject>(),
+                characters: &*characters,
+                charactersIgnoringModifiers: &*characters,
+                isARepeat: false,
+                keyCode: 0u16
+            ];
+            let event = event.ok_or("failed to create the key event")?;
+            let app: Retained<AnyObject> = msg_send![class!(NSApplication), sharedApplication];
+            let menu: Option<Retained<AnyObject>> = msg_send![&*app, mainMenu];
+            let Some(menu) = menu else {
+                return Ok(false);
+            };
+            Ok(msg_send![&*menu, performKeyEquivalent: &*event])
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use gtk::gdk;
+
+    use super::{Accelerator, Key};
+
+    pub(super) fn activate_menu<R: tauri::Runtime>(
+        window: &tauri::WebviewWindow<R>,
+        accelerator: &Accelerator,
+    ) -> Result<bool, String> {
+        let keyval = match accelerator.key {
+            Key::Char(c) => gdk::keys::Key::from_unicode(c),
+            Key::F(n) => gdk::keys::Key::from_name(&format!("F{n}")),
+            key => gdk::keys::Key::from_name(match key {
+                Key::Enter => "Return",
+                Key::Tab => "Tab",
+                Key::Escape => "Escape",
+                Key::Backspace => "BackSpace",
+                Key::Delete => "Delete",
+                Key::Insert => "Insert",
+                Key::Home => "Home",
+                Key::End => "End",
+                Key::PageUp => "Page_Up",
+                Key::PageDown => "Page_Down",
+                Key::ArrowUp => "Up",
+                Key::ArrowDown => "Down",
+                Key::ArrowLeft => "Left",
+                _ => "Right",
+            }),
+        };
+        // The masks muda registers menu accelerators with.
+        let mut mods = gdk::ModifierType::empty();
+        mods.set(gdk::ModifierType::SHIFT_MASK, accelerator.shift);
+        mods.set(gdk::ModifierType::CONTROL_MASK, accelerator.control);
+        mods.set(gdk::ModifierType::MOD1_MASK, accelerator.alt);
+        mods.set(gdk::ModifierType::META_MASK, accelerator.meta);
+        let gtk_window = window.gtk_window().map_err(|e| e.to_string())?;
+        Ok(gtk::accel_groups_activate(&gtk_window, *keyval, mods))
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP,
+        VIRTUAL_KEY,
+    };
+
+    use super::{Accelerator, Key};
+
+    /// Press `accelerator` with `SendInput`: key events for the foreground
+    /// window, and for hotkeys registered with the OS.
+    pub(super) fn send_input(accelerator: &Accelerator) -> Result<(), String> {
+        let key = match accelerator.key {
+            Key::Char(c @ ('a'..='z' | '0'..='9' | ' ')) => c.to_ascii_uppercase() as u16,
+            Key::Char(c) => match c {
+                ';' => 0xBA,
+                '=' | '+' => 0xBB,
+                ',' => 0xBC,
+                '-' => 0xBD,
+                '.' => 0xBE,
+                '/' => 0xBF,
+                '`' => 0xC0,
+                '[' => 0xDB,
+                '\\' => 0xDC,
+                ']' => 0xDD,
+                '\'' => 0xDE,
+                _ => return Err(format!("'{c}' has no key on the US layout")),
+            },
+            Key::Enter => 0x0D,
+            Key::Tab => 0x09,
+            Key::Escape => 0x1B,
+            Key::Backspace => 0x08,
+            Key::Delete => 0x2E,
+            Key::Insert => 0x2D,
+            Key::Home => 0x24,
+            Key::End => 0x23,
+            Key::PageUp => 0x21,
+            Key::PageDown => 0x22,
+            Key::ArrowLeft => 0x25,
+            Key::ArrowUp => 0x26,
+            Key::ArrowRight => 0x27,
+            Key::ArrowDown => 0x28,
+            Key::F(n) => 0x70 + u16::from(n) - 1,
+        };
+        // Shift, Control, Alt (VK_MENU) and the Windows key, then the key.
+        let mut keys: Vec<u16> = [
+            (accelerator.shift, 0x10),
+            (accelerator.control, 0x11),
+            (accelerator.alt, 0x12),
+            (accelerator.meta, 0x5B),
+        ]
+        .into_iter()
+        .filter_map(|(held, vk)| held.then_some(vk))
+        .collect();
+        keys.push(key);
+        let input = |vk: u16, flags: KEYBD_EVENT_FLAGS| INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: VIRTUAL_KEY(vk),
+                    dwFlags: flags,
+                    ..Default::default()
+                },
+            },
+        };
+        let inputs: Vec<INPUT> = keys
+            .iter()
+            .map(|&vk| input(vk, KEYBD_EVENT_FLAGS(0)))
+            .chain(keys.iter().rev().map(|&vk| input(vk, KEYEVENTF_KEYUP)))
+            .collect();
+        // SAFETY: `inputs` are keyboard inputs of the size passed.
+        let sent = unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) };
+        if sent as usize != inputs.len() {
+            return Err("SendInput was blocked".into());
+        }
+        Ok(())
+    }
+}
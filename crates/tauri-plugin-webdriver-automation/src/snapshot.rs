@@ -0,0 +1,139 @@
+// snapshot: screenshots taken by the webview itself.
+//
+// The bridge renders screenshots by drawing the DOM serialized into an SVG
+// foreignObject, which loses cross-origin images, web fonts and canvas
+// content, and is slow on large pages. Where the platform webview can
+// snapshot its own rendering (WKWebView's takeSnapshot on macOS), the
+// screenshot routes use that, and only fall back to the SVG rendering when
+// it's unavailable or fails.
+
+// Elsewhere the rect is only passed along to be refused.
+#![cfg_attr(not(target_os = "macos"), allow(dead_code))]
+
+use std::time::Duration;
+
+use tauri::Runtime;
+
+/// Whether this platform's webview can be snapshotted natively.
+pub(crate) const SUPPORTED: bool = cfg!(target_os = "macos");
+
+/// How long the webview has to deliver a snapshot.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A rectangle of the webview's viewport, in CSS pixels.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+type Sender = tokio::sync::oneshot::Sender<Result<Vec<u8>, String>>;
+
+/// Snapshot `rect` of `window`'s webview, or its whole viewport, as PNG.
+pub(crate) async fn capture<R: Runtime>(
+    window: &tauri::WebviewWindow<R>,
+    rect: Option<Rect>,
+) -> Result<Vec<u8>, String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    window
+        .with_webview(move |webview| platform::capture(&webview, rect, tx))
+        .map_err(|e| e.to_string())?;
+    tokio::time::timeout(SNAPSHOT_TIMEOUT, rx)
+        .await
+        .map_err(|_| "the snapshot timed out".to_string())?
+        .map_err(|_| "the webview dropped the snapshot".to_string())?
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::sync::Mutex;
+
+    use block2::RcBlock;
+    use objc2::rc::Retained;
+    use objc2::runtime::AnyObject;
+    use objc2::{class, msg_send};
+    use objc2_foundation::{NSData, NSPoint, NSRect, NSSize, NSString};
+
+    use super::{Rect, Sender};
+
+    /// `NSBitmapImageFileTypePNG`.
+    const PNG_FILE_TYPE: usize = 4;
+
+    pub(super) fn capture(
+        webview: &tauri::webview::PlatformWebview,
+        rect: Option<Rect>,
+        tx: Sender,
+    ) {
+        // SAFETY: `inner` is the live WKWebView, and we're on the main thread.
+        let wk_webview = unsafe { &*(webview.inner() as *const AnyObject) };
+        let config: Retained<AnyObject> =
+            unsafe { msg_send![class!(WKSnapshotConfiguration), new] };
+        if let Some(r) = rect {
+            let rect = NSRect::new(NSPoint::new(r.x, r.y), NSSize::new(r.width, r.height));
+            let _: () = unsafe { msg_send![&*config, setRect: rect] };
+        }
+        // The handler is a Fn block; it's only ever called once.
+        let tx = Mutex::new(Some(tx));
+        let handler = RcBlock::new(move |image: *mut AnyObject, error: *mut AnyObject| {
+            let Some(tx) = tx.lock().expect("lock poisoned").take() else {
+                return;
+            };
+            // SAFETY: WebKit passes a live NSImage, or nil and an NSError.
+            let result = match unsafe { image.as_ref() } {
+                Some(image) => png(image),
+                None => Err(describe(unsafe { error.as_ref() })),
+            };
+            let _ = tx.send(result);
+        });
+        // SAFETY: the configuration and handler match the method's
+        // signature, and WebKit copies the handler.
+        let _: () = unsafe {
+            msg_send![
+                wk_webview,
+                takeSnapshotWithConfiguration: &*config,
+                completionHandler: &*handler
+            ]
+        };
+    }
+
+    /// The PNG encoding of an NSImage.
+    fn png(image: &AnyObject) -> Result<Vec<u8>, String> {
+        // SAFETY: plain AppKit calls on live objects, on the main thread.
+        unsafe {
+            let tiff: Option<Retained<NSData>> = msg_send![image, TIFFRepresentation];
+            let tiff = tiff.ok_or("the snapshot has no bitmap")?;
+            let rep: Option<Retained<AnyObject>> =
+                msg_send![class!(NSBitmapImageRep), imageRepWithData: &*tiff];
+            let rep = rep.ok_or("the snapshot has no bitmap")?;
+            let properties: Retained<AnyObject> = msg_send![class!(NSDictionary), dictionary];
+            let data: Option<Retained<NSData>> = msg_send![
+                &*rep,
+                representationUsingType: PNG_FILE_TYPE,
+                properties: &*properties
+            ];
+            Ok(data.ok_or("the snapshot could not be encoded")?.to_vec())
+        }
+    }
+
+    fn describe(error: Option<&AnyObject>) -> String {
+        let Some(error) = error else {
+            return "the snapshot failed".into();
+        };
+        // SAFETY: `error` is an NSError.
+        let description: Retained<NSString> = unsafe { msg_send![error, localizedDescription] };
+        format!("the snapshot failed: {description}")
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod platform {
+    use super::{Rect, Sender};
+
+    pub(super) fn capture(_: &tauri::webview::PlatformWebview, _: Option<Rect>, tx: Sender) {
+        let _ = tx.send(Err(
+            "native snapshots are only supported on macOS".to_string()
+        ));
+    }
+}
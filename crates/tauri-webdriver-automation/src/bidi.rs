@@ -0,0 +1,569 @@
+// bidi: a minimal WebDriver BiDi channel over WebSocket.
+//
+// Sessions created with `webSocketUrl: true` get a `ws://host/session/{id}`
+// URL in their capabilities. Commands arrive as `{id, method, params}` and
+// are answered with `success`/`error` messages; subscribed events are pushed
+// as `event` messages. Console entries and uncaught exceptions are polled
+// from the plugin and forwarded as `log.entryAdded`. The script module runs
+// on the plugin's async script endpoint; each window (browsing context) has
+// exactly one realm, `realm-{handle}`.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State as AxumState};
+use axum::response::{IntoResponse, Response};
+use serde_json::{json, Value};
+
+use crate::{plugin_post, SharedState, W3cError};
+
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub(crate) async fn connect(
+    ws: WebSocketUpgrade,
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> Response {
+    let enabled = match state.find_session(&sid).await {
+        Some(session) => session.read().await.bidi,
+        None => false,
+    };
+    if !enabled {
+        return W3cError::no_session().into_response();
+    }
+    ws.on_upgrade(move |socket| serve(socket, state, sid))
+}
+
+#[derive(Default)]
+struct Channel {
+    subscriptions: HashSet<String>,
+    /// (document id, last seen sequence number) for console polling.
+    log_cursor: Option<(Option<String>, u64)>,
+}
+
+impl Channel {
+    /// Whether `event` is covered by a subscription to it or to its module.
+    fn subscribed(&self, event: &str) -> bool {
+        let module = event.split('.').next().unwrap_or(event);
+        self.subscriptions.contains(event) || self.subscriptions.contains(module)
+    }
+}
+
+/// Error raised while handling a BiDi command.
+struct BidiError {
+    error: &'static str,
+    message: String,
+}
+
+impl BidiError {
+    fn new(error: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            error,
+            message: message.into(),
+        }
+    }
+    fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new("invalid argument", message)
+    }
+}
+
+impl From<W3cError> for BidiError {
+    fn from(e: W3cError) -> Self {
+        Self {
+            error: "unknown error",
+            message: e.message,
+        }
+    }
+}
+
+async fn serve(mut socket: WebSocket, state: SharedState, sid: String) {
+    tracing::info!("BiDi channel opened for session {sid}");
+    let mut channel = Channel::default();
+    let mut poll = tokio::time::interval(LOG_POLL_INTERVAL);
+    poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => continue,
+                };
+                let reply = handle_message(&state, &sid, &mut channel, text.as_str()).await;
+                if socket.send(Message::Text(reply.to_string().into())).await.is_err() {
+                    break;
+                }
+            }
+            _ = poll.tick(), if channel.subscribed("log.entryAdded") => {
+                let Some(entries) = poll_log_entries(&state, &sid, &mut channel).await else {
+                    break;
+                };
+                for params in entries {
+                    let event = json!({
+                        "type": "event",
+                        "method": "log.entryAdded",
+                        "params": params,
+                    });
+                    if socket.send(Message::Text(event.to_string().into())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+    tracing::info!("BiDi channel closed for session {sid}");
+}
+
+async fn handle_message(
+    state: &SharedState,
+    sid: &str,
+    channel: &mut Channel,
+    text: &str,
+) -> Value {
+    let Ok(command) = serde_json::from_str::<Value>(text) else {
+        return json!({
+            "type": "error",
+            "id": null,
+            "error": "invalid argument",
+            "message": "Message is not valid JSON",
+        });
+    };
+    let id = command.get("id").cloned().unwrap_or(Value::Null);
+    let method = command.get("method").and_then(|m| m.as_str()).unwrap_or("");
+    let params = command.get("params").cloned().unwrap_or_else(|| json!({}));
+
+    let result = if !id.is_u64() {
+        Err(BidiError::invalid_argument(
+            "id must be a non-negative integer",
+        ))
+    } else {
+        // Wait for HTTP commands on the session, as they would for each other.
+        let gate = state.command_gate(sid).await;
+        let _pass = match gate {
+            Some((gate, _)) => Some(gate.write_owned().await),
+            None => None,
+        };
+        dispatch(state, sid, channel, method, &params).await
+    };
+    match result {
+        Ok(result) => json!({"type": "success", "id": id, "result": result}),
+        Err(e) => json!({
+            "type": "error",
+            "id": id,
+            "error": e.error,
+            "message": e.message,
+        }),
+    }
+}
+
+async fn dispatch(
+    state: &SharedState,
+    sid: &str,
+    channel: &mut Channel,
+    method: &str,
+    params: &Value,
+) -> Result<Value, BidiError> {
+    match method {
+        "session.status" => Ok(json!({"ready": false, "message": "session already started"})),
+        "session.subscribe" => {
+            let events = event_names(params)?;
+            let was_logging = channel.subscribed("log.entryAdded");
+            channel.subscriptions.extend(events);
+            if !was_logging && channel.subscribed("log.entryAdded") {
+                // Only entries logged after subscribing are reported.
+                channel.log_cursor = None;
+                poll_log_entries(state, sid, channel).await;
+            }
+            Ok(json!({"subscription": uuid::Uuid::new_v4().to_string()}))
+        }
+        "session.unsubscribe" => {
+            for event in event_names(params)? {
+                channel.subscriptions.remove(&event);
+            }
+            Ok(json!({}))
+        }
+        "script.evaluate" => script_evaluate(state, sid, params).await,
+        "script.callFunction" => script_call_function(state, sid, params).await,
+        "script.addPreloadScript" => script_add_preload(state, sid, params).await,
+        "script.removePreloadScript" => script_remove_preload(state, sid, params).await,
+        "script.getRealms" => script_get_realms(state, sid, params).await,
+        // Values are never held on the driver side, so there is nothing to release.
+        "script.disown" => Ok(json!({})),
+        _ => Err(BidiError::new(
+            "unknown command",
+            format!("Unknown command {method}"),
+        )),
+    }
+}
+
+// --- script module ---
+
+fn string_param<'a>(params: &'a Value, key: &str) -> Result<&'a str, BidiError> {
+    params
+        .get(key)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| BidiError::invalid_argument(format!("{key} must be a string")))
+}
+
+/// Window label addressed by a `script.Target`.
+fn target_window(params: &Value) -> Result<String, BidiError> {
+    let target = params
+        .get("target")
+        .ok_or_else(|| BidiError::invalid_argument("target is required"))?;
+    if target.get("sandbox").is_some() {
+        return Err(BidiError::new(
+            "unsupported operation",
+            "Sandboxed realms are not supported",
+        ));
+    }
+    if let Some(context) = target.get("context").and_then(|c| c.as_str()) {
+        return Ok(context.to_string());
+    }
+    if let Some(realm) = target.get("realm").and_then(|r| r.as_str()) {
+        return realm
+            .strip_prefix("realm-")
+            .map(String::from)
+            .ok_or_else(|| BidiError::new("no such frame", format!("Unknown realm {realm}")));
+    }
+    Err(BidiError::invalid_argument(
+        "target must have a context or realm",
+    ))
+}
+
+/// `serializationOptions.maxObjectDepth` as a JS literal (`null` = unlimited).
+fn max_depth(params: &Value) -> String {
+    params
+        .pointer("/serializationOptions/maxObjectDepth")
+        .and_then(|d| d.as_u64())
+        .map_or_else(|| "null".to_string(), |d| d.to_string())
+}
+
+/// Run a script built around `__WEBDRIVER__.bidiRun` in `window` and
+/// return its `script.EvaluateResult`.
+async fn run_script(
+    state: &SharedState,
+    sid: &str,
+    window: &str,
+    script: String,
+) -> Result<Value, BidiError> {
+    let session = state
+        .find_session(sid)
+        .await
+        .ok_or_else(|| BidiError::new("invalid session id", "No active session"))?;
+    let guard = session.read().await;
+    let session = &*guard;
+    let result = plugin_post(
+        session,
+        "/script/execute-async",
+        json!({"script": script, "args": [], "window": window}),
+    )
+    .await
+    .map_err(|e| {
+        if e.error == "no such window" {
+            BidiError::new("no such frame", format!("No browsing context {window}"))
+        } else {
+            BidiError::from(e)
+        }
+    })?;
+    let mut value = result.get("value").cloned().unwrap_or(Value::Null);
+    if value.get("type").and_then(|t| t.as_str()) == Some("error") {
+        let message = value
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("invalid argument");
+        let error = if message.starts_with("no such node") {
+            "no such node"
+        } else {
+            "invalid argument"
+        };
+        return Err(BidiError::new(error, message));
+    }
+    value["realm"] = json!(format!("realm-{window}"));
+    Ok(value)
+}
+
+async fn script_evaluate(
+    state: &SharedState,
+    sid: &str,
+    params: &Value,
+) -> Result<Value, BidiError> {
+    let expression = serde_json::to_string(string_param(params, "expression")?).unwrap();
+    let await_promise = params
+        .get("awaitPromise")
+        .and_then(|a| a.as_bool())
+        .unwrap_or(false);
+    let window = target_window(params)?;
+    let script = format!(
+        "var __done=arguments[arguments.length-1];\
+         __WEBDRIVER__.bidiRun(function(){{return (0,eval)({expression})}},\
+         {await_promise},{depth},__done);",
+        depth = max_depth(params),
+    );
+    run_script(state, sid, &window, script).await
+}
+
+async fn script_call_function(
+    state: &SharedState,
+    sid: &str,
+    params: &Value,
+) -> Result<Value, BidiError> {
+    let declaration = serde_json::to_string(string_param(params, "functionDeclaration")?).unwrap();
+    let await_promise = params
+        .get("awaitPromise")
+        .and_then(|a| a.as_bool())
+        .unwrap_or(false);
+    let args = params
+        .get("arguments")
+        .cloned()
+        .unwrap_or_else(|| json!([]));
+    if !args.is_array() {
+        return Err(BidiError::invalid_argument("arguments must be an array"));
+    }
+    let this = params.get("this").cloned().unwrap_or(Value::Null);
+    let window = target_window(params)?;
+    let script = format!(
+        "var __done=arguments[arguments.length-1];var __w=__WEBDRIVER__;var __a,__t;\
+         try{{__a={args}.map(__w.fromLocalValue);\
+         __t={this}===null?undefined:__w.fromLocalValue({this})}}\
+         catch(e){{__done({{type:\"error\",message:String(e.message)}});return}}\
+         __w.bidiRun(function(){{return (0,eval)(\"(\"+{declaration}+\")\").apply(__t,__a)}},\
+         {await_promise},{depth},__done);",
+        depth = max_depth(params),
+    );
+    run_script(state, sid, &window, script).await
+}
+
+async fn script_add_preload(
+    state: &SharedState,
+    sid: &str,
+    params: &Value,
+) -> Result<Value, BidiError> {
+    let declaration = string_param(params, "functionDeclaration")?;
+    if params
+        .get("arguments")
+        .and_then(|a| a.as_array())
+        .is_some_and(|a| !a.is_empty())
+    {
+        return Err(BidiError::new(
+            "unsupported operation",
+            "Preload script channel arguments are not supported",
+        ));
+    }
+    if params.get("sandbox").is_some() {
+        return Err(BidiError::new(
+            "unsupported operation",
+            "Sandboxed realms are not supported",
+        ));
+    }
+    let contexts = params.get("contexts").cloned().unwrap_or(Value::Null);
+    let id = uuid::Uuid::new_v4().to_string();
+    let source = format!("(function(){{try{{({declaration})()}}catch(e){{console.error(e)}}}})()");
+
+    let session = state
+        .find_session(sid)
+        .await
+        .ok_or_else(|| BidiError::new("invalid session id", "No active session"))?;
+    let mut guard = session.write().await;
+    let session = &mut *guard;
+    session
+        .preload_scripts
+        .push(json!({"id": id, "source": source, "contexts": contexts}));
+    plugin_post(
+        session,
+        "/script/preload",
+        json!({"scripts": session.preload_scripts}),
+    )
+    .await?;
+    Ok(json!({"script": id}))
+}
+
+async fn script_remove_preload(
+    state: &SharedState,
+    sid: &str,
+    params: &Value,
+) -> Result<Value, BidiError> {
+    let id = string_param(params, "script")?;
+    let session = state
+        .find_session(sid)
+        .await
+        .ok_or_else(|| BidiError::new("invalid session id", "No active session"))?;
+    let mut guard = session.write().await;
+    let session = &mut *guard;
+    let before = session.preload_scripts.len();
+    session
+        .preload_scripts
+        .retain(|s| s.get("id").and_then(|i| i.as_str()) != Some(id));
+    if session.preload_scripts.len() == before {
+        return Err(BidiError::new(
+            "no such script",
+            format!("No preload script {id}"),
+        ));
+    }
+    plugin_post(
+        session,
+        "/script/preload",
+        json!({"scripts": session.preload_scripts}),
+    )
+    .await?;
+    Ok(json!({}))
+}
+
+async fn script_get_realms(
+    state: &SharedState,
+    sid: &str,
+    params: &Value,
+) -> Result<Value, BidiError> {
+    if params
+        .get("type")
+        .and_then(|t| t.as_str())
+        .is_some_and(|t| t != "window")
+    {
+        return Ok(json!({"realms": []}));
+    }
+    let context = params.get("context").and_then(|c| c.as_str());
+    let session = state
+        .find_session(sid)
+        .await
+        .ok_or_else(|| BidiError::new("invalid session id", "No active session"))?;
+    let guard = session.read().await;
+    let session = &*guard;
+    let handles = plugin_post(session, "/window/handles", json!({})).await?;
+    let mut realms = Vec::new();
+    for handle in handles.as_array().into_iter().flatten() {
+        let Some(handle) = handle.as_str() else {
+            continue;
+        };
+        if context.is_some_and(|c| c != handle) {
+            continue;
+        }
+        let origin = plugin_post(
+            session,
+            "/script/execute-async",
+            json!({
+                "script": "arguments[arguments.length-1](location.origin)",
+                "args": [],
+                "window": handle,
+            }),
+        )
+        .await
+        .ok()
+        .and_then(|r| r.get("value").cloned())
+        .unwrap_or(json!("null"));
+        realms.push(json!({
+            "realm": format!("realm-{handle}"),
+            "origin": origin,
+            "type": "window",
+            "context": handle,
+        }));
+    }
+    if let Some(context) = context {
+        if realms.is_empty() {
+            return Err(BidiError::new(
+                "no such frame",
+                format!("No browsing context {context}"),
+            ));
+        }
+    }
+    Ok(json!({"realms": realms}))
+}
+
+fn event_names(params: &Value) -> Result<Vec<String>, BidiError> {
+    params
+        .get("events")
+        .and_then(|e| e.as_array())
+        .ok_or_else(|| BidiError::invalid_argument("events must be an array"))?
+        .iter()
+        .map(|e| {
+            e.as_str()
+                .map(String::from)
+                .ok_or_else(|| BidiError::invalid_argument("event names must be strings"))
+        })
+        .collect()
+}
+
+/// Fetch console entries logged since the last poll and convert them to
+/// `log.entryAdded` params. With no cursor yet, existing entries only set
+/// the cursor. Returns `None` once the session is gone.
+async fn poll_log_entries(
+    state: &SharedState,
+    sid: &str,
+    channel: &mut Channel,
+) -> Option<Vec<Value>> {
+    let session = state.find_session(sid).await?;
+    let guard = session.read().await;
+    let session = &*guard;
+    let (document, since) = channel.log_cursor.clone().unwrap_or((None, 0));
+    let body = json!({"since": since, "document": document});
+    let Ok(result) = plugin_post(session, "/log/console", body).await else {
+        // The page may be mid-navigation; try again on the next tick.
+        return Some(Vec::new());
+    };
+    drop(guard);
+
+    let document = result
+        .get("document")
+        .and_then(|d| d.as_str())
+        .map(String::from);
+    let entries = result
+        .get("entries")
+        .and_then(|e| e.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let last_seq = entries
+        .iter()
+        .filter_map(|e| e.get("seq").and_then(|s| s.as_u64()))
+        .max();
+    let report = channel.log_cursor.is_some();
+    let seq = match (&channel.log_cursor, last_seq) {
+        (_, Some(seq)) => seq,
+        (Some((old, since)), None) if *old == document => *since,
+        _ => 0,
+    };
+    channel.log_cursor = Some((document, seq));
+
+    if !report {
+        return Some(Vec::new());
+    }
+    Some(entries.iter().map(log_entry_params).collect())
+}
+
+fn log_entry_params(entry: &Value) -> Value {
+    let level = match entry.get("level").and_then(|l| l.as_str()) {
+        Some("error") => "error",
+        Some("warn") => "warn",
+        Some("debug") => "debug",
+        _ => "info",
+    };
+    let text = entry.get("message").cloned().unwrap_or(json!(""));
+    let timestamp = entry.get("timestamp").cloned().unwrap_or(json!(0));
+    let source = json!({"realm": ""});
+    if entry.get("source").and_then(|s| s.as_str()) == Some("javascript") {
+        return json!({
+            "type": "javascript",
+            "level": "error",
+            "source": source,
+            "text": text,
+            "timestamp": timestamp,
+        });
+    }
+    let args: Vec<Value> = entry
+        .get("args")
+        .and_then(|a| a.as_array())
+        .map(|args| {
+            args.iter()
+                .map(|a| json!({"type": "string", "value": a}))
+                .collect()
+        })
+        .unwrap_or_default();
+    json!({
+        "type": "console",
+        "level": level,
+        "source": source,
+        "text": text,
+        "timestamp": timestamp,
+        "method": entry.get("level").cloned().unwrap_or(json!("log")),
+        "args": args,
+    })
+}
@@ -0,0 +1,131 @@
+// build: `tauri:options.project`, building the app before launching it.
+//
+// Runs `cargo tauri build --debug --no-bundle` in the project and returns the
+// path of the produced debug binary, so CI doesn't need a separate build step.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+/// Lines of build output included in the error when the build fails.
+const OUTPUT_TAIL: usize = 40;
+
+/// The directory holding `tauri.conf.json`: `{project}/src-tauri` or the
+/// project itself.
+fn tauri_dir(project: &Path) -> Result<PathBuf, String> {
+    [project.join("src-tauri"), project.to_path_buf()]
+        .into_iter()
+        .find(|dir| dir.join("tauri.conf.json").is_file())
+        .ok_or_else(|| format!("No tauri.conf.json found in {}", project.display()))
+}
+
+/// Work out which binary the build produced: `mainBinaryName` from
+/// `tauri.conf.json` if set, otherwise the package's binary target.
+async fn built_binary(tauri_dir: &Path) -> Result<PathBuf, String> {
+    let metadata = tokio::process::Command::new("cargo")
+        .args([
+            "metadata",
+            "--no-deps",
+            "--format-version",
+            "1",
+            "--manifest-path",
+        ])
+        .arg(tauri_dir.join("Cargo.toml"))
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run cargo metadata: {e}"))?;
+    if !metadata.status.success() {
+        return Err(format!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&metadata.stderr).trim()
+        ));
+    }
+    let metadata: Value = serde_json::from_slice(&metadata.stdout)
+        .map_err(|e| format!("Invalid cargo metadata output: {e}"))?;
+    let target_dir = metadata["target_directory"]
+        .as_str()
+        .ok_or("cargo metadata did not report a target directory")?;
+
+    let config: Value = std::fs::read_to_string(tauri_dir.join("tauri.conf.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or(Value::Null);
+    let name = match config["mainBinaryName"].as_str() {
+        Some(name) => name.to_string(),
+        None => {
+            let package = &metadata["packages"][0];
+            let bins: Vec<&str> = package["targets"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|t| {
+                    t["kind"]
+                        .as_array()
+                        .is_some_and(|k| k.iter().any(|k| k == "bin"))
+                })
+                .filter_map(|t| t["name"].as_str())
+                .collect();
+            let preferred = package["default_run"]
+                .as_str()
+                .or_else(|| package["name"].as_str());
+            match (bins.as_slice(), preferred) {
+                ([only], _) => only.to_string(),
+                (_, Some(name)) if bins.contains(&name) => name.to_string(),
+                _ => {
+                    return Err(format!(
+                        "Could not tell which binary {} builds; set tauri:options.binary",
+                        tauri_dir.display()
+                    ))
+                }
+            }
+        }
+    };
+    Ok(Path::new(target_dir)
+        .join("debug")
+        .join(format!("{name}{}", std::env::consts::EXE_SUFFIX)))
+}
+
+/// Build the project in debug mode and return the binary to launch.
+pub(crate) async fn build_project(project: &Path) -> Result<PathBuf, String> {
+    let tauri_dir = tauri_dir(project)?;
+    tracing::info!("Building {}", project.display());
+    let started = std::time::Instant::now();
+    let output = tokio::process::Command::new("cargo")
+        .args(["tauri", "build", "--debug", "--no-bundle"])
+        .current_dir(project)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run cargo tauri build: {e}"))?;
+    if !output.status.success() {
+        let text = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let lines: Vec<&str> = text.lines().collect();
+        let tail = lines[lines.len().saturating_sub(OUTPUT_TAIL)..].join("\n");
+        let hint = if text.contains("no such command: `tauri`") {
+            " (install the Tauri CLI with `cargo install tauri-cli`)"
+        } else {
+            ""
+        };
+        return Err(format!(
+            "cargo tauri build failed with {}{hint}:\n{tail}",
+            output.status
+        ));
+    }
+    tracing::info!(
+        "Built {} in {:.1}s",
+        project.display(),
+        started.elapsed().as_secs_f64()
+    );
+
+    let binary = built_binary(&tauri_dir).await?;
+    if !binary.is_file() {
+        return Err(format!(
+            "Build succeeded but {} does not exist",
+            binary.display()
+        ));
+    }
+    Ok(binary)
+}
@@ -0,0 +1,566 @@
+// conformance: the `tauri-wd conformance` runner.
+//
+// Runs a suite of protocol checks against an app and reports which pass.
+// The vendored suite (`conformance/wpt-classic.json`) is a subset of the
+// WPT WebDriver classic tests (webdriver/tests/classic), each case adapted
+// to plain requests and named after the WPT file and test it comes from, so
+// the report reads as "which parts of the spec work for Tauri".
+//
+// A case is a list of steps: a request relative to the session (or to the
+// server with `"global": true`) and what its response must be. Before each
+// case the session is put back to a known state: prompts dismissed, main
+// window, top-level frame, default timeouts, no cookies, start page. WPT's
+// `inline()` documents become `"inline"` HTML written into that page with
+// `document.write`, since there is no wptserve to load them from.
+//
+// Steps can `"save"` their value under a name. `{name}` in a path is the
+// saved element, shadow root or string id; a JSON string `"${name}"` in a
+// body or expected value is the saved value itself. Either may add a JSON
+// pointer into the value, as in `{options/0}`.
+//
+// `expect` holds one of `value` (equal, numbers compared as numbers),
+// `error` (the W3C error code), `type` (`null`, `boolean`, `number`,
+// `string`, `array`, `object`, `element` or `shadow`), `length` (of an
+// array) or `prefix` (of a string), optionally applied at the JSON pointer
+// `at` within the value. Without `error`, the request must succeed.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::{W3C_ELEMENT_KEY, W3C_SHADOW_KEY};
+
+/// The vendored WPT subset.
+const VENDORED: &str = include_str!("../conformance/wpt-classic.json");
+
+/// How long one request may take. Above the default script timeout, so
+/// scripts fail with `script timeout` rather than here.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Replaces the start page with `arguments[0]` and waits for it to load.
+const INLINE_SCRIPT: &str = "\
+var html = arguments[0], done = arguments[arguments.length - 1], called = false;
+function finish() { if (!called) { called = true; done(null); } }
+document.open();
+window.addEventListener('load', finish);
+document.write(html);
+document.close();
+if (document.readyState === 'complete') finish();
+setTimeout(finish, 5000);";
+
+struct Outcome {
+    id: String,
+    module: String,
+    failure: Option<String>,
+}
+
+struct Runner {
+    client: reqwest::Client,
+    server: String,
+    session_url: String,
+    start_url: Value,
+    main_window: Value,
+    timeouts: Value,
+    saved: HashMap<String, Value>,
+}
+
+impl Runner {
+    /// Send a request and return the status and `value`.
+    async fn send(
+        &self,
+        method: &str,
+        url: &str,
+        body: Option<&Value>,
+    ) -> Result<(u16, Value), String> {
+        let method = reqwest::Method::from_bytes(method.as_bytes())
+            .map_err(|_| format!("invalid method {method}"))?;
+        let mut request = self.client.request(method, url).timeout(REQUEST_TIMEOUT);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status().as_u16();
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        Ok((status, body.get("value").cloned().unwrap_or(Value::Null)))
+    }
+
+    /// Send a session command that has to succeed.
+    async fn command(&self, method: &str, path: &str, body: Value) -> Result<Value, String> {
+        let url = format!("{}{path}", self.session_url);
+        let (status, value) = self.send(method, &url, Some(&body)).await?;
+        if status >= 400 {
+            return Err(format!("{method} {path}: {}", describe(status, &value)));
+        }
+        Ok(value)
+    }
+
+    /// Put the session back to the state every case starts from.
+    async fn reset(&mut self, inline: Option<&str>) -> Result<(), String> {
+        self.saved.clear();
+        for _ in 0..5 {
+            let url = format!("{}/alert/dismiss", self.session_url);
+            match self.send("POST", &url, Some(&json!({}))).await? {
+                (status, _) if status >= 400 => break,
+                _ => {}
+            }
+        }
+        self.command("POST", "/window", json!({"handle": self.main_window}))
+            .await?;
+        self.command("POST", "/frame", json!({"id": null})).await?;
+        self.command("POST", "/timeouts", self.timeouts.clone())
+            .await?;
+        self.command("DELETE", "/actions", json!({})).await?;
+        self.command("DELETE", "/cookie", json!({})).await?;
+        self.command("POST", "/url", json!({"url": self.start_url}))
+            .await?;
+        if let Some(html) = inline {
+            self.command(
+                "POST",
+                "/execute/async",
+                json!({"script": INLINE_SCRIPT, "args": [html]}),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Run one step, saving its value if asked to.
+    async fn step(&mut self, step: &Value) -> Result<(), String> {
+        let method = step.get("method").and_then(Value::as_str).unwrap_or("GET");
+        let path = step.get("path").and_then(Value::as_str).unwrap_or("");
+        let path = fill_path(path, &self.saved)?;
+        let global = step.get("global").and_then(Value::as_bool) == Some(true);
+        let url = if global {
+            format!("{}{path}", self.server)
+        } else {
+            format!("{}{path}", self.session_url)
+        };
+        let body = match step.get("body") {
+            Some(body) => Some(fill_value(body, &self.saved)?),
+            None => None,
+        };
+        let (status, value) = self.send(method, &url, body.as_ref()).await?;
+        let expect = fill_value(step.get("expect").unwrap_or(&json!({})), &self.saved)?;
+        check(&expect, status, &value).map_err(|why| format!("{method} {path}: {why}"))?;
+        if let Some(name) = step.get("save").and_then(Value::as_str) {
+            self.saved.insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    async fn run_case(&mut self, case: &Value) -> Result<(), String> {
+        let inline = case.get("inline").and_then(Value::as_str);
+        self.reset(inline)
+            .await
+            .map_err(|why| format!("setup: {why}"))?;
+        let steps = case
+            .get("steps")
+            .and_then(Value::as_array)
+            .ok_or("case has no steps")?;
+        for (i, step) in steps.iter().enumerate() {
+            self.step(step)
+                .await
+                .map_err(|why| format!("step {}: {why}", i + 1))?;
+        }
+        Ok(())
+    }
+}
+
+/// `error (status): message` for an error response, or the value.
+fn describe(status: u16, value: &Value) -> String {
+    match value.get("error").and_then(Value::as_str) {
+        Some(error) => format!(
+            "{error} ({status}): {}",
+            value.get("message").and_then(Value::as_str).unwrap_or("")
+        ),
+        None => format!("{status} {value}"),
+    }
+}
+
+/// The saved value `spec` names: `name` or `name/json/pointer`.
+fn lookup<'a>(spec: &str, saved: &'a HashMap<String, Value>) -> Result<&'a Value, String> {
+    let (name, pointer) = match spec.find('/') {
+        Some(at) => spec.split_at(at),
+        None => (spec, ""),
+    };
+    saved
+        .get(name)
+        .and_then(|value| value.pointer(pointer))
+        .ok_or_else(|| format!("nothing saved as {spec}"))
+}
+
+/// Replace `{name}` in a path with the saved reference's id.
+fn fill_path(path: &str, saved: &HashMap<String, Value>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = path;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .map(|close| open + close)
+            .ok_or_else(|| format!("unclosed placeholder in {path}"))?;
+        let value = lookup(&rest[open + 1..close], saved)?;
+        let id = [W3C_ELEMENT_KEY, W3C_SHADOW_KEY]
+            .iter()
+            .find_map(|key| value.get(key))
+            .unwrap_or(value);
+        out.push_str(&rest[..open]);
+        match id {
+            Value::String(id) => out.push_str(id),
+            other => out.push_str(&other.to_string()),
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Replace `"${name}"` strings in `value` with the saved values.
+fn fill_value(value: &Value, saved: &HashMap<String, Value>) -> Result<Value, String> {
+    Ok(match value {
+        Value::String(text) => match text.strip_prefix("${").and_then(|t| t.strip_suffix('}')) {
+            Some(spec) => lookup(spec, saved)?.clone(),
+            None => value.clone(),
+        },
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| fill_value(item, saved))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, item)| Ok((key.clone(), fill_value(item, saved)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        _ => value.clone(),
+    })
+}
+
+/// JSON equality, except that numbers are compared by value (`10` equals
+/// `10.0`).
+fn same(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| same(a, b)))
+        }
+        _ => expected == actual,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(map) if map.contains_key(W3C_ELEMENT_KEY) => "element",
+        Value::Object(map) if map.contains_key(W3C_SHADOW_KEY) => "shadow",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Whether a response matches `expect`.
+fn check(expect: &Value, status: u16, value: &Value) -> Result<(), String> {
+    if let Some(error) = expect.get("error").and_then(Value::as_str) {
+        return match value.get("error").and_then(Value::as_str) {
+            Some(got) if status >= 400 && got == error => Ok(()),
+            _ => Err(format!(
+                "expected error {error:?}, got {}",
+                describe(status, value)
+            )),
+        };
+    }
+    if status >= 400 {
+        return Err(format!("failed with {}", describe(status, value)));
+    }
+    let at = expect.get("at").and_then(Value::as_str).unwrap_or("");
+    let value = value
+        .pointer(at)
+        .ok_or_else(|| format!("no {at} in {value}"))?;
+    if let Some(expected) = expect.get("value") {
+        if !same(expected, value) {
+            return Err(format!("expected {expected}, got {value}"));
+        }
+    }
+    if let Some(expected) = expect.get("type").and_then(Value::as_str) {
+        if type_name(value) != expected {
+            return Err(format!("expected a {expected}, got {value}"));
+        }
+    }
+    if let Some(expected) = expect.get("length").and_then(Value::as_u64) {
+        let length = value.as_array().map(|items| items.len() as u64);
+        if length != Some(expected) {
+            return Err(format!("expected {expected} item(s), got {value}"));
+        }
+    }
+    if let Some(prefix) = expect.get("prefix").and_then(Value::as_str) {
+        if !value.as_str().is_some_and(|text| text.starts_with(prefix)) {
+            let shown: String = value.to_string().chars().take(40).collect();
+            return Err(format!(
+                "expected a string starting {prefix:?}, got {shown}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Run the suite (`suite`, or the vendored one) against a session for
+/// `binary` on `server`, printing a line per case and a summary per WPT
+/// module. `filter` keeps the cases whose `module/file.py::name` contains
+/// it; `report` also gets the results as JSON. Returns whether every case
+/// passed.
+pub(crate) async fn run(
+    server: &str,
+    binary: &Path,
+    suite: Option<&Path>,
+    filter: Option<&str>,
+    report: Option<&Path>,
+) -> Result<bool, String> {
+    let text = match suite {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?,
+        None => VENDORED.to_string(),
+    };
+    let suite: Value = serde_json::from_str(&text).map_err(|e| format!("invalid suite: {e}"))?;
+    let cases: Vec<&Value> = suite
+        .get("cases")
+        .and_then(Value::as_array)
+        .ok_or("suite has no cases")?
+        .iter()
+        .filter(|case| filter.map_or(true, |filter| case_id(case).contains(filter)))
+        .collect();
+    if cases.is_empty() {
+        return Err("no cases to run".into());
+    }
+
+    let client = reqwest::Client::new();
+    let created: Value = client
+        .post(format!("{server}/session"))
+        .json(&json!({"capabilities": {"alwaysMatch": {
+            "tauri:options": {"binary": binary.display().to_string()}
+        }}}))
+        .send()
+        .await
+        .map_err(|e| format!("failed to create session: {e}"))?
+        .json()
+        .await
+        .map_err(|e| format!("failed to create session: {e}"))?;
+    let sid = created
+        .pointer("/value/sessionId")
+        .and_then(|s| s.as_str())
+        .ok_or_else(|| {
+            format!(
+                "failed to create session: {}",
+                created
+                    .pointer("/value/message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("unexpected response")
+            )
+        })?
+        .to_string();
+
+    let mut runner = Runner {
+        client: client.clone(),
+        server: server.to_string(),
+        session_url: format!("{server}/session/{sid}"),
+        start_url: Value::Null,
+        main_window: Value::Null,
+        timeouts: Value::Null,
+        saved: HashMap::new(),
+    };
+    let outcomes = async {
+        runner.start_url = runner.command("GET", "/url", json!({})).await?;
+        runner.main_window = runner.command("GET", "/window", json!({})).await?;
+        runner.timeouts = runner.command("GET", "/timeouts", json!({})).await?;
+        println!(
+            "Running {} conformance case(s) in session {sid}",
+            cases.len()
+        );
+        let mut outcomes = Vec::with_capacity(cases.len());
+        for case in &cases {
+            let id = case_id(case);
+            let failure = runner.run_case(case).await.err();
+            match &failure {
+                None => println!("PASS  {id}"),
+                Some(why) => println!("FAIL  {id}\n      {why}"),
+            }
+            let module = id.split('/').next().unwrap_or_default().to_string();
+            outcomes.push(Outcome {
+                id,
+                module,
+                failure,
+            });
+        }
+        Ok::<_, String>(outcomes)
+    }
+    .await;
+    let _ = client
+        .delete(format!("{server}/session/{sid}"))
+        .send()
+        .await;
+    let outcomes = outcomes?;
+
+    let mut modules: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for outcome in &outcomes {
+        let entry = modules.entry(&outcome.module).or_default();
+        entry.1 += 1;
+        if outcome.failure.is_none() {
+            entry.0 += 1;
+        }
+    }
+    let passed = outcomes.iter().filter(|o| o.failure.is_none()).count();
+    let total = outcomes.len();
+    println!();
+    let width = modules.keys().map(|m| m.len()).max().unwrap_or(0);
+    for (module, (ok, count)) in &modules {
+        println!("{module:width$}  {ok}/{count}");
+    }
+    println!(
+        "\nConformance: {passed} passed, {} failed, {total} total ({:.1}%)",
+        total - passed,
+        passed as f64 * 100.0 / total as f64
+    );
+
+    if let Some(path) = report {
+        let report = json!({
+            "suite": suite.get("source"),
+            "summary": {"passed": passed, "failed": total - passed, "total": total},
+            "modules": modules
+                .iter()
+                .map(|(module, (ok, count))| (module.to_string(), json!({"passed": ok, "total": count})))
+                .collect::<serde_json::Map<_, _>>(),
+            "results": outcomes
+                .iter()
+                .map(|o| json!({
+                    "id": o.id,
+                    "outcome": if o.failure.is_none() { "pass" } else { "fail" },
+                    "message": o.failure,
+                }))
+                .collect::<Vec<_>>(),
+        });
+        let text = serde_json::to_string_pretty(&report).unwrap_or_default();
+        std::fs::write(path, text + "\n")
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    }
+    Ok(passed == total)
+}
+
+/// `module/file.py::test_name`.
+fn case_id(case: &Value) -> String {
+    format!(
+        "{}::{}",
+        case.get("wpt").and_then(Value::as_str).unwrap_or("?"),
+        case.get("name").and_then(Value::as_str).unwrap_or("?")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved() -> HashMap<String, Value> {
+        HashMap::from([
+            ("el".to_string(), json!({W3C_ELEMENT_KEY: "e-1"})),
+            ("root".to_string(), json!({W3C_SHADOW_KEY: "s-1"})),
+            ("handle".to_string(), json!("w-1")),
+            ("options".to_string(), json!([{W3C_ELEMENT_KEY: "o-1"}, 7])),
+        ])
+    }
+
+    #[test]
+    fn fill_path_substitutes_saved_ids() {
+        let saved = saved();
+        assert_eq!(
+            fill_path("/element/{el}/shadow", &saved).unwrap(),
+            "/element/e-1/shadow"
+        );
+        assert_eq!(
+            fill_path("/shadow/{root}/element", &saved).unwrap(),
+            "/shadow/s-1/element"
+        );
+        assert_eq!(
+            fill_path("/window/{handle}", &saved).unwrap(),
+            "/window/w-1"
+        );
+        assert_eq!(
+            fill_path("/element/{options/0}/click", &saved).unwrap(),
+            "/element/o-1/click"
+        );
+        assert_eq!(fill_path("/frame/{options/1}", &saved).unwrap(), "/frame/7");
+        assert_eq!(fill_path("/title", &saved).unwrap(), "/title");
+    }
+
+    #[test]
+    fn fill_path_rejects_unknown_and_unclosed_placeholders() {
+        let saved = saved();
+        assert!(fill_path("/element/{missing}/text", &saved).is_err());
+        assert!(fill_path("/element/{options/5}/text", &saved).is_err());
+        assert!(fill_path("/element/{el/text", &saved).is_err());
+    }
+
+    #[test]
+    fn fill_value_replaces_whole_string_placeholders() {
+        let saved = saved();
+        let body = json!({
+            "args": ["${el}", "${options/1}", "plain ${el}"],
+            "nested": {"handle": "${handle}"},
+            "n": 1,
+        });
+        assert_eq!(
+            fill_value(&body, &saved).unwrap(),
+            json!({
+                "args": [{W3C_ELEMENT_KEY: "e-1"}, 7, "plain ${el}"],
+                "nested": {"handle": "w-1"},
+                "n": 1,
+            })
+        );
+        assert!(fill_value(&json!(["${missing}"]), &saved).is_err());
+    }
+
+    #[test]
+    fn same_compares_numbers_by_value() {
+        assert!(same(&json!(10), &json!(10.0)));
+        assert!(same(&json!({"x": [1, 2.5]}), &json!({"x": [1.0, 2.5]})));
+        assert!(!same(&json!(10), &json!("10")));
+        assert!(!same(&json!([1, 2]), &json!([1, 2, 3])));
+        assert!(!same(&json!({"a": 1}), &json!({"a": 1, "b": 2})));
+        assert!(!same(&json!({"a": 1}), &json!({"b": 1})));
+    }
+
+    #[test]
+    fn check_matches_values_types_lengths_and_prefixes() {
+        let value = json!({"rect": {"x": 5}, "items": [1, 2], "url": "http://a/b"});
+        assert!(check(&json!({"at": "/rect/x", "value": 5.0}), 200, &value).is_ok());
+        assert!(check(&json!({"at": "/rect/x", "value": 6}), 200, &value).is_err());
+        assert!(check(&json!({"at": "/items", "type": "array"}), 200, &value).is_ok());
+        assert!(check(&json!({"at": "/items", "type": "object"}), 200, &value).is_err());
+        assert!(check(&json!({"at": "/items", "length": 2}), 200, &value).is_ok());
+        assert!(check(&json!({"at": "/items", "length": 3}), 200, &value).is_err());
+        assert!(check(&json!({"at": "/url", "prefix": "http:"}), 200, &value).is_ok());
+        assert!(check(&json!({"at": "/url", "prefix": "https:"}), 200, &value).is_err());
+        assert!(check(&json!({"at": "/missing"}), 200, &value).is_err());
+        assert!(check(&json!({}), 200, &value).is_ok());
+        let element = json!({W3C_ELEMENT_KEY: "e-1"});
+        assert!(check(&json!({"type": "element"}), 200, &element).is_ok());
+    }
+
+    #[test]
+    fn check_requires_the_expected_error() {
+        let error = json!({"error": "no such element", "message": "gone"});
+        assert!(check(&json!({"error": "no such element"}), 404, &error).is_ok());
+        assert!(check(&json!({"error": "no such window"}), 404, &error).is_err());
+        // A success never satisfies an expected error, nor an error a success.
+        assert!(check(&json!({"error": "no such element"}), 200, &json!(null)).is_err());
+        assert!(check(&json!({"type": "object"}), 404, &error).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+// doctor: the `tauri-wd doctor` diagnostic subcommand.
+//
+// Launches an app the same way session creation does, then walks through
+// each step that can go wrong (binary, plugin port announcement, plugin
+// endpoints) and prints what passed, what failed, and how to fix it.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tokio::io::AsyncBufReadExt;
+
+/// Print one check result; returns whether it passed.
+fn report(ok: bool, label: &str, detail: &str) -> bool {
+    let mark = if ok { "ok  " } else { "FAIL" };
+    if detail.is_empty() {
+        println!("[{mark}] {label}");
+    } else {
+        println!("[{mark}] {label}: {detail}");
+    }
+    ok
+}
+
+fn hint(text: &str) {
+    for line in text.lines() {
+        println!("       {line}");
+    }
+}
+
+/// Run the diagnostics. Returns `true` if every check passed.
+pub(crate) async fn run(binary: &Path, timeout: Duration) -> bool {
+    println!("tauri-wd {}", env!("CARGO_PKG_VERSION"));
+    println!("Checking {}", binary.display());
+
+    // 1. The binary itself.
+    let binary = match crate::launch::resolve_executable(binary) {
+        Ok(path) => path,
+        Err(e) => {
+            report(false, "binary", &e);
+            return false;
+        }
+    };
+    let binary = binary.as_path();
+    let metadata = match std::fs::metadata(binary) {
+        Ok(m) => m,
+        Err(e) => {
+            report(false, "binary", &e.to_string());
+            hint("Pass the path to the built app executable, e.g.\nsrc-tauri/target/debug/my-app");
+            return false;
+        }
+    };
+    if metadata.is_dir() {
+        report(false, "binary", "is a directory");
+        hint("Pass the app executable or a .app bundle");
+        return false;
+    }
+    report(true, "binary", &binary.display().to_string());
+    if binary
+        .components()
+        .any(|c| c.as_os_str() == std::ffi::OsStr::new("release"))
+    {
+        println!("[warn] binary is under a `release` directory");
+        hint("The plugin is usually registered only under #[cfg(debug_assertions)].\nUse the debug build (target/debug) or `cargo tauri build --debug`.");
+    }
+
+    if let Err(e) = crate::launch::check_display(&Default::default()) {
+        report(false, "display", &e);
+        hint("On a headless machine (e.g. CI) start a virtual display first:\nxvfb-run -a tauri-wd doctor --binary <PATH>");
+        return false;
+    }
+
+    // 2. Launch and wait for the plugin to announce its port.
+    let started = Instant::now();
+    let port_file =
+        std::env::temp_dir().join(format!("tauri-wd-doctor-port-{}", std::process::id()));
+    let token = crate::launch::new_token();
+    let mut child = match tokio::process::Command::new(binary)
+        .env("TAURI_WEBVIEW_AUTOMATION", "true")
+        .env(crate::launch::PORT_FILE_ENV, &port_file)
+        .env(crate::launch::TOKEN_ENV, &token)
+        .env(crate::launch::PEER_PID_ENV, std::process::id().to_string())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            report(false, "launch", &e.to_string());
+            hint("Check that the file is executable (chmod +x) and built for this machine.");
+            return false;
+        }
+    };
+    report(true, "launch", &format!("pid {}", child.id().unwrap_or(0)));
+
+    // Keep the tail of stderr for the report if the app dies.
+    let stderr_tail = std::sync::Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+    if let Some(stderr) = child.stderr.take() {
+        let tail = stderr_tail.clone();
+        tokio::spawn(async move {
+            let mut lines = tokio::io::BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let mut tail = tail.lock().unwrap_or_else(|e| e.into_inner());
+                tail.push(line);
+                if tail.len() > 10 {
+                    tail.remove(0);
+                }
+            }
+        });
+    }
+
+    let Some(stdout) = child.stdout.take() else {
+        report(false, "plugin port", "failed to capture app stdout");
+        return false;
+    };
+    let mut reader = tokio::io::BufReader::new(stdout).lines();
+    let addr = crate::launch::wait_for_plugin_port(&mut reader, &port_file, None, timeout).await;
+    let addr = match addr {
+        Ok(Some(addr)) => addr,
+        other => {
+            let exited = child.try_wait().ok().flatten();
+            let detail = match (&other, exited) {
+                (Err(e), _) => format!("error reading app stdout: {e}"),
+                (_, Some(status)) => format!("app exited ({status}) before announcing a port"),
+                _ => format!("no announcement within {}s", timeout.as_secs()),
+            };
+            report(false, "plugin port", &detail);
+            hint("The plugin never reported its port. Check that:\n- the plugin is registered: .plugin(tauri_plugin_webdriver_automation::init())\n- the binary is a debug build (the plugin is normally debug-only)\n- the app creates its window (the plugin starts with the app)");
+            let tail = stderr_tail
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone();
+            if !tail.is_empty() {
+                println!("       Last stderr lines:");
+                for line in tail {
+                    println!("         {line}");
+                }
+            }
+            crate::launch::kill_tree(&mut child).await;
+            return false;
+        }
+    };
+    report(
+        true,
+        "plugin port",
+        &format!("{addr} after {:.1}s", started.elapsed().as_secs_f64()),
+    );
+    tokio::spawn(async move { while let Ok(Some(_)) = reader.next_line().await {} });
+
+    // 3. Plugin endpoints.
+    let client = crate::launch::plugin_client(Some(&token));
+    let plugin_url = match crate::launch::negotiate_api(&client, &addr.base_url()).await {
+        Ok(api) => {
+            report(
+                true,
+                "plugin API",
+                &format!(
+                    "v{}, bridge window.{}",
+                    crate::launch::PLUGIN_API_VERSION,
+                    api.bridge
+                ),
+            );
+            api.url
+        }
+        Err(e) => {
+            report(false, "plugin API", &e);
+            hint("Build the app against the tauri-plugin-webdriver-automation release\nthat matches this tauri-wd.");
+            crate::launch::kill_tree(&mut child).await;
+            return false;
+        }
+    };
+    let call = |path: &'static str, body: Value| {
+        let request = client
+            .post(format!("{plugin_url}{path}"))
+            .json(&body)
+            .timeout(Duration::from_secs(10));
+        async move {
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            let status = response.status();
+            let value: Value = response.json().await.map_err(|e| e.to_string())?;
+            if status.is_success() {
+                Ok(value)
+            } else {
+                Err(value
+                    .get("error")
+                    .and_then(|e| e.as_str())
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("HTTP {status}")))
+            }
+        }
+    };
+
+    let mut all_ok = true;
+    match call("/info", json!({})).await {
+        Ok(info) => {
+            let field = |key: &str| {
+                info.get(key)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown")
+                    .to_string()
+            };
+            report(true, "plugin version", &field("plugin"));
+            report(true, "tauri version", &field("tauri"));
+            report(true, "webview version", &field("webview"));
+            if info.get("debugBuild").and_then(|v| v.as_bool()) == Some(false) {
+                println!("[warn] the app is a release build");
+            }
+        }
+        Err(e) => {
+            println!("[warn] /info unavailable ({e}); the plugin predates version reporting");
+        }
+    }
+
+    let checks: [(&str, &'static str, Value); 5] = [
+        ("webview loaded", "/ready", json!({"timeoutMs": 10000})),
+        ("window handles", "/window/handles", json!({})),
+        ("current window", "/window/handle", json!({})),
+        ("current URL", "/navigate/current", json!({})),
+        (
+            "script execution",
+            "/script/execute",
+            json!({"script": "return 1 + 1", "args": []}),
+        ),
+    ];
+    for (label, path, body) in checks {
+        match call(path, body).await {
+            Ok(value) => {
+                report(true, label, &value.to_string());
+            }
+            Err(e) => {
+                report(false, label, &e);
+                all_ok = false;
+                if path == "/script/execute" || path == "/ready" {
+                    hint("The page did not answer. If it loads from a dev server (devUrl),\nmake sure the dev server is running.");
+                }
+            }
+        }
+    }
+
+    crate::launch::kill_tree(&mut child).await;
+    println!();
+    if all_ok {
+        println!("All checks passed.");
+    } else {
+        println!("Some checks failed; see the hints above.");
+    }
+    all_ok
+}
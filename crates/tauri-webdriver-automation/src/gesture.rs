@@ -0,0 +1,114 @@
+// gesture: touch gestures as W3C action sequences.
+//
+// Backs /session/{id}/tauri/gesture/{swipe,pinch,longpress}. Each gesture
+// becomes one touch pointer source per finger, run through Perform Actions.
+// Positions are `x`/`y` in the viewport, or offsets from the center of
+// `element` (a W3C element reference) when one is given.
+
+use serde_json::{json, Value};
+
+/// Default gesture length, in ms, for swipes and pinches.
+const DEFAULT_DURATION_MS: u64 = 300;
+
+/// Default hold, in ms, for long presses.
+const DEFAULT_LONG_PRESS_MS: u64 = 1000;
+
+/// Default distance, in CSS pixels, between the fingers at the start of a
+/// pinch.
+const DEFAULT_PINCH_DISTANCE: f64 = 100.0;
+
+/// Actions body for the gesture `name` with parameters `params`, or a
+/// message for invalid parameters.
+pub(crate) fn actions(name: &str, params: &Value) -> Result<Value, String> {
+    let origin = match params.get("element") {
+        None | Some(Value::Null) => json!("viewport"),
+        Some(element) if element.is_object() => element.clone(),
+        Some(_) => return Err("'element' must be an element reference".into()),
+    };
+    let x = number(params, "x")?.unwrap_or(0.0);
+    let y = number(params, "y")?.unwrap_or(0.0);
+    let fingers = match name {
+        "swipe" => {
+            let dx = number(params, "deltaX")?.unwrap_or(0.0);
+            let dy = number(params, "deltaY")?.unwrap_or(0.0);
+            if dx == 0.0 && dy == 0.0 {
+                return Err("a swipe needs a non-zero 'deltaX' or 'deltaY'".into());
+            }
+            let duration = duration(params, DEFAULT_DURATION_MS)?;
+            vec![stroke(&origin, (x, y), (x + dx, y + dy), duration)]
+        }
+        "pinch" => {
+            let scale = number(params, "scale")?
+                .filter(|s| *s > 0.0)
+                .ok_or("'scale' must be a positive number")?;
+            let distance = number(params, "distance")?
+                .filter(|d| *d > 0.0)
+                .unwrap_or(DEFAULT_PINCH_DISTANCE);
+            let duration = duration(params, DEFAULT_DURATION_MS)?;
+            // Two fingers on a horizontal line through the center, moving
+            // apart (scale > 1) or together (scale < 1).
+            let (from, to) = (distance / 2.0, distance * scale / 2.0);
+            vec![
+                stroke(&origin, (x - from, y), (x - to, y), duration),
+                stroke(&origin, (x + from, y), (x + to, y), duration),
+            ]
+        }
+        "longpress" => {
+            let duration = duration(params, DEFAULT_LONG_PRESS_MS)?;
+            vec![json!([
+                move_to(&origin, (x, y), 0),
+                {"type": "pointerDown", "button": 0},
+                {"type": "pause", "duration": duration},
+                {"type": "pointerUp", "button": 0},
+            ])]
+        }
+        _ => return Err(format!("unknown gesture: {name}")),
+    };
+    let sources: Vec<Value> = fingers
+        .into_iter()
+        .enumerate()
+        .map(|(i, actions)| {
+            json!({
+                "type": "pointer",
+                "id": format!("gesture-finger{}", i + 1),
+                "parameters": {"pointerType": "touch"},
+                "actions": actions,
+            })
+        })
+        .collect();
+    Ok(json!({ "actions": sources }))
+}
+
+/// A finger going down at `from`, moving to `to` over `duration` ms, and
+/// lifting.
+fn stroke(origin: &Value, from: (f64, f64), to: (f64, f64), duration: u64) -> Value {
+    json!([
+        move_to(origin, from, 0),
+        {"type": "pointerDown", "button": 0},
+        move_to(origin, to, duration),
+        {"type": "pointerUp", "button": 0},
+    ])
+}
+
+fn move_to(origin: &Value, (x, y): (f64, f64), duration: u64) -> Value {
+    json!({"type": "pointerMove", "origin": origin, "x": x, "y": y, "duration": duration})
+}
+
+fn number(params: &Value, key: &str) -> Result<Option<f64>, String> {
+    match params.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v
+            .as_f64()
+            .map(Some)
+            .ok_or_else(|| format!("'{key}' must be a number")),
+    }
+}
+
+fn duration(params: &Value, default: u64) -> Result<u64, String> {
+    match params.get("duration") {
+        None | Some(Value::Null) => Ok(default),
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| "'duration' must be a non-negative integer".to_string()),
+    }
+}
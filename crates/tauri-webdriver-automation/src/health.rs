@@ -0,0 +1,112 @@
+// health: a keep-alive ping from tauri-wd to each session's plugin.
+//
+// A plugin that stops answering (the app hung, or its server died while the
+// process lives on) would otherwise cost every command a full connect or
+// request timeout before failing. Each session pings its plugin's `/info`
+// in the background; after a few failures in a row the session is marked
+// unhealthy and commands fail at once, saying why, until a ping gets
+// through again.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+/// Failed pings in a row before the session is unhealthy.
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// How long a single ping may take.
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+pub(crate) struct PluginHealth {
+    /// Failed pings since the last one that got through.
+    failures: AtomicU32,
+    /// Why the last ping failed.
+    last_error: Mutex<Option<String>>,
+}
+
+impl PluginHealth {
+    /// `Err` with a description while the plugin is considered unreachable.
+    pub fn check(&self) -> Result<(), String> {
+        let failures = self.failures.load(Ordering::Relaxed);
+        if failures < FAILURE_THRESHOLD {
+            return Ok(());
+        }
+        let error = self.last_error.lock().expect("lock poisoned");
+        Err(format!(
+            "plugin unreachable: the last {failures} keep-alive pings failed ({}); \
+             the app may be hung; delete the session if it doesn't recover",
+            error.as_deref().unwrap_or("no answer")
+        ))
+    }
+
+    fn record(&self, result: Result<(), String>) {
+        match result {
+            Ok(()) => {
+                if self.failures.swap(0, Ordering::Relaxed) >= FAILURE_THRESHOLD {
+                    tracing::info!("Plugin answers keep-alive pings again");
+                }
+            }
+            Err(e) => {
+                let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+                if failures == FAILURE_THRESHOLD {
+                    tracing::warn!("Plugin missed {failures} keep-alive pings: {e}");
+                }
+                *self.last_error.lock().expect("lock poisoned") = Some(e);
+            }
+        }
+    }
+}
+
+/// Ping `plugin_url` every `interval` (never, for zero) until the returned
+/// health is dropped with its session.
+pub(crate) fn watch(
+    client: reqwest::Client,
+    plugin_url: String,
+    interval: Duration,
+) -> Arc<PluginHealth> {
+    let health = Arc::new(PluginHealth::default());
+    if !interval.is_zero() {
+        tokio::spawn(ping_loop(
+            client,
+            plugin_url,
+            interval,
+            Arc::downgrade(&health),
+        ));
+    }
+    health
+}
+
+async fn ping_loop(
+    client: reqwest::Client,
+    plugin_url: String,
+    interval: Duration,
+    health: Weak<PluginHealth>,
+) {
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick is immediate, and the plugin just answered.
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        let result = ping(&client, &plugin_url).await;
+        let Some(health) = health.upgrade() else {
+            return;
+        };
+        health.record(result);
+    }
+}
+
+async fn ping(client: &reqwest::Client, plugin_url: &str) -> Result<(), String> {
+    let response = client
+        .post(format!("{plugin_url}/info"))
+        .json(&serde_json::json!({}))
+        .timeout(PING_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("status {}", response.status()));
+    }
+    Ok(())
+}
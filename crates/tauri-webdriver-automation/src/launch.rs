@@ -0,0 +1,721 @@
+// launch: starting app processes and discovering their plugin port.
+//
+// Used by session creation, the warm pool and `tauri-wd doctor`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::io::AsyncBufReadExt;
+
+/// How to start an app: everything from `tauri:options` that affects the
+/// process. Two sessions with equal configs can share a pooled instance.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct LaunchConfig {
+    pub executable: PathBuf,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub cwd: Option<PathBuf>,
+}
+
+/// A running app whose plugin has reported its port.
+pub(crate) struct LaunchedApp {
+    pub process: AppProcess,
+    pub plugin_url: String,
+    pub log: AppLog,
+    /// Client for `plugin_url` that sends the app's launch token.
+    pub client: reqwest::Client,
+    /// `window` property holding the plugin's JS bridge.
+    pub bridge: String,
+}
+
+/// What `/api/version` told us about a compatible plugin.
+pub(crate) struct PluginApi {
+    /// Base URL of the plugin's versioned routes.
+    pub url: String,
+    /// `window` property holding the JS bridge (random per launch).
+    pub bridge: String,
+}
+
+/// Bytes the app log may reach before it's rotated: the current file moves
+/// to `{path}.1` (replacing the previous one) and a new one is started, so
+/// an app that logs without end keeps at most twice this on disk.
+const APP_LOG_MAX_BYTES: u64 = 32 * 1024 * 1024;
+
+/// The app's stdout and stderr, written line by line to a file with a
+/// `[stdout]` / `[stderr]` prefix so panics survive into CI artifacts.
+#[derive(Clone)]
+pub(crate) struct AppLog {
+    path: PathBuf,
+    file: std::sync::Arc<std::sync::Mutex<LogFile>>,
+}
+
+struct LogFile {
+    file: std::fs::File,
+    /// Bytes written to `file` so far.
+    len: u64,
+}
+
+impl AppLog {
+    pub fn create(path: PathBuf) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::File::create(&path)?;
+        Ok(Self {
+            path,
+            file: std::sync::Arc::new(std::sync::Mutex::new(LogFile { file, len: 0 })),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the log rotates to once it's full.
+    fn previous_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".1");
+        path.into()
+    }
+
+    pub fn write(&self, stream: &str, line: &str) {
+        use std::io::Write as _;
+        let mut log = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = format!("[{stream}] {line}\n");
+        if log.len > 0 && log.len + entry.len() as u64 > APP_LOG_MAX_BYTES {
+            let rotated = std::fs::rename(&self.path, self.previous_path())
+                .and_then(|()| std::fs::File::create(&self.path));
+            match rotated {
+                Ok(file) => *log = LogFile { file, len: 0 },
+                Err(e) => tracing::warn!("failed to rotate {}: {e}", self.path.display()),
+            }
+        }
+        if log.file.write_all(entry.as_bytes()).is_ok() {
+            log.len += entry.len() as u64;
+        }
+    }
+
+    /// The last `count` lines of the log, reaching into the rotated file
+    /// when the current one has fewer.
+    pub fn tail(&self, count: usize) -> std::io::Result<Vec<String>> {
+        let mut lines = tail_file(&self.path, count)?;
+        if lines.len() < count {
+            if let Ok(mut earlier) = tail_file(&self.previous_path(), count - lines.len()) {
+                earlier.append(&mut lines);
+                lines = earlier;
+            }
+        }
+        Ok(lines)
+    }
+}
+
+/// The last `count` lines of the file at `path`, read backwards from the
+/// end in blocks, so a long log isn't read whole.
+fn tail_file(path: &Path, count: usize) -> std::io::Result<Vec<String>> {
+    use std::io::{Read as _, Seek as _, SeekFrom};
+    const BLOCK: u64 = 64 * 1024;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut start = file.metadata()?.len();
+    let mut buf = Vec::new();
+    let mut newlines = 0;
+    // One newline more than lines wanted marks where the first one starts.
+    while start > 0 && newlines <= count {
+        let len = BLOCK.min(start);
+        start -= len;
+        file.seek(SeekFrom::Start(start))?;
+        let mut block = vec![0; len as usize];
+        file.read_exact(&mut block)?;
+        newlines += block.iter().filter(|&&b| b == b'\n').count();
+        block.append(&mut buf);
+        buf = block;
+    }
+    let text = String::from_utf8_lossy(&buf);
+    // Short of the start of the file, the first line is cut off.
+    let lines: Vec<&str> = text.lines().skip(usize::from(start > 0)).collect();
+    let first = lines.len().saturating_sub(count);
+    Ok(lines[first..].iter().map(|l| l.to_string()).collect())
+}
+
+/// A launched app process. A background task owns the `Child` and waits on
+/// it, so an exit (including a crash) is noticed as soon as it happens.
+pub(crate) struct AppProcess {
+    pid: Option<u32>,
+    exit: tokio::sync::watch::Receiver<Option<std::process::ExitStatus>>,
+    kill: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl AppProcess {
+    /// Watch `child`. When the process is killed, the `stop` commands run
+    /// afterwards, for apps that `child` only stands in for (e.g. an app on
+    /// a simulator, watched through the device bridge).
+    pub(crate) fn monitor(
+        mut child: tokio::process::Child,
+        stop: Vec<tokio::process::Command>,
+    ) -> Self {
+        let pid = child.id();
+        let (exit_tx, exit) = tokio::sync::watch::channel(None);
+        let (kill, kill_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            // Dropping the sender (e.g. a detached session) leaves the app running.
+            let status = tokio::select! {
+                status = child.wait() => {
+                    if let Ok(status) = &status {
+                        tracing::info!(
+                            "App process {} exited with {}",
+                            pid.unwrap_or(0),
+                            describe_exit(status)
+                        );
+                    }
+                    status
+                }
+                Ok(()) = kill_rx => {
+                    kill_tree(&mut child).await;
+                    for mut command in stop {
+                        let _ = command
+                            .stdout(std::process::Stdio::null())
+                            .stderr(std::process::Stdio::null())
+                            .status()
+                            .await;
+                    }
+                    child.wait().await
+                }
+            };
+            if let Ok(status) = status {
+                let _ = exit_tx.send(Some(status));
+            }
+        });
+        Self {
+            pid,
+            exit,
+            kill: Some(kill),
+        }
+    }
+
+    /// The pid, or `None` once the process has exited.
+    pub fn id(&self) -> Option<u32> {
+        if self.exit.borrow().is_some() {
+            None
+        } else {
+            self.pid
+        }
+    }
+
+    pub fn try_wait(&mut self) -> std::io::Result<Option<std::process::ExitStatus>> {
+        Ok(*self.exit.borrow())
+    }
+
+    /// Resolves once the process has exited, however it ends.
+    pub fn exited(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut exit = self.exit.clone();
+        async move {
+            let _ = exit.wait_for(Option::is_some).await;
+        }
+    }
+
+    /// Whether the process has exited.
+    pub fn has_exited(&self) -> bool {
+        self.exit.borrow().is_some()
+    }
+
+    /// How the process exited, e.g. `code 134` or `signal 9`, if it has.
+    pub fn exit_description(&self) -> Option<String> {
+        self.exit.borrow().as_ref().map(describe_exit)
+    }
+
+    /// Ask the monitor task to kill the process without waiting.
+    pub fn start_kill(&mut self) -> std::io::Result<()> {
+        if let Some(kill) = self.kill.take() {
+            let _ = kill.send(());
+        }
+        Ok(())
+    }
+
+    /// Kill the process and wait for it to exit.
+    pub async fn kill(&mut self) -> std::io::Result<()> {
+        self.start_kill()?;
+        let _ = self.exit.wait_for(|status| status.is_some()).await;
+        Ok(())
+    }
+}
+
+/// Kill the app and, on Windows, every process it started: WebView2 runs
+/// its renderer in child processes that would otherwise outlive the app.
+pub(crate) async fn kill_tree(child: &mut tokio::process::Child) {
+    #[cfg(windows)]
+    {
+        if let Some(pid) = child.id() {
+            let _ = tokio::process::Command::new("taskkill")
+                .args(["/T", "/F", "/PID", &pid.to_string()])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .await;
+        }
+    }
+    let _ = child.kill().await;
+}
+
+fn describe_exit(status: &std::process::ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("code {code}");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt as _;
+        if let Some(signal) = status.signal() {
+            return format!("signal {signal}");
+        }
+    }
+    status.to_string()
+}
+
+/// Resolve a macOS `.app` bundle to the executable inside it, using
+/// `CFBundleExecutable` from `Contents/Info.plist`. Other paths are returned
+/// unchanged. Launching the executable directly (rather than via `open`)
+/// keeps the app a child of tauri-wd so it can be killed with the session.
+///
+/// On Windows a path without an extension also matches `<path>.exe`.
+pub(crate) fn resolve_executable(path: &Path) -> Result<PathBuf, String> {
+    if cfg!(windows) && path.extension().is_none() && !path.is_file() {
+        let exe = path.with_extension("exe");
+        if exe.is_file() {
+            return Ok(exe);
+        }
+    }
+    let is_bundle = path.extension().is_some_and(|ext| ext == "app") && path.is_dir();
+    if !is_bundle {
+        return Ok(path.to_path_buf());
+    }
+    let macos_dir = path.join("Contents").join("MacOS");
+    let plist = std::fs::read_to_string(path.join("Contents").join("Info.plist")).ok();
+    let name = plist.as_deref().and_then(|plist| {
+        let rest = &plist[plist.find("<key>CFBundleExecutable</key>")?..];
+        let start = rest.find("<string>")? + "<string>".len();
+        let end = rest[start..].find("</string>")? + start;
+        Some(rest[start..end].trim().to_string())
+    });
+    let executable = match name {
+        Some(name) => macos_dir.join(name),
+        // No (XML) Info.plist: fall back to the only file in Contents/MacOS.
+        None => {
+            let mut entries: Vec<_> = std::fs::read_dir(&macos_dir)
+                .map_err(|e| format!("{} is not an app bundle: {e}", path.display()))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file())
+                .collect();
+            if entries.len() != 1 {
+                return Err(format!(
+                    "Could not determine the executable in {}; set tauri:options.binary to Contents/MacOS/<name>",
+                    path.display()
+                ));
+            }
+            entries.remove(0)
+        }
+    };
+    if !executable.is_file() {
+        return Err(format!(
+            "App bundle executable {} does not exist",
+            executable.display()
+        ));
+    }
+    Ok(executable)
+}
+
+/// On Linux the app needs an X11 or Wayland display; without one GTK fails
+/// to initialise and the app exits before the plugin starts. `env` is the
+/// extra environment the app is launched with.
+pub(crate) fn check_display(env: &BTreeMap<String, String>) -> Result<(), String> {
+    if !cfg!(target_os = "linux") {
+        return Ok(());
+    }
+    let set = |name: &str| {
+        env.get(name).is_some_and(|v| !v.is_empty())
+            || std::env::var_os(name).is_some_and(|v| !v.is_empty())
+    };
+    if set("DISPLAY") || set("WAYLAND_DISPLAY") {
+        Ok(())
+    } else {
+        Err("No display for the app: set DISPLAY or WAYLAND_DISPLAY, or run tauri-wd under xvfb-run".to_string())
+    }
+}
+
+/// Environment variable naming the file the plugin writes its port to.
+pub(crate) const PORT_FILE_ENV: &str = "TAURI_WEBDRIVER_PORT_FILE";
+
+/// Environment variable carrying a random per-launch token. The plugin then
+/// rejects requests without it, and release builds only start with one.
+pub(crate) const TOKEN_ENV: &str = "TAURI_WEBDRIVER_TOKEN";
+
+/// Environment variable carrying tauri-wd's PID, which the plugin's
+/// `Builder::peer_check` requires connections to come from.
+pub(crate) const PEER_PID_ENV: &str = "TAURI_WEBDRIVER_PEER_PID";
+
+/// Header the launch token is sent in.
+const TOKEN_HEADER: &str = "x-tauri-webdriver-token";
+
+pub(crate) fn new_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// An HTTP client for the plugin that sends `token` with every request.
+pub(crate) fn plugin_client(token: Option<&str>) -> reqwest::Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(value) = token.and_then(|t| reqwest::header::HeaderValue::from_str(t).ok()) {
+        headers.insert(TOKEN_HEADER, value);
+    }
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap_or_default()
+}
+
+/// Where a plugin listens, as it reported it.
+#[derive(Clone, Debug)]
+pub(crate) struct PluginAddr {
+    /// The plugin's advertised host, `127.0.0.1` unless it's configured to
+    /// be reached from elsewhere.
+    pub host: String,
+    pub port: u16,
+}
+
+impl PluginAddr {
+    /// Parse a port report: the port, optionally followed by the host,
+    /// either on the next line (port file) or after `host` (stdout).
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let port = words.next()?.parse().ok()?;
+        let host = match words.next() {
+            Some("host") => words.next()?,
+            Some(host) => host,
+            None => "127.0.0.1",
+        };
+        Some(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// `http://host:port`, with IPv6 addresses in brackets.
+    pub fn base_url(&self) -> String {
+        if self.host.contains(':') && !self.host.starts_with('[') {
+            format!("http://[{}]:{}", self.host, self.port)
+        } else {
+            format!("http://{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl std::fmt::Display for PluginAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.base_url().trim_start_matches("http://"))
+    }
+}
+
+/// Wait for the plugin to report where it listens, either by writing
+/// `port_file` or (for older plugins) by printing `[webdriver] listening on
+/// port N` to stdout. Returns `None` if the app exits or `timeout` elapses
+/// first.
+pub(crate) async fn wait_for_plugin_port(
+    reader: &mut tokio::io::Lines<tokio::io::BufReader<tokio::process::ChildStdout>>,
+    port_file: &Path,
+    log: Option<&AppLog>,
+    timeout: Duration,
+) -> std::io::Result<Option<PluginAddr>> {
+    let read_port_file = || {
+        let port = std::fs::read_to_string(port_file)
+            .ok()
+            .and_then(|text| PluginAddr::parse(&text));
+        if port.is_some() {
+            let _ = std::fs::remove_file(port_file);
+        }
+        port
+    };
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut poll = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        tokio::select! {
+            line = tokio::time::timeout_at(deadline, reader.next_line()) => match line {
+                Ok(Ok(Some(line))) => {
+                    tracing::debug!("app stdout: {}", line);
+                    if let Some(log) = log {
+                        log.write("stdout", &line);
+                    }
+                    if let Some(rest) = line.strip_prefix("[webdriver] listening on port ") {
+                        if let Some(addr) = PluginAddr::parse(rest) {
+                            let _ = std::fs::remove_file(port_file);
+                            return Ok(Some(addr));
+                        }
+                    }
+                }
+                // stdout closed (the app exited) or timed out.
+                Ok(Ok(None)) | Err(_) => return Ok(read_port_file()),
+                Ok(Err(e)) => return Err(e),
+            },
+            _ = poll.tick() => {
+                if let Some(port) = read_port_file() {
+                    return Ok(Some(port));
+                }
+            }
+        }
+    }
+}
+
+/// Plugin HTTP API version this tauri-wd speaks. Its routes live under
+/// `/v{PLUGIN_API_VERSION}`.
+pub(crate) const PLUGIN_API_VERSION: u64 = 1;
+
+/// Check that the plugin at `base_url` speaks [`PLUGIN_API_VERSION`] and
+/// return where its routes are served.
+pub(crate) async fn negotiate_api(
+    client: &reqwest::Client,
+    base_url: &str,
+) -> Result<PluginApi, String> {
+    let response = client
+        .post(format!("{base_url}/api/version"))
+        .json(&serde_json::json!({}))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .map_err(|e| format!("Plugin version check failed: {e}"))?;
+    let ours = env!("CARGO_PKG_VERSION");
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(format!(
+            "The app's webdriver plugin predates API versioning; \
+             update tauri-plugin-webdriver-automation to {ours}"
+        ));
+    }
+    let info: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Plugin version check failed: {e}"))?;
+    let plugin = info["plugin"].as_str().unwrap_or("unknown");
+    match info["api"].as_u64() {
+        Some(PLUGIN_API_VERSION) => Ok(PluginApi {
+            url: format!("{base_url}/v{PLUGIN_API_VERSION}"),
+            bridge: info["bridge"]
+                .as_str()
+                .unwrap_or("__WEBDRIVER__")
+                .to_string(),
+        }),
+        Some(api) => Err(format!(
+            "The app's webdriver plugin {plugin} speaks API v{api} but tauri-wd {ours} \
+             speaks v{PLUGIN_API_VERSION}; use matching versions of tauri-wd and \
+             tauri-plugin-webdriver-automation"
+        )),
+        None => Err(format!(
+            "The app's webdriver plugin {plugin} did not report an API version"
+        )),
+    }
+}
+
+/// Poll the plugin's `/healthz` until a webview has loaded and its bridge
+/// answers scripts. Plugins without `/healthz` are asked to wait on
+/// `/ready` instead.
+async fn wait_for_health(
+    client: &reqwest::Client,
+    base_url: &str,
+    api: &PluginApi,
+    timeout: Duration,
+) -> Result<(), String> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut last = None;
+    loop {
+        let response = client
+            .get(format!("{base_url}/healthz"))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => {
+                let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+                return wait_for_webview(client, &api.url, remaining).await;
+            }
+            Ok(response) => last = response.json::<serde_json::Value>().await.ok(),
+            Err(e) => tracing::debug!("plugin health check failed: {e}"),
+        }
+        if tokio::time::Instant::now() >= deadline {
+            let why = match last {
+                Some(health) if health["webview"] == false => "no webview was created",
+                Some(health) if health["loaded"] == false => "no webview finished loading",
+                Some(_) => "the webview's bridge did not answer",
+                None => "the plugin did not report its health",
+            };
+            return Err(format!("App was not ready in time: {why}"));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Ask the plugin to wait until a webview has finished loading. Plugins
+/// without `/ready` are assumed ready.
+async fn wait_for_webview(
+    client: &reqwest::Client,
+    plugin_url: &str,
+    timeout: Duration,
+) -> Result<(), String> {
+    let response = client
+        .post(format!("{plugin_url}/ready"))
+        .json(&serde_json::json!({"timeoutMs": timeout.as_millis() as u64}))
+        .timeout(timeout + Duration::from_secs(5))
+        .send()
+        .await
+        .map_err(|e| format!("Plugin readiness check failed: {e}"))?;
+    if response.status().is_success() || response.status() == reqwest::StatusCode::NOT_FOUND {
+        Ok(())
+    } else {
+        Err("App webview did not finish loading in time".to_string())
+    }
+}
+
+/// Negotiate the API version with the plugin at `base_url` and wait until
+/// it can take commands.
+pub(crate) async fn connect_plugin(
+    client: &reqwest::Client,
+    base_url: &str,
+    timeout: Duration,
+) -> Result<PluginApi, String> {
+    let api = negotiate_api(client, base_url).await?;
+    wait_for_health(client, base_url, &api, timeout).await?;
+    Ok(api)
+}
+
+/// The plugin's instance id from `/info`, if it reports one.
+pub(crate) async fn plugin_instance(client: &reqwest::Client, plugin_url: &str) -> Option<String> {
+    let info: serde_json::Value = client
+        .post(format!("{plugin_url}/info"))
+        .json(&serde_json::json!({}))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    info["instance"].as_str().map(|s| s.to_string())
+}
+
+/// Launch the app and wait up to `timeout` for its plugin port. `id` makes
+/// the port file name unique; the app's output goes to `log_path`.
+pub(crate) async fn launch(
+    config: &LaunchConfig,
+    id: &str,
+    log_path: PathBuf,
+    timeout: Duration,
+) -> Result<LaunchedApp, String> {
+    check_display(&config.env)?;
+    let log = AppLog::create(log_path.clone())
+        .map_err(|e| format!("Failed to create app log {}: {e}", log_path.display()))?;
+    let port_file = std::env::temp_dir().join(format!("tauri-wd-port-{id}"));
+    let token = new_token();
+    let mut command = tokio::process::Command::new(&config.executable);
+    if let Some(dir) = &config.cwd {
+        command.current_dir(dir);
+    }
+    let mut child = command
+        .args(&config.args)
+        .envs(&config.env)
+        .env("TAURI_WEBVIEW_AUTOMATION", "true")
+        .env(PORT_FILE_ENV, &port_file)
+        .env(TOKEN_ENV, &token)
+        .env(PEER_PID_ENV, std::process::id().to_string())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to launch {}: {e}", config.executable.display()))?;
+    crate::registry::register(child.id(), id, &config.executable);
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| "Failed to capture app stdout".to_string())?;
+    if let Some(stderr) = child.stderr.take() {
+        let log = log.clone();
+        tokio::spawn(async move {
+            let mut lines = tokio::io::BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                // Still shown on tauri-wd's stderr, as before it was captured.
+                eprintln!("{line}");
+                log.write("stderr", &line);
+            }
+        });
+    }
+
+    // Wait for the plugin port announcement.
+    let mut reader = tokio::io::BufReader::new(stdout).lines();
+    let addr = match wait_for_plugin_port(&mut reader, &port_file, Some(&log), timeout).await {
+        Ok(Some(addr)) => addr,
+        Ok(None) => {
+            crate::registry::unregister(child.id());
+            kill_tree(&mut child).await;
+            return Err(format!(
+                "App did not report plugin port in time (app output: {})",
+                log.path().display()
+            ));
+        }
+        Err(e) => {
+            crate::registry::unregister(child.id());
+            kill_tree(&mut child).await;
+            return Err(format!("IO error reading app stdout: {e}"));
+        }
+    };
+
+    // Drain remaining stdout in background so the app doesn't block.
+    let stdout_log = log.clone();
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = reader.next_line().await {
+            tracing::trace!("app: {}", line);
+            stdout_log.write("stdout", &line);
+        }
+    });
+
+    // Don't hand out the session until a webview has loaded its first page.
+    let client = plugin_client(Some(&token));
+    let api = match connect_plugin(&client, &addr.base_url(), timeout).await {
+        Ok(api) => api,
+        Err(e) => {
+            crate::registry::unregister(child.id());
+            kill_tree(&mut child).await;
+            return Err(format!("{e} (app output: {})", log.path().display()));
+        }
+    };
+
+    Ok(LaunchedApp {
+        process: AppProcess::monitor(child, Vec::new()),
+        plugin_url: api.url,
+        log,
+        client,
+        bridge: api.bridge,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_reads_the_last_lines_across_blocks() {
+        let path = std::env::temp_dir().join(format!("tauri-wd-tail-{}", uuid::Uuid::new_v4()));
+        let log = AppLog::create(path.clone()).unwrap();
+        // Lines of 20 bytes, so the tail crosses a 64 KiB block boundary.
+        for i in 0..10_000 {
+            log.write("stdout", &format!("line {i:08}"));
+        }
+        let tail = log.tail(3).unwrap();
+        assert_eq!(
+            tail,
+            [
+                "[stdout] line 00009997",
+                "[stdout] line 00009998",
+                "[stdout] line 00009999"
+            ]
+        );
+        assert_eq!(log.tail(5_000).unwrap().len(), 5_000);
+        assert_eq!(log.tail(5_000).unwrap()[0], "[stdout] line 00005000");
+        assert_eq!(log.tail(20_000).unwrap().len(), 10_000);
+        assert!(log.tail(0).unwrap().is_empty());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -4,20 +4,43 @@
 // and translates W3C WebDriver commands into plugin API calls.
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use base64::Engine as _;
+use tracing::Instrument as _;
 
-use axum::extract::{Path, State as AxumState};
+use axum::extract::{Path, Query, State as AxumState};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::routing::{delete, get, post};
 use axum::{Json, Router};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use serde_json::{json, Value};
-use tokio::io::AsyncBufReadExt;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+use params::Params;
+
+mod bidi;
+mod build;
+mod conformance;
+mod doctor;
+mod gesture;
+mod health;
+mod launch;
+mod metrics;
+mod mobile;
+mod params;
+mod pool;
+mod refs;
+mod registry;
+mod repl;
+mod replay;
+mod retry;
+mod tls;
+mod trace;
+mod workers;
 
 const W3C_ELEMENT_KEY: &str = "element-6066-11e4-a52e-4f735466cecf";
 const W3C_SHADOW_KEY: &str = "shadow-6066-11e4-a52e-4f735466cecf";
@@ -35,6 +58,11 @@ struct Cli {
     #[arg(long, default_value = "127.0.0.1")]
     host: String,
 
+    /// Run this many independent servers, on consecutive ports from
+    /// --port, and print where they are as JSON
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    workers: Option<u16>,
+
     /// Log level: error, warn, info, debug, trace
     #[arg(long, default_value = "info")]
     log_level: String,
@@ -42,16 +70,150 @@ struct Cli {
     /// Maximum concurrent sessions (0 = unlimited)
     #[arg(long, default_value = "0")]
     max_sessions: usize,
+
+    /// Seconds a new session waits for a free slot when --max-sessions is
+    /// reached (0 = fail immediately)
+    #[arg(long, default_value = "0")]
+    queue_timeout: u64,
+
+    /// Idle app instances to keep pre-launched for sessions that set
+    /// tauri:options.pool (0 = no pool)
+    #[arg(long, default_value = "0")]
+    pool_size: usize,
+
+    /// Retries (with exponential backoff from 100ms) for plugin requests that
+    /// fail to connect, or for read-only requests, fail in transit
+    #[arg(long, default_value = "2")]
+    plugin_retries: u32,
+
+    /// Element (and, separately, shadow root) references kept per session;
+    /// past it the least recently used are dropped
+    #[arg(long, default_value_t = refs::DEFAULT_CAPACITY)]
+    max_elements: usize,
+
+    /// Page errors, network requests and print calls kept per session;
+    /// past it the oldest are dropped
+    #[arg(long, default_value_t = DEFAULT_MAX_LOG_ENTRIES)]
+    max_log_entries: usize,
+
+    /// Log output format
+    #[arg(long, value_enum, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Save a screenshot and page source here whenever a command fails
+    #[arg(long)]
+    artifacts_dir: Option<std::path::PathBuf>,
+
+    /// Enable non-standard debug endpoints such as GET /sessions
+    #[arg(long)]
+    debug_endpoints: bool,
+
+    /// Serve HTTPS using this PEM certificate chain (requires --tls-key)
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<std::path::PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<std::path::PathBuf>,
+
+    /// What a command does while another runs on the same session: queue
+    /// behind it, or fail at once
+    #[arg(long, value_enum, default_value = "queue")]
+    overlap: Overlap,
+
+    /// Let commands that only read (most GETs) on a session run alongside
+    /// each other
+    #[arg(long)]
+    parallel_reads: bool,
+
+    /// Seconds between keep-alive pings to each session's plugin; after 3
+    /// failures in a row commands fail at once. 0 disables them
+    #[arg(long, default_value = "5")]
+    ping_interval: u64,
+
+    /// Largest request body accepted, in MiB; bigger ones fail with
+    /// "invalid argument: payload too large"
+    #[arg(long, default_value = "64")]
+    body_limit: usize,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Replay a recording exported from /session/{id}/tauri/recording
+    Replay {
+        /// Recording JSON file
+        file: std::path::PathBuf,
+
+        /// URL of the tauri-wd server to replay against
+        #[arg(long, default_value = "http://127.0.0.1:4444")]
+        server: String,
+    },
+    /// Kill app processes left behind by a tauri-wd that exited uncleanly
+    Cleanup,
+    /// Launch an app and check that the plugin is reachable
+    Doctor {
+        /// Path to the Tauri app binary
+        #[arg(long)]
+        binary: std::path::PathBuf,
+
+        /// Seconds to wait for the plugin to announce its port
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+    /// Launch an app and explore it from an interactive prompt
+    Repl {
+        /// Path to the Tauri app binary
+        #[arg(long)]
+        binary: std::path::PathBuf,
+    },
+    /// Run WebDriver conformance cases (a WPT classic subset) against an app
+    Conformance {
+        /// Path to the Tauri app binary
+        #[arg(long)]
+        binary: std::path::PathBuf,
+
+        /// Suite JSON file to run instead of the vendored WPT subset
+        #[arg(long)]
+        suite: Option<std::path::PathBuf>,
+
+        /// Only run cases whose `module/file.py::test_name` contains this
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Also write the results to this file as JSON
+        #[arg(long)]
+        report: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+/// What a command does when another one is running on its session.
+#[derive(Clone, Copy, ValueEnum)]
+enum Overlap {
+    /// Wait for it to finish.
+    Queue,
+    /// Fail at once.
+    Reject,
 }
 
 // --- State types ---
 
+#[derive(Clone, PartialEq, Eq, Hash)]
 struct ElementRef {
     selector: String,
     index: usize,
     using: String,
 }
 
+#[derive(Clone, PartialEq, Eq, Hash)]
 struct ShadowRef {
     host_selector: String,
     host_index: usize,
@@ -59,15 +221,15 @@ struct ShadowRef {
 }
 
 struct Timeouts {
-    script: u64,    // ms, default 30000
-    page_load: u64, // ms, default 300000
-    implicit: u64,  // ms, default 0
+    script: Option<u64>, // ms, default 30000; None: no limit
+    page_load: u64,      // ms, default 300000
+    implicit: u64,       // ms, default 0
 }
 
 impl Default for Timeouts {
     fn default() -> Self {
         Self {
-            script: 30000,
+            script: Some(30000),
             page_load: 300000,
             implicit: 0,
         }
@@ -75,23 +237,174 @@ impl Default for Timeouts {
 }
 
 struct Session {
+    binary: String,
+    created_at: std::time::Instant,
     plugin_url: String,
-    process: tokio::process::Child,
-    elements: HashMap<String, ElementRef>,
-    shadows: HashMap<String, ShadowRef>,
+    /// `window` property holding the plugin's JS bridge, from `/api/version`.
+    bridge: String,
+    process: launch::AppProcess,
+    /// The app's stdout/stderr log file.
+    app_log: launch::AppLog,
+    elements: refs::RefStore<ElementRef>,
+    shadows: refs::RefStore<ShadowRef>,
     client: reqwest::Client,
     timeouts: Timeouts,
+    /// Uncaught page errors collected from the plugin over the session
+    /// lifetime, the latest `max_log_entries`.
+    page_errors: Vec<Value>,
+    /// `window.print()` calls collected from the plugin over the session
+    /// lifetime, the latest `max_log_entries`.
+    print_requests: Vec<Value>,
+    /// `tauri:options.failOnPageError`: fail a command if the page threw during it.
+    fail_on_page_error: bool,
+    /// `tauri:options.nativeInput`: perform actions with OS input events.
+    native_input: bool,
+    /// `tauri:options.fastSendKeys`: set text directly instead of typing it
+    /// key by key.
+    fast_send_keys: bool,
+    /// `tauri:options.retry`: retry element interactions that fail with
+    /// these errors.
+    retry: Option<retry::RetryPolicy>,
+    /// Network requests collected from the plugin over the session
+    /// lifetime, the latest `max_log_entries`.
+    network_log: Vec<Value>,
+    /// `--max-log-entries`.
+    max_log_entries: usize,
+    /// Request mock rules installed in the webview, in match order.
+    mocks: Vec<Value>,
+    /// `tauri:options.trace`: command recording written to a zip on session end.
+    trace: Option<trace::TraceRecorder>,
+    /// `tauri:options.record`: every command, for export and replay.
+    recording: Option<replay::Recording>,
+    /// `webSocketUrl` was requested: the session accepts a BiDi connection.
+    bidi: bool,
+    /// BiDi preload scripts (`{id, source, contexts}`) installed in the plugin.
+    preload_scripts: Vec<Value>,
+    /// Temp dir holding files uploaded via `/se/file`, removed with the session.
+    upload_dir: Option<std::path::PathBuf>,
+    /// `tauri:options.baseUrl`: relative navigation URLs are resolved against it.
+    base_url: Option<reqwest::Url>,
+    /// `tauri:options.detach`: leave the app running when the session is deleted.
+    detach: bool,
+    /// The plugin's instance id from `/info`, to tell a reconnect to the same
+    /// plugin from a restarted app. `None` for plugins that don't report one.
+    plugin_instance: Option<String>,
+    /// `--plugin-retries`.
+    plugin_retries: u32,
+    /// Keep-alive ping results for the plugin.
+    health: Arc<health::PluginHealth>,
+}
+
+/// A session, and the gate its commands pass one at a time (see
+/// `session_gate`).
+struct SessionEntry {
+    /// Held for the length of each command: exclusively, or shared among
+    /// reads with `--parallel-reads`.
+    gate: Arc<RwLock<()>>,
+    /// Whether reads may share the gate. Not with `failOnPageError`: its
+    /// check couldn't tell which of the commands running a page error
+    /// happened during.
+    shared_reads: bool,
+    session: Arc<RwLock<Session>>,
+    /// Whether the session's app has exited, readable without waiting for
+    /// the session's commands.
+    exited: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl SessionEntry {
+    /// The entry for `session`, and a future that resolves once its app
+    /// has exited and it no longer counts against `--max-sessions`.
+    fn new(session: Session) -> (Self, impl std::future::Future<Output = ()>) {
+        let exited = Arc::new(std::sync::atomic::AtomicBool::new(
+            session.process.has_exited(),
+        ));
+        let app_exit = session.process.exited();
+        let flag = exited.clone();
+        let entry = Self {
+            gate: Arc::new(RwLock::new(())),
+            shared_reads: !session.fail_on_page_error,
+            session: Arc::new(RwLock::new(session)),
+            exited,
+        };
+        let reaped = async move {
+            app_exit.await;
+            flag.store(true, Ordering::Relaxed);
+        };
+        (entry, reaped)
+    }
+}
+
+/// Sessions holding a `--max-sessions` slot: those whose app still runs.
+/// One whose app exited stays until deleted, so its commands can report
+/// the exit, but doesn't keep a queued session from starting.
+fn running_sessions(sessions: &HashMap<String, SessionEntry>) -> usize {
+    sessions
+        .values()
+        .filter(|entry| !entry.exited.load(Ordering::Relaxed))
+        .count()
 }
 
 struct AppState {
-    sessions: Mutex<HashMap<String, Session>>,
+    sessions: Mutex<HashMap<String, SessionEntry>>,
     max_sessions: usize,
+    artifacts_dir: Option<std::path::PathBuf>,
+    debug_endpoints: bool,
+    pool: Arc<pool::Pool>,
+    /// How long session creation waits for a free slot at `max_sessions`.
+    queue_timeout: Duration,
+    /// Notified whenever a session is deleted.
+    session_freed: tokio::sync::Notify,
+    /// Sessions whose app is being launched, counted against `max_sessions`.
+    launching: AtomicUsize,
+    /// `--plugin-retries`.
+    plugin_retries: u32,
+    /// `--max-elements`.
+    max_elements: usize,
+    /// `--max-log-entries`.
+    max_log_entries: usize,
+    /// Served over HTTPS (`--tls-cert`), so BiDi URLs use `wss://`.
+    tls: bool,
+    /// `--body-limit`, in bytes.
+    body_limit: usize,
+    /// `--ping-interval`.
+    ping_interval: Duration,
+    /// `--overlap`.
+    overlap: Overlap,
+    /// `--parallel-reads`.
+    parallel_reads: bool,
 }
 
 type SharedState = Arc<AppState>;
 
+/// A session slot taken while its app launches; freed once the session is
+/// in the map, or if creating it fails.
+struct LaunchSlot<'a>(&'a AppState);
+
+impl<'a> LaunchSlot<'a> {
+    fn take(state: &'a AppState) -> Self {
+        state.launching.fetch_add(1, Ordering::Relaxed);
+        Self(state)
+    }
+}
+
+impl Drop for LaunchSlot<'_> {
+    fn drop(&mut self) {
+        self.0.launching.fetch_sub(1, Ordering::Relaxed);
+        self.0.session_freed.notify_waiters();
+    }
+}
+
 // --- W3C error handling ---
 
+/// Header carrying a command's request id: echoed on every response, and
+/// sent with each plugin request the command makes.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    /// Id of the W3C command being handled, set by `log_command`.
+    static REQUEST_ID: String;
+}
+
 struct W3cError {
     status: StatusCode,
     error: String,
@@ -136,21 +449,58 @@ impl W3cError {
     fn javascript_error(msg: impl Into<String>) -> Self {
         Self::new(StatusCode::INTERNAL_SERVER_ERROR, "javascript error", msg)
     }
+    fn timeout(msg: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "timeout", msg)
+    }
+    /// The W3C error for a plugin error response to `path`, from the `code`
+    /// the plugin sends with it. Responses without a known code (app
+    /// extension routes, say) are unknown errors.
+    fn from_plugin(path: &str, code: Option<&str>, msg: impl Into<String>) -> Self {
+        let (status, error) = match code {
+            Some("noSuchElement") => (StatusCode::NOT_FOUND, "no such element"),
+            Some("staleElement") => (StatusCode::NOT_FOUND, "stale element reference"),
+            Some("noSuchWindow") => (StatusCode::NOT_FOUND, "no such window"),
+            Some("noSuchFrame") => (StatusCode::NOT_FOUND, "no such frame"),
+            Some("noSuchAlert") => (StatusCode::NOT_FOUND, "no such alert"),
+            Some("noSuchShadowRoot") => (StatusCode::NOT_FOUND, "no such shadow root"),
+            Some("unknownCommand") => (StatusCode::NOT_FOUND, "unknown command"),
+            Some("invalidArgument") => (StatusCode::BAD_REQUEST, "invalid argument"),
+            Some("elementClickIntercepted") => {
+                (StatusCode::BAD_REQUEST, "element click intercepted")
+            }
+            Some("elementNotInteractable") => (StatusCode::BAD_REQUEST, "element not interactable"),
+            Some("unexpectedAlertOpen") => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "unexpected alert open")
+            }
+            Some("javascriptError") => (StatusCode::INTERNAL_SERVER_ERROR, "javascript error"),
+            Some("timeout") if path.starts_with("/script/") => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "script timeout")
+            }
+            Some("timeout") => (StatusCode::INTERNAL_SERVER_ERROR, "timeout"),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "unknown error"),
+        };
+        Self::new(status, error, msg)
+    }
 }
 
+/// W3C error code attached to error responses so middleware can report it.
+#[derive(Clone)]
+struct W3cErrorCode(String);
+
 impl IntoResponse for W3cError {
     fn into_response(self) -> Response {
-        (
-            self.status,
-            Json(json!({
-                "value": {
-                    "error": self.error,
-                    "message": self.message,
-                    "stacktrace": ""
-                }
-            })),
-        )
-            .into_response()
+        let code = W3cErrorCode(self.error.clone());
+        let mut value = json!({
+            "error": self.error,
+            "message": self.message,
+            "stacktrace": ""
+        });
+        if let Ok(id) = REQUEST_ID.try_with(Clone::clone) {
+            value["data"] = json!({"requestId": id});
+        }
+        let mut response = (self.status, Json(json!({ "value": value }))).into_response();
+        response.extensions_mut().insert(code);
+        response
     }
 }
 
@@ -162,31 +512,302 @@ fn w3c_value(val: Value) -> Json<Value> {
     Json(json!({"value": val}))
 }
 
-async fn plugin_post(session: &Session, path: &str, body: Value) -> Result<Value, W3cError> {
-    let url = format!("{}{}", session.plugin_url, path);
-    let resp = session
+/// Plugin requests give up after the W3C timeout that governs them plus this
+/// margin, so a wedged plugin can't hold a handler (and the session lock)
+/// forever.
+const PLUGIN_TIMEOUT_MARGIN: Duration = Duration::from_secs(10);
+
+/// The plugin gives up on a single webview evaluation after this long, so
+/// no request should need less.
+const PLUGIN_EVAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for the plugin to answer `path`: the session's script
+/// timeout for scripts, its page load timeout for navigation, its implicit
+/// wait for finds and the request's own timeout for waits, plus a margin.
+fn plugin_timeout(session: &Session, path: &str, body: &Value) -> Duration {
+    let w3c_ms = match path {
+        "/wait/element" | "/wait/idle" | "/wait/script" => {
+            body.get("timeout").and_then(|t| t.as_u64()).unwrap_or(0)
+        }
+        "/script/execute" | "/script/execute-async" => {
+            session.timeouts.script.unwrap_or(params::MAX_SAFE_INTEGER)
+        }
+        "/navigate/url" | "/navigate/back" | "/navigate/forward" | "/navigate/refresh" => {
+            session.timeouts.page_load
+        }
+        "/element/find" | "/element/find-from" | "/shadow/find" => session.timeouts.implicit,
+        _ => 0,
+    };
+    Duration::from_millis(w3c_ms).max(PLUGIN_EVAL_TIMEOUT) + PLUGIN_TIMEOUT_MARGIN
+}
+
+/// First delay between plugin request retries; doubled on each retry.
+const PLUGIN_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Plugin endpoints that only read state, so a request that may or may not
+/// have reached the plugin can be sent again. Setters stay off the list even
+/// when they set an absolute value: a retry could undo a change made since.
+fn plugin_idempotent(path: &str) -> bool {
+    matches!(
+        path,
+        "/info"
+            | "/ready"
+            | "/window/handle"
+            | "/window/handles"
+            | "/window/rect"
+            | "/window/insets"
+            | "/element/find"
+            | "/element/find-from"
+            | "/element/text"
+            | "/element/attribute"
+            | "/element/property"
+            | "/element/tag"
+            | "/element/rect"
+            | "/element/displayed"
+            | "/element/enabled"
+            | "/element/selected"
+            | "/element/active"
+            | "/element/shadow"
+            | "/element/computed-role"
+            | "/element/computed-label"
+            | "/shadow/find"
+            | "/navigate/current"
+            | "/navigate/title"
+            | "/screenshot"
+            | "/screenshot/element"
+            | "/cookie/get"
+            | "/cookie/get-all"
+            | "/alert/text"
+            | "/source"
+            | "/print"
+    )
+}
+
+/// Ask the plugin for its instance id after a transport error. Fails if a
+/// different plugin instance now answers (the app was restarted), since the
+/// session's element references and window state no longer apply.
+async fn verify_plugin_instance(session: &Session) -> Result<(), W3cError> {
+    let Some(expected) = &session.plugin_instance else {
+        return Ok(());
+    };
+    let info = session
         .client
-        .post(&url)
-        .json(&body)
+        .post(format!("{}/info", session.plugin_url))
+        .json(&json!({}))
+        .timeout(Duration::from_secs(5))
         .send()
+        .await;
+    let Ok(info) = info else {
+        // Still unreachable; the retry will report it.
+        return Ok(());
+    };
+    let instance = info.json::<Value>().await.ok().and_then(|v| {
+        v.get("instance")
+            .and_then(|i| i.as_str())
+            .map(|i| i.to_string())
+    });
+    match instance {
+        Some(instance) if &instance != expected => Err(W3cError::unknown(
+            "plugin restarted: a different app instance now answers on the session's port",
+        )),
+        _ => Ok(()),
+    }
+}
+
+async fn plugin_post(session: &Session, path: &str, body: Value) -> Result<Value, W3cError> {
+    let timeout = plugin_timeout(session, path, &body);
+    let resp = plugin_send(session, path, &body, timeout).await?;
+    resp.json()
         .await
-        .map_err(|e| W3cError::unknown(format!("plugin request failed: {e}")))?;
+        .map_err(|e| plugin_read_error(path, timeout, e))
+}
+
+/// [`plugin_post`] for an element interaction (click, clear, send keys),
+/// sent again after failures the session's `tauri:options.retry` covers.
+async fn plugin_interact(session: &Session, path: &str, body: Value) -> Result<Value, W3cError> {
+    let mut attempt = 1;
+    loop {
+        let e = match plugin_post(session, path, body.clone()).await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        let Some(delay) = session
+            .retry
+            .as_ref()
+            .and_then(|policy| policy.delay(attempt, &e.error))
+        else {
+            return Err(e);
+        };
+        tracing::debug!(
+            "{path} failed with {} (try {attempt}); retrying in {}ms",
+            e.error,
+            delay.as_millis()
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Post to a plugin endpoint that can answer with raw bytes (screenshots,
+/// PDFs), asking it to. Returns the response for its body to be read or
+/// streamed.
+async fn plugin_post_raw(
+    session: &Session,
+    path: &str,
+    mut body: Value,
+) -> Result<reqwest::Response, W3cError> {
+    body.as_object_mut()
+        .ok_or_else(|| W3cError::bad_request("the body must be an object"))?
+        .insert("raw".into(), json!(true));
+    let timeout = plugin_timeout(session, path, &body);
+    plugin_send(session, path, &body, timeout).await
+}
 
-    let status = resp.status();
-    let val: Value = resp
-        .json()
+/// [`plugin_post_raw`], reading the whole body.
+async fn plugin_bytes(session: &Session, path: &str, body: Value) -> Result<Vec<u8>, W3cError> {
+    let timeout = plugin_timeout(session, path, &body);
+    let resp = plugin_post_raw(session, path, body).await?;
+    resp.bytes()
         .await
-        .map_err(|e| W3cError::unknown(format!("plugin response parse failed: {e}")))?;
+        .map(Vec::from)
+        .map_err(|e| plugin_read_error(path, timeout, e))
+}
 
-    if !status.is_success() {
-        let msg = val
-            .get("error")
-            .and_then(|e| e.as_str())
-            .unwrap_or("plugin error");
-        return Err(W3cError::unknown(msg));
+/// Error for a plugin response whose body couldn't be read.
+fn plugin_read_error(path: &str, timeout: Duration, e: reqwest::Error) -> W3cError {
+    if e.is_timeout() {
+        metrics::plugin_failure(path, "timeout");
+        return W3cError::timeout(format!(
+            "plugin did not answer {path} within {}s",
+            timeout.as_secs()
+        ));
+    }
+    metrics::plugin_failure(path, "parse");
+    W3cError::unknown(format!("plugin response parse failed: {e}"))
+}
+
+/// Send a request to the plugin, retrying as its path allows, and turn an
+/// error response into the W3C error it stands for.
+async fn plugin_send(
+    session: &Session,
+    path: &str,
+    body: &Value,
+    timeout: Duration,
+) -> Result<reqwest::Response, W3cError> {
+    let url = format!("{}{}", session.plugin_url, path);
+    let mut attempt = 0;
+    loop {
+        let mut request = session.client.post(&url).json(body).timeout(timeout);
+        if let Ok(id) = REQUEST_ID.try_with(Clone::clone) {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        let sent = request.send().await;
+        let e = match sent {
+            Ok(resp) if resp.status().is_success() => return Ok(resp),
+            Ok(resp) => {
+                let val: Value = resp
+                    .json()
+                    .await
+                    .map_err(|e| plugin_read_error(path, timeout, e))?;
+                metrics::plugin_failure(path, "status");
+                let msg = val
+                    .get("error")
+                    .and_then(|e| e.as_str())
+                    .unwrap_or("plugin error");
+                let code = val.get("code").and_then(|c| c.as_str());
+                // The page navigated away mid-request; the plugin answers
+                // once the new document has loaded, so reads can run again.
+                if code == Some("documentUnloaded")
+                    && plugin_idempotent(path)
+                    && attempt < session.plugin_retries
+                {
+                    attempt += 1;
+                    tracing::debug!(
+                        "Retrying plugin request {path} (attempt {attempt}) after: {msg}"
+                    );
+                    continue;
+                }
+                return Err(W3cError::from_plugin(path, code, msg));
+            }
+            Err(e) => e,
+        };
+        if e.is_timeout() {
+            metrics::plugin_failure(path, "timeout");
+            check_app_alive(session)?;
+            let message = format!("plugin did not answer {path} within {}s", timeout.as_secs());
+            return Err(if path.starts_with("/script/") {
+                W3cError::new(StatusCode::INTERNAL_SERVER_ERROR, "script timeout", message)
+            } else {
+                W3cError::timeout(message)
+            });
+        }
+        metrics::plugin_failure(path, "transport");
+        // A refused connection never reached the plugin; anything else may
+        // have, so only idempotent requests are sent again.
+        let retryable = e.is_connect() || (plugin_idempotent(path) && !e.is_builder());
+        if !retryable || attempt >= session.plugin_retries {
+            return Err(check_app_alive(session)
+                .err()
+                .unwrap_or_else(|| W3cError::unknown(format!("plugin request failed: {e}"))));
+        }
+        tokio::time::sleep(PLUGIN_RETRY_BACKOFF * 2u32.pow(attempt)).await;
+        attempt += 1;
+        check_app_alive(session)?;
+        verify_plugin_instance(session).await?;
+        tracing::debug!("Retrying plugin request {path} (attempt {attempt}) after: {e}");
+    }
+}
+
+/// The W3C response `{"value": "<base64 of resp's body>"}`, encoded as the
+/// plugin's bytes arrive, so a large screenshot or PDF is never held whole
+/// in either form.
+fn base64_value_response(resp: reqwest::Response) -> Response {
+    use axum::body::Bytes;
+
+    struct Encoder {
+        resp: Option<reqwest::Response>,
+        /// Bytes left over from the last chunk, fewer than 3, encoded with
+        /// the next one so no padding lands mid-string.
+        carry: Vec<u8>,
+        started: bool,
     }
 
-    Ok(val)
+    let encoder = Encoder {
+        resp: Some(resp),
+        carry: Vec::new(),
+        started: false,
+    };
+    let stream = futures_util::stream::unfold(encoder, |mut enc| async move {
+        if !enc.started {
+            enc.started = true;
+            return Some((Ok(Bytes::from_static(b"{\"value\":\"")), enc));
+        }
+        let resp = enc.resp.as_mut()?;
+        match resp.chunk().await {
+            Ok(Some(chunk)) => {
+                enc.carry.extend_from_slice(&chunk);
+                let whole = enc.carry.len() / 3 * 3;
+                let encoded = base64::engine::general_purpose::STANDARD.encode(&enc.carry[..whole]);
+                enc.carry.drain(..whole);
+                Some((Ok(Bytes::from(encoded)), enc))
+            }
+            Ok(None) => {
+                enc.resp = None;
+                let mut tail = base64::engine::general_purpose::STANDARD.encode(&enc.carry);
+                tail.push_str("\"}");
+                Some((Ok(Bytes::from(tail)), enc))
+            }
+            Err(e) => {
+                enc.resp = None;
+                Some((Err(e), enc))
+            }
+        }
+    });
+    (
+        [(axum::http::header::CONTENT_TYPE, "application/json")],
+        axum::body::Body::from_stream(stream),
+    )
+        .into_response()
 }
 
 fn resolve_element<'a>(session: &'a Session, eid: &str) -> Result<&'a ElementRef, W3cError> {
@@ -196,15 +817,8 @@ fn resolve_element<'a>(session: &'a Session, eid: &str) -> Result<&'a ElementRef
         .ok_or_else(|| W3cError::no_element(eid))
 }
 
-fn extract_locator(body: &Value) -> Result<(String, String), W3cError> {
-    let strategy = body
-        .get("using")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| W3cError::bad_request("Missing 'using'"))?;
-    let value = body
-        .get("value")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| W3cError::bad_request("Missing 'value'"))?;
+fn extract_locator(body: &params::LocatorReq) -> Result<(String, String), W3cError> {
+    let (strategy, value) = (body.using.as_str(), body.value.as_str());
 
     let (using, actual_value) = match strategy {
         "css selector" => ("css".to_string(), value.to_string()),
@@ -238,45 +852,283 @@ fn store_element(session: &mut Session, elem: &Value) -> String {
         .unwrap_or("css")
         .to_string();
 
-    // Return existing ID if we already mapped this exact element.
-    for (eid, eref) in &session.elements {
-        if eref.selector == selector && eref.index == index && eref.using == using {
-            return eid.clone();
+    session.elements.insert(ElementRef {
+        selector,
+        index,
+        using,
+    })
+}
+
+/// Swap the element and shadow root ids in script arguments for the plugin
+/// references behind them, which the bridge turns back into nodes.
+fn script_args_to_plugin(session: &Session, value: &mut Value) -> Result<(), W3cError> {
+    let reference = match &*value {
+        Value::Object(map) => {
+            if let Some(eid) = map.get(W3C_ELEMENT_KEY).and_then(Value::as_str) {
+                let elem = resolve_element(session, eid)?;
+                Some(json!({W3C_ELEMENT_KEY: {
+                    "selector": elem.selector, "index": elem.index, "using": elem.using
+                }}))
+            } else if let Some(id) = map.get(W3C_SHADOW_KEY).and_then(Value::as_str) {
+                let shadow = session.shadows.get(id).ok_or_else(|| {
+                    W3cError::new(
+                        StatusCode::NOT_FOUND,
+                        "no such shadow root",
+                        format!("Shadow root {id} not found"),
+                    )
+                })?;
+                Some(json!({W3C_SHADOW_KEY: {
+                    "selector": shadow.host_selector,
+                    "index": shadow.host_index,
+                    "using": shadow.host_using
+                }}))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    if let Some(reference) = reference {
+        *value = reference;
+        return Ok(());
+    }
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                script_args_to_plugin(session, item)?;
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values_mut() {
+                script_args_to_plugin(session, item)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Whether a script result holds plugin element or shadow root references.
+fn holds_plugin_refs(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => items.iter().any(holds_plugin_refs),
+        Value::Object(map) => {
+            [W3C_ELEMENT_KEY, W3C_SHADOW_KEY]
+                .iter()
+                .any(|key| map.get(*key).is_some_and(Value::is_object))
+                || map.values().any(holds_plugin_refs)
         }
+        _ => false,
     }
+}
 
-    let eid = uuid::Uuid::new_v4().to_string();
-    session.elements.insert(
-        eid.clone(),
-        ElementRef {
-            selector,
-            index,
-            using,
-        },
-    );
-    eid
+/// Give the plugin references in a script result ids in the session, the
+/// way finds do.
+fn store_script_refs(session: &mut Session, value: &mut Value) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                store_script_refs(session, item);
+            }
+        }
+        Value::Object(map) => {
+            if let Some(elem) = map.get(W3C_ELEMENT_KEY).filter(|e| e.is_object()) {
+                let eid = store_element(session, elem);
+                *value = json!({W3C_ELEMENT_KEY: eid});
+            } else if let Some(host) = map.get(W3C_SHADOW_KEY).filter(|h| h.is_object()) {
+                let shadow_id = session.shadows.insert(ShadowRef {
+                    host_selector: host["selector"].as_str().unwrap_or("").to_string(),
+                    host_index: host["index"].as_u64().unwrap_or(0) as usize,
+                    host_using: host["using"].as_str().unwrap_or("css").to_string(),
+                });
+                *value = json!({W3C_SHADOW_KEY: shadow_id});
+            } else {
+                for item in map.values_mut() {
+                    store_script_refs(session, item);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Error for commands on a session whose app has exited, or whose plugin
+/// has stopped answering keep-alive pings.
+fn check_app_alive(session: &Session) -> Result<(), W3cError> {
+    match session.process.exit_description() {
+        Some(exit) => Err(W3cError::unknown(format!(
+            "app process exited with {exit}; delete the session"
+        ))),
+        None => session.health.check().map_err(W3cError::unknown),
+    }
 }
 
-fn get_session<'a>(
-    sessions: &'a HashMap<String, Session>,
-    sid: &str,
-) -> Result<&'a Session, W3cError> {
-    sessions.get(sid).ok_or(W3cError::no_session())
+impl AppState {
+    /// The session `sid`, whether or not its app still runs.
+    async fn find_session(&self, sid: &str) -> Option<Arc<RwLock<Session>>> {
+        let sessions = self.sessions.lock().await;
+        sessions.get(sid).map(|entry| entry.session.clone())
+    }
+
+    /// The gate `sid`'s commands pass through, see `session_gate`, and
+    /// whether reads may share it.
+    async fn command_gate(&self, sid: &str) -> Option<(Arc<RwLock<()>>, bool)> {
+        let sessions = self.sessions.lock().await;
+        sessions
+            .get(sid)
+            .map(|entry| (entry.gate.clone(), entry.shared_reads))
+    }
+
+    /// Every session, by id, without holding the map while each is used.
+    async fn all_sessions(&self) -> Vec<(String, Arc<RwLock<Session>>)> {
+        let sessions = self.sessions.lock().await;
+        sessions
+            .iter()
+            .map(|(sid, entry)| (sid.clone(), entry.session.clone()))
+            .collect()
+    }
+
+    /// The session `sid` to read, failing if its app is gone.
+    async fn session(&self, sid: &str) -> Result<OwnedRwLockReadGuard<Session>, W3cError> {
+        let session = self
+            .find_session(sid)
+            .await
+            .ok_or(W3cError::no_session())?
+            .read_owned()
+            .await;
+        check_app_alive(&session)?;
+        Ok(session)
+    }
+
+    /// The session `sid` to change, failing if its app is gone.
+    async fn session_mut(&self, sid: &str) -> Result<OwnedRwLockWriteGuard<Session>, W3cError> {
+        let session = self
+            .find_session(sid)
+            .await
+            .ok_or(W3cError::no_session())?
+            .write_owned()
+            .await;
+        check_app_alive(&session)?;
+        Ok(session)
+    }
 }
 
-fn get_session_mut<'a>(
-    sessions: &'a mut HashMap<String, Session>,
-    sid: &str,
-) -> Result<&'a mut Session, W3cError> {
-    sessions.get_mut(sid).ok_or(W3cError::no_session())
+/// Look up a `tauri:options` entry, checking `alwaysMatch` then the first `firstMatch`.
+fn tauri_option<'a>(body: &'a Value, key: &str) -> Option<&'a Value> {
+    body.pointer(&format!("/capabilities/alwaysMatch/tauri:options/{key}"))
+        .or_else(|| body.pointer(&format!("/capabilities/firstMatch/0/tauri:options/{key}")))
 }
 
 // --- Session handlers ---
 
+async fn get_metrics(AxumState(state): AxumState<SharedState>) -> Response {
+    let (active, exited) = {
+        let sessions = state.sessions.lock().await;
+        let running = running_sessions(&sessions);
+        (running, sessions.len() - running)
+    };
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        metrics::render(active, exited, state.max_sessions),
+    )
+        .into_response()
+}
+
+/// Debug endpoint (with `--debug-endpoints`): list active sessions.
+async fn list_sessions(AxumState(state): AxumState<SharedState>) -> W3cResult {
+    if !state.debug_endpoints {
+        return Err(W3cError::new(
+            StatusCode::NOT_FOUND,
+            "unknown command",
+            "GET /sessions requires --debug-endpoints",
+        ));
+    }
+    let entries: Vec<_> = {
+        let sessions = state.sessions.lock().await;
+        sessions
+            .iter()
+            .map(|(sid, entry)| (sid.clone(), entry.gate.clone(), entry.session.clone()))
+            .collect()
+    };
+    let mut list = Vec::new();
+    for (sid, gate, session) in entries {
+        // Waiting for the session would wait out the command it's running;
+        // report it as busy instead.
+        let idle = gate.try_read().is_ok();
+        let (true, Ok(session)) = (idle, session.try_read()) else {
+            list.push(json!({"id": sid, "busy": true}));
+            continue;
+        };
+        let healthy = session.health.check().is_ok();
+        // Don't wait out a timeout on a plugin that's known to be gone.
+        let current_window = if healthy {
+            plugin_post(&session, "/window/handle", json!({}))
+                .await
+                .unwrap_or(Value::Null)
+        } else {
+            Value::Null
+        };
+        list.push(json!({
+            "id": sid,
+            "binary": session.binary,
+            "pluginUrl": session.plugin_url,
+            "bridge": session.bridge,
+            "uptimeSecs": session.created_at.elapsed().as_secs(),
+            "pid": session.process.id(),
+            "currentWindow": current_window,
+            "healthy": healthy,
+            "busy": false,
+        }));
+    }
+    Ok(w3c_value(json!(list)))
+}
+
 async fn get_status(AxumState(state): AxumState<SharedState>) -> Json<Value> {
-    let sessions = state.sessions.lock().await;
+    let running = running_sessions(&*state.sessions.lock().await);
+    let sessions = state.all_sessions().await;
     let count = sessions.len();
-    let ready = state.max_sessions == 0 || count < state.max_sessions;
+    let ready = state.max_sessions == 0 || running < state.max_sessions;
+
+    // Per-session health, so orchestration can spot crashed or wedged apps.
+    let mut health = Vec::new();
+    for (sid, session) in sessions {
+        // Probing would wait for the running command; it's busy, not dead.
+        let Ok(mut session) = session.try_write_owned() else {
+            health.push(json!({"id": sid, "busy": true}));
+            continue;
+        };
+        let process = match session.process.try_wait() {
+            Ok(None) => json!({"alive": true, "pid": session.process.id()}),
+            Ok(Some(status)) => json!({
+                "alive": false,
+                "exitCode": status.code(),
+                "exit": session.process.exit_description(),
+            }),
+            Err(e) => json!({"alive": false, "error": e.to_string()}),
+        };
+        let probe = tokio::time::timeout(
+            Duration::from_secs(1),
+            // Not `/window/handle`, which fails once the current window is closed.
+            plugin_post(&session, "/window/handles", json!({})),
+        )
+        .await;
+        let plugin = match probe {
+            Ok(Ok(_)) => json!({"reachable": true}),
+            Ok(Err(e)) => json!({"reachable": false, "error": e.message}),
+            Err(_) => json!({"reachable": false, "error": "timed out"}),
+        };
+        let healthy = process["alive"] == json!(true) && plugin["reachable"] == json!(true);
+        health.push(json!({
+            "id": sid,
+            "healthy": healthy,
+            "process": process,
+            "plugin": plugin,
+        }));
+    }
+
     w3c_value(json!({
         "ready": ready,
         "message": if count == 0 {
@@ -285,110 +1137,374 @@ async fn get_status(AxumState(state): AxumState<SharedState>) -> Json<Value> {
             format!("{count} session(s) active, accepting more")
         } else {
             format!("{count} session(s) active, at capacity")
-        }
+        },
+        "sessions": health,
+        "pool": {"size": state.pool.size(), "idle": state.pool.idle_count()}
     }))
 }
 
+/// W3C `platformName` for the OS tauri-wd (and so the app) runs on.
+fn platform_name() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "mac",
+        os => os,
+    }
+}
+
 async fn create_session(
     AxumState(state): AxumState<SharedState>,
-    Json(body): Json<Value>,
+    headers: axum::http::HeaderMap,
+    Params(body): Params<Value>,
 ) -> Result<(StatusCode, Json<Value>), W3cError> {
-    let mut sessions = state.sessions.lock().await;
-    if state.max_sessions > 0 && sessions.len() >= state.max_sessions {
+    params::parse::<params::NewSessionReq>(&body)?;
+    // Build first, without holding the session lock, when given a project.
+    let built = match tauri_option(&body, "project") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(project)) => Some(
+            build::build_project(std::path::Path::new(project))
+                .await
+                .map_err(W3cError::session_not_created)?,
+        ),
+        Some(_) => {
+            return Err(W3cError::bad_request(
+                "tauri:options.project must be a directory path",
+            ))
+        }
+    };
+
+    // At capacity, wait up to --queue-timeout for a session to end.
+    let deadline = tokio::time::Instant::now() + state.queue_timeout;
+    // The slot is held while the app launches, without blocking commands
+    // on other sessions.
+    let _slot = loop {
+        let sessions = state.sessions.lock().await;
+        let launching = state.launching.load(Ordering::Relaxed);
+        if state.max_sessions == 0 || running_sessions(&sessions) + launching < state.max_sessions {
+            break LaunchSlot::take(&state);
+        }
+        if state.queue_timeout.is_zero() {
+            return Err(W3cError::session_not_created(
+                "Maximum number of sessions reached",
+            ));
+        }
+        let freed = state.session_freed.notified();
+        drop(sessions);
+        if tokio::time::timeout_at(deadline, freed).await.is_err() {
+            return Err(W3cError::session_not_created(format!(
+                "Maximum number of sessions reached; no session ended within {}s",
+                state.queue_timeout.as_secs()
+            )));
+        }
+    };
+
+    let mobile = match tauri_option(&body, "mobile") {
+        None | Some(Value::Null) => None,
+        Some(value) => {
+            Some(mobile::MobileTarget::from_option(value).map_err(W3cError::bad_request)?)
+        }
+    };
+
+    // Extract binary path from capabilities.
+    // Accept both "binary" and "application" as capability keys.
+    let binary = match (built, &mobile) {
+        (Some(path), _) => path.display().to_string(),
+        (None, Some(target)) => target.to_string(),
+        (None, None) => tauri_option(&body, "binary")
+            .or_else(|| tauri_option(&body, "application"))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                W3cError::session_not_created(
+                    "Missing tauri:options.binary (or application, or project) in capabilities",
+                )
+            })?
+            .to_string(),
+    };
+    let fail_on_page_error = tauri_option(&body, "failOnPageError")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let native_input = tauri_option(&body, "nativeInput")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let fast_send_keys = tauri_option(&body, "fastSendKeys")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let retry = match tauri_option(&body, "retry") {
+        None => None,
+        Some(value) => retry::RetryPolicy::from_option(value).map_err(W3cError::bad_request)?,
+    };
+    let popup_behavior = match tauri_option(&body, "popupBehavior") {
+        None | Some(Value::Null) => "open-window",
+        Some(Value::String(b)) if ["open-window", "same-window", "block"].contains(&b.as_str()) => {
+            b.as_str()
+        }
+        Some(_) => {
+            return Err(W3cError::bad_request(
+                "tauri:options.popupBehavior must be \"open-window\", \"same-window\" or \"block\"",
+            ))
+        }
+    };
+    let basic_auth = match tauri_option(&body, "basicAuth") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(value) => basic_auth_credentials(value)
+            .map_err(|e| W3cError::bad_request(format!("tauri:options.basicAuth: {e}")))?,
+    };
+    if native_input && (mobile.is_some() || !cfg!(target_os = "macos")) {
         return Err(W3cError::session_not_created(
-            "Maximum number of sessions reached",
+            "tauri:options.nativeInput is only supported for macOS desktop apps",
         ));
     }
+    let args: Vec<String> = match tauri_option(&body, "args") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|_| W3cError::bad_request("tauri:options.args must be an array of strings"))?,
+    };
+    let env: std::collections::BTreeMap<String, String> = match tauri_option(&body, "env") {
+        None | Some(Value::Null) => std::collections::BTreeMap::new(),
+        Some(value) => serde_json::from_value(value.clone()).map_err(|_| {
+            W3cError::bad_request("tauri:options.env must be an object of string values")
+        })?,
+    };
+    let cwd = match tauri_option(&body, "cwd") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(dir)) => {
+            let dir = std::path::PathBuf::from(dir);
+            if !dir.is_dir() {
+                return Err(W3cError::session_not_created(format!(
+                    "tauri:options.cwd {} is not a directory",
+                    dir.display()
+                )));
+            }
+            Some(dir)
+        }
+        Some(_) => {
+            return Err(W3cError::bad_request(
+                "tauri:options.cwd must be a directory path",
+            ))
+        }
+    };
+    let detach = tauri_option(&body, "detach")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let pooled = tauri_option(&body, "pool")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+        && state.pool.enabled();
+    let base_url = match tauri_option(&body, "baseUrl") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(url)) => Some(reqwest::Url::parse(url).map_err(|e| {
+            W3cError::bad_request(format!("tauri:options.baseUrl is not an absolute URL: {e}"))
+        })?),
+        Some(_) => {
+            return Err(W3cError::bad_request(
+                "tauri:options.baseUrl must be a string",
+            ))
+        }
+    };
+    let record = tauri_option(&body, "record")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let bidi = body
+        .pointer("/capabilities/alwaysMatch/webSocketUrl")
+        .or_else(|| body.pointer("/capabilities/firstMatch/0/webSocketUrl"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let trace_path = match tauri_option(&body, "trace") {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => None,
+        Some(Value::Bool(true)) => {
+            Some(std::env::temp_dir().join(format!("tauri-wd-trace-{session_id}.zip")))
+        }
+        Some(Value::String(path)) => Some(std::path::PathBuf::from(path)),
+        Some(_) => {
+            return Err(W3cError::bad_request(
+                "tauri:options.trace must be a boolean or a file path",
+            ))
+        }
+    };
 
-    // Extract binary path from capabilities.
-    // Accept both "binary" and "application" as capability keys.
-    let binary = body
-        .pointer("/capabilities/alwaysMatch/tauri:options/binary")
-        .or_else(|| body.pointer("/capabilities/alwaysMatch/tauri:options/application"))
-        .or_else(|| body.pointer("/capabilities/firstMatch/0/tauri:options/binary"))
-        .or_else(|| body.pointer("/capabilities/firstMatch/0/tauri:options/application"))
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| {
-            W3cError::session_not_created(
-                "Missing tauri:options.binary (or application) in capabilities",
-            )
-        })?
-        .to_string();
+    let app_log_path = match tauri_option(&body, "appLog") {
+        None | Some(Value::Null) => {
+            std::env::temp_dir().join(format!("tauri-wd-app-{session_id}.log"))
+        }
+        // A pooled app was launched before the session, logging to its own
+        // file; the capabilities say which.
+        Some(Value::String(_)) if pooled && mobile.is_none() => return Err(W3cError::bad_request(
+            "tauri:options.appLog can't be combined with pool: pooled apps log to their own file",
+        )),
+        Some(Value::String(path)) => std::path::PathBuf::from(path),
+        Some(_) => {
+            return Err(W3cError::bad_request(
+                "tauri:options.appLog must be a file path",
+            ))
+        }
+    };
 
     // Launch the Tauri app.
-    let mut child = tokio::process::Command::new(&binary)
-        .env("TAURI_WEBVIEW_AUTOMATION", "true")
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::inherit())
-        .spawn()
-        .map_err(|e| W3cError::session_not_created(format!("Failed to launch {binary}: {e}")))?;
-
-    let stdout = child
-        .stdout
-        .take()
-        .ok_or_else(|| W3cError::session_not_created("Failed to capture app stdout"))?;
-
-    // Watch stdout for the plugin port announcement.
-    let mut reader = tokio::io::BufReader::new(stdout).lines();
-    let mut port: Option<u16> = None;
-    let deadline = tokio::time::Instant::now() + Duration::from_secs(30);
-
-    loop {
-        match tokio::time::timeout_at(deadline, reader.next_line()).await {
-            Ok(Ok(Some(line))) => {
-                tracing::debug!("app stdout: {}", line);
-                if let Some(rest) = line.strip_prefix("[webdriver] listening on port ") {
-                    if let Ok(p) = rest.trim().parse::<u16>() {
-                        port = Some(p);
-                        break;
-                    }
+    let (app, binary) = match &mobile {
+        Some(target) => {
+            // Installing and booting on a device is slower than a local launch.
+            let app = mobile::launch(
+                target,
+                &args,
+                &env,
+                &session_id,
+                app_log_path,
+                Duration::from_secs(60),
+            )
+            .await
+            .map_err(W3cError::session_not_created)?;
+            (app, binary)
+        }
+        None => {
+            let mut executable = launch::resolve_executable(std::path::Path::new(&binary))
+                .map_err(W3cError::session_not_created)?;
+            // A relative binary path is relative to tauri-wd's directory, not `cwd`.
+            if executable.is_relative() && executable.components().count() > 1 {
+                if let Ok(dir) = std::env::current_dir() {
+                    executable = dir.join(executable);
                 }
             }
-            Ok(Ok(None)) => break,
-            Ok(Err(e)) => {
-                return Err(W3cError::session_not_created(format!(
-                    "IO error reading app stdout: {e}"
-                )));
+            let config = launch::LaunchConfig {
+                executable: executable.clone(),
+                args: args.clone(),
+                env: env.clone(),
+                cwd: cwd.clone(),
+            };
+            let app = match pooled.then(|| state.pool.take(&config)).flatten() {
+                Some(app) => {
+                    tracing::debug!("Session {session_id} uses a pooled app");
+                    app
+                }
+                None => launch::launch(&config, &session_id, app_log_path, Duration::from_secs(30))
+                    .await
+                    .map_err(W3cError::session_not_created)?,
+            };
+            if pooled {
+                state.pool.refill(config);
             }
-            Err(_) => break,
+            (app, executable.display().to_string())
         }
-    }
+    };
+    let plugin_url = app.plugin_url;
+    let plugin_instance = launch::plugin_instance(&app.client, &plugin_url).await;
+    let health = health::watch(app.client.clone(), plugin_url.clone(), state.ping_interval);
+    tracing::info!("Session {session_id} created, plugin at {plugin_url}");
 
-    let port = port
-        .ok_or_else(|| W3cError::session_not_created("App did not report plugin port in time"))?;
+    let session_retry = retry.as_ref().map(retry::RetryPolicy::to_json);
+    let mut session = Session {
+        binary: binary.clone(),
+        created_at: std::time::Instant::now(),
+        plugin_url,
+        bridge: app.bridge,
+        process: app.process,
+        app_log: app.log.clone(),
+        elements: refs::RefStore::new(state.max_elements),
+        shadows: refs::RefStore::new(state.max_elements),
+        client: app.client,
+        timeouts: Timeouts::default(),
+        page_errors: Vec::new(),
+        print_requests: Vec::new(),
+        fail_on_page_error,
+        native_input,
+        fast_send_keys,
+        retry,
+        network_log: Vec::new(),
+        max_log_entries: state.max_log_entries,
+        mocks: Vec::new(),
+        trace: trace_path.clone().map(trace::TraceRecorder::new),
+        recording: record.then(|| {
+            replay::Recording::new(body.get("capabilities").cloned().unwrap_or(json!({})))
+        }),
+        bidi,
+        preload_scripts: Vec::new(),
+        upload_dir: None,
+        base_url: base_url.clone(),
+        detach,
+        plugin_instance,
+        plugin_retries: state.plugin_retries,
+        health,
+    };
+    // Options the plugin takes over HTTP once the app is up.
+    let mut settings = Vec::new();
+    if popup_behavior != "open-window" {
+        settings.push((
+            "popupBehavior",
+            "/popup/behavior",
+            json!({"behavior": popup_behavior}),
+        ));
+    }
+    if !basic_auth.is_empty() {
+        settings.push((
+            "basicAuth",
+            "/auth/basic",
+            json!({"credentials": basic_auth}),
+        ));
+    }
+    for (option, path, payload) in settings {
+        if let Err(e) = plugin_post(&session, path, payload).await {
+            registry::unregister(session.process.id());
+            let _ = session.process.kill().await;
+            return Err(W3cError::session_not_created(format!(
+                "Failed to apply tauri:options.{option}: {}",
+                e.message
+            )));
+        }
+    }
 
-    // Drain remaining stdout in background so the app doesn't block.
+    let (entry, reaped) = SessionEntry::new(session);
+    state
+        .sessions
+        .lock()
+        .await
+        .insert(session_id.clone(), entry);
+    // A queued session can take the slot as soon as the app exits.
+    let freed = state.clone();
     tokio::spawn(async move {
-        while let Ok(Some(line)) = reader.next_line().await {
-            tracing::trace!("app: {}", line);
-        }
+        reaped.await;
+        freed.session_freed.notify_waiters();
     });
 
-    let session_id = uuid::Uuid::new_v4().to_string();
-    let plugin_url = format!("http://127.0.0.1:{port}");
-    tracing::info!("Session {session_id} created, plugin at {plugin_url}");
-
-    sessions.insert(
-        session_id.clone(),
-        Session {
-            plugin_url,
-            process: child,
-            elements: HashMap::new(),
-            shadows: HashMap::new(),
-            client: reqwest::Client::new(),
-            timeouts: Timeouts::default(),
-        },
-    );
+    let mut capabilities = json!({
+        "browserName": "tauri",
+        "platformName": mobile.as_ref().map_or(platform_name(), |m| m.platform_name()),
+        "tauri:options": {
+            "binary": mobile.is_none().then_some(&binary),
+            "project": tauri_option(&body, "project"),
+            "args": args,
+            "env": env,
+            "cwd": cwd.map(|d| d.display().to_string()),
+            "failOnPageError": fail_on_page_error,
+            "nativeInput": native_input,
+            "fastSendKeys": fast_send_keys,
+            "retry": session_retry,
+            "popupBehavior": popup_behavior,
+            // Echoed without the passwords.
+            "basicAuth": basic_auth
+                .iter()
+                .map(|c| json!({"host": c.get("host"), "username": c["username"]}))
+                .collect::<Vec<_>>(),
+            "trace": trace_path.map(|p| p.display().to_string()),
+            "record": record,
+            "detach": detach,
+            "baseUrl": base_url.map(|u| u.to_string()),
+            "pool": pooled && mobile.is_none(),
+            "appLog": app.log.path().display().to_string()
+        }
+    });
+    if bidi {
+        let host = headers
+            .get(axum::http::header::HOST)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("127.0.0.1:4444");
+        let scheme = if state.tls { "wss" } else { "ws" };
+        capabilities["webSocketUrl"] = json!(format!("{scheme}://{host}/session/{session_id}"));
+    }
 
     Ok((
         StatusCode::OK,
         w3c_value(json!({
             "sessionId": session_id,
-            "capabilities": {
-                "browserName": "tauri",
-                "platformName": "mac",
-                "tauri:options": { "binary": binary }
-            }
+            "capabilities": capabilities
         })),
     ))
 }
@@ -397,9 +1513,47 @@ async fn delete_session(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let mut sessions = state.sessions.lock().await;
-    let mut session = sessions.remove(&sid).ok_or(W3cError::no_session())?;
-    let _ = session.process.kill().await;
+    // Fail any script still running in the app, so the command waiting on
+    // it returns now rather than at its script timeout; then let commands
+    // already running on the session finish.
+    let session = state
+        .find_session(&sid)
+        .await
+        .ok_or(W3cError::no_session())?;
+    {
+        let session = session.read().await;
+        if session.process.exit_description().is_none() {
+            let _ = plugin_post(&session, "/script/cancel", json!({})).await;
+        }
+    }
+    let (gate, _) = state
+        .command_gate(&sid)
+        .await
+        .ok_or(W3cError::no_session())?;
+    let _gate = gate.write_owned().await;
+    let entry = state
+        .sessions
+        .lock()
+        .await
+        .remove(&sid)
+        .ok_or(W3cError::no_session())?;
+    let mut session = entry.session.write_owned().await;
+    finish_trace(&mut session).await;
+    if session.detach {
+        // The app outlives the session, so fail any script a command gave
+        // up on since.
+        let _ = plugin_post(&session, "/script/cancel", json!({})).await;
+        tracing::info!(
+            "Session {sid} detached; app left running (pid {})",
+            session.process.id().unwrap_or(0)
+        );
+        registry::unregister(session.process.id());
+    } else {
+        registry::unregister(session.process.id());
+        let _ = session.process.kill().await;
+    }
+    remove_uploads(&session).await;
+    state.session_freed.notify_waiters();
     tracing::info!("Session {sid} deleted");
     Ok(w3c_value(json!(null)))
 }
@@ -410,8 +1564,8 @@ async fn get_timeouts(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     Ok(w3c_value(json!({
         "script": session.timeouts.script,
         "pageLoad": session.timeouts.page_load,
@@ -422,17 +1576,17 @@ async fn get_timeouts(
 async fn set_timeouts(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::TimeoutsReq>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
-    if let Some(v) = body.get("script").and_then(|v| v.as_u64()) {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    if let Some(v) = body.script {
         session.timeouts.script = v;
     }
-    if let Some(v) = body.get("pageLoad").and_then(|v| v.as_u64()) {
+    if let Some(v) = body.page_load {
         session.timeouts.page_load = v;
     }
-    if let Some(v) = body.get("implicit").and_then(|v| v.as_u64()) {
+    if let Some(v) = body.implicit {
         session.timeouts.implicit = v;
     }
     Ok(w3c_value(json!(null)))
@@ -443,35 +1597,42 @@ async fn set_timeouts(
 async fn navigate_to(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::NavigateReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let url = body
-        .get("url")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| W3cError::bad_request("Missing url"))?;
-    plugin_post(session, "/navigate/url", json!({"url": url})).await?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let url = body.url.as_str();
+    // `join` leaves absolute URLs unchanged.
+    let url = match &session.base_url {
+        Some(base) => base.join(url),
+        None => reqwest::Url::parse(url),
+    }
+    .map_err(|e| {
+        W3cError::bad_request(format!(
+            "Invalid url {url:?}: {e} (set tauri:options.baseUrl to use relative URLs)"
+        ))
+    })?;
+    plugin_post(session, "/navigate/url", json!({"url": url.as_str()})).await?;
     Ok(w3c_value(json!(null)))
 }
 
 async fn get_url(AxumState(state): AxumState<SharedState>, Path(sid): Path<String>) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let result = plugin_post(session, "/navigate/current", json!({})).await?;
     Ok(w3c_value(result.get("url").cloned().unwrap_or(json!(""))))
 }
 
 async fn get_title(AxumState(state): AxumState<SharedState>, Path(sid): Path<String>) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let result = plugin_post(session, "/navigate/title", json!({})).await?;
     Ok(w3c_value(result.get("title").cloned().unwrap_or(json!(""))))
 }
 
 async fn go_back(AxumState(state): AxumState<SharedState>, Path(sid): Path<String>) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/navigate/back", json!({})).await?;
     Ok(w3c_value(json!(null)))
 }
@@ -480,15 +1641,15 @@ async fn go_forward(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/navigate/forward", json!({})).await?;
     Ok(w3c_value(json!(null)))
 }
 
 async fn refresh(AxumState(state): AxumState<SharedState>, Path(sid): Path<String>) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/navigate/refresh", json!({})).await?;
     Ok(w3c_value(json!(null)))
 }
@@ -499,8 +1660,8 @@ async fn get_window_handle(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let result = plugin_post(session, "/window/handle", json!({})).await?;
     Ok(w3c_value(result))
 }
@@ -509,12 +1670,17 @@ async fn close_window(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let handle = plugin_post(session, "/window/handle", json!({})).await?;
     let label = handle.as_str().unwrap_or("main");
     plugin_post(session, "/window/close", json!({"label": label})).await?;
-    let handles = plugin_post(session, "/window/handles", json!({})).await?;
+    // The window may not be gone yet; it's closed as far as the client is
+    // concerned.
+    let mut handles = plugin_post(session, "/window/handles", json!({})).await?;
+    if let Some(list) = handles.as_array_mut() {
+        list.retain(|h| h.as_str() != Some(label));
+    }
     Ok(w3c_value(handles))
 }
 
@@ -522,8 +1688,8 @@ async fn get_window_handles(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let result = plugin_post(session, "/window/handles", json!({})).await?;
     Ok(w3c_value(result))
 }
@@ -532,8 +1698,8 @@ async fn get_window_rect(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let result = plugin_post(session, "/window/rect", json!({})).await?;
     Ok(w3c_value(result))
 }
@@ -541,11 +1707,11 @@ async fn get_window_rect(
 async fn set_window_rect(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::WindowRectReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    plugin_post(session, "/window/set-rect", body).await?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    plugin_post(session, "/window/set-rect", json!(body)).await?;
     let result = plugin_post(session, "/window/rect", json!({})).await?;
     Ok(w3c_value(result))
 }
@@ -554,8 +1720,8 @@ async fn maximize_window(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/window/maximize", json!({})).await?;
     let result = plugin_post(session, "/window/rect", json!({})).await?;
     Ok(w3c_value(result))
@@ -565,8 +1731,8 @@ async fn minimize_window(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/window/minimize", json!({})).await?;
     let result = plugin_post(session, "/window/rect", json!({})).await?;
     Ok(w3c_value(result))
@@ -576,8 +1742,8 @@ async fn fullscreen_window(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/window/fullscreen", json!({})).await?;
     let result = plugin_post(session, "/window/rect", json!({})).await?;
     Ok(w3c_value(result))
@@ -588,11 +1754,11 @@ async fn fullscreen_window(
 async fn new_window(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::NewWindowReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let result = plugin_post(session, "/window/new", body).await?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let result = plugin_post(session, "/window/new", json!({"type": body.type_hint})).await?;
     let handle = result.get("handle").cloned().unwrap_or(json!(""));
     let type_val = result.get("type").cloned().unwrap_or(json!("window"));
     Ok(w3c_value(json!({"handle": handle, "type": type_val})))
@@ -603,10 +1769,10 @@ async fn new_window(
 async fn find_element(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::LocatorReq>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
     let (using, value) = extract_locator(&body)?;
     let result = plugin_post(
         session,
@@ -641,10 +1807,10 @@ async fn find_element(
 async fn find_elements(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::LocatorReq>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
     let (using, value) = extract_locator(&body)?;
     let result = plugin_post(
         session,
@@ -674,10 +1840,10 @@ async fn click_element(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
-    plugin_post(
+    plugin_interact(
         session,
         "/element/click",
         json!({"selector": elem.selector, "index": elem.index, "using": elem.using}),
@@ -690,10 +1856,10 @@ async fn clear_element(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
-    plugin_post(
+    plugin_interact(
         session,
         "/element/clear",
         json!({"selector": elem.selector, "index": elem.index, "using": elem.using}),
@@ -705,68 +1871,189 @@ async fn clear_element(
 async fn send_keys(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::SendKeysReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
-    let text = body.get("text").and_then(|v| v.as_str()).unwrap_or("");
+    let text = body.text.as_str();
 
-    // Check if this is a file input by querying its tag and type attribute.
-    let tag_result = plugin_post(
+    // Type in one round trip; file inputs come back untouched, since their
+    // text names files to read here.
+    let typed = plugin_interact(
         session,
-        "/element/tag",
-        json!({"selector": elem.selector, "index": elem.index, "using": elem.using}),
+        "/element/type",
+        json!({
+            "selector": elem.selector,
+            "index": elem.index,
+            "using": elem.using,
+            "text": text,
+            "native": session.native_input,
+            "fast": session.fast_send_keys,
+        }),
     )
     .await?;
-    let tag = tag_result.get("tag").and_then(|v| v.as_str()).unwrap_or("");
+    if typed.get("file").and_then(|v| v.as_bool()) == Some(true) {
+        // W3C spec: text contains newline-separated file paths.
+        let paths: Vec<&str> = text.lines().filter(|l| !l.is_empty()).collect();
+        if paths.is_empty() {
+            return Err(W3cError::bad_request("no file paths given"));
+        }
+        // Chunks sent for a request that fails are dropped from the webview.
+        let mut uploads = Vec::new();
+        let sent = match file_entries(session, &paths, &mut uploads).await {
+            Ok(files) => {
+                plugin_post(
+                    session,
+                    "/element/set-files",
+                    json!({"selector": elem.selector, "index": elem.index, "using": elem.using, "files": files}),
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            if !uploads.is_empty() {
+                if let Err(e) =
+                    plugin_post(session, "/file/discard", json!({"uploads": uploads})).await
+                {
+                    tracing::debug!("plugin did not discard uploads: {}", e.message);
+                }
+            }
+            return Err(e);
+        }
+    }
+    Ok(w3c_value(json!(null)))
+}
 
-    if tag.eq_ignore_ascii_case("input") {
-        let attr_result = plugin_post(
-            session,
-            "/element/attribute",
-            json!({"selector": elem.selector, "index": elem.index, "using": elem.using, "name": "type"}),
-        )
-        .await?;
-        let input_type = attr_result
-            .get("value")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-
-        if input_type.eq_ignore_ascii_case("file") {
-            // W3C spec: text contains newline-separated file paths.
-            let paths: Vec<&str> = text.lines().filter(|l| !l.is_empty()).collect();
-            let mut files = Vec::new();
-            for path in &paths {
-                let data = tokio::fs::read(path)
-                    .await
-                    .map_err(|e| W3cError::bad_request(format!("Cannot read file {path}: {e}")))?;
-                let encoded = base64::engine::general_purpose::STANDARD.encode(&data);
-                let name = std::path::Path::new(path)
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("file")
-                    .to_string();
-                let mime = mime_from_extension(path);
-                files.push(json!({"name": name, "data": encoded, "mime": mime}));
+/// The `/element/set-files` entries for the files `paths` name, with their
+/// content inline or sent ahead in chunks. The ids of chunked uploads go to
+/// `uploads` as soon as their first chunk is sent.
+async fn file_entries(
+    session: &Session,
+    paths: &[&str],
+    uploads: &mut Vec<String>,
+) -> Result<Vec<Value>, W3cError> {
+    let mut files = Vec::new();
+    for path in paths {
+        for (file, relative) in upload_files(std::path::Path::new(path)).await? {
+            let name = file
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("file")
+                .to_string();
+            let mime = mime_from_extension(&file.to_string_lossy());
+            let mut entry = json!({"name": name, "mime": mime, "path": relative});
+            match upload_content(session, &file, uploads).await? {
+                UploadContent::Inline(data) => entry["data"] = json!(data),
+                UploadContent::Chunked(upload) => entry["upload"] = json!(upload),
+            }
+            files.push(entry);
+        }
+    }
+    Ok(files)
+}
+
+/// Files up to this size go to `/element/set-files` inline; larger ones are
+/// streamed to the webview in chunks of this size (1 MiB once base64-encoded)
+/// so no single request or script has to hold the whole file.
+const UPLOAD_CHUNK_BYTES: usize = 768 * 1024;
+
+enum UploadContent {
+    /// The base64-encoded file.
+    Inline(String),
+    /// Id of the chunks sent through the plugin's `/file/chunk`.
+    Chunked(String),
+}
+
+/// Send `file`'s content towards the webview: inline when it fits in one
+/// chunk, otherwise read and posted chunk by chunk, its upload id added to
+/// `uploads` before the first one.
+async fn upload_content(
+    session: &Session,
+    file: &std::path::Path,
+    uploads: &mut Vec<String>,
+) -> Result<UploadContent, W3cError> {
+    use tokio::io::AsyncReadExt as _;
+
+    let unreadable = |e: std::io::Error| {
+        W3cError::bad_request(format!("Cannot read file {}: {e}", file.display()))
+    };
+    let mut reader = tokio::fs::File::open(file).await.map_err(unreadable)?;
+    let mut chunk = Vec::with_capacity(UPLOAD_CHUNK_BYTES);
+    let mut upload = None;
+    loop {
+        chunk.clear();
+        // Fill a whole chunk: `read` may return less before the end of file.
+        while chunk.len() < UPLOAD_CHUNK_BYTES {
+            let mut limited = (&mut reader).take((UPLOAD_CHUNK_BYTES - chunk.len()) as u64);
+            if limited.read_buf(&mut chunk).await.map_err(unreadable)? == 0 {
+                break;
             }
+        }
+        let full = chunk.len() == UPLOAD_CHUNK_BYTES;
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&chunk);
+        if upload.is_none() && !full {
+            return Ok(UploadContent::Inline(encoded));
+        }
+        let id = upload.get_or_insert_with(|| {
+            let id = uuid::Uuid::new_v4().to_string();
+            uploads.push(id.clone());
+            id
+        });
+        if !chunk.is_empty() {
             plugin_post(
                 session,
-                "/element/set-files",
-                json!({"selector": elem.selector, "index": elem.index, "using": elem.using, "files": files}),
+                "/file/chunk",
+                json!({"upload": id, "data": encoded}),
             )
             .await?;
-            return Ok(w3c_value(json!(null)));
+        }
+        if !full {
+            return Ok(UploadContent::Chunked(id.clone()));
         }
     }
+}
 
-    plugin_post(
-        session,
-        "/element/send-keys",
-        json!({"selector": elem.selector, "index": elem.index, "using": elem.using, "text": text}),
-    )
-    .await?;
-    Ok(w3c_value(json!(null)))
+/// The files to upload for `path`: the file itself, or every file under a
+/// directory (sorted, recursively) with its path relative to the
+/// directory's parent, which becomes the file's `webkitRelativePath`.
+async fn upload_files(
+    path: &std::path::Path,
+) -> Result<Vec<(std::path::PathBuf, Option<String>)>, W3cError> {
+    let unreadable = |e: std::io::Error| {
+        W3cError::bad_request(format!("Cannot read file {}: {e}", path.display()))
+    };
+    if !tokio::fs::metadata(path)
+        .await
+        .map_err(unreadable)?
+        .is_dir()
+    {
+        return Ok(vec![(path.to_path_buf(), None)]);
+    }
+    let base = path.parent().unwrap_or(path);
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await.map_err(unreadable)?;
+        while let Some(entry) = entries.next_entry().await.map_err(unreadable)? {
+            let entry_path = entry.path();
+            if entry.file_type().await.map_err(unreadable)?.is_dir() {
+                dirs.push(entry_path);
+            } else {
+                let relative = entry_path
+                    .strip_prefix(base)
+                    .unwrap_or(&entry_path)
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push((entry_path, Some(relative)));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
 }
 
 fn mime_from_extension(path: &str) -> String {
@@ -800,8 +2087,8 @@ async fn get_element_text(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -816,8 +2103,8 @@ async fn get_element_tag(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -832,8 +2119,8 @@ async fn get_element_attribute(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid, name)): Path<(String, String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -850,8 +2137,8 @@ async fn get_element_property(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid, name)): Path<(String, String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -868,8 +2155,8 @@ async fn get_element_css(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid, name)): Path<(String, String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     // CSS values use the property endpoint with a computed-style JS property.
     let result = plugin_post(
@@ -895,8 +2182,8 @@ async fn get_element_rect(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -911,8 +2198,8 @@ async fn is_element_enabled(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -929,8 +2216,8 @@ async fn is_element_selected(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -947,8 +2234,8 @@ async fn is_element_displayed(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -966,43 +2253,50 @@ async fn is_element_displayed(
 async fn execute_sync(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::ScriptReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let script = body.get("script").and_then(|v| v.as_str()).unwrap_or("");
-    let args = body.get("args").cloned().unwrap_or(json!([]));
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let mut args = Value::Array(body.args);
+    script_args_to_plugin(session, &mut args)?;
     let result = plugin_post(
         session,
         "/script/execute",
-        json!({"script": script, "args": args}),
+        json!({"script": body.script, "args": args}),
     )
-    .await
-    .map_err(|e| W3cError::javascript_error(e.message))?;
-    Ok(w3c_value(
-        result.get("value").cloned().unwrap_or(Value::Null),
-    ))
+    .await?;
+    drop(guard);
+    script_value(&state, &sid, result).await
 }
 
 async fn execute_async(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::ScriptReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let script = body.get("script").and_then(|v| v.as_str()).unwrap_or("");
-    let args = body.get("args").cloned().unwrap_or(json!([]));
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let mut args = Value::Array(body.args);
+    script_args_to_plugin(session, &mut args)?;
     let result = plugin_post(
         session,
         "/script/execute-async",
-        json!({"script": script, "args": args}),
+        json!({"script": body.script, "args": args}),
     )
-    .await
-    .map_err(|e| W3cError::javascript_error(e.message))?;
-    Ok(w3c_value(
-        result.get("value").cloned().unwrap_or(Value::Null),
-    ))
+    .await?;
+    drop(guard);
+    script_value(&state, &sid, result).await
+}
+
+/// A script result's `value`, with the elements and shadow roots in it given
+/// ids. The session is only taken for writing when there are any.
+async fn script_value(state: &AppState, sid: &str, result: Value) -> W3cResult {
+    let mut value = result.get("value").cloned().unwrap_or(Value::Null);
+    if holds_plugin_refs(&value) {
+        let mut guard = state.session_mut(sid).await?;
+        store_script_refs(&mut guard, &mut value);
+    }
+    Ok(w3c_value(value))
 }
 
 // --- Cookie handlers ---
@@ -1011,8 +2305,8 @@ async fn get_all_cookies(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let result = plugin_post(session, "/cookie/get-all", json!({})).await?;
     Ok(w3c_value(
         result.get("cookies").cloned().unwrap_or(json!([])),
@@ -1023,8 +2317,8 @@ async fn get_named_cookie(
     AxumState(state): AxumState<SharedState>,
     Path((sid, name)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let result = plugin_post(session, "/cookie/get", json!({"name": name})).await?;
     let cookie = result.get("cookie").cloned().unwrap_or(Value::Null);
     if cookie.is_null() {
@@ -1040,12 +2334,11 @@ async fn get_named_cookie(
 async fn add_cookie(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::AddCookieReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let cookie = body.get("cookie").cloned().unwrap_or(json!({}));
-    plugin_post(session, "/cookie/add", json!({"cookie": cookie})).await?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    plugin_post(session, "/cookie/add", json!({"cookie": body.cookie})).await?;
     Ok(w3c_value(json!(null)))
 }
 
@@ -1053,8 +2346,8 @@ async fn delete_cookie(
     AxumState(state): AxumState<SharedState>,
     Path((sid, name)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/cookie/delete", json!({"name": name})).await?;
     Ok(w3c_value(json!(null)))
 }
@@ -1063,8 +2356,8 @@ async fn delete_all_cookies(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/cookie/delete-all", json!({})).await?;
     Ok(w3c_value(json!(null)))
 }
@@ -1074,41 +2367,40 @@ async fn delete_all_cookies(
 async fn perform_actions(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(mut body): Params<params::ActionsReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-
-    // Walk through the actions and resolve any W3C element references in
-    // pointer action origins before forwarding to the plugin.
-    let mut resolved_body = body.clone();
-    if let Some(actions) = resolved_body
-        .get_mut("actions")
-        .and_then(|a| a.as_array_mut())
+    body.validate()?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+
+    // Resolve W3C element references in pointer action origins before
+    // forwarding to the plugin.
+    for action in body
+        .actions
+        .iter_mut()
+        .flat_map(|seq| seq.actions.iter_mut())
     {
-        for seq in actions.iter_mut() {
-            if let Some(sub_actions) = seq.get_mut("actions").and_then(|a| a.as_array_mut()) {
-                for action in sub_actions.iter_mut() {
-                    // Check if origin is a W3C element reference object.
-                    if let Some(origin) = action.get("origin").cloned() {
-                        if let Some(eid) = origin.get(W3C_ELEMENT_KEY).and_then(|v| v.as_str()) {
-                            if let Some(elem_ref) = session.elements.get(eid) {
-                                // Replace element UUID with selector/index for the plugin.
-                                action["origin"] = json!({
-                                    W3C_ELEMENT_KEY: {
-                                        "selector": elem_ref.selector,
-                                        "index": elem_ref.index,
-                                        "using": elem_ref.using
-                                    }
-                                });
-                            }
-                        }
-                    }
-                }
+        let Some(origin) = &mut action.origin else {
+            continue;
+        };
+        let Some(eid) = origin.get(W3C_ELEMENT_KEY).and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let elem_ref = resolve_element(session, eid)?;
+        // Replace element UUID with selector/index for the plugin.
+        *origin = json!({
+            W3C_ELEMENT_KEY: {
+                "selector": elem_ref.selector,
+                "index": elem_ref.index,
+                "using": elem_ref.using
             }
-        }
+        });
     }
+    let mut resolved_body = json!(body);
 
+    if let (true, Some(body)) = (session.native_input, resolved_body.as_object_mut()) {
+        body.insert("native".into(), json!(true));
+    }
     plugin_post(session, "/actions/perform", resolved_body).await?;
     Ok(w3c_value(json!(null)))
 }
@@ -1117,8 +2409,8 @@ async fn release_actions(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/actions/release", json!({})).await?;
     Ok(w3c_value(json!(null)))
 }
@@ -1129,17 +2421,9 @@ async fn dismiss_alert(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    plugin_post(session, "/alert/dismiss", json!({}))
-        .await
-        .map_err(|e| {
-            if e.message.contains("no such alert") {
-                W3cError::new(StatusCode::NOT_FOUND, "no such alert", &e.message)
-            } else {
-                e
-            }
-        })?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    plugin_post(session, "/alert/dismiss", json!({})).await?;
     Ok(w3c_value(json!(null)))
 }
 
@@ -1147,17 +2431,9 @@ async fn accept_alert(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    plugin_post(session, "/alert/accept", json!({}))
-        .await
-        .map_err(|e| {
-            if e.message.contains("no such alert") {
-                W3cError::new(StatusCode::NOT_FOUND, "no such alert", &e.message)
-            } else {
-                e
-            }
-        })?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    plugin_post(session, "/alert/accept", json!({})).await?;
     Ok(w3c_value(json!(null)))
 }
 
@@ -1165,66 +2441,52 @@ async fn get_alert_text(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let result = plugin_post(session, "/alert/text", json!({}))
-        .await
-        .map_err(|e| {
-            if e.message.contains("no such alert") {
-                W3cError::new(StatusCode::NOT_FOUND, "no such alert", &e.message)
-            } else {
-                e
-            }
-        })?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let result = plugin_post(session, "/alert/text", json!({})).await?;
     Ok(w3c_value(result.get("text").cloned().unwrap_or(json!(""))))
 }
 
 async fn send_alert_text(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::AlertTextReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let text = body.get("text").and_then(|v| v.as_str()).unwrap_or("");
-    plugin_post(session, "/alert/send-text", json!({"text": text}))
-        .await
-        .map_err(|e| {
-            if e.message.contains("no such alert") {
-                W3cError::new(StatusCode::NOT_FOUND, "no such alert", &e.message)
-            } else {
-                e
-            }
-        })?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    plugin_post(session, "/alert/send-text", json!({"text": body.text})).await?;
     Ok(w3c_value(json!(null)))
 }
 
 // --- Screenshot handlers ---
 
+// Screenshots and PDFs come from the plugin as raw bytes and are
+// base64-encoded into the W3C response as they stream through.
+
 async fn take_screenshot(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let result = plugin_post(session, "/screenshot", json!({})).await?;
-    Ok(w3c_value(result.get("data").cloned().unwrap_or(json!(""))))
+) -> Result<Response, W3cError> {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let png = plugin_post_raw(session, "/screenshot", json!({})).await?;
+    Ok(base64_value_response(png))
 }
 
 async fn element_screenshot(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
-) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+) -> Result<Response, W3cError> {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
-    let result = plugin_post(
+    let png = plugin_post_raw(
         session,
         "/screenshot/element",
         json!({"selector": elem.selector, "index": elem.index, "using": elem.using}),
     )
     .await?;
-    Ok(w3c_value(result.get("data").cloned().unwrap_or(json!(""))))
+    Ok(base64_value_response(png))
 }
 
 // --- Print handler ---
@@ -1232,12 +2494,13 @@ async fn element_screenshot(
 async fn print_page(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
-) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let result = plugin_post(session, "/print", body).await?;
-    Ok(w3c_value(result.get("data").cloned().unwrap_or(json!(""))))
+    Params(body): Params<params::PrintReq>,
+) -> Result<Response, W3cError> {
+    body.validate()?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let pdf = plugin_post_raw(session, "/print", json!({})).await?;
+    Ok(base64_value_response(pdf))
 }
 
 // --- Shadow DOM handlers ---
@@ -1246,8 +2509,8 @@ async fn get_shadow_root(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
     let elem = session
         .elements
         .get(&eid)
@@ -1272,25 +2535,21 @@ async fn get_shadow_root(
             format!("Element {eid} does not have a shadow root"),
         ));
     }
-    let shadow_id = uuid::Uuid::new_v4().to_string();
-    session.shadows.insert(
-        shadow_id.clone(),
-        ShadowRef {
-            host_selector,
-            host_index,
-            host_using,
-        },
-    );
+    let shadow_id = session.shadows.insert(ShadowRef {
+        host_selector,
+        host_index,
+        host_using,
+    });
     Ok(w3c_value(json!({W3C_SHADOW_KEY: shadow_id})))
 }
 
 async fn find_in_shadow(
     AxumState(state): AxumState<SharedState>,
     Path((sid, shadow_id)): Path<(String, String)>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::LocatorReq>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
     let shadow = session.shadows.get(&shadow_id).ok_or_else(|| {
         W3cError::new(
             StatusCode::NOT_FOUND,
@@ -1341,10 +2600,10 @@ async fn find_in_shadow(
 async fn find_all_in_shadow(
     AxumState(state): AxumState<SharedState>,
     Path((sid, shadow_id)): Path<(String, String)>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::LocatorReq>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
     let shadow = session.shadows.get(&shadow_id).ok_or_else(|| {
         W3cError::new(
             StatusCode::NOT_FOUND,
@@ -1386,17 +2645,82 @@ async fn find_all_in_shadow(
     Ok(w3c_value(json!(mapped)))
 }
 
+/// Forget element and shadow root references the test no longer needs (all
+/// of them without `elements`), so a long session doesn't run into
+/// `--max-elements`. Unknown ids are skipped. Nodes the bridge registered
+/// for them are released in the page too, unless another reference still
+/// points there.
+async fn release_elements(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Params(body): Params<params::ReleaseReq>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    let (elements, shadows) = match body.elements {
+        Some(ids) => {
+            let mut elements = Vec::new();
+            let mut shadows = Vec::new();
+            for id in &ids {
+                if let Some(elem) = session.elements.remove(id) {
+                    elements.push(elem);
+                } else if let Some(shadow) = session.shadows.remove(id) {
+                    shadows.push(shadow);
+                }
+            }
+            (elements, shadows)
+        }
+        None => (session.elements.clear(), session.shadows.clear()),
+    };
+    let released = elements.len() + shadows.len();
+
+    let mut nodes: Vec<String> = elements
+        .into_iter()
+        .filter(|elem| elem.using == "ref")
+        .map(|elem| elem.selector)
+        .chain(
+            shadows
+                .into_iter()
+                .filter(|shadow| shadow.host_using == "ref")
+                .map(|shadow| shadow.host_selector),
+        )
+        .filter(|node| {
+            let elem = ElementRef {
+                selector: node.clone(),
+                index: 0,
+                using: "ref".to_string(),
+            };
+            let shadow = ShadowRef {
+                host_selector: node.clone(),
+                host_index: 0,
+                host_using: "ref".to_string(),
+            };
+            !session.elements.contains(&elem) && !session.shadows.contains(&shadow)
+        })
+        .collect();
+    nodes.sort();
+    nodes.dedup();
+    if !nodes.is_empty() {
+        // Plugins that predate `/element/release` keep them until the page
+        // unloads.
+        if let Err(e) = plugin_post(session, "/element/release", json!({"ids": nodes})).await {
+            tracing::debug!("plugin did not release element nodes: {}", e.message);
+        }
+    }
+    Ok(w3c_value(json!({"released": released})))
+}
+
 // --- Frame handlers ---
 
 async fn switch_to_frame(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::FrameReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
 
-    let frame_id = body.get("id").cloned().unwrap_or(Value::Null);
+    let frame_id = body.id;
 
     if frame_id.is_null() {
         // Switch to top-level
@@ -1405,6 +2729,11 @@ async fn switch_to_frame(
     }
 
     if let Some(idx) = frame_id.as_u64() {
+        if idx > u64::from(u16::MAX) {
+            return Err(W3cError::bad_request(
+                "'id': frame indexes range from 0 to 65535",
+            ));
+        }
         // Switch by index
         plugin_post(session, "/frame/switch", json!({"id": idx})).await?;
         return Ok(w3c_value(json!(null)));
@@ -1416,21 +2745,23 @@ async fn switch_to_frame(
         plugin_post(
             session,
             "/frame/switch",
-            json!({"id": {"selector": elem.selector, "index": elem.index}}),
+            json!({"id": {"selector": elem.selector, "index": elem.index, "using": elem.using}}),
         )
         .await?;
         return Ok(w3c_value(json!(null)));
     }
 
-    Err(W3cError::bad_request("Invalid frame id"))
+    Err(W3cError::bad_request(
+        "'id' must be null, a frame index or an element reference",
+    ))
 }
 
 async fn switch_to_parent_frame(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     plugin_post(session, "/frame/parent", json!({})).await?;
     Ok(w3c_value(json!(null)))
 }
@@ -1440,14 +2771,11 @@ async fn switch_to_parent_frame(
 async fn switch_to_window(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::SwitchWindowReq>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
-    let handle = body
-        .get("handle")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| W3cError::bad_request("Missing 'handle'"))?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let handle = body.handle.as_str();
     plugin_post(session, "/window/set-current", json!({"label": handle}))
         .await
         .map_err(|_| {
@@ -1465,10 +2793,10 @@ async fn switch_to_window(
 async fn find_element_from_element(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::LocatorReq>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
     let parent = session
         .elements
         .get(&eid)
@@ -1516,10 +2844,10 @@ async fn find_element_from_element(
 async fn find_elements_from_element(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
-    Json(body): Json<Value>,
+    Params(body): Params<params::LocatorReq>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
     let parent = session
         .elements
         .get(&eid)
@@ -1564,8 +2892,8 @@ async fn get_computed_role(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -1582,8 +2910,8 @@ async fn get_computed_label(
     AxumState(state): AxumState<SharedState>,
     Path((sid, eid)): Path<(String, String)>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let elem = resolve_element(session, &eid)?;
     let result = plugin_post(
         session,
@@ -1600,8 +2928,8 @@ async fn get_active_element(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let mut guard = state.sessions.lock().await;
-    let session = get_session_mut(&mut guard, &sid)?;
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
     let result = plugin_post(session, "/element/active", json!({})).await?;
     let elem = result.get("element").cloned().unwrap_or(Value::Null);
     if elem.is_null() {
@@ -1621,182 +2949,1800 @@ async fn get_page_source(
     AxumState(state): AxumState<SharedState>,
     Path(sid): Path<String>,
 ) -> W3cResult {
-    let guard = state.sessions.lock().await;
-    let session = get_session(&guard, &sid)?;
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
     let result = plugin_post(session, "/source", json!({})).await?;
     Ok(w3c_value(
         result.get("source").cloned().unwrap_or(json!("")),
     ))
 }
 
-// --- Main ---
+// --- Log handlers (Selenium-compatible /se/log) ---
 
-#[tokio::main]
-async fn main() {
-    let cli = Cli::parse();
+const LOG_TYPES: &[&str] = &["browser"];
 
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&cli.log_level)),
-        )
-        .init();
+/// Map a console method name to a Selenium log level.
+fn selenium_log_level(level: &str) -> &'static str {
+    match level {
+        "error" => "SEVERE",
+        "warn" => "WARNING",
+        "debug" => "DEBUG",
+        _ => "INFO",
+    }
+}
 
-    let state: SharedState = Arc::new(AppState {
-        sessions: Mutex::new(HashMap::new()),
-        max_sessions: cli.max_sessions,
-    });
+async fn fetch_logs(session: &Session, log_type: &str) -> Result<Value, W3cError> {
+    if !LOG_TYPES.contains(&log_type) {
+        return Err(W3cError::bad_request(format!(
+            "Unsupported log type: {log_type}"
+        )));
+    }
+    // Selenium semantics: each call returns the entries since the previous call.
+    let result = plugin_post(session, "/log/console", json!({"clear": true})).await?;
+    let entries: Vec<Value> = result
+        .get("entries")
+        .and_then(|e| e.as_array())
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|e| {
+                    let level = e.get("level").and_then(|l| l.as_str()).unwrap_or("log");
+                    json!({
+                        "level": selenium_log_level(level),
+                        "message": e.get("message").cloned().unwrap_or(json!("")),
+                        "timestamp": e.get("timestamp").cloned().unwrap_or(json!(0)),
+                        "source": e.get("source").cloned().unwrap_or(json!("console-api")),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(json!(entries))
+}
 
-    let router = Router::new()
-        // Session
-        .route("/status", get(get_status))
-        .route("/session", post(create_session))
-        .route("/session/{sid}", delete(delete_session))
-        // Timeouts
-        .route("/session/{sid}/timeouts", get(get_timeouts))
-        .route("/session/{sid}/timeouts", post(set_timeouts))
-        // Navigation
-        .route("/session/{sid}/url", post(navigate_to))
-        .route("/session/{sid}/url", get(get_url))
-        .route("/session/{sid}/title", get(get_title))
-        .route("/session/{sid}/source", get(get_page_source))
-        .route("/session/{sid}/back", post(go_back))
-        .route("/session/{sid}/forward", post(go_forward))
-        .route("/session/{sid}/refresh", post(refresh))
-        // Window
-        .route("/session/{sid}/window", get(get_window_handle))
-        .route("/session/{sid}/window", post(switch_to_window))
-        .route("/session/{sid}/window", delete(close_window))
-        .route("/session/{sid}/window/handles", get(get_window_handles))
-        .route("/session/{sid}/window/rect", get(get_window_rect))
-        .route("/session/{sid}/window/rect", post(set_window_rect))
-        .route("/session/{sid}/window/maximize", post(maximize_window))
-        .route("/session/{sid}/window/minimize", post(minimize_window))
-        .route("/session/{sid}/window/fullscreen", post(fullscreen_window))
-        .route("/session/{sid}/window/new", post(new_window))
-        // Frames
-        .route("/session/{sid}/frame", post(switch_to_frame))
-        .route("/session/{sid}/frame/parent", post(switch_to_parent_frame))
-        // Elements
-        .route("/session/{sid}/element", post(find_element))
-        .route("/session/{sid}/elements", post(find_elements))
-        .route("/session/{sid}/element/active", get(get_active_element))
-        .route(
-            "/session/{sid}/element/{eid}/element",
-            post(find_element_from_element),
-        )
-        .route(
-            "/session/{sid}/element/{eid}/elements",
-            post(find_elements_from_element),
-        )
-        .route("/session/{sid}/element/{eid}/click", post(click_element))
-        .route("/session/{sid}/element/{eid}/clear", post(clear_element))
-        .route("/session/{sid}/element/{eid}/value", post(send_keys))
-        .route("/session/{sid}/element/{eid}/text", get(get_element_text))
-        .route("/session/{sid}/element/{eid}/name", get(get_element_tag))
-        .route(
-            "/session/{sid}/element/{eid}/attribute/{name}",
-            get(get_element_attribute),
-        )
-        .route(
-            "/session/{sid}/element/{eid}/property/{name}",
-            get(get_element_property),
-        )
-        .route(
-            "/session/{sid}/element/{eid}/css/{name}",
-            get(get_element_css),
-        )
-        .route("/session/{sid}/element/{eid}/rect", get(get_element_rect))
-        .route(
-            "/session/{sid}/element/{eid}/enabled",
-            get(is_element_enabled),
-        )
-        .route(
-            "/session/{sid}/element/{eid}/selected",
-            get(is_element_selected),
-        )
-        .route(
-            "/session/{sid}/element/{eid}/displayed",
-            get(is_element_displayed),
-        )
-        .route(
-            "/session/{sid}/element/{eid}/computedrole",
-            get(get_computed_role),
-        )
-        .route(
-            "/session/{sid}/element/{eid}/computedlabel",
-            get(get_computed_label),
-        )
-        .route("/session/{sid}/element/{eid}/shadow", get(get_shadow_root))
-        .route("/session/{sid}/shadow/{sid2}/element", post(find_in_shadow))
-        .route(
-            "/session/{sid}/shadow/{sid2}/elements",
-            post(find_all_in_shadow),
-        )
-        // Scripts
-        .route("/session/{sid}/execute/sync", post(execute_sync))
-        .route("/session/{sid}/execute/async", post(execute_async))
-        // Cookies
-        .route("/session/{sid}/cookie", get(get_all_cookies))
-        .route("/session/{sid}/cookie", post(add_cookie))
-        .route("/session/{sid}/cookie", delete(delete_all_cookies))
-        .route("/session/{sid}/cookie/{name}", get(get_named_cookie))
-        .route("/session/{sid}/cookie/{name}", delete(delete_cookie))
-        // Alerts
-        .route("/session/{sid}/alert/dismiss", post(dismiss_alert))
-        .route("/session/{sid}/alert/accept", post(accept_alert))
-        .route("/session/{sid}/alert/text", get(get_alert_text))
-        .route("/session/{sid}/alert/text", post(send_alert_text))
-        // Actions
-        .route("/session/{sid}/actions", post(perform_actions))
-        .route("/session/{sid}/actions", delete(release_actions))
-        // Print
-        .route("/session/{sid}/print", post(print_page))
-        // Screenshots
-        .route("/session/{sid}/screenshot", get(take_screenshot))
-        .route(
-            "/session/{sid}/element/{eid}/screenshot",
-            get(element_screenshot),
-        )
-        .with_state(state.clone());
+async fn get_log(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Params(body): Params<params::LogReq>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    Ok(w3c_value(fetch_logs(session, &body.log_type).await?))
+}
 
-    let shutdown_state = state;
+async fn get_log_by_query(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let log_type = query.get("type").map(String::as_str).unwrap_or("browser");
+    Ok(w3c_value(fetch_logs(session, log_type).await?))
+}
 
-    let addr = format!("{}:{}", cli.host, cli.port);
-    tracing::info!("tauri-wd listening on {}", addr);
+async fn get_log_types(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    state.session(&sid).await?;
+    Ok(w3c_value(json!(LOG_TYPES)))
+}
 
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
+// --- File upload handler (Selenium-compatible /se/file) ---
+
+/// Accept a base64-encoded zip holding one file, extract it into the
+/// session's upload dir, and return the local path. This lets clients on
+/// another machine set file inputs.
+async fn upload_file(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Params(body): Params<params::UploadReq>,
+) -> W3cResult {
+    let archive = base64::engine::general_purpose::STANDARD
+        .decode(&body.file)
+        .map_err(|e| W3cError::bad_request(format!("Invalid base64 in 'file': {e}")))?;
+
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    let upload_dir = session
+        .upload_dir
+        .get_or_insert_with(|| std::env::temp_dir().join(format!("tauri-wd-upload-{sid}")))
+        .clone();
+    // Each upload gets its own directory so files with the same name don't clash.
+    let target_dir = upload_dir.join(uuid::Uuid::new_v4().to_string());
+
+    let path = tokio::task::spawn_blocking(move || extract_upload(&archive, &target_dir))
+        .await
+        .map_err(|e| W3cError::unknown(format!("upload failed: {e}")))??;
+    tracing::debug!("Uploaded file for session {sid} to {}", path.display());
+    Ok(w3c_value(json!(path.display().to_string())))
+}
+
+fn extract_upload(
+    archive: &[u8],
+    target_dir: &std::path::Path,
+) -> Result<std::path::PathBuf, W3cError> {
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(archive))
+        .map_err(|e| W3cError::bad_request(format!("'file' is not a zip archive: {e}")))?;
+    let files: Vec<usize> = (0..zip.len())
+        .filter(|&i| zip.by_index(i).is_ok_and(|f| f.is_file()))
+        .collect();
+    let [index] = files[..] else {
+        return Err(W3cError::bad_request(format!(
+            "Expected exactly one file in the zip archive, found {}",
+            files.len()
+        )));
+    };
+    let mut entry = zip
+        .by_index(index)
+        .map_err(|e| W3cError::bad_request(format!("Invalid zip entry: {e}")))?;
+    let name = entry
+        .enclosed_name()
+        .and_then(|p| p.file_name().map(std::path::PathBuf::from))
+        .ok_or_else(|| W3cError::bad_request("Zip entry has an unsafe file name"))?;
+
+    std::fs::create_dir_all(target_dir)
+        .map_err(|e| W3cError::unknown(format!("Failed to create upload dir: {e}")))?;
+    let path = target_dir.join(name);
+    let mut file = std::fs::File::create(&path)
+        .map_err(|e| W3cError::unknown(format!("Failed to create {}: {e}", path.display())))?;
+    std::io::copy(&mut entry, &mut file)
+        .map_err(|e| W3cError::unknown(format!("Failed to write {}: {e}", path.display())))?;
+    Ok(path)
+}
+
+async fn remove_uploads(session: &Session) {
+    if let Some(dir) = &session.upload_dir {
+        let _ = tokio::fs::remove_dir_all(dir).await;
+    }
+}
+
+// --- Page error handlers ---
+
+/// Per-session entries kept in each of the page error, network and print
+/// logs unless `--max-log-entries` says otherwise.
+const DEFAULT_MAX_LOG_ENTRIES: usize = 10_000;
+
+/// Append `entries` to `log`, dropping its oldest entries past `cap`.
+/// Returns how many of `entries` are still in it, at the end.
+fn append_capped(log: &mut Vec<Value>, entries: &[Value], cap: usize) -> usize {
+    log.extend_from_slice(entries);
+    let excess = log.len().saturating_sub(cap);
+    if excess > 0 {
+        log.drain(..excess);
+        tracing::debug!("dropped the oldest {excess} log entries (--max-log-entries {cap})");
+    }
+    entries.len().min(log.len())
+}
+
+/// Move page errors buffered in the webview into the session buffer.
+/// Returns the number of newly collected errors still in it.
+async fn collect_page_errors(session: &mut Session) -> Result<usize, W3cError> {
+    let result = plugin_post(session, "/log/errors", json!({"clear": true})).await?;
+    let new_errors = result
+        .get("errors")
+        .and_then(|e| e.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    Ok(append_capped(
+        &mut session.page_errors,
+        new_errors,
+        session.max_log_entries,
+    ))
+}
+
+async fn get_page_errors(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    collect_page_errors(session).await?;
+    Ok(w3c_value(json!(session.page_errors)))
+}
+
+async fn clear_page_errors(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    collect_page_errors(session).await?;
+    session.page_errors.clear();
+    Ok(w3c_value(json!(null)))
+}
+
+// --- Print request handlers ---
+
+/// Move `window.print()` calls recorded in the webview into the session
+/// buffer.
+async fn collect_print_requests(session: &mut Session) -> Result<(), W3cError> {
+    let result = plugin_post(session, "/log/prints", json!({"clear": true})).await?;
+    if let Some(prints) = result.get("prints").and_then(|p| p.as_array()) {
+        append_capped(&mut session.print_requests, prints, session.max_log_entries);
+    }
+    Ok(())
+}
+
+async fn get_print_requests(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    collect_print_requests(session).await?;
+    Ok(w3c_value(json!(session.print_requests)))
+}
+
+async fn clear_print_requests(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    collect_print_requests(session).await?;
+    session.print_requests.clear();
+    Ok(w3c_value(json!(null)))
+}
+
+/// Session id of a command under `/session/{id}/...` (not session creation
+/// or deletion themselves).
+fn command_session_id(path: &str) -> Option<String> {
+    let rest = path.strip_prefix("/session/")?;
+    let (sid, _) = rest.split_once('/')?;
+    Some(sid.to_string())
+}
+
+/// Commands `--parallel-reads` lets run alongside each other: GETs that
+/// only read, by path under `/session/{id}`. Not every GET qualifies; some
+/// drain a buffer (`/se/log`, `/tauri/errors`, `/tauri/prints`,
+/// `/tauri/network/...`) or store an element reference (`/element/active`,
+/// `/element/{id}/shadow`).
+fn parallel_read(path: &str) -> bool {
+    let Some((_, command)) = path
+        .strip_prefix("/session/")
+        .and_then(|rest| rest.split_once('/'))
+    else {
+        return false;
+    };
+    let segments: Vec<&str> = command.split('/').collect();
+    matches!(
+        segments.as_slice(),
+        ["timeouts" | "url" | "title" | "source" | "window" | "cookie" | "screenshot"]
+            | ["window", "handles" | "rect"]
+            | ["cookie", _]
+            | ["alert", "text"]
+            | ["se", "log", "types"]
+            | ["tauri", "recording" | "app-log" | "mocks" | "selection"]
+            | ["tauri", "element", _, "selection"]
+            | [
+                "element",
+                _,
+                "text"
+                    | "name"
+                    | "rect"
+                    | "enabled"
+                    | "selected"
+                    | "displayed"
+                    | "computedrole"
+                    | "computedlabel"
+            ]
+            | ["element", _, "attribute" | "property" | "css", _]
+    )
+}
+
+/// Marks a request run by a batch, whose gate pass it shares.
+#[derive(Clone, Copy)]
+struct GateHeld;
+
+/// Middleware running a session's commands one at a time, so overlapping
+/// requests (parallel workers sharing a session, say) can't interleave in
+/// the plugin. With `--parallel-reads`, reads (see `parallel_read`) only
+/// exclude commands that change something. A command that can't pass waits for the gate,
+/// or with `--overlap reject` fails at once.
+async fn session_gate(
+    AxumState(state): AxumState<SharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let Some(sid) = command_session_id(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    if request.extensions().get::<GateHeld>().is_some() {
+        return next.run(request).await;
+    }
+    // Without a session the handler reports the error.
+    let Some((gate, shared_reads)) = state.command_gate(&sid).await else {
+        return next.run(request).await;
+    };
+    let shared = state.parallel_reads
+        && shared_reads
+        && request.method() == axum::http::Method::GET
+        && parallel_read(request.uri().path());
+    let pass: Box<dyn Send> = match (state.overlap, shared) {
+        (Overlap::Queue, true) => Box::new(gate.read_owned().await),
+        (Overlap::Queue, false) => Box::new(gate.write_owned().await),
+        (Overlap::Reject, true) => match gate.try_read_owned() {
+            Ok(pass) => Box::new(pass),
+            Err(_) => return session_busy(),
+        },
+        (Overlap::Reject, false) => match gate.try_write_owned() {
+            Ok(pass) => Box::new(pass),
+            Err(_) => return session_busy(),
+        },
+    };
+    let response = next.run(request).await;
+    drop(pass);
+    response
+}
+
+fn session_busy() -> Response {
+    W3cError::unknown(
+        "session busy: another command is still running on this session (--overlap reject)",
+    )
+    .into_response()
+}
+
+/// Middleware for sessions created with `failOnPageError`: after a successful
+/// command, turn it into a "javascript error" if the page threw while it ran.
+/// It runs inside `session_gate`, which such sessions never share, so the
+/// errors are collected before the next command can start.
+async fn page_error_guard(
+    AxumState(state): AxumState<SharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let sid = match command_session_id(&path) {
+        Some(sid) if !path.contains("/tauri/errors") => sid,
+        _ => return next.run(request).await,
+    };
+    let enabled = match state.find_session(&sid).await {
+        Some(session) => session.read().await.fail_on_page_error,
+        None => false,
+    };
+    if !enabled {
+        return next.run(request).await;
+    }
+
+    let started_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or(0.0);
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let Some(session) = state.find_session(&sid).await else {
+        return response;
+    };
+    let mut session = session.write().await;
+    let Ok(count) = collect_page_errors(&mut session).await else {
+        return response;
+    };
+    let during = session.page_errors[session.page_errors.len() - count..]
+        .iter()
+        .find(|e| {
+            e.get("timestamp")
+                .and_then(|t| t.as_f64())
+                .is_some_and(|t| t >= started_ms)
+        });
+    match during {
+        Some(err) => {
+            let message = err
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("page error");
+            W3cError::javascript_error(format!("Page error during command: {message}"))
+                .into_response()
+        }
+        None => response,
+    }
+}
+
+// --- Network log handlers ---
+
+/// Move network entries buffered in the webview into the session log.
+async fn collect_network(session: &mut Session) -> Result<(), W3cError> {
+    let result = plugin_post(session, "/log/network", json!({"clear": true})).await?;
+    if let Some(entries) = result.get("entries").and_then(|e| e.as_array()) {
+        append_capped(&mut session.network_log, entries, session.max_log_entries);
+    }
+    Ok(())
+}
+
+async fn get_network_log(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    collect_network(session).await?;
+    Ok(w3c_value(json!(session.network_log)))
+}
+
+async fn clear_network_log(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    collect_network(session).await?;
+    session.network_log.clear();
+    Ok(w3c_value(json!(null)))
+}
+
+async fn get_network_har(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    collect_network(session).await?;
+    Ok(w3c_value(build_har(&session.network_log)))
+}
+
+/// Convert captured network entries into a HAR 1.2 document.
+fn build_har(entries: &[Value]) -> Value {
+    fn headers(v: Option<&Value>) -> Vec<Value> {
+        v.and_then(|h| h.as_object())
+            .map(|h| {
+                h.iter()
+                    .map(|(name, value)| json!({"name": name, "value": value}))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    let har_entries: Vec<Value> = entries
+        .iter()
+        .map(|e| {
+            let started = e.get("startedAt").and_then(|t| t.as_f64()).unwrap_or(0.0);
+            let duration = e.get("duration").and_then(|d| d.as_f64()).unwrap_or(0.0);
+            let url = e.get("url").and_then(|u| u.as_str()).unwrap_or("");
+            let query: Vec<Value> = url
+                .split_once('?')
+                .map(|(_, q)| q.split('#').next().unwrap_or(""))
+                .unwrap_or("")
+                .split('&')
+                .filter(|p| !p.is_empty())
+                .map(|p| {
+                    let (name, value) = p.split_once('=').unwrap_or((p, ""));
+                    json!({"name": name, "value": value})
+                })
+                .collect();
+            json!({
+                "startedDateTime": iso8601_from_millis(started as i64),
+                "time": duration,
+                "request": {
+                    "method": e.get("method").cloned().unwrap_or(json!("GET")),
+                    "url": url,
+                    "httpVersion": "HTTP/1.1",
+                    "cookies": [],
+                    "headers": headers(e.get("requestHeaders")),
+                    "queryString": query,
+                    "headersSize": -1,
+                    "bodySize": e.get("requestSize").cloned().unwrap_or(json!(-1)),
+                },
+                "response": {
+                    "status": e.get("status").cloned().unwrap_or(json!(0)),
+                    "statusText": e.get("statusText").cloned().unwrap_or(json!("")),
+                    "httpVersion": "HTTP/1.1",
+                    "cookies": [],
+                    "headers": headers(e.get("responseHeaders")),
+                    "content": {
+                        "size": e.get("responseSize").cloned().unwrap_or(json!(-1)),
+                        "mimeType": e.get("mimeType").cloned().unwrap_or(json!("")),
+                    },
+                    "redirectURL": "",
+                    "headersSize": -1,
+                    "bodySize": e.get("responseSize").cloned().unwrap_or(json!(-1)),
+                    "_error": e.get("error").cloned().unwrap_or(Value::Null),
+                },
+                "cache": {},
+                "timings": { "send": 0, "wait": duration, "receive": 0 },
+                "_resourceType": e.get("type").cloned().unwrap_or(Value::Null),
+            })
+        })
+        .collect();
+
+    json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "tauri-wd", "version": env!("CARGO_PKG_VERSION") },
+            "pages": [],
+            "entries": har_entries,
+        }
+    })
+}
+
+/// Format milliseconds since the Unix epoch as an ISO 8601 UTC timestamp.
+fn iso8601_from_millis(ms: i64) -> String {
+    let secs = ms.div_euclid(1000);
+    let millis = ms.rem_euclid(1000);
+    let days = secs.div_euclid(86_400);
+    let tod = secs.rem_euclid(86_400);
+    // Civil-from-days (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{millis:03}Z",
+        tod / 3600,
+        (tod % 3600) / 60,
+        tod % 60
+    )
+}
+
+// --- Request mock handlers ---
+
+/// Push the session's mock rules to the plugin, replacing whatever it had.
+async fn sync_mocks(session: &Session) -> Result<(), W3cError> {
+    plugin_post(session, "/mock/set", json!({"rules": session.mocks})).await?;
+    Ok(())
+}
+
+async fn add_mock(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(mut body): Json<Value>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    let Some(rule) = body.as_object_mut() else {
+        return Err(W3cError::bad_request("mock rule must be an object"));
+    };
+    if !rule.get("urlPattern").is_some_and(|p| p.is_string()) {
+        return Err(W3cError::bad_request("urlPattern must be a string"));
+    }
+    if let Some(status) = rule.get("status") {
+        if !status.as_u64().is_some_and(|s| (100..=599).contains(&s)) {
+            return Err(W3cError::bad_request("status must be an HTTP status code"));
+        }
+    }
+    for key in ["delayMs", "times"] {
+        if rule.get(key).is_some_and(|v| v.as_u64().is_none()) {
+            return Err(W3cError::bad_request(format!(
+                "{key} must be a non-negative integer"
+            )));
+        }
+    }
+    let id = format!("mock-{}", uuid::Uuid::new_v4());
+    rule.insert("id".into(), json!(id));
+    session.mocks.push(body);
+    sync_mocks(session).await?;
+    Ok(w3c_value(json!({"id": id})))
+}
+
+async fn get_mocks(AxumState(state): AxumState<SharedState>, Path(sid): Path<String>) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    Ok(w3c_value(json!(session.mocks)))
+}
+
+async fn clear_mocks(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    session.mocks.clear();
+    sync_mocks(session).await?;
+    Ok(w3c_value(json!(null)))
+}
+
+async fn remove_mock(
+    AxumState(state): AxumState<SharedState>,
+    Path((sid, mock_id)): Path<(String, String)>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    let before = session.mocks.len();
+    session
+        .mocks
+        .retain(|m| m.get("id").and_then(|id| id.as_str()) != Some(mock_id.as_str()));
+    if session.mocks.len() == before {
+        return Err(W3cError::bad_request(format!("No mock with id {mock_id}")));
+    }
+    sync_mocks(session).await?;
+    Ok(w3c_value(json!(null)))
+}
+
+// --- Command logging ---
+
+/// Middleware: log every command with its route, session, duration and outcome.
+async fn log_command(request: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    let method = request.method().clone();
+    let matched = request
+        .extensions()
+        .get::<axum::extract::MatchedPath>()
+        .map(|p| p.as_str().to_string());
+    let route = matched
+        .clone()
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let session_id = request
+        .uri()
+        .path()
+        .strip_prefix("/session/")
+        .and_then(|rest| rest.split('/').next())
+        .unwrap_or("")
+        .to_string();
+    // A client's own id is kept, so its logs and ours line up.
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(String::from)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let started = std::time::Instant::now();
+    let span = tracing::info_span!("command", request_id = %request_id);
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request).instrument(span))
+        .await;
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let status = response.status().as_u16();
+    if let Ok(value) = axum::http::HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    // Unmatched paths share one label to keep metric cardinality bounded.
+    let metric_route = matched.as_deref().unwrap_or("unmatched");
+    metrics::command(method.as_str(), metric_route, status, duration_ms / 1000.0);
+    if method == axum::http::Method::POST && metric_route == "/session" {
+        if response.status().is_success() {
+            metrics::session_created();
+        } else {
+            metrics::session_create_failed();
+        }
+    }
+
+    match response.extensions().get::<W3cErrorCode>() {
+        Some(W3cErrorCode(error)) => tracing::warn!(
+            target: "tauri_wd::command",
+            %method,
+            route,
+            session_id,
+            request_id,
+            status,
+            duration_ms,
+            error = error.as_str(),
+            "command failed"
+        ),
+        None => tracing::info!(
+            target: "tauri_wd::command",
+            %method,
+            route,
+            session_id,
+            request_id,
+            status,
+            duration_ms,
+            "command"
+        ),
+    }
+    response
+}
+
+// --- Error artifacts ---
+
+/// Middleware (with `--artifacts-dir`): when a command fails, save a
+/// screenshot and the page source and report their paths in the error's
+/// `data` field.
+async fn capture_error_artifacts(
+    AxumState(state): AxumState<SharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let Some(dir) = state.artifacts_dir.clone() else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path().to_string();
+    let Some(sid) = command_session_id(&path) else {
+        return next.run(request).await;
+    };
+    let response = next.run(request).await;
+    if response.extensions().get::<W3cErrorCode>().is_none() {
+        return response;
+    }
+
+    let (screenshot, source) = {
+        let Some(session) = state.find_session(&sid).await else {
+            return response;
+        };
+        let session = session.read().await;
+        let screenshot = plugin_bytes(&session, "/screenshot", json!({})).await.ok();
+        let source = plugin_post(&session, "/source", json!({}))
+            .await
+            .ok()
+            .and_then(|r| r.get("source").and_then(|s| s.as_str()).map(String::from));
+        (screenshot, source)
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let command = path
+        .split('/')
+        .skip(3)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    let session_dir = dir.join(&sid);
+    if let Err(e) = tokio::fs::create_dir_all(&session_dir).await {
+        tracing::warn!("Failed to create {}: {e}", session_dir.display());
+        return response;
+    }
+    let mut data = serde_json::Map::new();
+    if let Some(png) = screenshot {
+        let file = session_dir.join(format!("{timestamp}-{command}.png"));
+        match tokio::fs::write(&file, png).await {
+            Ok(()) => {
+                data.insert("screenshot".into(), json!(file.display().to_string()));
+            }
+            Err(e) => tracing::warn!("Failed to write {}: {e}", file.display()),
+        }
+    }
+    if let Some(html) = source {
+        let file = session_dir.join(format!("{timestamp}-{command}.html"));
+        match tokio::fs::write(&file, html).await {
+            Ok(()) => {
+                data.insert("pageSource".into(), json!(file.display().to_string()));
+            }
+            Err(e) => tracing::warn!("Failed to write {}: {e}", file.display()),
+        }
+    }
+    if data.is_empty() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    let mut payload: Value = serde_json::from_slice(&bytes).unwrap_or(json!({"value": {}}));
+    // Keep what's there already, such as the request id.
+    match payload["value"]["data"].as_object_mut() {
+        Some(existing) => existing.extend(data),
+        None => payload["value"]["data"] = Value::Object(data),
+    }
+    let mut response = Response::from_parts(parts, axum::body::Body::from(payload.to_string()));
+    response
+        .headers_mut()
+        .remove(axum::http::header::CONTENT_LENGTH);
+    response
+}
+
+// --- Trace recording ---
+
+/// Whether a command may change what's on screen, and so gets a screenshot
+/// in the trace. Finders, reads and driver-side extensions don't.
+fn changes_state(method: &axum::http::Method, path: &str) -> bool {
+    if method == axum::http::Method::GET {
+        return false;
+    }
+    let read_only = [
+        "/element",
+        "/elements",
+        "/timeouts",
+        "/se/log",
+        "/print",
+        "/window/handles",
+    ];
+    !read_only.iter().any(|suffix| path.ends_with(suffix))
+        && !path.contains("/tauri/")
+        && !path.contains("/screenshot")
+}
+
+/// Report a request body over `--body-limit` as a W3C error; axum's own
+/// rejection is a bare 413 with a plain-text body clients can't parse.
+async fn payload_too_large(
+    AxumState(state): AxumState<SharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let response = next.run(request).await;
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE {
+        return response;
+    }
+    W3cError::bad_request(format!(
+        "payload too large: request bodies are limited to {} bytes (see --body-limit)",
+        state.body_limit
+    ))
+    .into_response()
+}
+
+/// Middleware for sessions created with `trace` or `record`: record each
+/// command and its response, plus (for traces) a screenshot after
+/// state-changing commands.
+async fn record_commands(
+    AxumState(state): AxumState<SharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let Some(sid) = command_session_id(&path) else {
+        return next.run(request).await;
+    };
+    let enabled = match state.find_session(&sid).await {
+        Some(session) => {
+            let session = session.read().await;
+            session.trace.is_some() || session.recording.is_some()
+        }
+        None => false,
+    };
+    if !enabled {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let method = parts.method.clone();
+    let request_bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    let started_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let started = std::time::Instant::now();
+    let response = next
+        .run(axum::extract::Request::from_parts(
+            parts,
+            axum::body::Body::from(request_bytes.clone()),
+        ))
+        .await;
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let (parts, body) = response.into_parts();
+    let response_bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let request_json = serde_json::from_slice::<Value>(&request_bytes).unwrap_or(Value::Null);
+    let response_json = serde_json::from_slice::<Value>(&response_bytes).unwrap_or(Value::Null);
+    let mut guard = match state.find_session(&sid).await {
+        Some(session) => Some(session.write_owned().await),
+        None => None,
+    };
+    if let Some(session) = guard.as_deref_mut() {
+        if !path.ends_with("/tauri/recording") {
+            if let Some(recording) = session.recording.as_mut() {
+                recording.record(
+                    &sid,
+                    method.as_str(),
+                    &path,
+                    request_json.clone(),
+                    parts.status.as_u16(),
+                    response_json.clone(),
+                );
+            }
+        }
+        let event = json!({
+            "method": method.as_str(),
+            "path": path,
+            "request": request_json,
+            "status": parts.status.as_u16(),
+            "response": response_json,
+            "startedAt": started_ms,
+            "durationMs": duration_ms,
+        });
+        let screenshot =
+            session.trace.is_some() && parts.status.is_success() && changes_state(&method, &path);
+        let png = if screenshot {
+            plugin_bytes(session, "/screenshot", json!({})).await.ok()
+        } else {
+            None
+        };
+        if let Some(recorder) = session.trace.as_mut() {
+            let index = recorder.record(event);
+            if let Some(png) = png {
+                recorder.attach_screenshot(index, png);
+            }
+        }
+    }
+    drop(guard);
+
+    Response::from_parts(parts, axum::body::Body::from(response_bytes))
+}
+
+/// Gather logs from the app and write the session's trace archive, if any.
+async fn finish_trace(session: &mut Session) {
+    let Some(recorder) = session.trace.take() else {
+        return;
+    };
+    let console = plugin_post(session, "/log/console", json!({}))
+        .await
+        .ok()
+        .and_then(|r| r.get("entries").cloned())
+        .unwrap_or(json!([]));
+    let _ = collect_page_errors(session).await;
+    let _ = collect_network(session).await;
+    let logs = trace::TraceLogs {
+        console,
+        errors: json!(session.page_errors),
+        network: build_har(&session.network_log),
+    };
+    let path = recorder.path.clone();
+    match tokio::task::spawn_blocking(move || recorder.write(&logs)).await {
+        Ok(Ok(())) => tracing::info!("Trace written to {}", path.display()),
+        Ok(Err(e)) => tracing::warn!("Failed to write trace {}: {e}", path.display()),
+        Err(e) => tracing::warn!("Failed to write trace {}: {e}", path.display()),
+    }
+}
+
+// --- Tauri extension handlers (non-W3C, under /session/{id}/tauri/) ---
+
+/// Tail of the app's stdout/stderr. Works after the app has crashed.
+async fn get_app_log(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> W3cResult {
+    let session = state
+        .find_session(&sid)
+        .await
+        .ok_or(W3cError::no_session())?;
+    let session = session.read().await;
+    let count = match query.get("lines") {
+        Some(n) => n
+            .parse::<usize>()
+            .map_err(|_| W3cError::bad_request("lines must be a non-negative integer"))?,
+        None => 200,
+    };
+    let lines = session
+        .app_log
+        .tail(count)
+        .map_err(|e| W3cError::unknown(format!("failed to read app log: {e}")))?;
+    Ok(w3c_value(json!({
+        "path": session.app_log.path().display().to_string(),
+        "lines": lines,
+    })))
+}
+
+async fn get_recording(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let recording = session.recording.as_ref().ok_or_else(|| {
+        W3cError::bad_request("Session was not created with tauri:options.record")
+    })?;
+    Ok(w3c_value(recording.export()))
+}
+
+/// Credentials for the plugin's `/auth/basic` from `value`: one
+/// `{username, password, host?}` object or an array of them.
+fn basic_auth_credentials(value: &Value) -> Result<Vec<Value>, String> {
+    let entries = match value {
+        Value::Array(entries) => entries.clone(),
+        entry => vec![entry.clone()],
+    };
+    for entry in &entries {
+        let string = |key: &str| entry.get(key).is_some_and(|v| v.is_string());
+        if !string("username") || !string("password") {
+            return Err("credentials need string 'username' and 'password'".into());
+        }
+        if !matches!(
+            entry.get("host"),
+            None | Some(Value::Null) | Some(Value::String(_))
+        ) {
+            return Err("'host' must be a string".into());
+        }
+    }
+    Ok(entries)
+}
+
+async fn set_basic_auth(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let credentials = basic_auth_credentials(&body).map_err(W3cError::bad_request)?;
+    plugin_post(session, "/auth/basic", json!({"credentials": credentials})).await?;
+    Ok(w3c_value(json!(null)))
+}
+
+async fn clear_basic_auth(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    plugin_post(session, "/auth/basic", json!({"credentials": []})).await?;
+    Ok(w3c_value(json!(null)))
+}
+
+/// How long `/tauri/wait/element` and `/tauri/wait/script` wait when the
+/// request doesn't say.
+const DEFAULT_WAIT_MS: u64 = 5000;
+
+/// The millisecond count `body[key]`, if given.
+fn optional_ms(body: &Value, key: &str) -> Result<Option<u64>, W3cError> {
+    match body.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v.as_u64().map(Some).ok_or_else(|| {
+            W3cError::bad_request(format!("'{key}' must be a non-negative integer"))
+        }),
+    }
+}
+
+/// Wait for an element the locator finds to meet a condition (`present`,
+/// `displayed`, `enabled`, `text-matches` with `pattern`, or
+/// `attribute-equals` with `name` and `expected`). The plugin re-checks in
+/// the page as the DOM changes, so this is one request however long it
+/// takes.
+async fn wait_for_element(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let mut guard = state.session_mut(&sid).await?;
+    let session = &mut *guard;
+    let (using, value) = extract_locator(&params::parse(&body)?)?;
+    let timeout = optional_ms(&body, "timeout")?.unwrap_or(DEFAULT_WAIT_MS);
+    let result = plugin_post(
+        session,
+        "/wait/element",
+        json!({
+            "using": using,
+            "value": value,
+            "condition": body.get("condition").and_then(|c| c.as_str()).unwrap_or("present"),
+            "pattern": body.get("pattern"),
+            "name": body.get("name"),
+            "expected": body.get("expected"),
+            "timeout": timeout,
+        }),
+    )
+    .await?;
+    let elem = result
+        .get("element")
+        .ok_or_else(|| W3cError::unknown("plugin returned no element"))?;
+    let eid = store_element(session, elem);
+    Ok(w3c_value(json!({W3C_ELEMENT_KEY: eid})))
+}
+
+/// Evaluate `script` (a function body, as for Execute Script, with `args`)
+/// every `interval` ms until it returns something truthy, and return that.
+/// Gives up with `timeout` after `timeout` ms (default 5000).
+async fn wait_for_script(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let script = body
+        .get("script")
+        .and_then(|s| s.as_str())
+        .ok_or_else(|| W3cError::bad_request("Missing 'script'"))?;
+    let mut payload = json!({
+        "script": script,
+        "args": body.get("args").cloned().unwrap_or(json!([])),
+        "timeout": optional_ms(&body, "timeout")?.unwrap_or(DEFAULT_WAIT_MS),
+    });
+    if let Some(interval) = optional_ms(&body, "interval")? {
+        payload["interval"] = json!(interval);
+    }
+    let result = plugin_post(session, "/wait/script", payload).await?;
+    Ok(w3c_value(
+        result.get("value").cloned().unwrap_or(Value::Null),
+    ))
+}
+
+/// Wait until the page has loaded and no fetch/XHR has been in flight for
+/// `quiet` ms (default 500), for at most `timeout` ms (default: the
+/// session's page load timeout).
+async fn wait_for_idle(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let mut payload =
+        json!({"timeout": optional_ms(&body, "timeout")?.unwrap_or(session.timeouts.page_load)});
+    if let Some(quiet) = optional_ms(&body, "quiet")? {
+        payload["quiet"] = json!(quiet);
+    }
+    plugin_post(session, "/wait/idle", payload).await?;
+    Ok(w3c_value(json!(null)))
+}
+
+async fn get_element_selection(
+    AxumState(state): AxumState<SharedState>,
+    Path((sid, eid)): Path<(String, String)>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let elem = resolve_element(session, &eid)?;
+    let result = plugin_post(
+        session,
+        "/element/selection",
+        json!({"selector": elem.selector, "index": elem.index, "using": elem.using}),
+    )
+    .await?;
+    Ok(w3c_value(result))
+}
+
+/// Select `start..end` in an input, textarea or contenteditable element;
+/// without `end`, place the caret at `start`.
+async fn set_element_selection(
+    AxumState(state): AxumState<SharedState>,
+    Path((sid, eid)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let elem = resolve_element(session, &eid)?;
+    let start = body
+        .get("start")
+        .and_then(|s| s.as_u64())
+        .ok_or_else(|| W3cError::bad_request("'start' must be a non-negative integer"))?;
+    let end = match body.get("end") {
+        None | Some(Value::Null) => start,
+        Some(end) => end
+            .as_u64()
+            .ok_or_else(|| W3cError::bad_request("'end' must be a non-negative integer"))?,
+    };
+    let result = plugin_post(
+        session,
+        "/element/set-selection",
+        json!({
+            "selector": elem.selector,
+            "index": elem.index,
+            "using": elem.using,
+            "start": start,
+            "end": end,
+            "direction": body.get("direction"),
+        }),
+    )
+    .await?;
+    Ok(w3c_value(result))
+}
+
+async fn get_selection(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let result = plugin_post(session, "/selection", json!({})).await?;
+    Ok(w3c_value(result))
+}
+
+/// Press a native menu accelerator (or, with `global`, a shortcut
+/// registered with the OS), which DOM key events can't reach.
+async fn press_shortcut(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let accelerator = body
+        .get("accelerator")
+        .and_then(|a| a.as_str())
+        .ok_or_else(|| W3cError::bad_request("Missing 'accelerator'"))?;
+    let global = body
+        .get("global")
+        .and_then(|g| g.as_bool())
+        .unwrap_or(false);
+    let result = plugin_post(
+        session,
+        "/shortcut/press",
+        json!({"accelerator": accelerator, "global": global}),
+    )
+    .await?;
+    Ok(w3c_value(result))
+}
+
+async fn set_viewport(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let width = body
+        .get("width")
+        .and_then(|v| v.as_f64())
+        .filter(|w| *w > 0.0)
+        .ok_or_else(|| W3cError::bad_request("'width' must be a positive number"))?;
+    let height = body
+        .get("height")
+        .and_then(|v| v.as_f64())
+        .filter(|h| *h > 0.0)
+        .ok_or_else(|| W3cError::bad_request("'height' must be a positive number"))?;
+    let scale = match body.get("deviceScaleFactor") {
+        None | Some(Value::Null) => None,
+        Some(v) => Some(v.as_f64().filter(|s| *s > 0.0).ok_or_else(|| {
+            W3cError::bad_request("'deviceScaleFactor' must be a positive number")
+        })?),
+    };
+    let result = plugin_post(
+        session,
+        "/window/set-viewport",
+        json!({"width": width, "height": height, "deviceScaleFactor": scale}),
+    )
+    .await?;
+    Ok(w3c_value(result))
+}
+
+async fn reset_viewport(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let result = plugin_post(session, "/window/reset-viewport", json!({})).await?;
+    Ok(w3c_value(result))
+}
+
+/// Scroll an element's scroll containers so it's in view; returns its new
+/// viewport rect.
+async fn scroll_into_view(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let eid = body
+        .get("element")
+        .and_then(|e| e.get(W3C_ELEMENT_KEY))
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| W3cError::bad_request("'element' must be an element reference"))?;
+    let elem = resolve_element(session, eid)?;
+    let result = plugin_post(
+        session,
+        "/element/scroll-into-view",
+        json!({
+            "selector": elem.selector,
+            "index": elem.index,
+            "using": elem.using,
+            "block": body.get("block"),
+            "inline": body.get("inline"),
+        }),
+    )
+    .await?;
+    Ok(w3c_value(result))
+}
+
+/// Run a touch gesture (swipe, pinch, longpress) as Perform Actions.
+async fn perform_gesture(
+    AxumState(state): AxumState<SharedState>,
+    Path((sid, name)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let actions = gesture::actions(&name, &body).map_err(W3cError::bad_request)?;
+    perform_actions(
+        AxumState(state),
+        Path(sid),
+        Params(params::parse(&actions)?),
+    )
+    .await
+}
+
+/// Run a list of W3C commands in order within the server, so a client far
+/// from the driver pays one round trip for all of them. Each command is
+/// `{method, path, body}` with `path` relative to the session (`/url`,
+/// `/element`, ...), and goes through the same router as a request of its
+/// own. Returns `{status, value}` for each command run; unless `stopOnError`
+/// is false, the batch stops at the first command that fails.
+async fn run_batch(
+    AxumState(state): AxumState<SharedState>,
+    Path(sid): Path<String>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    use tower::ServiceExt as _;
+
+    state.session(&sid).await?;
+    let commands = body
+        .get("commands")
+        .and_then(|c| c.as_array())
+        .ok_or_else(|| W3cError::bad_request("'commands' must be an array"))?;
+    let stop_on_error = body
+        .get("stopOnError")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+
+    // Check every command before running any, so a malformed batch has no
+    // effect.
+    let mut requests = Vec::with_capacity(commands.len());
+    for (i, command) in commands.iter().enumerate() {
+        let invalid = |message: &str| W3cError::bad_request(format!("command {i}: {message}"));
+        let method = command
+            .get("method")
+            .and_then(|m| m.as_str())
+            .unwrap_or("GET");
+        let method = axum::http::Method::from_bytes(method.as_bytes())
+            .map_err(|_| invalid(&format!("invalid method '{method}'")))?;
+        let path = command
+            .get("path")
+            .and_then(|p| p.as_str())
+            .filter(|p| p.starts_with('/'))
+            .ok_or_else(|| invalid("'path' must start with '/'"))?;
+        if path.trim_end_matches('/') == "/tauri/batch" {
+            return Err(invalid("batches can't be nested"));
+        }
+        // W3C clients send `{}` with commands that take no parameters.
+        let body = match command.get("body") {
+            Some(body) if !body.is_null() => Some(body.to_string()),
+            _ if method == axum::http::Method::POST => Some("{}".to_string()),
+            _ => None,
+        };
+        // The batch itself holds the session's gate.
+        let mut request = axum::extract::Request::builder()
+            .method(method)
+            .uri(format!("/session/{sid}{path}"))
+            .extension(GateHeld);
+        if let Ok(id) = REQUEST_ID.try_with(Clone::clone) {
+            request = request.header(REQUEST_ID_HEADER, format!("{id}/{i}"));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body)),
+            None => request.body(axum::body::Body::empty()),
+        }
+        .map_err(|e| invalid(&e.to_string()))?;
+        requests.push(request);
+    }
+
+    let router = build_router(state.clone());
+    let mut results = Vec::with_capacity(requests.len());
+    for request in requests {
+        let response = router
+            .clone()
+            .oneshot(request)
+            .await
+            .unwrap_or_else(|e| match e {});
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap_or_default();
+        let value = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .and_then(|v| v.get("value").cloned())
+            .unwrap_or(Value::Null);
+        results.push(json!({"status": status.as_u16(), "value": value}));
+        if stop_on_error && !status.is_success() {
+            break;
+        }
+    }
+    Ok(w3c_value(json!(results)))
+}
+
+/// Forward to a route the app registered with the plugin's `Builder::routes`
+/// or `Builder::command`.
+async fn call_extension(
+    AxumState(state): AxumState<SharedState>,
+    Path((sid, name)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> W3cResult {
+    let guard = state.session(&sid).await?;
+    let session = &*guard;
+    let result = plugin_post(session, &format!("/ext/{name}"), body).await?;
+    Ok(w3c_value(result))
+}
+
+// --- Main ---
+
+/// Serve on an ephemeral loopback port for a subcommand's own use (`repl`,
+/// `conformance`) and return the server's URL.
+async fn serve_locally(state: &SharedState) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
         .expect("failed to bind WebDriver server");
-    let shutdown = async move {
-        let ctrl_c = tokio::signal::ctrl_c();
-        #[cfg(unix)]
-        {
-            let mut sigterm =
-                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-                    .expect("failed to create SIGTERM handler");
-            tokio::select! {
-                _ = ctrl_c => { tracing::info!("Received SIGINT, shutting down"); }
-                _ = sigterm.recv() => { tracing::info!("Received SIGTERM, shutting down"); }
+    let server = format!(
+        "http://{}",
+        listener.local_addr().expect("failed to read local address")
+    );
+    let router = build_router(state.clone());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    server
+}
+
+/// Close the apps of sessions a subcommand left behind.
+async fn end_local_sessions(state: &SharedState) {
+    for (_, session) in state.all_sessions().await {
+        let mut session = session.write().await;
+        registry::unregister(session.process.id());
+        let _ = session.process.kill().await;
+        remove_uploads(&session).await;
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&cli.log_level));
+    match cli.log_format {
+        LogFormat::Text => tracing_subscriber::fmt().with_env_filter(env_filter).init(),
+        LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .with_env_filter(env_filter)
+            .init(),
+    }
+
+    let state: SharedState = Arc::new(AppState {
+        sessions: Mutex::new(HashMap::new()),
+        max_sessions: cli.max_sessions,
+        artifacts_dir: cli.artifacts_dir,
+        debug_endpoints: cli.debug_endpoints,
+        pool: Arc::new(pool::Pool::new(cli.pool_size)),
+        queue_timeout: Duration::from_secs(cli.queue_timeout),
+        session_freed: tokio::sync::Notify::new(),
+        launching: AtomicUsize::new(0),
+        plugin_retries: cli.plugin_retries,
+        max_elements: cli.max_elements,
+        max_log_entries: cli.max_log_entries,
+        tls: cli.tls_cert.is_some(),
+        body_limit: cli.body_limit.saturating_mul(1024 * 1024),
+        ping_interval: Duration::from_secs(cli.ping_interval),
+        overlap: cli.overlap,
+        parallel_reads: cli.parallel_reads,
+    });
+
+    match cli.command {
+        Some(Command::Replay { file, server }) => {
+            let code = match replay::run(&file, &server).await {
+                Ok(0) => 0,
+                Ok(_) => 1,
+                Err(e) => {
+                    eprintln!("replay failed: {e}");
+                    2
+                }
+            };
+            std::process::exit(code);
+        }
+        Some(Command::Cleanup) => {
+            let (killed, stale) = registry::sweep();
+            println!(
+                "Killed {} orphaned app process(es){}",
+                killed.len(),
+                if stale > 0 {
+                    format!("; removed {stale} stale record(s)")
+                } else {
+                    String::new()
+                }
+            );
+            return;
+        }
+        Some(Command::Doctor { binary, timeout }) => {
+            let ok = doctor::run(&binary, Duration::from_secs(timeout)).await;
+            std::process::exit(if ok { 0 } else { 1 });
+        }
+        Some(Command::Repl { binary }) => {
+            let server = serve_locally(&state).await;
+            let result = repl::run(&server, &binary).await;
+            end_local_sessions(&state).await;
+            if let Err(e) = result {
+                eprintln!("{e}");
+                std::process::exit(1);
             }
+            return;
         }
-        #[cfg(not(unix))]
-        {
-            ctrl_c.await.ok();
-            tracing::info!("Received SIGINT, shutting down");
+        Some(Command::Conformance {
+            binary,
+            suite,
+            filter,
+            report,
+        }) => {
+            let server = serve_locally(&state).await;
+            let result = conformance::run(
+                &server,
+                &binary,
+                suite.as_deref(),
+                filter.as_deref(),
+                report.as_deref(),
+            )
+            .await;
+            end_local_sessions(&state).await;
+            let code = match result {
+                Ok(true) => 0,
+                Ok(false) => 1,
+                Err(e) => {
+                    eprintln!("conformance run failed: {e}");
+                    2
+                }
+            };
+            std::process::exit(code);
         }
+        None => {}
+    }
+
+    if let Some(count) = cli.workers {
+        let code = workers::run(count as usize, &cli.host, cli.port, cli.tls_cert.is_some()).await;
+        std::process::exit(code);
+    }
+
+    let (killed, _) = registry::sweep();
+    if !killed.is_empty() {
+        tracing::info!(
+            "Killed {} orphaned app process(es) on startup",
+            killed.len()
+        );
+    }
+
+    let router = build_router(state.clone());
+    let shutdown_state = state;
 
+    let tls_config = match (&cli.tls_cert, &cli.tls_key) {
+        (Some(cert), Some(key)) => match tls::load_config(cert, key) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
+
+    let addr = format!("{}:{}", cli.host, cli.port);
+    let scheme = if tls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    tracing::info!("tauri-wd listening on {scheme}://{addr}");
+
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .expect("failed to bind WebDriver server");
+    let shutdown = async move {
+        shutdown_signal().await;
+        shutdown_state.pool.drain();
         // Kill all active sessions' app processes
         let mut sessions = shutdown_state.sessions.lock().await;
-        for (sid, session) in sessions.iter_mut() {
+        for (sid, entry) in sessions.drain() {
+            let mut session = entry.session.write().await;
+            finish_trace(&mut session).await;
+            registry::unregister(session.process.id());
             let _ = session.process.kill().await;
+            remove_uploads(&session).await;
             tracing::info!("Killed app process for session {sid} on shutdown");
         }
-        sessions.clear();
+        drop(sessions);
+        shutdown_state.session_freed.notify_waiters();
     };
 
-    axum::serve(listener, router)
-        .with_graceful_shutdown(shutdown)
-        .await
-        .expect("WebDriver server error");
+    match tls_config {
+        Some(config) => {
+            let listener =
+                tls::TlsListener::new(listener, config).expect("failed to start TLS listener");
+            axum::serve(listener, router)
+                .with_graceful_shutdown(shutdown)
+                .await
+        }
+        None => {
+            axum::serve(listener, router)
+                .with_graceful_shutdown(shutdown)
+                .await
+        }
+    }
+    .expect("WebDriver server error");
+}
+
+/// Wait for SIGINT, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    let ctrl_c = tokio::signal::ctrl_c();
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to create SIGTERM handler");
+        tokio::select! {
+            _ = ctrl_c => { tracing::info!("Received SIGINT, shutting down"); }
+            _ = sigterm.recv() => { tracing::info!("Received SIGTERM, shutting down"); }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        ctrl_c.await.ok();
+        tracing::info!("Received SIGINT, shutting down");
+    }
+}
+
+fn build_router(state: SharedState) -> Router {
+    Router::new()
+        // Session
+        .route("/status", get(get_status))
+        .route("/metrics", get(get_metrics))
+        .route("/sessions", get(list_sessions))
+        .route("/session", post(create_session))
+        .route("/session/{sid}", delete(delete_session))
+        .route("/session/{sid}", get(bidi::connect))
+        // Timeouts
+        .route("/session/{sid}/timeouts", get(get_timeouts))
+        .route("/session/{sid}/timeouts", post(set_timeouts))
+        // Navigation
+        .route("/session/{sid}/url", post(navigate_to))
+        .route("/session/{sid}/url", get(get_url))
+        .route("/session/{sid}/title", get(get_title))
+        .route("/session/{sid}/source", get(get_page_source))
+        .route("/session/{sid}/back", post(go_back))
+        .route("/session/{sid}/forward", post(go_forward))
+        .route("/session/{sid}/refresh", post(refresh))
+        // Window
+        .route("/session/{sid}/window", get(get_window_handle))
+        .route("/session/{sid}/window", post(switch_to_window))
+        .route("/session/{sid}/window", delete(close_window))
+        .route("/session/{sid}/window/handles", get(get_window_handles))
+        .route("/session/{sid}/window/rect", get(get_window_rect))
+        .route("/session/{sid}/window/rect", post(set_window_rect))
+        .route("/session/{sid}/window/maximize", post(maximize_window))
+        .route("/session/{sid}/window/minimize", post(minimize_window))
+        .route("/session/{sid}/window/fullscreen", post(fullscreen_window))
+        .route("/session/{sid}/window/new", post(new_window))
+        // Frames
+        .route("/session/{sid}/frame", post(switch_to_frame))
+        .route("/session/{sid}/frame/parent", post(switch_to_parent_frame))
+        // Elements
+        .route("/session/{sid}/element", post(find_element))
+        .route("/session/{sid}/elements", post(find_elements))
+        .route("/session/{sid}/element/active", get(get_active_element))
+        .route(
+            "/session/{sid}/element/{eid}/element",
+            post(find_element_from_element),
+        )
+        .route(
+            "/session/{sid}/element/{eid}/elements",
+            post(find_elements_from_element),
+        )
+        .route("/session/{sid}/element/{eid}/click", post(click_element))
+        .route("/session/{sid}/element/{eid}/clear", post(clear_element))
+        .route("/session/{sid}/element/{eid}/value", post(send_keys))
+        .route("/session/{sid}/element/{eid}/text", get(get_element_text))
+        .route("/session/{sid}/element/{eid}/name", get(get_element_tag))
+        .route(
+            "/session/{sid}/element/{eid}/attribute/{name}",
+            get(get_element_attribute),
+        )
+        .route(
+            "/session/{sid}/element/{eid}/property/{name}",
+            get(get_element_property),
+        )
+        .route(
+            "/session/{sid}/element/{eid}/css/{name}",
+            get(get_element_css),
+        )
+        .route("/session/{sid}/element/{eid}/rect", get(get_element_rect))
+        .route(
+            "/session/{sid}/element/{eid}/enabled",
+            get(is_element_enabled),
+        )
+        .route(
+            "/session/{sid}/element/{eid}/selected",
+            get(is_element_selected),
+        )
+        .route(
+            "/session/{sid}/element/{eid}/displayed",
+            get(is_element_displayed),
+        )
+        .route(
+            "/session/{sid}/element/{eid}/computedrole",
+            get(get_computed_role),
+        )
+        .route(
+            "/session/{sid}/element/{eid}/computedlabel",
+            get(get_computed_label),
+        )
+        .route("/session/{sid}/element/{eid}/shadow", get(get_shadow_root))
+        .route("/session/{sid}/shadow/{sid2}/element", post(find_in_shadow))
+        .route(
+            "/session/{sid}/shadow/{sid2}/elements",
+            post(find_all_in_shadow),
+        )
+        // Scripts
+        .route("/session/{sid}/execute/sync", post(execute_sync))
+        .route("/session/{sid}/execute/async", post(execute_async))
+        // Cookies
+        .route("/session/{sid}/cookie", get(get_all_cookies))
+        .route("/session/{sid}/cookie", post(add_cookie))
+        .route("/session/{sid}/cookie", delete(delete_all_cookies))
+        .route("/session/{sid}/cookie/{name}", get(get_named_cookie))
+        .route("/session/{sid}/cookie/{name}", delete(delete_cookie))
+        // Alerts
+        .route("/session/{sid}/alert/dismiss", post(dismiss_alert))
+        .route("/session/{sid}/alert/accept", post(accept_alert))
+        .route("/session/{sid}/alert/text", get(get_alert_text))
+        .route("/session/{sid}/alert/text", post(send_alert_text))
+        // Actions
+        .route("/session/{sid}/actions", post(perform_actions))
+        .route("/session/{sid}/actions", delete(release_actions))
+        // Print
+        .route("/session/{sid}/print", post(print_page))
+        // Screenshots
+        .route("/session/{sid}/screenshot", get(take_screenshot))
+        .route(
+            "/session/{sid}/element/{eid}/screenshot",
+            get(element_screenshot),
+        )
+        // Logs
+        .route("/session/{sid}/se/log", post(get_log))
+        .route("/session/{sid}/se/log", get(get_log_by_query))
+        .route("/session/{sid}/se/log/types", get(get_log_types))
+        // File upload
+        .route("/session/{sid}/se/file", post(upload_file))
+        // Tauri extensions
+        .route("/session/{sid}/tauri/recording", get(get_recording))
+        .route("/session/{sid}/tauri/app-log", get(get_app_log))
+        .route("/session/{sid}/tauri/errors", get(get_page_errors))
+        .route("/session/{sid}/tauri/errors", delete(clear_page_errors))
+        .route("/session/{sid}/tauri/prints", get(get_print_requests))
+        .route("/session/{sid}/tauri/prints", delete(clear_print_requests))
+        .route("/session/{sid}/tauri/network/log", get(get_network_log))
+        .route(
+            "/session/{sid}/tauri/network/log",
+            delete(clear_network_log),
+        )
+        .route("/session/{sid}/tauri/network/har", get(get_network_har))
+        .route("/session/{sid}/tauri/mocks", post(add_mock))
+        .route("/session/{sid}/tauri/mocks", get(get_mocks))
+        .route("/session/{sid}/tauri/mocks", delete(clear_mocks))
+        .route("/session/{sid}/tauri/mocks/{mid}", delete(remove_mock))
+        .route("/session/{sid}/tauri/basic-auth", post(set_basic_auth))
+        .route("/session/{sid}/tauri/basic-auth", delete(clear_basic_auth))
+        .route("/session/{sid}/tauri/shortcut", post(press_shortcut))
+        .route("/session/{sid}/tauri/selection", get(get_selection))
+        .route("/session/{sid}/tauri/wait/element", post(wait_for_element))
+        .route("/session/{sid}/tauri/wait/idle", post(wait_for_idle))
+        .route("/session/{sid}/tauri/wait/script", post(wait_for_script))
+        .route(
+            "/session/{sid}/tauri/element/{eid}/selection",
+            get(get_element_selection),
+        )
+        .route(
+            "/session/{sid}/tauri/element/{eid}/selection",
+            post(set_element_selection),
+        )
+        .route("/session/{sid}/tauri/viewport", post(set_viewport))
+        .route("/session/{sid}/tauri/viewport", delete(reset_viewport))
+        .route("/session/{sid}/tauri/gesture/{name}", post(perform_gesture))
+        .route(
+            "/session/{sid}/tauri/scroll-into-view",
+            post(scroll_into_view),
+        )
+        .route(
+            "/session/{sid}/tauri/elements/release",
+            post(release_elements),
+        )
+        .route("/session/{sid}/tauri/batch", post(run_batch))
+        .route("/session/{sid}/tauri/ext/{*name}", post(call_extension))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            payload_too_large,
+        ))
+        .layer(axum::extract::DefaultBodyLimit::max(state.body_limit))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            page_error_guard,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            capture_error_artifacts,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            record_commands,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            session_gate,
+        ))
+        .layer(axum::middleware::from_fn(log_command))
+        .with_state(state)
 }
@@ -0,0 +1,198 @@
+// metrics: Prometheus metrics for long-running tauri-wd instances.
+//
+// Counters and histograms are kept in a process-wide registry and rendered
+// in the Prometheus text exposition format by `GET /metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+
+/// Upper bounds (seconds) of the command latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+struct Registry {
+    sessions_created: u64,
+    session_create_failures: u64,
+    /// (method, route, status) -> count
+    commands: BTreeMap<(String, String, u16), u64>,
+    /// (method, route) -> latency histogram
+    latency: BTreeMap<(String, String), Histogram>,
+    /// (plugin path, reason) -> count
+    plugin_failures: BTreeMap<(String, &'static str), u64>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    sessions_created: 0,
+    session_create_failures: 0,
+    commands: BTreeMap::new(),
+    latency: BTreeMap::new(),
+    plugin_failures: BTreeMap::new(),
+});
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(crate) fn session_created() {
+    registry().sessions_created += 1;
+}
+
+pub(crate) fn session_create_failed() {
+    registry().session_create_failures += 1;
+}
+
+pub(crate) fn command(method: &str, route: &str, status: u16, seconds: f64) {
+    let mut reg = registry();
+    *reg.commands
+        .entry((method.to_string(), route.to_string(), status))
+        .or_default() += 1;
+    let hist = reg
+        .latency
+        .entry((method.to_string(), route.to_string()))
+        .or_insert(Histogram {
+            buckets: [0; LATENCY_BUCKETS.len()],
+            count: 0,
+            sum: 0.0,
+        });
+    for (bucket, bound) in hist.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+        if seconds <= bound {
+            *bucket += 1;
+        }
+    }
+    hist.count += 1;
+    hist.sum += seconds;
+}
+
+/// `reason` is one of `transport`, `timeout`, `parse` or `status`.
+pub(crate) fn plugin_failure(path: &str, reason: &'static str) {
+    *registry()
+        .plugin_failures
+        .entry((path.to_string(), reason))
+        .or_default() += 1;
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render all metrics in the Prometheus text format.
+pub(crate) fn render(
+    active_sessions: usize,
+    exited_sessions: usize,
+    max_sessions: usize,
+) -> String {
+    let reg = registry();
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "# HELP tauri_wd_sessions_created_total Sessions successfully created."
+    );
+    let _ = writeln!(out, "# TYPE tauri_wd_sessions_created_total counter");
+    let _ = writeln!(
+        out,
+        "tauri_wd_sessions_created_total {}",
+        reg.sessions_created
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP tauri_wd_session_create_failures_total Session creation attempts that failed."
+    );
+    let _ = writeln!(out, "# TYPE tauri_wd_session_create_failures_total counter");
+    let _ = writeln!(
+        out,
+        "tauri_wd_session_create_failures_total {}",
+        reg.session_create_failures
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP tauri_wd_active_sessions Sessions currently open whose app is running."
+    );
+    let _ = writeln!(out, "# TYPE tauri_wd_active_sessions gauge");
+    let _ = writeln!(out, "tauri_wd_active_sessions {active_sessions}");
+
+    let _ = writeln!(
+        out,
+        "# HELP tauri_wd_exited_sessions Sessions still open whose app has exited."
+    );
+    let _ = writeln!(out, "# TYPE tauri_wd_exited_sessions gauge");
+    let _ = writeln!(out, "tauri_wd_exited_sessions {exited_sessions}");
+
+    let _ = writeln!(
+        out,
+        "# HELP tauri_wd_max_sessions Configured session limit (0 = unlimited)."
+    );
+    let _ = writeln!(out, "# TYPE tauri_wd_max_sessions gauge");
+    let _ = writeln!(out, "tauri_wd_max_sessions {max_sessions}");
+
+    let _ = writeln!(
+        out,
+        "# HELP tauri_wd_commands_total Commands handled, by route and HTTP status."
+    );
+    let _ = writeln!(out, "# TYPE tauri_wd_commands_total counter");
+    for ((method, route, status), count) in &reg.commands {
+        let _ = writeln!(
+            out,
+            "tauri_wd_commands_total{{method=\"{method}\",route=\"{}\",status=\"{status}\"}} {count}",
+            escape(route)
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP tauri_wd_command_duration_seconds Command latency, by route."
+    );
+    let _ = writeln!(out, "# TYPE tauri_wd_command_duration_seconds histogram");
+    for ((method, route), hist) in &reg.latency {
+        let labels = format!("method=\"{method}\",route=\"{}\"", escape(route));
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(hist.buckets) {
+            let _ = writeln!(
+                out,
+                "tauri_wd_command_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {count}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "tauri_wd_command_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+            hist.count
+        );
+        let _ = writeln!(
+            out,
+            "tauri_wd_command_duration_seconds_sum{{{labels}}} {}",
+            hist.sum
+        );
+        let _ = writeln!(
+            out,
+            "tauri_wd_command_duration_seconds_count{{{labels}}} {}",
+            hist.count
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP tauri_wd_plugin_request_failures_total Failed requests to the in-app plugin."
+    );
+    let _ = writeln!(out, "# TYPE tauri_wd_plugin_request_failures_total counter");
+    for ((path, reason), count) in &reg.plugin_failures {
+        let _ = writeln!(
+            out,
+            "tauri_wd_plugin_request_failures_total{{path=\"{}\",reason=\"{reason}\"}} {count}",
+            escape(path)
+        );
+    }
+
+    out
+}
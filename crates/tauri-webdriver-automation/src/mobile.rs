@@ -0,0 +1,405 @@
+// mobile: sessions on an iOS simulator or Android emulator.
+//
+// `tauri:options.mobile` replaces `binary`: the app is (optionally) installed
+// from a prebuilt `.app` / `.apk` and launched on the device, and commands go
+// to the plugin through the device bridge. iOS simulators share the host's
+// network and filesystem, so the plugin port is read from the usual port
+// file. On Android the plugin writes it to the app's cache directory, which
+// is read with `adb shell run-as`, and the port is forwarded with
+// `adb forward`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::io::AsyncBufReadExt;
+
+use crate::launch::{AppLog, AppProcess, LaunchedApp};
+
+/// Where the plugin writes its port on Android, relative to the app's data
+/// directory (the plugin uses its cache directory).
+const ANDROID_PORT_FILE: &str = "cache/tauri-webdriver-port";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Platform {
+    Ios,
+    Android,
+}
+
+/// A parsed `tauri:options.mobile`.
+pub(crate) struct MobileTarget {
+    pub platform: Platform,
+    /// Simulator UDID or name (iOS, default `booted`) or adb serial (Android).
+    device: Option<String>,
+    /// Prebuilt `.app` (iOS) or `.apk` (Android) to install before launching.
+    app: Option<PathBuf>,
+    /// Bundle identifier / Android package.
+    identifier: String,
+    /// Android activity to start, default `.MainActivity`.
+    activity: String,
+}
+
+impl MobileTarget {
+    pub fn from_option(value: &Value) -> Result<Self, String> {
+        let field = |key: &str| -> Result<Option<String>, String> {
+            match value.get(key) {
+                None | Some(Value::Null) => Ok(None),
+                Some(Value::String(s)) => Ok(Some(s.clone())),
+                Some(_) => Err(format!("tauri:options.mobile.{key} must be a string")),
+            }
+        };
+        let platform = match field("platform")?.as_deref() {
+            Some("ios") => Platform::Ios,
+            Some("android") => Platform::Android,
+            _ => {
+                return Err(
+                    "tauri:options.mobile.platform must be \"ios\" or \"android\"".to_string(),
+                )
+            }
+        };
+        let app = field("app")?.map(PathBuf::from);
+        let identifier = match (field("identifier")?, platform, &app) {
+            (Some(id), _, _) => id,
+            (None, Platform::Ios, Some(app)) => ios_bundle_id(app)?,
+            (None, _, _) => {
+                return Err(
+                    "Missing tauri:options.mobile.identifier (the app's bundle identifier)"
+                        .to_string(),
+                )
+            }
+        };
+        // Interpolated into device shell commands.
+        if !identifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+        {
+            return Err(format!("Invalid app identifier {identifier:?}"));
+        }
+        Ok(Self {
+            platform,
+            device: field("device")?,
+            app,
+            identifier,
+            activity: field("activity")?.unwrap_or_else(|| ".MainActivity".to_string()),
+        })
+    }
+
+    /// The W3C `platformName` of the device.
+    pub fn platform_name(&self) -> &'static str {
+        match self.platform {
+            Platform::Ios => "ios",
+            Platform::Android => "android",
+        }
+    }
+}
+
+impl std::fmt::Display for MobileTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.platform_name(), self.identifier)
+    }
+}
+
+/// `CFBundleIdentifier` of a simulator `.app` (its Info.plist is usually
+/// binary, so read it with `plutil`).
+fn ios_bundle_id(app: &std::path::Path) -> Result<String, String> {
+    let output = std::process::Command::new("plutil")
+        .args(["-extract", "CFBundleIdentifier", "raw", "-o", "-"])
+        .arg(app.join("Info.plist"))
+        .output()
+        .map_err(|e| format!("Failed to run plutil: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Could not read the bundle identifier of {}; set tauri:options.mobile.identifier",
+            app.display()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Run a bridge command to completion, returning its stdout.
+async fn run(command: &mut tokio::process::Command, what: &str) -> Result<String, String> {
+    let output = command
+        .output()
+        .await
+        .map_err(|e| format!("Failed to {what}: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to {what}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn simctl(args: &[&str]) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("xcrun");
+    command.arg("simctl").args(args);
+    command
+}
+
+fn adb(target: &MobileTarget, args: &[&str]) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("adb");
+    if let Some(serial) = &target.device {
+        command.args(["-s", serial]);
+    }
+    command.args(args);
+    command
+}
+
+/// Install (if given an artifact) and launch the app, and wait up to
+/// `timeout` for its plugin to come up.
+pub(crate) async fn launch(
+    target: &MobileTarget,
+    args: &[String],
+    env: &BTreeMap<String, String>,
+    id: &str,
+    log_path: PathBuf,
+    timeout: Duration,
+) -> Result<LaunchedApp, String> {
+    let log = AppLog::create(log_path.clone())
+        .map_err(|e| format!("Failed to create app log {}: {e}", log_path.display()))?;
+    match target.platform {
+        Platform::Ios => launch_ios(target, args, env, id, log, timeout).await,
+        Platform::Android => {
+            if !args.is_empty() || !env.is_empty() {
+                return Err("tauri:options.args and env are not supported on Android".to_string());
+            }
+            launch_android(target, log, timeout).await
+        }
+    }
+}
+
+async fn launch_ios(
+    target: &MobileTarget,
+    args: &[String],
+    env: &BTreeMap<String, String>,
+    id: &str,
+    log: AppLog,
+    timeout: Duration,
+) -> Result<LaunchedApp, String> {
+    let device = target.device.as_deref().unwrap_or("booted");
+    if let Some(app) = &target.app {
+        tracing::info!("Installing {} on simulator {device}", app.display());
+        run(
+            simctl(&["install", device]).arg(app),
+            "install the app on the simulator",
+        )
+        .await?;
+    }
+
+    // simctl passes SIMCTL_CHILD_* variables to the app without the prefix.
+    let port_file = std::env::temp_dir().join(format!("tauri-wd-port-{id}"));
+    let token = crate::launch::new_token();
+    let mut command = simctl(&[
+        "launch",
+        "--console-pty",
+        "--terminate-running-process",
+        device,
+        &target.identifier,
+    ]);
+    for (key, value) in env {
+        command.env(format!("SIMCTL_CHILD_{key}"), value);
+    }
+    let mut child = command
+        .args(args)
+        .env("SIMCTL_CHILD_TAURI_WEBVIEW_AUTOMATION", "true")
+        .env(
+            format!("SIMCTL_CHILD_{}", crate::launch::PORT_FILE_ENV),
+            &port_file,
+        )
+        .env(format!("SIMCTL_CHILD_{}", crate::launch::TOKEN_ENV), &token)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run xcrun simctl launch: {e}"))?;
+    let stop = || {
+        let mut command = simctl(&["terminate", device, &target.identifier]);
+        command.kill_on_drop(true);
+        command
+    };
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| "Failed to capture app stdout".to_string())?;
+    if let Some(stderr) = child.stderr.take() {
+        let log = log.clone();
+        tokio::spawn(async move {
+            let mut lines = tokio::io::BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                log.write("stderr", &line);
+            }
+        });
+    }
+    let mut reader = tokio::io::BufReader::new(stdout).lines();
+    let addr =
+        crate::launch::wait_for_plugin_port(&mut reader, &port_file, Some(&log), timeout).await;
+    let addr = match addr {
+        Ok(Some(addr)) => addr,
+        Ok(None) | Err(_) => {
+            let _ = child.kill().await;
+            let _ = stop().status().await;
+            return Err(format!(
+                "App on simulator {device} did not report plugin port in time (app output: {})",
+                log.path().display()
+            ));
+        }
+    };
+    let stdout_log = log.clone();
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = reader.next_line().await {
+            stdout_log.write("stdout", &line);
+        }
+    });
+
+    // The simulator shares the host's loopback interface.
+    let client = crate::launch::plugin_client(Some(&token));
+    let api = match crate::launch::connect_plugin(&client, &addr.base_url(), timeout).await {
+        Ok(api) => api,
+        Err(e) => {
+            let _ = child.kill().await;
+            let _ = stop().status().await;
+            return Err(format!("{e} (app output: {})", log.path().display()));
+        }
+    };
+    Ok(LaunchedApp {
+        process: AppProcess::monitor(child, vec![stop()]),
+        plugin_url: api.url,
+        log,
+        client,
+        bridge: api.bridge,
+    })
+}
+
+async fn launch_android(
+    target: &MobileTarget,
+    log: AppLog,
+    timeout: Duration,
+) -> Result<LaunchedApp, String> {
+    let package = target.identifier.as_str();
+    if let Some(apk) = &target.app {
+        tracing::info!("Installing {}", apk.display());
+        run(
+            adb(target, &["install", "-r"]).arg(apk),
+            "install the APK with adb",
+        )
+        .await?;
+    }
+    // A port file from an earlier run would point at a dead server.
+    let _ = adb(
+        target,
+        &["shell", "run-as", package, "rm", "-f", ANDROID_PORT_FILE],
+    )
+    .output()
+    .await;
+    let component = format!("{package}/{}", target.activity);
+    run(
+        &mut adb(
+            target,
+            &["shell", "am", "start", "-S", "-W", "-n", &component],
+        ),
+        "start the app with adb",
+    )
+    .await?;
+    let force_stop = || {
+        let mut command = adb(target, &["shell", "am", "force-stop", package]);
+        command.kill_on_drop(true);
+        command
+    };
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let device_port = loop {
+        let output = adb(
+            target,
+            &["shell", "run-as", package, "cat", ANDROID_PORT_FILE],
+        )
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run adb: {e}"))?;
+        // adb forwards the device's port to our loopback, so any advertised
+        // host doesn't apply.
+        if let Some(addr) =
+            crate::launch::PluginAddr::parse(&String::from_utf8_lossy(&output.stdout))
+        {
+            break addr.port;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            let _ = force_stop().status().await;
+            return Err(format!(
+                "App {package} did not report plugin port in time (is it a debug build with the plugin registered?)"
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    };
+    let local_port = run(
+        &mut adb(target, &["forward", "tcp:0", &format!("tcp:{device_port}")]),
+        "forward the plugin port with adb",
+    )
+    .await?
+    .trim()
+    .to_string();
+    let remove_forward = || {
+        let mut command = adb(
+            target,
+            &["forward", "--remove", &format!("tcp:{local_port}")],
+        );
+        command.kill_on_drop(true);
+        command
+    };
+
+    // Android apps can't be given a launch token, so release builds can't
+    // be automated there.
+    let client = crate::launch::plugin_client(None);
+    let api = match crate::launch::connect_plugin(
+        &client,
+        &format!("http://127.0.0.1:{local_port}"),
+        timeout,
+    )
+    .await
+    {
+        Ok(api) => api,
+        Err(e) => {
+            let _ = force_stop().status().await;
+            let _ = remove_forward().status().await;
+            return Err(e);
+        }
+    };
+
+    // Stand-in process for the app: streams its logcat output and exits
+    // when the app process goes away. `pidof` can come up empty while the
+    // process is being renamed, so it's retried for up to 5s; if it never
+    // answers (no `pidof` on the device, say), the whole log is streamed
+    // and only health pings notice the app going away.
+    let watch = format!(
+        "pid=; for i in 1 2 3 4 5 6 7 8 9 10; do \
+         pid=$(pidof {package}); pid=${{pid%% *}}; [ -n \"$pid\" ] && break; sleep 0.5; \
+         done; \
+         if [ -z \"$pid\" ]; then \
+         echo 'tauri-wd: no pid for {package}, logging the whole device'; exec logcat -v brief; \
+         fi; \
+         logcat -v brief --pid=$pid & \
+         while [ -d /proc/$pid ]; do sleep 1; done; kill $!"
+    );
+    let mut child = adb(target, &["shell", &watch])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run adb: {e}"))?;
+    if let Some(stdout) = child.stdout.take() {
+        let log = log.clone();
+        tokio::spawn(async move {
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                log.write("logcat", &line);
+            }
+        });
+    }
+    Ok(LaunchedApp {
+        process: AppProcess::monitor(child, vec![force_stop(), remove_forward()]),
+        plugin_url: api.url,
+        log,
+        client,
+        bridge: api.bridge,
+    })
+}
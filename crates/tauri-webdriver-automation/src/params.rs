@@ -0,0 +1,469 @@
+// params: typed bodies of the W3C commands.
+//
+// Each W3C route takes its body as `Params<T>`, so a missing field or one of
+// the wrong type is `invalid argument` naming the field (`'actions[0].id':
+// missing field ...`) before the command reaches the plugin, rather than
+// falling back to a default or failing inside the page.
+
+use axum::extract::{FromRequest, Request};
+use axum::response::{IntoResponse, Response};
+use serde::de::{DeserializeOwned, Deserializer, Error as _};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::W3cError;
+
+/// Largest integer JSON numbers carry exactly: the W3C bound on timeouts
+/// and other integer arguments.
+pub(crate) const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// A W3C command body deserialized into `T`. An empty body counts as `{}`.
+pub(crate) struct Params<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for Params<T> {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Response> {
+        // Body limit rejections pass through as they are, for the
+        // `payload_too_large` layer to report.
+        let bytes = axum::body::Bytes::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let body: Value = if bytes.iter().all(u8::is_ascii_whitespace) {
+            Value::Object(Map::new())
+        } else {
+            serde_json::from_slice(&bytes).map_err(|e| {
+                W3cError::bad_request(format!("request body is not valid JSON: {e}"))
+                    .into_response()
+            })?
+        };
+        parse(&body)
+            .map(Params)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+/// `body` as a `T`, or `invalid argument` naming the field at fault.
+pub(crate) fn parse<T: DeserializeOwned>(body: &Value) -> Result<T, W3cError> {
+    if !body.is_object() {
+        return Err(W3cError::bad_request("request body must be a JSON object"));
+    }
+    serde_path_to_error::deserialize(body).map_err(|e| {
+        let path = e.path().to_string();
+        let inner = e.into_inner();
+        if path == "." {
+            W3cError::bad_request(inner.to_string())
+        } else {
+            W3cError::bad_request(format!("'{path}': {inner}"))
+        }
+    })
+}
+
+/// A timeout in ms, which W3C bounds to `0..=2^53 - 1`.
+fn timeout_ms<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<u64>::deserialize(deserializer)? {
+        Some(ms) if ms > MAX_SAFE_INTEGER => Err(D::Error::custom(
+            "timeouts must be integers from 0 to 2^53 - 1",
+        )),
+        ms => Ok(ms),
+    }
+}
+
+/// Like `timeout_ms`, but telling an explicit `null` (`Some(None)`) from a
+/// missing field (`None`).
+fn nullable_timeout_ms<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<u64>>, D::Error> {
+    timeout_ms(deserializer).map(Some)
+}
+
+// --- Session ---
+
+/// New Session. The capabilities are read from the JSON itself; this only
+/// checks their shape.
+#[derive(Deserialize)]
+#[allow(dead_code)]
+pub(crate) struct NewSessionReq {
+    #[serde(default)]
+    capabilities: CapabilitiesReq,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+struct CapabilitiesReq {
+    always_match: Option<Map<String, Value>>,
+    first_match: Option<Vec<Map<String, Value>>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TimeoutsReq {
+    /// `Some(None)` for `null`: scripts never time out.
+    #[serde(default, deserialize_with = "nullable_timeout_ms")]
+    pub script: Option<Option<u64>>,
+    #[serde(default, deserialize_with = "timeout_ms")]
+    pub page_load: Option<u64>,
+    #[serde(default, deserialize_with = "timeout_ms")]
+    pub implicit: Option<u64>,
+}
+
+// --- Navigation and windows ---
+
+#[derive(Deserialize)]
+pub(crate) struct NavigateReq {
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SwitchWindowReq {
+    pub handle: String,
+}
+
+/// Set Window Rect. `null` or a missing field leaves that part as it is.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct WindowRectReq {
+    #[serde(default)]
+    pub x: Option<i32>,
+    #[serde(default)]
+    pub y: Option<i32>,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct NewWindowReq {
+    #[serde(rename = "type", default)]
+    pub type_hint: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct FrameReq {
+    /// `null` (or missing) for the top-level document, a frame index, or a
+    /// W3C element reference.
+    #[serde(default)]
+    pub id: Value,
+}
+
+// --- Elements ---
+
+#[derive(Deserialize)]
+pub(crate) struct LocatorReq {
+    pub using: String,
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SendKeysReq {
+    pub text: String,
+}
+
+// --- Scripts ---
+
+#[derive(Deserialize)]
+pub(crate) struct ScriptReq {
+    pub script: String,
+    #[serde(default)]
+    pub args: Vec<Value>,
+}
+
+// --- Cookies ---
+
+#[derive(Deserialize)]
+pub(crate) struct AddCookieReq {
+    pub cookie: CookieReq,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CookieReq {
+    name: String,
+    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    domain: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secure: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    http_only: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expiry: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    same_site: Option<SameSite>,
+}
+
+#[derive(Deserialize, Serialize)]
+enum SameSite {
+    Lax,
+    Strict,
+    None,
+}
+
+// --- Alerts ---
+
+#[derive(Deserialize)]
+pub(crate) struct AlertTextReq {
+    pub text: String,
+}
+
+// --- Actions ---
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct ActionsReq {
+    pub actions: Vec<ActionSequence>,
+}
+
+/// One input source's actions, one per tick.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct ActionSequence {
+    #[serde(rename = "type")]
+    kind: SourceType,
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parameters: Option<PointerParameters>,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SourceType {
+    None,
+    Key,
+    Pointer,
+    Wheel,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PointerParameters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pointer_type: Option<PointerType>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PointerType {
+    Mouse,
+    Pen,
+    Touch,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum ActionType {
+    Pause,
+    KeyDown,
+    KeyUp,
+    PointerDown,
+    PointerUp,
+    PointerMove,
+    PointerCancel,
+    Scroll,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Action {
+    #[serde(rename = "type")]
+    kind: ActionType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    button: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    x: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    y: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delta_x: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delta_y: Option<f64>,
+    /// `"viewport"`, `"pointer"` or a W3C element reference.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Value>,
+    /// Pointer properties (`width`, `pressure`, ...), passed on as given.
+    #[serde(flatten)]
+    rest: Map<String, Value>,
+}
+
+impl ActionsReq {
+    /// Check each action fits its source, naming the first that doesn't.
+    pub fn validate(&self) -> Result<(), W3cError> {
+        for (i, sequence) in self.actions.iter().enumerate() {
+            if sequence.parameters.is_some() && sequence.kind != SourceType::Pointer {
+                return Err(W3cError::bad_request(format!(
+                    "'actions[{i}].parameters': only pointer sources take parameters"
+                )));
+            }
+            for (j, action) in sequence.actions.iter().enumerate() {
+                action.validate(sequence.kind).map_err(|e| {
+                    W3cError::bad_request(format!("'actions[{i}].actions[{j}]': {e}"))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Action {
+    fn validate(&self, source: SourceType) -> Result<(), String> {
+        use ActionType::*;
+        let allowed = match source {
+            SourceType::None => &[Pause][..],
+            SourceType::Key => &[Pause, KeyDown, KeyUp][..],
+            SourceType::Pointer => &[Pause, PointerDown, PointerUp, PointerMove, PointerCancel][..],
+            SourceType::Wheel => &[Pause, Scroll][..],
+        };
+        if !allowed.contains(&self.kind) {
+            return Err("action type not allowed for this input source".into());
+        }
+        if self.duration.is_some_and(|d| d > MAX_SAFE_INTEGER) {
+            return Err("duration must be an integer from 0 to 2^53 - 1".into());
+        }
+        match self.kind {
+            KeyDown | KeyUp if self.value.is_none() => Err("missing field `value`".into()),
+            PointerDown | PointerUp if self.button.is_none() => {
+                Err("missing field `button`".into())
+            }
+            PointerMove if self.x.is_none() || self.y.is_none() => {
+                Err("pointerMove needs `x` and `y`".into())
+            }
+            Scroll
+                if self.x.is_none()
+                    || self.y.is_none()
+                    || self.delta_x.is_none()
+                    || self.delta_y.is_none() =>
+            {
+                Err("scroll needs `x`, `y`, `deltaX` and `deltaY`".into())
+            }
+            _ => self.validate_origin(),
+        }
+    }
+
+    fn validate_origin(&self) -> Result<(), String> {
+        match &self.origin {
+            None => Ok(()),
+            Some(Value::String(origin)) if origin == "viewport" || origin == "pointer" => Ok(()),
+            Some(Value::Object(reference)) if reference.contains_key(crate::W3C_ELEMENT_KEY) => {
+                Ok(())
+            }
+            Some(_) => {
+                Err("origin must be \"viewport\", \"pointer\" or an element reference".into())
+            }
+        }
+    }
+}
+
+// --- Print ---
+
+/// Print Page. The plugin prints one Letter page of the whole document, so
+/// these are only checked.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+pub(crate) struct PrintReq {
+    #[serde(default)]
+    orientation: Option<Orientation>,
+    #[serde(default)]
+    scale: Option<f64>,
+    #[serde(default)]
+    background: Option<bool>,
+    #[serde(default)]
+    page: Option<PrintPage>,
+    #[serde(default)]
+    margin: Option<PrintMargin>,
+    #[serde(default)]
+    shrink_to_fit: Option<bool>,
+    #[serde(default)]
+    page_ranges: Option<Vec<PageRange>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+#[derive(Deserialize)]
+struct PrintPage {
+    width: Option<f64>,
+    height: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct PrintMargin {
+    top: Option<f64>,
+    bottom: Option<f64>,
+    left: Option<f64>,
+    right: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum PageRange {
+    Page(u64),
+    Range(String),
+}
+
+impl PrintReq {
+    pub fn validate(&self) -> Result<(), W3cError> {
+        if self.scale.is_some_and(|s| !(0.1..=2.0).contains(&s)) {
+            return Err(W3cError::bad_request("'scale' must be from 0.1 to 2"));
+        }
+        // The smallest page W3C allows is one point, in cm.
+        let page = self.page.as_ref();
+        for (name, size) in [
+            ("page.width", page.and_then(|p| p.width)),
+            ("page.height", page.and_then(|p| p.height)),
+        ] {
+            if size.is_some_and(|s| s < 2.54 / 72.0) {
+                return Err(W3cError::bad_request(format!(
+                    "'{name}' must be at least 0.0352"
+                )));
+            }
+        }
+        let margin = self.margin.as_ref();
+        for (name, size) in [
+            ("margin.top", margin.and_then(|m| m.top)),
+            ("margin.bottom", margin.and_then(|m| m.bottom)),
+            ("margin.left", margin.and_then(|m| m.left)),
+            ("margin.right", margin.and_then(|m| m.right)),
+        ] {
+            if size.is_some_and(|s| s < 0.0) {
+                return Err(W3cError::bad_request(format!(
+                    "'{name}' must not be negative"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// --- Logs and uploads ---
+
+#[derive(Deserialize)]
+pub(crate) struct LogReq {
+    #[serde(rename = "type")]
+    pub log_type: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UploadReq {
+    /// Base64 of a zip archive holding one file.
+    pub file: String,
+}
+
+// --- Extensions ---
+
+#[derive(Deserialize)]
+pub(crate) struct ReleaseReq {
+    /// Element and shadow root ids to release; all of them when absent.
+    pub elements: Option<Vec<String>>,
+}
@@ -0,0 +1,125 @@
+// pool: pre-launched app instances for `--pool-size`.
+//
+// Sessions created with `tauri:options.pool` take an idle instance launched
+// with the same binary, args, env and cwd instead of cold-launching one.
+// Instances are never reused: a session's app is killed when the session
+// ends, and the pool launches a fresh one in the background, so every session
+// still starts from clean app state.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::launch::{self, LaunchConfig, LaunchedApp};
+
+pub(crate) struct Pool {
+    size: usize,
+    idle: Mutex<Vec<(LaunchConfig, LaunchedApp)>>,
+    /// Configs with a refill task running.
+    refilling: Mutex<HashSet<LaunchConfig>>,
+    /// Set on shutdown so in-flight launches are killed instead of pooled.
+    closed: AtomicBool,
+}
+
+impl Pool {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            idle: Mutex::new(Vec::new()),
+            refilling: Mutex::new(HashSet::new()),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.size > 0
+    }
+
+    /// Take an idle instance for `config`, skipping any that have exited.
+    pub fn take(&self, config: &LaunchConfig) -> Option<LaunchedApp> {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        while let Some(pos) = idle.iter().position(|(c, _)| c == config) {
+            let (_, mut app) = idle.remove(pos);
+            if matches!(app.process.try_wait(), Ok(None)) {
+                return Some(app);
+            }
+            tracing::warn!("Discarding pooled app that exited while idle");
+        }
+        None
+    }
+
+    /// Launch instances in the background until `size` are idle for `config`.
+    pub fn refill(self: &Arc<Self>, config: LaunchConfig) {
+        if !self
+            .refilling
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(config.clone())
+        {
+            return;
+        }
+        let pool = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let idle = pool
+                    .idle
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .iter()
+                    .filter(|(c, _)| *c == config)
+                    .count();
+                if idle >= pool.size {
+                    break;
+                }
+                let id = format!("pool-{}", uuid::Uuid::new_v4());
+                let log_path = std::env::temp_dir().join(format!("tauri-wd-app-{id}.log"));
+                match launch::launch(&config, &id, log_path, Duration::from_secs(30)).await {
+                    Ok(mut app) if pool.closed.load(Ordering::SeqCst) => {
+                        crate::registry::unregister(app.process.id());
+                        let _ = app.process.kill().await;
+                        break;
+                    }
+                    Ok(app) => {
+                        tracing::info!(
+                            "Pooled app for {} ready at {}",
+                            config.executable.display(),
+                            app.plugin_url
+                        );
+                        pool.idle
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .push((config.clone(), app));
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to launch pooled app: {e}");
+                        break;
+                    }
+                }
+            }
+            pool.refilling
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&config);
+        });
+    }
+
+    /// Number of idle instances, for `/status`.
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Kill every idle instance (on shutdown).
+    pub fn drain(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        for (_, mut app) in idle.drain(..) {
+            crate::registry::unregister(app.process.id());
+            let _ = app.process.start_kill();
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// refs: the element and shadow root references a session hands out.
+//
+// Every find returns an id the client can use in later commands, so the
+// maps behind them would grow for as long as a session runs. They're capped
+// (`--max-elements`): past the cap the least recently used references are
+// dropped, and commands using them fail with `no such element` / `no such
+// shadow root`. Tests can also release references they're done with.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// References held per session unless `--max-elements` says otherwise.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+struct Entry<T> {
+    target: T,
+    /// `RefStore::clock` when the reference was last looked up or handed
+    /// out.
+    last_used: AtomicU64,
+}
+
+/// References by id, indexed both ways so a find returning a target
+/// already seen gets its id back without a scan. Lookups only need `&self`
+/// (commands that read the page hold the session shared), so recency is
+/// kept in atomics.
+pub struct RefStore<T> {
+    entries: HashMap<String, Entry<T>>,
+    ids: HashMap<T, String>,
+    capacity: usize,
+    clock: AtomicU64,
+}
+
+impl<T: Clone + Eq + Hash> RefStore<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            ids: HashMap::new(),
+            capacity: capacity.max(1),
+            clock: AtomicU64::new(0),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&T> {
+        let entry = self.entries.get(id)?;
+        entry.last_used.store(self.tick(), Ordering::Relaxed);
+        Some(&entry.target)
+    }
+
+    /// The id of `target`: the one it already has, or a new one.
+    pub fn insert(&mut self, target: T) -> String {
+        if let Some(id) = self.ids.get(&target) {
+            self.entries[id]
+                .last_used
+                .store(self.tick(), Ordering::Relaxed);
+            return id.clone();
+        }
+        if self.entries.len() >= self.capacity {
+            self.evict();
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        self.ids.insert(target.clone(), id.clone());
+        let last_used = AtomicU64::new(self.tick());
+        self.entries.insert(id.clone(), Entry { target, last_used });
+        id
+    }
+
+    /// Drop the least recently used tenth of the references (at least one),
+    /// so a full store isn't scanned on every insert.
+    fn evict(&mut self) {
+        let mut by_use: Vec<(u64, String)> = self
+            .entries
+            .iter()
+            .map(|(id, entry)| (entry.last_used.load(Ordering::Relaxed), id.clone()))
+            .collect();
+        let count = (self.capacity / 10).clamp(1, by_use.len());
+        by_use.select_nth_unstable(count - 1);
+        for (_, id) in by_use.into_iter().take(count) {
+            self.remove(&id);
+        }
+        tracing::debug!("dropped {count} least recently used references");
+    }
+
+    /// Forget the reference `id`, returning what it referred to.
+    pub fn remove(&mut self, id: &str) -> Option<T> {
+        let entry = self.entries.remove(id)?;
+        self.ids.remove(&entry.target);
+        Some(entry.target)
+    }
+
+    /// Forget every reference, returning what they referred to.
+    pub fn clear(&mut self) -> Vec<T> {
+        self.ids.clear();
+        self.entries
+            .drain()
+            .map(|(_, entry)| entry.target)
+            .collect()
+    }
+
+    /// Whether some reference still refers to `target`.
+    pub fn contains(&self, target: &T) -> bool {
+        self.ids.contains_key(target)
+    }
+}
@@ -0,0 +1,159 @@
+// registry: on-disk record of launched app processes.
+//
+// Every app tauri-wd launches gets a small JSON file under
+// `{temp_dir}/tauri-wd/processes/` naming its pid, the session it belongs to
+// and the tauri-wd process that launched it. The file is removed when the
+// app is killed. If tauri-wd itself dies (SIGKILL, CI timeout), the files
+// stay behind and `tauri-wd cleanup` (also run at server startup) uses them
+// to find and kill the orphaned apps.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+fn dir() -> PathBuf {
+    std::env::temp_dir().join("tauri-wd").join("processes")
+}
+
+/// Record a launched app.
+pub(crate) fn register(pid: Option<u32>, id: &str, executable: &Path) {
+    let Some(pid) = pid else {
+        return;
+    };
+    let entry = json!({
+        "pid": pid,
+        "id": id,
+        "driverPid": std::process::id(),
+        "executable": executable.display().to_string(),
+    });
+    let written = std::fs::create_dir_all(dir())
+        .and_then(|()| std::fs::write(dir().join(format!("{pid}.json")), entry.to_string()));
+    if let Err(e) = written {
+        tracing::debug!("Failed to register app process {pid}: {e}");
+    }
+}
+
+/// Forget an app that was killed or deliberately left running.
+pub(crate) fn unregister(pid: Option<u32>) {
+    if let Some(pid) = pid {
+        let _ = std::fs::remove_file(dir().join(format!("{pid}.json")));
+    }
+}
+
+#[cfg(unix)]
+fn alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Whether `pid` is still running `executable`, to guard against pid reuse.
+#[cfg(unix)]
+fn runs_executable(pid: u32, executable: &str) -> bool {
+    let Ok(output) = std::process::Command::new("ps")
+        .args(["-p", &pid.to_string(), "-o", "command="])
+        .output()
+    else {
+        return false;
+    };
+    output.status.success()
+        && String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .starts_with(executable)
+}
+
+#[cfg(unix)]
+fn force_kill(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-9", &pid.to_string()])
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// The image name of `pid` (e.g. `my-app.exe`), from `tasklist`.
+#[cfg(windows)]
+fn image_name(pid: u32) -> Option<String> {
+    let output = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"])
+        .output()
+        .ok()?;
+    // `"my-app.exe","1234",...`, or an INFO line when nothing matches.
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut fields = text.trim().split("\",\"");
+    let name = fields.next()?.trim_start_matches('"').to_string();
+    let matched = fields.next()? == pid.to_string();
+    matched.then_some(name)
+}
+
+#[cfg(windows)]
+fn alive(pid: u32) -> bool {
+    image_name(pid).is_some()
+}
+
+/// Whether `pid` is still running `executable`, to guard against pid reuse.
+#[cfg(windows)]
+fn runs_executable(pid: u32, executable: &str) -> bool {
+    let file_name = Path::new(executable)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string());
+    image_name(pid).is_some_and(|name| file_name.is_some_and(|f| f.eq_ignore_ascii_case(&name)))
+}
+
+/// Kill the app along with its WebView2 child processes.
+#[cfg(windows)]
+fn force_kill(pid: u32) -> bool {
+    std::process::Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Kill apps whose launching tauri-wd is gone and drop stale entries.
+/// Returns `(killed, stale)` where `stale` counts entries for apps that had
+/// already exited.
+#[cfg(any(unix, windows))]
+pub(crate) fn sweep() -> (Vec<u32>, usize) {
+    let mut killed = Vec::new();
+    let mut stale = 0;
+    let Ok(entries) = std::fs::read_dir(dir()) else {
+        return (killed, stale);
+    };
+    for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+        let Some(entry) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+        else {
+            let _ = std::fs::remove_file(&path);
+            continue;
+        };
+        let pid = entry["pid"].as_u64().unwrap_or(0) as u32;
+        let driver_pid = entry["driverPid"].as_u64().unwrap_or(0) as u32;
+        if driver_pid == std::process::id() || alive(driver_pid) {
+            // Still owned by a running tauri-wd.
+            continue;
+        }
+        let executable = entry["executable"].as_str().unwrap_or("");
+        let same_app = !executable.is_empty() && alive(pid) && runs_executable(pid, executable);
+        if same_app {
+            if force_kill(pid) {
+                tracing::info!(
+                    "Killed orphaned app {executable} (pid {pid}, session {})",
+                    entry["id"].as_str().unwrap_or("?")
+                );
+                killed.push(pid);
+            }
+        } else {
+            stale += 1;
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+    (killed, stale)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn sweep() -> (Vec<u32>, usize) {
+    (Vec::new(), 0)
+}
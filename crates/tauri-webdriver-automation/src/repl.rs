@@ -0,0 +1,283 @@
+// repl: the `tauri-wd repl` interactive prompt.
+//
+// Starts the WebDriver server on an ephemeral loopback port, creates a
+// session for the given binary, and reads commands from stdin. Elements
+// found with `find`/`xpath` are numbered `@1`, `@2`, ... so they can be used
+// in later commands, which keeps the selector-debugging loop short.
+
+use std::io::Write as _;
+use std::path::Path;
+
+use base64::Engine as _;
+use serde_json::{json, Value};
+use tokio::io::AsyncBufReadExt;
+
+use crate::W3C_ELEMENT_KEY;
+
+const HELP: &str = "\
+Commands:
+  find <css>             Find elements by CSS selector (numbered @1, @2, ...)
+  xpath <expr>           Find elements by XPath
+  click @N               Click an element
+  clear @N               Clear an input
+  type @N <text>         Send keys to an element
+  text @N                Visible text of an element
+  attr @N <name>         Attribute value
+  prop @N <name>         Property value
+  rect @N                Element position and size
+  eval <expression>      Evaluate a JS expression and print the result
+  exec <script>          Run a JS function body (use `return` for a result)
+  url [<url>]            Print the current URL, or navigate
+  title                  Print the page title
+  back | forward | refresh
+  source                 Print the page source
+  windows                List window handles
+  screenshot <file>      Save a PNG screenshot
+  get|post|delete <path> [json]
+                         Send a raw command; <path> is relative to the session
+  help                   Show this help
+  quit                   Delete the session and exit";
+
+struct Repl {
+    client: reqwest::Client,
+    session_url: String,
+    elements: Vec<String>,
+}
+
+impl Repl {
+    /// Send a command and return its `value`, or the W3C error message.
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Value,
+    ) -> Result<Value, String> {
+        let mut request = self
+            .client
+            .request(method.clone(), format!("{}{path}", self.session_url));
+        if method != reqwest::Method::GET && method != reqwest::Method::DELETE {
+            request = request.json(&body);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        let value = body.get("value").cloned().unwrap_or(Value::Null);
+        if status.is_success() {
+            Ok(value)
+        } else {
+            Err(format!(
+                "{}: {}",
+                value
+                    .get("error")
+                    .and_then(|e| e.as_str())
+                    .unwrap_or("error"),
+                value.get("message").and_then(|m| m.as_str()).unwrap_or("")
+            ))
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<Value, String> {
+        self.send(reqwest::Method::GET, path, Value::Null).await
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<Value, String> {
+        self.send(reqwest::Method::POST, path, body).await
+    }
+
+    /// Resolve `@N` to an element id.
+    fn element(&self, reference: &str) -> Result<String, String> {
+        reference
+            .strip_prefix('@')
+            .and_then(|n| n.parse::<usize>().ok())
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| self.elements.get(i).cloned())
+            .ok_or_else(|| format!("unknown element {reference:?}; use find first"))
+    }
+
+    async fn find(&mut self, using: &str, selector: &str) -> Result<Value, String> {
+        let found = self
+            .post("/elements", json!({"using": using, "value": selector}))
+            .await?;
+        let ids: Vec<String> = found
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.get(W3C_ELEMENT_KEY).and_then(|id| id.as_str()))
+            .map(String::from)
+            .collect();
+        if ids.is_empty() {
+            return Ok(json!("no elements found"));
+        }
+        let mut lines = Vec::new();
+        for id in ids {
+            self.elements.push(id.clone());
+            let tag = self.get(&format!("/element/{id}/name")).await?;
+            let text = self.get(&format!("/element/{id}/text")).await?;
+            let mut text = text.as_str().unwrap_or("").replace('\n', " ");
+            if text.chars().count() > 60 {
+                text = text.chars().take(57).collect::<String>() + "...";
+            }
+            lines.push(format!(
+                "@{} <{}> {text:?}",
+                self.elements.len(),
+                tag.as_str().unwrap_or("?")
+            ));
+        }
+        Ok(json!(lines.join("\n")))
+    }
+
+    async fn run_line(&mut self, line: &str) -> Result<Value, String> {
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let mut args = rest.splitn(2, ' ');
+        let first = args.next().unwrap_or("");
+        let second = args.next().unwrap_or("").trim();
+        match command {
+            "find" => self.find("css selector", rest).await,
+            "xpath" => self.find("xpath", rest).await,
+            "click" => {
+                let id = self.element(first)?;
+                self.post(&format!("/element/{id}/click"), json!({})).await
+            }
+            "clear" => {
+                let id = self.element(first)?;
+                self.post(&format!("/element/{id}/clear"), json!({})).await
+            }
+            "type" => {
+                let id = self.element(first)?;
+                self.post(&format!("/element/{id}/value"), json!({"text": second}))
+                    .await
+            }
+            "text" => {
+                let id = self.element(first)?;
+                self.get(&format!("/element/{id}/text")).await
+            }
+            "attr" => {
+                let id = self.element(first)?;
+                self.get(&format!("/element/{id}/attribute/{second}")).await
+            }
+            "prop" => {
+                let id = self.element(first)?;
+                self.get(&format!("/element/{id}/property/{second}")).await
+            }
+            "rect" => {
+                let id = self.element(first)?;
+                self.get(&format!("/element/{id}/rect")).await
+            }
+            "eval" => {
+                self.post(
+                    "/execute/sync",
+                    json!({"script": format!("return ({rest});"), "args": []}),
+                )
+                .await
+            }
+            "exec" => {
+                self.post("/execute/sync", json!({"script": rest, "args": []}))
+                    .await
+            }
+            "url" if rest.is_empty() => self.get("/url").await,
+            "url" => self.post("/url", json!({"url": rest})).await,
+            "title" => self.get("/title").await,
+            "back" | "forward" | "refresh" => self.post(&format!("/{command}"), json!({})).await,
+            "source" => self.get("/source").await,
+            "windows" => self.get("/window/handles").await,
+            "screenshot" if !rest.is_empty() => {
+                let data = self.get("/screenshot").await?;
+                let png = base64::engine::general_purpose::STANDARD
+                    .decode(data.as_str().unwrap_or(""))
+                    .map_err(|e| e.to_string())?;
+                std::fs::write(rest, png).map_err(|e| e.to_string())?;
+                Ok(json!(format!("saved {rest}")))
+            }
+            "get" | "post" | "delete" => {
+                let path = if first.starts_with('/') {
+                    first.to_string()
+                } else {
+                    format!("/{first}")
+                };
+                let body = if second.is_empty() {
+                    json!({})
+                } else {
+                    serde_json::from_str(second).map_err(|e| format!("invalid JSON: {e}"))?
+                };
+                let method = reqwest::Method::from_bytes(command.to_uppercase().as_bytes())
+                    .map_err(|e| e.to_string())?;
+                self.send(method, &path, body).await
+            }
+            "help" => Ok(json!(HELP)),
+            _ => Err(format!("unknown command {command:?}; type `help`")),
+        }
+    }
+}
+
+/// Create a session for `binary` on `server` and run the prompt until
+/// `quit`, end of input, or Ctrl-C. The session is deleted on exit.
+pub(crate) async fn run(server: &str, binary: &Path) -> Result<(), String> {
+    let client = reqwest::Client::new();
+    let created: Value = client
+        .post(format!("{server}/session"))
+        .json(&json!({"capabilities": {"alwaysMatch": {
+            "tauri:options": {"binary": binary.display().to_string()}
+        }}}))
+        .send()
+        .await
+        .map_err(|e| format!("failed to create session: {e}"))?
+        .json()
+        .await
+        .map_err(|e| format!("failed to create session: {e}"))?;
+    let sid = created
+        .pointer("/value/sessionId")
+        .and_then(|s| s.as_str())
+        .ok_or_else(|| {
+            format!(
+                "failed to create session: {}",
+                created
+                    .pointer("/value/message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("unexpected response")
+            )
+        })?
+        .to_string();
+    println!("Session {sid} ready. Type `help` for commands.");
+
+    let mut repl = Repl {
+        client: client.clone(),
+        session_url: format!("{server}/session/{sid}"),
+        elements: Vec::new(),
+    };
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("tauri-wd> ");
+        let _ = std::io::stdout().flush();
+        let line = tokio::select! {
+            line = lines.next_line() => line.ok().flatten(),
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        let Some(line) = line else {
+            println!();
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "quit" || line == "exit" {
+            break;
+        }
+        match repl.run_line(line).await {
+            Ok(Value::String(s)) => println!("{s}"),
+            Ok(Value::Null) => println!("ok"),
+            Ok(value) => println!(
+                "{}",
+                serde_json::to_string_pretty(&value).unwrap_or_default()
+            ),
+            Err(e) => println!("error: {e}"),
+        }
+    }
+
+    let _ = client
+        .delete(format!("{server}/session/{sid}"))
+        .send()
+        .await;
+    Ok(())
+}
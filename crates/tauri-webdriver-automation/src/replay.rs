@@ -0,0 +1,170 @@
+// replay: command recordings and the `tauri-wd replay` runner.
+//
+// A session created with `tauri:options.record` keeps every W3C command it
+// receives. `GET /session/{id}/tauri/recording` exports them as JSON, and
+// `tauri-wd replay <file>` runs that file against a tauri-wd server: it
+// creates a fresh session with the recorded capabilities, sends each command
+// in order (mapping recorded element ids to the new ones), and reports any
+// command whose status differs from the recording.
+
+use serde_json::{json, Value};
+
+use crate::{W3C_ELEMENT_KEY, W3C_SHADOW_KEY};
+
+/// Placeholder for the session id in recorded paths.
+const SESSION_PLACEHOLDER: &str = "{sessionId}";
+
+pub(crate) struct Recording {
+    capabilities: Value,
+    commands: Vec<Value>,
+}
+
+impl Recording {
+    pub fn new(capabilities: Value) -> Self {
+        Self {
+            capabilities,
+            commands: Vec::new(),
+        }
+    }
+
+    /// Record a command. The session id in `path` is replaced by a placeholder.
+    pub fn record(
+        &mut self,
+        sid: &str,
+        method: &str,
+        path: &str,
+        body: Value,
+        status: u16,
+        response: Value,
+    ) {
+        self.commands.push(json!({
+            "method": method,
+            "path": path.replacen(sid, SESSION_PLACEHOLDER, 1),
+            "body": body,
+            "status": status,
+            "response": response,
+        }));
+    }
+
+    pub fn export(&self) -> Value {
+        json!({
+            "version": 1,
+            "capabilities": self.capabilities,
+            "commands": self.commands,
+        })
+    }
+}
+
+/// Collect element and shadow root ids from a response value, in order.
+fn collect_ids(value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for key in [W3C_ELEMENT_KEY, W3C_SHADOW_KEY] {
+                if let Some(id) = map.get(key).and_then(|id| id.as_str()) {
+                    out.push(id.to_string());
+                }
+            }
+            for v in map.values() {
+                collect_ids(v, out);
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_ids(v, out)),
+        _ => {}
+    }
+}
+
+/// Replace recorded ids with their replayed counterparts.
+fn substitute(text: &str, ids: &[(String, String)]) -> String {
+    ids.iter()
+        .fold(text.to_string(), |acc, (old, new)| acc.replace(old, new))
+}
+
+/// Run a recording against `server`. Returns the number of mismatches.
+pub(crate) async fn run(file: &std::path::Path, server: &str) -> Result<usize, String> {
+    let text = std::fs::read_to_string(file)
+        .map_err(|e| format!("failed to read {}: {e}", file.display()))?;
+    let recording: Value =
+        serde_json::from_str(&text).map_err(|e| format!("invalid recording: {e}"))?;
+    let commands = recording
+        .get("commands")
+        .and_then(|c| c.as_array())
+        .ok_or("recording has no commands")?;
+    let server = server.trim_end_matches('/');
+    let client = reqwest::Client::new();
+
+    let created: Value = client
+        .post(format!("{server}/session"))
+        .json(&json!({"capabilities": recording.get("capabilities").cloned().unwrap_or(json!({}))}))
+        .send()
+        .await
+        .map_err(|e| format!("failed to create session: {e}"))?
+        .json()
+        .await
+        .map_err(|e| format!("failed to create session: {e}"))?;
+    let sid = created
+        .pointer("/value/sessionId")
+        .and_then(|s| s.as_str())
+        .ok_or_else(|| format!("failed to create session: {created}"))?
+        .to_string();
+    println!("Replaying {} command(s) in session {sid}", commands.len());
+
+    let mut ids: Vec<(String, String)> = Vec::new();
+    let mut mismatches = 0;
+    for (i, command) in commands.iter().enumerate() {
+        let method = command
+            .get("method")
+            .and_then(|m| m.as_str())
+            .unwrap_or("GET");
+        let path = command.get("path").and_then(|p| p.as_str()).unwrap_or("");
+        let path = substitute(&path.replace(SESSION_PLACEHOLDER, &sid), &ids);
+        let body = command.get("body").cloned().unwrap_or(Value::Null);
+        let method = reqwest::Method::from_bytes(method.as_bytes())
+            .map_err(|e| format!("command {i}: invalid method: {e}"))?;
+
+        let mut request = client.request(method.clone(), format!("{server}{path}"));
+        if !body.is_null() {
+            let body: Value =
+                serde_json::from_str(&substitute(&body.to_string(), &ids)).unwrap_or(body);
+            request = request.json(&body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("command {i}: {e}"))?;
+        let status = response.status().as_u16();
+        let value: Value = response.json().await.unwrap_or(Value::Null);
+
+        let recorded_status = command.get("status").and_then(|s| s.as_u64()).unwrap_or(0);
+        let matched = u64::from(status) == recorded_status;
+        if !matched {
+            mismatches += 1;
+        }
+        println!(
+            "[{i:>4}] {method} {path} -> {status}{}",
+            if matched {
+                String::new()
+            } else {
+                format!(" (recorded {recorded_status})")
+            }
+        );
+
+        let mut old_ids = Vec::new();
+        let mut new_ids = Vec::new();
+        collect_ids(
+            command.get("response").unwrap_or(&Value::Null),
+            &mut old_ids,
+        );
+        collect_ids(&value, &mut new_ids);
+        ids.extend(old_ids.into_iter().zip(new_ids));
+    }
+
+    let _ = client
+        .delete(format!("{server}/session/{sid}"))
+        .send()
+        .await;
+    println!(
+        "Replay finished: {} command(s), {mismatches} mismatch(es)",
+        commands.len()
+    );
+    Ok(mismatches)
+}
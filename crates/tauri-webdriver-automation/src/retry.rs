@@ -0,0 +1,220 @@
+// retry: `tauri:options.retry`, retrying element interactions that failed
+// for a moment.
+//
+// A click that lands while an overlay fades out, or on an element the page
+// just re-rendered, fails with `element click intercepted` or `stale element
+// reference` though the same click would work a moment later, so every test
+// suite ends up wrapping its clicks in a retry loop. With `retry` set,
+// tauri-wd does that itself for element click, clear and send keys: a
+// command failing with one of the retryable errors is sent again after a
+// backoff that doubles each time, up to `attempts` tries in all. These
+// errors come from the page before anything was done to the element, so
+// sending the command again doesn't repeat it.
+//
+//   "retry": true
+//   "retry": {"attempts": 5, "backoff": 200, "errors": ["element click intercepted"]}
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+/// Errors retried unless `errors` says otherwise.
+const DEFAULT_ERRORS: [&str; 2] = ["stale element reference", "element click intercepted"];
+
+/// Errors `errors` may list: those raised before the command touched the
+/// element, so a retry can't do anything twice.
+const RETRYABLE_ERRORS: [&str; 4] = [
+    "stale element reference",
+    "element click intercepted",
+    "element not interactable",
+    "no such element",
+];
+
+const DEFAULT_ATTEMPTS: u32 = 3;
+const MAX_ATTEMPTS: u32 = 10;
+const DEFAULT_BACKOFF_MS: u64 = 100;
+const MAX_BACKOFF_MS: u64 = 10_000;
+
+/// A parsed `tauri:options.retry`.
+pub(crate) struct RetryPolicy {
+    /// Tries in all, the first one included.
+    attempts: u32,
+    /// Wait before the first retry; doubled for each one after.
+    backoff: Duration,
+    /// W3C error codes that get a retry.
+    errors: Vec<String>,
+}
+
+impl RetryPolicy {
+    /// The policy `value` asks for: `None` for `false`/`null`, the defaults
+    /// for `true`, or an object overriding some of them.
+    pub fn from_option(value: &Value) -> Result<Option<Self>, String> {
+        let mut policy = Self {
+            attempts: DEFAULT_ATTEMPTS,
+            backoff: Duration::from_millis(DEFAULT_BACKOFF_MS),
+            errors: DEFAULT_ERRORS.iter().map(|e| e.to_string()).collect(),
+        };
+        let options = match value {
+            Value::Null | Value::Bool(false) => return Ok(None),
+            Value::Bool(true) => return Ok(Some(policy)),
+            Value::Object(options) => options,
+            _ => return Err("tauri:options.retry must be a boolean or an object".to_string()),
+        };
+        for key in options.keys() {
+            if !["attempts", "backoff", "errors"].contains(&key.as_str()) {
+                return Err(format!("tauri:options.retry has no option {key:?}"));
+            }
+        }
+        match options.get("attempts") {
+            None | Some(Value::Null) => {}
+            Some(value) => {
+                policy.attempts = value
+                    .as_u64()
+                    .filter(|n| (1..=u64::from(MAX_ATTEMPTS)).contains(n))
+                    .ok_or(format!(
+                        "tauri:options.retry.attempts must be an integer from 1 to {MAX_ATTEMPTS}"
+                    ))? as u32;
+            }
+        }
+        match options.get("backoff") {
+            None | Some(Value::Null) => {}
+            Some(value) => {
+                let ms = value
+                    .as_u64()
+                    .filter(|ms| *ms <= MAX_BACKOFF_MS)
+                    .ok_or(format!(
+                        "tauri:options.retry.backoff must be from 0 to {MAX_BACKOFF_MS} ms"
+                    ))?;
+                policy.backoff = Duration::from_millis(ms);
+            }
+        }
+        match options.get("errors") {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(errors)) => {
+                policy.errors = Vec::with_capacity(errors.len());
+                for error in errors {
+                    match error.as_str() {
+                        Some(error) if RETRYABLE_ERRORS.contains(&error) => {
+                            policy.errors.push(error.to_string())
+                        }
+                        _ => {
+                            return Err(format!(
+                                "tauri:options.retry.errors can only list {}; got {error}",
+                                RETRYABLE_ERRORS.map(|e| format!("\"{e}\"")).join(", ")
+                            ))
+                        }
+                    }
+                }
+            }
+            Some(_) => {
+                return Err("tauri:options.retry.errors must be an array of error codes".into())
+            }
+        }
+        Ok(Some(policy))
+    }
+
+    /// How long to wait before trying again after try number `attempt`
+    /// (from 1) failed with `error`, or `None` to give up.
+    pub fn delay(&self, attempt: u32, error: &str) -> Option<Duration> {
+        if attempt >= self.attempts || !self.errors.iter().any(|e| e == error) {
+            return None;
+        }
+        Some(self.backoff.saturating_mul(1 << (attempt - 1).min(16)))
+    }
+
+    /// The policy as echoed in the session's capabilities.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "attempts": self.attempts,
+            "backoff": self.backoff.as_millis() as u64,
+            "errors": self.errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(value: Value) -> RetryPolicy {
+        RetryPolicy::from_option(&value)
+            .expect("valid policy")
+            .expect("retry enabled")
+    }
+
+    #[test]
+    fn from_option_accepts_booleans_and_defaults() {
+        assert!(RetryPolicy::from_option(&json!(false)).unwrap().is_none());
+        assert!(RetryPolicy::from_option(&json!(null)).unwrap().is_none());
+        assert_eq!(
+            policy(json!(true)).to_json(),
+            json!({
+                "attempts": DEFAULT_ATTEMPTS,
+                "backoff": DEFAULT_BACKOFF_MS,
+                "errors": DEFAULT_ERRORS,
+            })
+        );
+        assert_eq!(policy(json!({})).to_json(), policy(json!(true)).to_json());
+    }
+
+    #[test]
+    fn from_option_bounds_attempts_and_backoff() {
+        assert_eq!(policy(json!({"attempts": 1})).attempts, 1);
+        assert_eq!(
+            policy(json!({"attempts": MAX_ATTEMPTS})).attempts,
+            MAX_ATTEMPTS
+        );
+        for attempts in [
+            json!(0),
+            json!(MAX_ATTEMPTS + 1),
+            json!(-1),
+            json!(2.5),
+            json!("3"),
+        ] {
+            assert!(
+                RetryPolicy::from_option(&json!({"attempts": attempts})).is_err(),
+                "attempts {attempts} should be refused"
+            );
+        }
+        assert_eq!(policy(json!({"backoff": 0})).backoff, Duration::ZERO);
+        assert_eq!(
+            policy(json!({"backoff": MAX_BACKOFF_MS})).backoff,
+            Duration::from_millis(MAX_BACKOFF_MS)
+        );
+        for backoff in [json!(MAX_BACKOFF_MS + 1), json!(-1), json!("100")] {
+            assert!(
+                RetryPolicy::from_option(&json!({"backoff": backoff})).is_err(),
+                "backoff {backoff} should be refused"
+            );
+        }
+    }
+
+    #[test]
+    fn from_option_refuses_unknown_options_and_errors() {
+        assert!(RetryPolicy::from_option(&json!(3)).is_err());
+        assert!(RetryPolicy::from_option(&json!({"tries": 3})).is_err());
+        assert!(RetryPolicy::from_option(&json!({"errors": "no such element"})).is_err());
+        assert!(RetryPolicy::from_option(&json!({"errors": ["javascript error"]})).is_err());
+        assert_eq!(
+            policy(json!({"errors": ["no such element"]})).errors,
+            ["no such element"]
+        );
+    }
+
+    #[test]
+    fn delay_doubles_until_attempts_run_out() {
+        let policy = policy(json!({"attempts": 4, "backoff": 50}));
+        let error = "element click intercepted";
+        assert_eq!(policy.delay(1, error), Some(Duration::from_millis(50)));
+        assert_eq!(policy.delay(2, error), Some(Duration::from_millis(100)));
+        assert_eq!(policy.delay(3, error), Some(Duration::from_millis(200)));
+        assert_eq!(policy.delay(4, error), None);
+    }
+
+    #[test]
+    fn delay_only_retries_listed_errors() {
+        let policy = policy(json!(true));
+        assert!(policy.delay(1, "stale element reference").is_some());
+        assert_eq!(policy.delay(1, "no such element"), None);
+    }
+}
@@ -0,0 +1,86 @@
+// trace: Playwright-style session trace archives.
+//
+// A session created with `tauri:options.trace` records every command, its
+// response, and a screenshot after each state-changing command. When the
+// session ends the recording is written, together with the console, page
+// error and network logs, into a single zip archive.
+
+use std::io::Write as _;
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+pub(crate) struct TraceRecorder {
+    pub path: PathBuf,
+    events: Vec<Value>,
+    screenshots: Vec<(String, Vec<u8>)>,
+}
+
+/// Logs gathered from the app when the session ends.
+pub(crate) struct TraceLogs {
+    pub console: Value,
+    pub errors: Value,
+    pub network: Value,
+}
+
+impl TraceRecorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            events: Vec::new(),
+            screenshots: Vec::new(),
+        }
+    }
+
+    /// Append a command event. Returns its index for attaching a screenshot.
+    pub fn record(&mut self, mut event: Value) -> usize {
+        let index = self.events.len();
+        event["index"] = json!(index);
+        self.events.push(event);
+        index
+    }
+
+    /// Attach a PNG screenshot taken after the command at `index`.
+    pub fn attach_screenshot(&mut self, index: usize, png: Vec<u8>) {
+        let name = format!("screenshots/{index:05}.png");
+        if let Some(event) = self.events.get_mut(index) {
+            event["screenshot"] = json!(name);
+        }
+        self.screenshots.push((name, png));
+    }
+
+    /// Write the archive: `trace.json`, `console.json`, `errors.json`,
+    /// `network.har` and the `screenshots/` directory.
+    pub fn write(&self, logs: &TraceLogs) -> Result<(), String> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let file = std::fs::File::create(&self.path).map_err(|e| e.to_string())?;
+        let mut zip = zip::ZipWriter::new(file);
+        let deflated = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        // PNGs are already compressed.
+        let stored = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+
+        let trace = json!({ "version": 1, "events": self.events });
+        let json_files = [
+            ("trace.json", &trace),
+            ("console.json", &logs.console),
+            ("errors.json", &logs.errors),
+            ("network.har", &logs.network),
+        ];
+        for (name, value) in json_files {
+            let bytes = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+            zip.start_file(name, deflated).map_err(|e| e.to_string())?;
+            zip.write_all(&bytes).map_err(|e| e.to_string())?;
+        }
+        for (name, png) in &self.screenshots {
+            zip.start_file(name.as_str(), stored)
+                .map_err(|e| e.to_string())?;
+            zip.write_all(png).map_err(|e| e.to_string())?;
+        }
+        zip.finish().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
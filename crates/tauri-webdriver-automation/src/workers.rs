@@ -0,0 +1,211 @@
+// workers: `tauri-wd --workers N`, a server process per test shard.
+//
+// Shards sharing one server share its session limit, its pool and its fate
+// when something wedges. With `--workers N` this process only supervises:
+// it starts N copies of itself on consecutive ports from `--port`, with the
+// rest of the command line as given, waits until each accepts connections,
+// and prints where they are as one line of JSON on stdout:
+//
+//   {"workers":[{"index":0,"pid":4242,"port":4444,"url":"http://127.0.0.1:4444"},...]}
+//
+// The workers' own output goes to stderr prefixed with `[worker N]`. On
+// SIGINT/SIGTERM, or when any worker exits, all of them are stopped.
+
+use std::ffi::OsString;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::io::AsyncBufReadExt as _;
+
+/// How long a worker may take to start listening.
+const START_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long a stopped worker may take to end its sessions before it's
+/// killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Worker {
+    index: usize,
+    port: u16,
+    child: tokio::process::Child,
+}
+
+/// Our arguments without `--workers` and `--port` (and their values).
+fn worker_args() -> Vec<OsString> {
+    let mut args = Vec::new();
+    let mut skip_value = false;
+    for arg in std::env::args_os().skip(1) {
+        if std::mem::take(&mut skip_value) {
+            continue;
+        }
+        let text = arg.to_string_lossy();
+        match text.as_ref() {
+            "--workers" | "--port" => skip_value = true,
+            _ if text.starts_with("--workers=") || text.starts_with("--port=") => {}
+            _ => args.push(arg),
+        }
+    }
+    args
+}
+
+/// Copy `from`'s lines to stderr, each prefixed with `[worker {index}]`.
+fn forward(index: usize, from: impl tokio::io::AsyncRead + Unpin + Send + 'static) {
+    tokio::spawn(async move {
+        let mut lines = tokio::io::BufReader::new(from).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            eprintln!("[worker {index}] {line}");
+        }
+    });
+}
+
+/// Wait until `worker` accepts connections at `host`. Checks it's still
+/// running first, so another server already on its port doesn't pass.
+async fn wait_listening(worker: &mut Worker, host: &str) -> Result<(), String> {
+    let deadline = tokio::time::Instant::now() + START_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        if let Ok(Some(status)) = worker.child.try_wait() {
+            return Err(format!("exited with {status}"));
+        }
+        if tokio::net::TcpStream::connect((host, worker.port))
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(format!(
+        "did not listen within {}s",
+        START_TIMEOUT.as_secs()
+    ))
+}
+
+/// Ask `worker` to shut down as it would on Ctrl-C, so it ends its
+/// sessions, then kill it if it takes too long.
+async fn stop(worker: &mut Worker) {
+    #[cfg(unix)]
+    if let Some(pid) = worker.child.id() {
+        let _ = tokio::process::Command::new("kill")
+            .args(["-TERM", &pid.to_string()])
+            .status()
+            .await;
+        if tokio::time::timeout(STOP_TIMEOUT, worker.child.wait())
+            .await
+            .is_ok()
+        {
+            return;
+        }
+        tracing::warn!("worker {} did not stop in time; killing it", worker.index);
+    }
+    // Takes the worker's apps with it on Windows.
+    crate::launch::kill_tree(&mut worker.child).await;
+}
+
+/// Run `count` workers on ports from `port` until told to stop. Returns the
+/// exit code: 0 when stopped by a signal, 1 when a worker failed.
+pub(crate) async fn run(count: usize, host: &str, port: u16, tls: bool) -> i32 {
+    let last = port as usize + count - 1;
+    if port == 0 || last > u16::MAX as usize {
+        eprintln!("--workers needs a fixed --port with {count} free ports from it (got {port})");
+        return 2;
+    }
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("can't find the tauri-wd executable to start workers: {e}");
+            return 2;
+        }
+    };
+    let args = worker_args();
+    // Workers listen where the host says; clients reach unspecified
+    // addresses through loopback.
+    let reach = match host {
+        "0.0.0.0" | "::" | "[::]" => "127.0.0.1",
+        host => host,
+    };
+    let scheme = if tls { "https" } else { "http" };
+
+    let mut workers: Vec<Worker> = Vec::with_capacity(count);
+    let mut failed = None;
+    for index in 0..count {
+        let port = port + index as u16;
+        let spawned = tokio::process::Command::new(&exe)
+            .args(&args)
+            .arg("--port")
+            .arg(port.to_string())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                failed = Some(format!("failed to start worker {index}: {e}"));
+                break;
+            }
+        };
+        if let Some(stdout) = child.stdout.take() {
+            forward(index, stdout);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward(index, stderr);
+        }
+        workers.push(Worker { index, port, child });
+    }
+
+    if failed.is_none() {
+        for worker in &mut workers {
+            if let Err(why) = wait_listening(worker, reach).await {
+                failed = Some(format!(
+                    "worker {} (port {}) {why}",
+                    worker.index, worker.port
+                ));
+                break;
+            }
+        }
+    }
+    if let Some(message) = failed {
+        eprintln!("{message}");
+        for worker in &mut workers {
+            stop(worker).await;
+        }
+        return 1;
+    }
+
+    let mapping: Vec<_> = workers
+        .iter()
+        .map(|worker| {
+            json!({
+                "index": worker.index,
+                "pid": worker.child.id(),
+                "port": worker.port,
+                "url": format!("{scheme}://{reach}:{}", worker.port),
+            })
+        })
+        .collect();
+    println!("{}", json!({ "workers": mapping }));
+
+    let exited = async {
+        loop {
+            for worker in &mut workers {
+                if let Ok(Some(status)) = worker.child.try_wait() {
+                    return (worker.index, status);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    };
+    let code = tokio::select! {
+        _ = crate::shutdown_signal() => 0,
+        (index, status) = exited => {
+            eprintln!("worker {index} exited with {status}; stopping the others");
+            1
+        }
+    };
+    for worker in &mut workers {
+        if matches!(worker.child.try_wait(), Ok(None)) {
+            stop(worker).await;
+        }
+    }
+    code
+}
@@ -0,0 +1,321 @@
+// End-to-end harness: builds `tauri-wd` and the test app, starts the
+// driver on a free port and talks W3C WebDriver to it, so the tests in
+// `tests/` exercise the CLI and the plugin together, the way a client would.
+//
+// The tests need a desktop session to open windows in, so they're
+// `#[ignore]`d and run on request:
+//
+//   cargo test -p tauri-webdriver-e2e -- --ignored --test-threads=1
+//
+// `TAURI_WD_BIN` and `TAURI_WD_TEST_APP` point the harness at binaries that
+// are already built instead.
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+pub const ELEMENT_KEY: &str = "element-6066-11e4-a52e-4f735466cecf";
+pub const SHADOW_KEY: &str = "shadow-6066-11e4-a52e-4f735466cecf";
+
+/// How long the driver may take to answer `/status` after it starts.
+const START_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long a stopped driver may take to end its sessions before it's
+/// killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The repository root.
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")
+}
+
+/// Build `args` with cargo and return the executable it produced for the
+/// binary target `name`.
+fn cargo_build(name: &str, args: &[&str]) -> PathBuf {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(cargo)
+        .arg("build")
+        .args(args)
+        .arg("--message-format=json-render-diagnostics")
+        .current_dir(root())
+        // Builds go to the target directories chosen below.
+        .env_remove("CARGO_TARGET_DIR")
+        .stderr(Stdio::inherit())
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run cargo to build {name}: {e}"));
+    assert!(output.status.success(), "building {name} failed");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|message| {
+            message["reason"] == "compiler-artifact" && message["target"]["name"] == name
+        })
+        .find_map(|message| message["executable"].as_str().map(PathBuf::from))
+        .unwrap_or_else(|| panic!("cargo built no executable named {name}"))
+}
+
+/// `tauri-wd`, built once per test binary.
+pub fn driver_bin() -> &'static Path {
+    static BIN: OnceLock<PathBuf> = OnceLock::new();
+    BIN.get_or_init(|| {
+        if let Some(bin) = std::env::var_os("TAURI_WD_BIN") {
+            return bin.into();
+        }
+        // `cargo test` holds the lock on the workspace's target directory
+        // while the tests run, so this build gets its own.
+        let target_dir = root().join("target/e2e");
+        cargo_build(
+            "tauri-wd",
+            &[
+                "-p",
+                "tauri-webdriver-automation",
+                "--target-dir",
+                &target_dir.to_string_lossy(),
+            ],
+        )
+    })
+}
+
+/// The test app (`tests/test-app`), built once per test binary.
+pub fn app_bin() -> &'static Path {
+    static BIN: OnceLock<PathBuf> = OnceLock::new();
+    BIN.get_or_init(|| {
+        if let Some(bin) = std::env::var_os("TAURI_WD_TEST_APP") {
+            return bin.into();
+        }
+        let manifest = root().join("tests/test-app/src-tauri/Cargo.toml");
+        cargo_build(
+            "webdriver-test-app",
+            &["--manifest-path", &manifest.to_string_lossy()],
+        )
+    })
+}
+
+/// An error response from the driver.
+#[derive(Debug)]
+pub struct WdError {
+    pub status: u16,
+    /// The W3C error code, e.g. `no such element`.
+    pub error: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for WdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.error, self.status, self.message)
+    }
+}
+
+impl std::error::Error for WdError {}
+
+pub type WdResult<T = Value> = Result<T, WdError>;
+
+/// A `tauri-wd` process on a port of its own, stopped on drop.
+pub struct Driver {
+    child: Child,
+    base: String,
+    http: reqwest::Client,
+}
+
+impl Driver {
+    /// Start the driver and wait until it's ready for sessions.
+    pub async fn start() -> Driver {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map(|addr| addr.port())
+            .expect("no free port for tauri-wd");
+        let child = Command::new(driver_bin())
+            .args(["--port", &port.to_string(), "--max-sessions", "1"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("failed to start tauri-wd");
+        let driver = Driver {
+            child,
+            base: format!("http://127.0.0.1:{port}"),
+            http: reqwest::Client::new(),
+        };
+        let deadline = Instant::now() + START_TIMEOUT;
+        loop {
+            if let Ok(status) = driver.request(reqwest::Method::GET, "/status", None).await {
+                if status["ready"] == true {
+                    return driver;
+                }
+            }
+            assert!(
+                Instant::now() < deadline,
+                "tauri-wd did not answer /status within {}s",
+                START_TIMEOUT.as_secs()
+            );
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Send a request to the driver and unwrap the W3C `value`.
+    pub async fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> WdResult {
+        let mut request = self.http.request(method, format!("{}{path}", self.base));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let failed = |message: String| WdError {
+            status: 0,
+            error: "request failed".into(),
+            message,
+        };
+        let response = request.send().await.map_err(|e| failed(e.to_string()))?;
+        let status = response.status().as_u16();
+        let body: Value = response.json().await.map_err(|e| failed(e.to_string()))?;
+        let value = body["value"].clone();
+        if status < 400 {
+            return Ok(value);
+        }
+        Err(WdError {
+            status,
+            error: value["error"].as_str().unwrap_or_default().to_string(),
+            message: value["message"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    /// Start a session on the test app.
+    pub async fn session(&self) -> Session<'_> {
+        self.session_with(json!({})).await
+    }
+
+    /// Start a session on the test app with more `tauri:options`.
+    pub async fn session_with(&self, options: Value) -> Session<'_> {
+        let mut options = options;
+        options["binary"] = json!(app_bin());
+        let value = self
+            .request(
+                reqwest::Method::POST,
+                "/session",
+                Some(json!({ "capabilities": { "alwaysMatch": { "tauri:options": options } } })),
+            )
+            .await
+            .unwrap_or_else(|e| panic!("new session failed: {e}"));
+        let id = value["sessionId"]
+            .as_str()
+            .expect("new session returned no sessionId")
+            .to_string();
+        Session { driver: self, id }
+    }
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        // SIGTERM lets the driver end its sessions and close the apps.
+        #[cfg(unix)]
+        {
+            let _ = Command::new("kill")
+                .args(["-TERM", &self.child.id().to_string()])
+                .status();
+            let deadline = Instant::now() + STOP_TIMEOUT;
+            while Instant::now() < deadline {
+                if let Ok(Some(_)) = self.child.try_wait() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A session on the test app. Paths are relative to `/session/{id}`.
+pub struct Session<'a> {
+    driver: &'a Driver,
+    pub id: String,
+}
+
+impl Session<'_> {
+    pub async fn get(&self, path: &str) -> WdResult {
+        self.driver
+            .request(reqwest::Method::GET, &self.path(path), None)
+            .await
+    }
+
+    pub async fn post(&self, path: &str, body: Value) -> WdResult {
+        self.driver
+            .request(reqwest::Method::POST, &self.path(path), Some(body))
+            .await
+    }
+
+    pub async fn delete(&self, path: &str) -> WdResult {
+        self.driver
+            .request(reqwest::Method::DELETE, &self.path(path), None)
+            .await
+    }
+
+    fn path(&self, path: &str) -> String {
+        format!("/session/{}{path}", self.id)
+    }
+
+    /// Find the element matching the CSS `selector` and return its id.
+    pub async fn find(&self, selector: &str) -> WdResult<String> {
+        let value = self
+            .post(
+                "/element",
+                json!({ "using": "css selector", "value": selector }),
+            )
+            .await?;
+        Ok(element_id(&value))
+    }
+
+    /// Run `script` in the page with `args`.
+    pub async fn execute(&self, script: &str, args: Value) -> WdResult {
+        self.post("/execute/sync", json!({ "script": script, "args": args }))
+            .await
+    }
+
+    /// Cover the page with a fixed, topmost div, so clicks are intercepted.
+    /// With `for_ms`, the cover removes itself after that many ms;
+    /// otherwise it stays until [`uncover_page`](Self::uncover_page).
+    pub async fn cover_page(&self, for_ms: Option<u64>) -> WdResult {
+        self.execute(
+            "var c=document.createElement('div');c.id='wd-cover';\
+             c.style.cssText='position:fixed;inset:0;z-index:99999';\
+             document.body.append(c);\
+             if(arguments[0]!=null)setTimeout(function(){c.remove()},arguments[0])",
+            json!([for_ms]),
+        )
+        .await
+    }
+
+    /// Remove the cover added by [`cover_page`](Self::cover_page).
+    pub async fn uncover_page(&self) -> WdResult {
+        self.execute("document.getElementById('wd-cover').remove()", json!([]))
+            .await
+    }
+
+    /// End the session, closing the app.
+    pub async fn end(self) -> WdResult {
+        self.driver
+            .request(
+                reqwest::Method::DELETE,
+                &format!("/session/{}", self.id),
+                None,
+            )
+            .await
+    }
+}
+
+/// The id in a W3C element reference.
+pub fn element_id(reference: &Value) -> String {
+    reference[ELEMENT_KEY]
+        .as_str()
+        .unwrap_or_else(|| panic!("not an element reference: {reference}"))
+        .to_string()
+}
+
+/// A W3C element reference to pass as a script argument or action origin.
+pub fn element_ref(id: &str) -> Value {
+    json!({ ELEMENT_KEY: id })
+}
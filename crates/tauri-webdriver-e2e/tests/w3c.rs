@@ -0,0 +1,652 @@
+// The W3C endpoints, end to end against the test app. Each test gets its
+// own driver and app, so a failure doesn't leave state for the next one.
+
+use serde_json::json;
+use tauri_webdriver_e2e::{element_id, element_ref, Driver, SHADOW_KEY};
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn status_and_sessions() {
+    let driver = Driver::start().await;
+    let status = driver
+        .request(reqwest::Method::GET, "/status", None)
+        .await
+        .unwrap();
+    assert_eq!(status["ready"], true);
+
+    let session = driver.session().await;
+    let status = driver
+        .request(reqwest::Method::GET, "/status", None)
+        .await
+        .unwrap();
+    assert_eq!(status["ready"], false, "one session is the limit");
+    let timeouts = session.get("/timeouts").await.unwrap();
+    assert_eq!(timeouts["script"], 30000);
+    session
+        .post("/timeouts", json!({ "script": 60000, "implicit": 500 }))
+        .await
+        .unwrap();
+    assert_eq!(session.get("/timeouts").await.unwrap()["script"], 60000);
+    let err = session
+        .post("/timeouts", json!({ "pageLoad": -1 }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.error, "invalid argument");
+
+    session.end().await.unwrap();
+    let status = driver
+        .request(reqwest::Method::GET, "/status", None)
+        .await
+        .unwrap();
+    assert_eq!(status["ready"], true);
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn navigation() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    assert_eq!(session.get("/title").await.unwrap(), "WebDriver Test App");
+    let url = session.get("/url").await.unwrap();
+    assert!(url.as_str().unwrap().contains("tauri"), "url: {url}");
+    let source = session.get("/source").await.unwrap();
+    assert!(source.as_str().unwrap().contains("<html"));
+
+    session.post("/refresh", json!({})).await.unwrap();
+    assert_eq!(session.get("/title").await.unwrap(), "WebDriver Test App");
+    let err = session
+        .post("/url", json!({ "url": "/settings" }))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.error, "invalid argument",
+        "relative URL without baseUrl"
+    );
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn windows() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    assert_eq!(session.get("/window").await.unwrap(), "main");
+    assert_eq!(
+        session.get("/window/handles").await.unwrap(),
+        json!(["main"])
+    );
+    let rect = session
+        .post("/window/rect", json!({ "width": 1024, "height": 768 }))
+        .await
+        .unwrap();
+    // Sizes come back as floats, and on Linux the outer size includes the
+    // window decorations, so the window is at least what was asked for.
+    assert!(rect["width"].as_f64().unwrap() >= 1024.0, "{rect}");
+    assert!(rect["height"].as_f64().unwrap() >= 768.0, "{rect}");
+    let err = session
+        .post("/window", json!({ "handle": "nonexistent" }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.error, "no such window");
+
+    let created = session
+        .post("/window/new", json!({ "type": "window" }))
+        .await
+        .unwrap();
+    let handle = created["handle"].as_str().unwrap().to_string();
+    let handles = session.get("/window/handles").await.unwrap();
+    assert!(handles.as_array().unwrap().contains(&json!(handle)));
+    session
+        .post("/window", json!({ "handle": handle }))
+        .await
+        .unwrap();
+    let left = session.delete("/window").await.unwrap();
+    assert_eq!(left, json!(["main"]));
+    session
+        .post("/window", json!({ "handle": "main" }))
+        .await
+        .unwrap();
+    assert_eq!(session.get("/title").await.unwrap(), "WebDriver Test App");
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn finding_elements() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    let title = session.find("#title").await.unwrap();
+    assert_eq!(
+        session.find("#title").await.unwrap(),
+        title,
+        "a found element keeps its id"
+    );
+    let path = format!("/element/{title}");
+    assert_eq!(
+        session.get(&format!("{path}/text")).await.unwrap(),
+        "Test App"
+    );
+    assert_eq!(session.get(&format!("{path}/name")).await.unwrap(), "h1");
+    assert_eq!(
+        session.get(&format!("{path}/attribute/id")).await.unwrap(),
+        "title"
+    );
+    assert_eq!(
+        session
+            .get(&format!("{path}/property/tagName"))
+            .await
+            .unwrap(),
+        "H1"
+    );
+    assert!(session.get(&format!("{path}/rect")).await.unwrap()["width"].is_number());
+    assert_eq!(
+        session.get(&format!("{path}/displayed")).await.unwrap(),
+        true
+    );
+    assert_eq!(
+        session.get(&format!("{path}/computedrole")).await.unwrap(),
+        "heading"
+    );
+
+    let hidden = session.find("#hidden").await.unwrap();
+    assert_eq!(
+        session
+            .get(&format!("/element/{hidden}/displayed"))
+            .await
+            .unwrap(),
+        false
+    );
+
+    let by_xpath = session
+        .post("/element", json!({ "using": "xpath", "value": "//h1" }))
+        .await
+        .unwrap();
+    assert_eq!(element_id(&by_xpath), title);
+    let link = session
+        .post(
+            "/element",
+            json!({ "using": "link text", "value": "Go to page 2" }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        session
+            .get(&format!("/element/{}/attribute/id", element_id(&link)))
+            .await
+            .unwrap(),
+        "link"
+    );
+
+    let options = session
+        .post(
+            "/elements",
+            json!({ "using": "tag name", "value": "option" }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(options.as_array().unwrap().len(), 2);
+    let dropdown = session.find("#dropdown").await.unwrap();
+    let children = session
+        .post(
+            &format!("/element/{dropdown}/elements"),
+            json!({ "using": "css selector", "value": "option" }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(children, options);
+    let selected = element_id(&options[1]);
+    assert_eq!(
+        session
+            .get(&format!("/element/{selected}/selected"))
+            .await
+            .unwrap(),
+        true
+    );
+
+    let err = session.find("#nonexistent").await.unwrap_err();
+    assert_eq!(err.error, "no such element");
+
+    let released = session
+        .post("/tauri/elements/release", json!({ "elements": [title] }))
+        .await
+        .unwrap();
+    assert_eq!(released["released"], 1);
+    let err = session.get(&format!("{path}/text")).await.unwrap_err();
+    assert_eq!(err.error, "no such element");
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn interacting_with_elements() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    let button = session.find("#increment").await.unwrap();
+    let counter = session.find("#counter").await.unwrap();
+    for count in 1..=3 {
+        session
+            .post(&format!("/element/{button}/click"), json!({}))
+            .await
+            .unwrap();
+        assert_eq!(
+            session
+                .get(&format!("/element/{counter}/text"))
+                .await
+                .unwrap(),
+            format!("Count: {count}")
+        );
+    }
+
+    session.cover_page(None).await.unwrap();
+    let err = session
+        .post(&format!("/element/{button}/click"), json!({}))
+        .await
+        .unwrap_err();
+    assert_eq!(err.error, "element click intercepted");
+    session.uncover_page().await.unwrap();
+
+    let input = session.find("#text-input").await.unwrap();
+    session
+        .post(
+            &format!("/element/{input}/value"),
+            json!({ "text": "hello\u{E003}" }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        session
+            .get(&format!("/element/{input}/property/value"))
+            .await
+            .unwrap(),
+        "hell"
+    );
+    session
+        .post(&format!("/element/{input}/clear"), json!({}))
+        .await
+        .unwrap();
+    assert_eq!(
+        session
+            .get(&format!("/element/{input}/property/value"))
+            .await
+            .unwrap(),
+        ""
+    );
+
+    session
+        .post(&format!("/element/{input}/click"), json!({}))
+        .await
+        .unwrap();
+    let active = session.get("/element/active").await.unwrap();
+    assert_eq!(element_id(&active), input);
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn actions() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    let button = session.find("#increment").await.unwrap();
+    let counter = session.find("#counter").await.unwrap();
+    session
+        .post(
+            "/actions",
+            json!({ "actions": [{
+                "type": "pointer",
+                "id": "mouse",
+                "parameters": { "pointerType": "mouse" },
+                "actions": [
+                    { "type": "pointerMove", "x": 0, "y": 0, "origin": element_ref(&button) },
+                    { "type": "pointerDown", "button": 0 },
+                    { "type": "pointerUp", "button": 0 }
+                ]
+            }] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        session
+            .get(&format!("/element/{counter}/text"))
+            .await
+            .unwrap(),
+        "Count: 1"
+    );
+
+    let input = session.find("#text-input").await.unwrap();
+    session
+        .post(&format!("/element/{input}/click"), json!({}))
+        .await
+        .unwrap();
+    session
+        .post(
+            "/actions",
+            json!({ "actions": [{
+                "type": "key",
+                "id": "keyboard",
+                "actions": [
+                    { "type": "keyDown", "value": "x" },
+                    { "type": "keyUp", "value": "x" }
+                ]
+            }] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        session
+            .get(&format!("/element/{input}/property/value"))
+            .await
+            .unwrap(),
+        "x"
+    );
+    session.delete("/actions").await.unwrap();
+
+    let err = session
+        .post(
+            "/actions",
+            json!({ "actions": [{
+                "type": "key",
+                "id": "k",
+                "actions": [{ "type": "pointerDown", "button": 0 }]
+            }] }),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.error, "invalid argument");
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn frames() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    session.post("/frame", json!({ "id": 0 })).await.unwrap();
+    let heading = session.find("#frame-title").await.unwrap();
+    assert_eq!(
+        session
+            .get(&format!("/element/{heading}/text"))
+            .await
+            .unwrap(),
+        "Inside Frame"
+    );
+    assert_eq!(
+        session.find("#title").await.unwrap_err().error,
+        "no such element",
+        "the frame's document is searched"
+    );
+
+    session.post("/frame/parent", json!({})).await.unwrap();
+    session.find("#title").await.unwrap();
+
+    let frame = session.find("#test-frame").await.unwrap();
+    session
+        .post("/frame", json!({ "id": element_ref(&frame) }))
+        .await
+        .unwrap();
+    session.find("#frame-text").await.unwrap();
+    session.post("/frame", json!({ "id": null })).await.unwrap();
+    session.find("#title").await.unwrap();
+
+    let err = session
+        .post("/frame", json!({ "id": 5 }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.error, "no such frame");
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn shadow_dom() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    let host = session.find("#shadow-host").await.unwrap();
+    let root = session
+        .get(&format!("/element/{host}/shadow"))
+        .await
+        .unwrap();
+    let root = root[SHADOW_KEY].as_str().unwrap().to_string();
+    let text = session
+        .post(
+            &format!("/shadow/{root}/element"),
+            json!({ "using": "css selector", "value": ".shadow-text" }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        session
+            .get(&format!("/element/{}/text", element_id(&text)))
+            .await
+            .unwrap(),
+        "Shadow Content"
+    );
+    let all = session
+        .post(
+            &format!("/shadow/{root}/elements"),
+            json!({ "using": "css selector", "value": "*" }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(all.as_array().unwrap().len(), 2);
+
+    let again = session
+        .get(&format!("/element/{host}/shadow"))
+        .await
+        .unwrap();
+    assert_eq!(again[SHADOW_KEY], root, "a shadow root keeps its id");
+
+    let title = session.find("#title").await.unwrap();
+    let err = session
+        .get(&format!("/element/{title}/shadow"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.error, "no such shadow root");
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn scripts() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    assert_eq!(session.execute("return 1+1", json!([])).await.unwrap(), 2);
+    assert_eq!(
+        session
+            .execute("return arguments[0]+arguments[1]", json!([10, 20]))
+            .await
+            .unwrap(),
+        30
+    );
+    let title = session.find("#title").await.unwrap();
+    assert_eq!(
+        session
+            .execute("return arguments[0].id", json!([element_ref(&title)]))
+            .await
+            .unwrap(),
+        "title"
+    );
+    let returned = session
+        .execute("return [document.getElementById('title')]", json!([]))
+        .await
+        .unwrap();
+    let returned = element_id(&returned[0]);
+    assert_eq!(
+        session
+            .get(&format!("/element/{returned}/attribute/id"))
+            .await
+            .unwrap(),
+        "title",
+        "a returned element can be used in later commands"
+    );
+    let err = session
+        .execute(
+            "var el=arguments[0];el.remove();return el",
+            json!([element_ref(&returned)]),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.error, "stale element reference");
+
+    let value = session
+        .post(
+            "/execute/async",
+            json!({
+                "script": "var done=arguments[arguments.length-1];setTimeout(function(){done(99)},100)",
+                "args": []
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(value, 99);
+
+    let err = session
+        .execute("throw new Error('test error')", json!([]))
+        .await
+        .unwrap_err();
+    assert_eq!(err.error, "javascript error");
+    assert!(
+        err.message.contains("test error"),
+        "message: {}",
+        err.message
+    );
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn cookies() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    session
+        .post(
+            "/cookie",
+            json!({ "cookie": { "name": "e2e", "value": "one", "path": "/" } }),
+        )
+        .await
+        .unwrap();
+    let cookie = session.get("/cookie/e2e").await.unwrap();
+    assert_eq!(cookie["value"], "one");
+    let all = session.get("/cookie").await.unwrap();
+    assert!(all
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|cookie| cookie["name"] == "e2e"));
+
+    session.post("/refresh", json!({})).await.unwrap();
+    assert_eq!(
+        session.get("/cookie/e2e").await.unwrap()["value"],
+        "one",
+        "cookies survive a reload"
+    );
+
+    session.delete("/cookie/e2e").await.unwrap();
+    let err = session.get("/cookie/e2e").await.unwrap_err();
+    assert_eq!(err.error, "no such cookie");
+
+    session
+        .post(
+            "/cookie",
+            json!({ "cookie": { "name": "e2e-other", "value": "two" } }),
+        )
+        .await
+        .unwrap();
+    session.delete("/cookie").await.unwrap();
+    assert_eq!(session.get("/cookie").await.unwrap(), json!([]));
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn screenshots() {
+    // A base64 PNG starts with the encoded PNG signature.
+    const PNG: &str = "iVBORw0KGgo";
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    let page = session.get("/screenshot").await.unwrap();
+    assert!(page.as_str().unwrap().starts_with(PNG));
+    let title = session.find("#title").await.unwrap();
+    let element = session
+        .get(&format!("/element/{title}/screenshot"))
+        .await
+        .unwrap();
+    assert!(element.as_str().unwrap().starts_with(PNG));
+    assert!(
+        element.as_str().unwrap().len() < page.as_str().unwrap().len(),
+        "the element shot is cropped"
+    );
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn alerts() {
+    let driver = Driver::start().await;
+    let session = driver.session().await;
+    let err = session.get("/alert/text").await.unwrap_err();
+    assert_eq!(err.error, "no such alert");
+
+    let alert = session.find("#trigger-alert").await.unwrap();
+    session
+        .post(&format!("/element/{alert}/click"), json!({}))
+        .await
+        .unwrap();
+    assert_eq!(session.get("/alert/text").await.unwrap(), "Hello Alert");
+    session.post("/alert/dismiss", json!({})).await.unwrap();
+
+    let confirm = session.find("#trigger-confirm").await.unwrap();
+    session
+        .post(&format!("/element/{confirm}/click"), json!({}))
+        .await
+        .unwrap();
+    assert_eq!(session.get("/alert/text").await.unwrap(), "Are you sure?");
+    session.post("/alert/accept", json!({})).await.unwrap();
+    assert_eq!(
+        session
+            .execute("return window.__confirmResult", json!([]))
+            .await
+            .unwrap(),
+        true
+    );
+
+    let prompt = session.find("#trigger-prompt").await.unwrap();
+    session
+        .post(&format!("/element/{prompt}/click"), json!({}))
+        .await
+        .unwrap();
+    session
+        .post("/alert/text", json!({ "text": "Bob" }))
+        .await
+        .unwrap();
+    session.post("/alert/accept", json!({})).await.unwrap();
+    assert_eq!(
+        session
+            .execute("return window.__promptResult", json!([]))
+            .await
+            .unwrap(),
+        "Bob"
+    );
+}
+
+#[tokio::test]
+#[ignore = "opens app windows"]
+async fn retried_interactions() {
+    let driver = Driver::start().await;
+    let session = driver
+        .session_with(json!({ "retry": { "attempts": 5, "backoff": 100 } }))
+        .await;
+    let button = session.find("#increment").await.unwrap();
+    let counter = session.find("#counter").await.unwrap();
+    session.cover_page(Some(250)).await.unwrap();
+    session
+        .post(&format!("/element/{button}/click"), json!({}))
+        .await
+        .expect("the click is retried until the cover is gone");
+    assert_eq!(
+        session
+            .get(&format!("/element/{counter}/text"))
+            .await
+            .unwrap(),
+        "Count: 1"
+    );
+
+    session.cover_page(None).await.unwrap();
+    let err = session
+        .post(&format!("/element/{button}/click"), json!({}))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.error, "element click intercepted",
+        "the last try's error once attempts run out"
+    );
+}
@@ -22,7 +22,15 @@ bash tests/run_w3c_tests.sh
 W3C_EXIT=$?
 echo ""
 echo "========================================="
-if [ $PLUGIN_EXIT -ne 0 ] || [ $W3C_EXIT -ne 0 ]; then
+echo "=== Running Rust End-to-End Tests ==="
+echo "========================================="
+TAURI_WD_BIN="$ROOT/target/debug/tauri-wd" \
+  TAURI_WD_TEST_APP="$ROOT/tests/test-app/src-tauri/target/debug/webdriver-test-app" \
+  cargo test -p tauri-webdriver-e2e -- --ignored --test-threads=1
+E2E_EXIT=$?
+echo ""
+echo "========================================="
+if [ $PLUGIN_EXIT -ne 0 ] || [ $W3C_EXIT -ne 0 ] || [ $E2E_EXIT -ne 0 ]; then
   echo "SOME TESTS FAILED"
   exit 1
 else
@@ -8,7 +8,7 @@ RESULT_FILE="/tmp/tauri-webdriver-test-results"
 rm -f "$PORT_FILE" "$RESULT_FILE"
 
 # Launch app, capture port from stdout
-$APP_BIN 2>/dev/null | while IFS= read -r line; do
+TAURI_WEBVIEW_AUTOMATION=true $APP_BIN 2>/dev/null | while IFS= read -r line; do
   echo "$line"
   if echo "$line" | grep -q '^\[webdriver\] listening on port'; then
     PORT=$(echo "$line" | sed 's/.*port //')
@@ -45,7 +45,13 @@ run_test() {
   local body="$3"
   local expected="$4"
 
-  result=$(curl -s -m 5 -X POST "http://127.0.0.1:$PORT$endpoint" \
+  # Everything but /api/version is served under the API version prefix.
+  local url="http://127.0.0.1:$PORT/v1$endpoint"
+  case "$endpoint" in
+    /api/*) url="http://127.0.0.1:$PORT$endpoint" ;;
+  esac
+
+  result=$(curl -s -m 5 -X POST "$url" \
     -H 'Content-Type: application/json' -d "$body" 2>&1)
 
   if echo "$result" | grep -q "$expected"; then
@@ -60,11 +66,41 @@ run_test() {
   fi
 }
 
+echo "=== Diagnostics ==="
+run_test "Plugin API version" "/api/version" "{}" '"api":1'
+run_test "GET plugin info" "/info" "{}" '"tauri"'
+run_test "Wait for webview ready" "/ready" '{"timeoutMs":5000}' '"ready":true'
+result=$(curl -s -m 5 -w ' %{http_code}' "http://127.0.0.1:$PORT/healthz" 2>&1)
+if echo "$result" | grep -q '"bridge":true' && echo "$result" | grep -q ' 200$'; then
+  echo "PASS: Health check reports the bridge"
+  PASS=$((PASS + 1))
+else
+  echo "FAIL: Health check reports the bridge"
+  echo "      Got: $result"
+  FAIL=$((FAIL + 1))
+fi
+for header in "Origin: http://example.com" "Sec-Fetch-Site: cross-site" "Host: rebound.example.com"; do
+  result=$(curl -s -m 5 -w ' %{http_code}' -X POST "http://127.0.0.1:$PORT/v1/info" \
+    -H 'Content-Type: application/json' -H "$header" -d '{}' 2>&1)
+  if echo "$result" | grep -q '"error".* 403$'; then
+    echo "PASS: Browser-style request refused ($header)"
+    PASS=$((PASS + 1))
+  else
+    echo "FAIL: Browser-style request refused ($header)"
+    echo "      Got: $result"
+    FAIL=$((FAIL + 1))
+  fi
+done
+
 echo "=== Window Operations ==="
 run_test "GET window handle" "/window/handle" "{}" '"main"'
 run_test "GET window handles" "/window/handles" "{}" '"main"'
 run_test "GET window rect" "/window/rect" "{}" '"width"'
 run_test "GET window insets" "/window/insets" "{}" '"top"'
+run_test "Set viewport" "/window/set-viewport" '{"width":640,"height":480,"deviceScaleFactor":3}' '"deviceScaleFactor":3'
+run_test "Set viewport with zero width" "/window/set-viewport" '{"width":0,"height":480}' '"invalidArgument"'
+run_test "Set viewport with negative scale" "/window/set-viewport" '{"width":640,"height":480,"deviceScaleFactor":-1}' '"invalidArgument"'
+run_test "Reset viewport" "/window/reset-viewport" "{}" '"width"'
 
 echo ""
 echo "=== Switch To Window ==="
@@ -106,12 +142,116 @@ echo ""
 echo "=== Script Execution ==="
 run_test "Execute sync (1+1)" "/script/execute" '{"script":"return 1+1","args":[]}' '"value":2'
 run_test "Execute sync (document.title)" "/script/execute" '{"script":"return document.title","args":[]}' '"WebDriver Test App"'
+run_test "Execute with awkward source" "/script/execute" '{"script":"return \"a\\\\b</script>{id}\" // trailing comment","args":[]}' '"a\\\\b</script>{id}"'
 run_test "Execute async (callback)" "/script/execute-async" '{"script":"var done=arguments[arguments.length-1];done(42)","args":[]}' '"value":42'
+run_test "Execute async resolving twice" "/script/execute-async" '{"script":"var done=arguments[arguments.length-1];done(1);done(2)","args":[]}' '"value":1'
+run_test "Resolve an unknown id" "/script/execute" '{"script":"__WEBDRIVER__.resolve(\"bogus\",1);return 2","args":[]}' '"value":2'
+run_test "Execute async in window" "/script/execute-async" '{"script":"var done=arguments[arguments.length-1];__WEBDRIVER__.bidiRun(function(){return document.body},false,null,done)","args":[],"window":"main"}' '"type":"node"'
+
+echo ""
+echo "=== Console Logs ==="
+run_test "Log to console" "/script/execute" '{"script":"console.error(\"wd-log-check\");return null","args":[]}' 'null'
+run_test "Get console entries (cursor)" "/log/console" '{"since":0}' '"document"'
+run_test "Get console entries" "/log/console" '{"clear":true}' '"wd-log-check"'
+run_test "Throw async page error" "/script/execute" '{"script":"setTimeout(function(){throw new Error(\"wd-error-check\")},0);return null","args":[]}' 'null'
+sleep 0.2
+run_test "Get page errors" "/log/errors" '{"clear":true}' '"wd-error-check"'
+run_test "Fetch a URL" "/script/execute-async" '{"script":"var done=arguments[arguments.length-1];fetch(location.href).then(function(r){done(r.status)},function(){done(0)})","args":[]}' '"value"'
+run_test "Get network entries" "/log/network" '{"clear":true}' '"type":"fetch"'
+
+echo ""
+echo "=== Request Mocks ==="
+run_test "Set mocks" "/mock/set" '{"rules":[{"urlPattern":"*/wd-mock","status":201,"body":{"mocked":true}}]}' '"count":1'
+run_test "Fetch mocked URL" "/script/execute-async" '{"script":"var done=arguments[arguments.length-1];fetch(\"/wd-mock\").then(function(r){return r.json()}).then(done)","args":[]}' '"mocked":true'
+run_test "Set a one-time mock" "/mock/set" '{"rules":[{"id":"wd-once","urlPattern":"*/wd-once","status":201,"times":1}]}' '"count":1'
+run_test "Fetch one-time mock" "/script/execute-async" '{"script":"var done=arguments[arguments.length-1];fetch(\"/wd-once\").then(function(r){done(r.status===201)},function(){done(false)})","args":[]}' '"value":true'
+run_test "Reload after using the mock" "/navigate/refresh" '{}' 'null'
+sleep 0.5
+run_test "Used mock stays used after a reload" "/script/execute-async" '{"script":"var done=arguments[arguments.length-1];fetch(\"/wd-once\").then(function(r){done(r.status===201)},function(){done(false)})","args":[]}' '"value":false'
+run_test "Setting the rules again keeps the count" "/mock/set" '{"rules":[{"id":"wd-once","urlPattern":"*/wd-once","status":201,"times":1}]}' '"count":1'
+run_test "Mock still used up" "/script/execute-async" '{"script":"var done=arguments[arguments.length-1];fetch(\"/wd-once\").then(function(r){done(r.status===201)},function(){done(false)})","args":[]}' '"value":false'
+run_test "Clear mocks" "/mock/set" '{"rules":[]}' '"count":0'
+
+echo ""
+echo "=== Shortcuts ==="
+run_test "Press an unbound accelerator" "/shortcut/press" '{"accelerator":"CmdOrCtrl+Shift+F12"}' '"handled"'
+run_test "Unknown accelerator key" "/shortcut/press" '{"accelerator":"Ctrl+Nope"}' '"invalidArgument"'
+run_test "Unknown accelerator modifier" "/shortcut/press" '{"accelerator":"Hyper+N"}' '"invalidArgument"'
+
+echo ""
+echo "=== HTTP Auth ==="
+run_test "Set credentials" "/auth/basic" '{"credentials":[{"username":"u","password":"p"},{"host":"example.com","username":"e","password":"q"}]}' '"count":2'
+run_test "Credentials need a password" "/auth/basic" '{"credentials":[{"username":"u"}]}' 'missing field'
+run_test "Clear credentials" "/auth/basic" '{"credentials":[]}' '"count":0'
+
+# A page behind basic auth: /ok takes u:p, /wrong takes nothing (in a realm
+# of its own, so cached credentials don't apply). Wrong credentials must be
+# given up on rather than sent again and again.
+AUTH_LOG=/tmp/tauri-wd-auth-requests
+rm -f "$AUTH_LOG" "$AUTH_LOG.port"
+python3 - "$AUTH_LOG" <<'PY' &
+import http.server, sys
+log = sys.argv[1]
+class Handler(http.server.BaseHTTPRequestHandler):
+    def do_GET(self):
+        with open(log, "a") as f:
+            f.write(self.path + "\n")
+        ok = self.path == "/ok" and self.headers.get("Authorization") == "Basic dTpw"
+        body = b"<p id=auth>authorized</p>" if ok else b"<p id=auth>denied</p>"
+        self.send_response(200 if ok else 401)
+        if not ok:
+            self.send_header("WWW-Authenticate", 'Basic realm="%s"' % self.path.strip("/"))
+        self.send_header("Content-Type", "text/html")
+        self.send_header("Content-Length", str(len(body)))
+        self.end_headers()
+        self.wfile.write(body)
+    def log_message(self, *args):
+        pass
+server = http.server.HTTPServer(("127.0.0.1", 0), Handler)
+with open(log + ".port", "w") as f:
+    f.write(str(server.server_address[1]))
+server.serve_forever()
+PY
+AUTH_PID=$!
+sleep 0.5
+AUTH_PORT=$(cat "$AUTH_LOG.port")
+APP_URL=$(curl -s -m 5 -X POST "http://127.0.0.1:$PORT/v1/navigate/current" -H 'Content-Type: application/json' -d '{}' | python3 -c "import json,sys; print(json.load(sys.stdin)['url'])")
+run_test "Set credentials for the auth server" "/auth/basic" '{"credentials":[{"host":"127.0.0.1","username":"u","password":"p"}]}' '"count":1'
+run_test "Load a page behind basic auth" "/navigate/url" "{\"url\":\"http://127.0.0.1:$AUTH_PORT/ok\"}" 'null'
+sleep 1
+run_test "The challenge was answered" "/script/execute" '{"script":"return document.getElementById(\"auth\").textContent","args":[]}' '"authorized"'
+run_test "Load a page the credentials don't open" "/navigate/url" "{\"url\":\"http://127.0.0.1:$AUTH_PORT/wrong\"}" 'null'
+sleep 1
+run_test "Wrong credentials load the 401 page" "/script/execute" '{"script":"return document.getElementById(\"auth\").textContent","args":[]}' '"denied"'
+WRONG_TRIES=$(grep -c '^/wrong$' "$AUTH_LOG" || true)
+if [ "$WRONG_TRIES" -le 2 ]; then
+  echo "PASS: Wrong credentials are sent once"
+  PASS=$((PASS + 1))
+else
+  echo "FAIL: Wrong credentials are sent once"
+  echo "      /wrong was requested $WRONG_TRIES times"
+  FAIL=$((FAIL + 1))
+fi
+run_test "Clear credentials again" "/auth/basic" '{"credentials":[]}' '"count":0'
+run_test "Back to the app" "/navigate/url" "{\"url\":\"$APP_URL\"}" 'null'
+sleep 1
+kill $AUTH_PID 2>/dev/null || true
+rm -f "$AUTH_LOG" "$AUTH_LOG.port"
+
+echo ""
+echo "=== Popup Behavior ==="
+run_test "Block popups" "/popup/behavior" '{"behavior":"block"}' 'null'
+run_test "Blocked window.open() returns null" "/script/execute" '{"script":"return window.open(\"about:blank\")===null","args":[]}' 'true'
+run_test "Blocked _blank link stays put" "/script/execute" '{"script":"var a=document.createElement(\"a\");a.href=\"about:blank\";a.target=\"_blank\";document.body.appendChild(a);a.click();a.remove();return location.href","args":[]}' '"value":"[^a]'
+run_test "Unknown popup behavior" "/popup/behavior" '{"behavior":"tabs"}' '"invalidArgument"'
+run_test "Open popups in windows again" "/popup/behavior" '{"behavior":"open-window"}' 'null'
 
 echo ""
 echo "=== Navigation ==="
 run_test "Get page title" "/navigate/title" "{}" '"WebDriver Test App"'
 run_test "Get current URL" "/navigate/current" "{}" '"url"'
+run_test "Script lost to a reload fails fast" "/script/execute-async" '{"script":"location.reload()","args":[]}' '"documentUnloaded"'
+run_test "Get page title after reload" "/navigate/title" "{}" '"WebDriver Test App"'
 
 echo ""
 echo "=== Page Source ==="
@@ -144,16 +284,86 @@ run_test "Computed role of button" "/element/computed-role" '{"selector":"#incre
 run_test "Computed role of h1" "/element/computed-role" '{"selector":"#title","index":0}' '"heading"'
 run_test "Computed label of text-input" "/element/computed-label" '{"selector":"#text-input","index":0}' '"Enter text"'
 
+echo ""
+echo "=== Wait For Element ==="
+run_test "Wait for a present element" "/wait/element" '{"using":"css","value":"#title","timeout":1000}' '"selector":"#title"'
+run_test "Add an element later" "/script/execute" '{"script":"setTimeout(function(){var p=document.createElement(\"p\");p.id=\"wd-wait\";p.textContent=\"ready now\";document.body.appendChild(p)},300);return null","args":[]}' 'null'
+run_test "Wait for text to match" "/wait/element" '{"using":"css","value":"#wd-wait","condition":"text-matches","pattern":"^ready","timeout":3000}' '"selector":"#wd-wait"'
+run_test "Wait for an attribute" "/wait/element" '{"using":"xpath","value":"//p","condition":"attribute-equals","name":"id","expected":"wd-wait","timeout":1000}' '"using":"xpath"'
+run_test "Remove the waited element" "/script/execute" '{"script":"document.getElementById(\"wd-wait\").remove();return null","args":[]}' 'null'
+run_test "Wait times out" "/wait/element" '{"using":"css","value":"#wd-never","condition":"displayed","timeout":200}' '"timeout"'
+run_test "Wait with unknown condition" "/wait/element" '{"using":"css","value":"#title","condition":"shiny","timeout":200}' '"invalidArgument"'
+run_test "Wait with bad pattern" "/wait/element" '{"using":"css","value":"#wd-never","condition":"text-matches","pattern":"(","timeout":200}' '"invalidArgument"'
+
+echo ""
+echo "=== Wait For Idle ==="
+run_test "Wait for an idle page" "/wait/idle" '{"quiet":100,"timeout":2000}' 'null'
+run_test "Mock a slow URL" "/mock/set" '{"rules":[{"urlPattern":"*/wd-slow","status":200,"body":"","delayMs":1000}]}' '"count":1'
+run_test "Fetch the slow URL" "/script/execute" '{"script":"fetch(\"/wd-slow\");return null","args":[]}' 'null'
+run_test "Wait times out while busy" "/wait/idle" '{"quiet":100,"timeout":300}' '"timeout"'
+run_test "Wait for the request to settle" "/wait/idle" '{"quiet":100,"timeout":3000}' 'null'
+run_test "Clear slow mock" "/mock/set" '{"rules":[]}' '"count":0'
+
+echo ""
+echo "=== Wait For Script ==="
+run_test "Set a flag later" "/script/execute" '{"script":"window.__wdFlag=0;setTimeout(function(){window.__wdFlag=42},300);return null","args":[]}' 'null'
+run_test "Wait for the flag" "/wait/script" '{"script":"return window.__wdFlag","interval":50,"timeout":3000}' '"value":42'
+run_test "Wait with args" "/wait/script" '{"script":"return arguments[0]+1","args":[1],"timeout":1000}' '"value":2'
+run_test "Wait for a promise" "/wait/script" '{"script":"return Promise.resolve(\"done\")","timeout":1000}' '"value":"done"'
+run_test "Wait for a falsy script" "/wait/script" '{"script":"return false","interval":50,"timeout":200}' '"timeout"'
+run_test "Wait for a throwing script" "/wait/script" '{"script":"throw new Error(\"wd-boom\")","timeout":1000}' '"javascriptError"'
+# A pending wait mustn't hold up the window's other commands: the script
+# releasing it would otherwise queue behind it until curl gives up.
+curl -s -m 15 -X POST "http://127.0.0.1:$PORT/v1/wait/script" -H 'Content-Type: application/json' \
+  -d '{"script":"return window.__wdRelease","interval":50,"timeout":10000}' > /tmp/tauri-wd-wait-result &
+WAIT_PID=$!
+sleep 0.3
+run_test "Run a script during a wait" "/script/execute" '{"script":"window.__wdRelease=1;return null","args":[]}' 'null'
+wait $WAIT_PID || true
+result=$(cat /tmp/tauri-wd-wait-result)
+if echo "$result" | grep -q '"value":1'; then
+  echo "PASS: Wait released by that script"
+  PASS=$((PASS + 1))
+else
+  echo "FAIL: Wait released by that script"
+  echo "      Got: $result"
+  FAIL=$((FAIL + 1))
+fi
+
+echo ""
+echo "=== Selection ==="
+run_test "Fill text-input for selection" "/script/execute" '{"script":"document.getElementById(\"text-input\").value=\"hello world\";return null","args":[]}' 'null'
+run_test "Select in text-input" "/element/set-selection" '{"selector":"#text-input","index":0,"start":0,"end":5}' '"text":"hello"'
+run_test "Get text-input selection" "/element/selection" '{"selector":"#text-input","index":0}' '"end":5'
+run_test "Place caret in text-input" "/element/set-selection" '{"selector":"#text-input","index":0,"start":3,"end":3}' '"start":3,"end":3'
+run_test "Selection start after end" "/element/set-selection" '{"selector":"#text-input","index":0,"start":3,"end":1}' '"invalidArgument"'
+run_test "Select text in h1" "/element/set-selection" '{"selector":"#title","index":0,"start":0,"end":4,"direction":"backward"}' '"direction":"backward"'
+run_test "Get page selection" "/selection" '{}' '"text":"Test"'
+run_test "Clear text-input after selection" "/element/clear" '{"selector":"#text-input","index":0}' 'null'
+
+echo ""
+echo "=== Typing ==="
+run_test "Type into text-input" "/element/type" '{"selector":"#text-input","index":0,"text":"typed"}' '"file":false'
+run_test "Verify typed value" "/element/property" '{"selector":"#text-input","index":0,"name":"value"}' '"typed"'
+run_test "Type into file input is skipped" "/element/type" '{"selector":"#file-input","index":0,"text":"/tmp/none.txt"}' '"file":true'
+run_test "Clear text-input after typing" "/element/clear" '{"selector":"#text-input","index":0}' 'null'
+
 echo ""
 echo "=== Active Element ==="
 run_test "Click text-input to focus" "/element/click" '{"selector":"#text-input","index":0}' 'null'
 sleep 0.2
-run_test "Get active element" "/element/active" "{}" '"selector"'
+run_test "Get active element" "/element/active" "{}" '"using":"ref"'
 
 echo ""
 echo "=== New Window ==="
 run_test "Create new window" "/window/new" '{}' '"handle"'
+NEW_HANDLE=$(echo "$result" | sed 's/.*"handle":"\([^"]*\)".*/\1/')
 run_test "Verify window handles (2+)" "/window/handles" "{}" '"wd-'
+run_test "Switch to new window" "/window/set-current" "{\"label\":\"$NEW_HANDLE\"}" 'true'
+# The in-process Automation API shares the server's current window.
+run_test "Automation sees the switch" "/ext/automation-handle" "{}" "\"handle\":\"$NEW_HANDLE\""
+run_test "Close current window" "/window/close" "{\"label\":\"$NEW_HANDLE\"}" 'true'
+run_test "No current window after close" "/window/handle" "{}" '"code":"noSuchWindow"'
 # Switch back to main for remaining tests
 run_test "Switch back to main" "/window/set-current" '{"label":"main"}' 'true'
 
@@ -185,6 +395,9 @@ FILE_B64=$(base64 < /tmp/tauri-webdriver-test-upload.txt | tr -d '\n')
 run_test "Set file on input" "/element/set-files" "{\"selector\":\"#file-input\",\"index\":0,\"files\":[{\"name\":\"test.txt\",\"data\":\"$FILE_B64\",\"mime\":\"text/plain\"}]}" 'null'
 sleep 0.3
 run_test "Verify file status text" "/element/text" '{"selector":"#file-status","index":0}' '"File: test.txt'
+run_test "Send a chunk" "/file/chunk" "{\"upload\":\"wd-discard\",\"data\":\"$FILE_B64\"}" 'null'
+run_test "Discard the upload" "/file/discard" '{"uploads":["wd-discard"]}' 'null'
+run_test "Discarded upload is gone" "/element/set-files" '{"selector":"#file-input","index":0,"files":[{"name":"test.txt","upload":"wd-discard"}]}' 'unknown upload'
 rm -f /tmp/tauri-webdriver-test-upload.txt
 
 echo ""
@@ -195,6 +408,8 @@ run_test "Element screenshot (#title)" "/screenshot/element" '{"selector":"#titl
 echo ""
 echo "=== Print to PDF ==="
 run_test "Print page to PDF" "/print" '{}' '"data"'
+run_test "Print page to raw PDF" "/print" '{"raw":true}' '%PDF-1.4'
+run_test "Printed page is a JPEG image" "/print" '{"raw":true}' '/Filter /DCTDecode'
 
 echo ""
 echo "=== Cookies ==="
@@ -203,9 +418,31 @@ run_test "Add cookie" "/cookie/add" '{"cookie":{"name":"testcookie","value":"tes
 sleep 0.3
 run_test "Get cookie by name" "/cookie/get" '{"name":"testcookie"}' '"testvalue"'
 run_test "Get all cookies (has cookie)" "/cookie/get-all" "{}" '"testcookie"'
+run_test "Reload with a cookie set" "/navigate/refresh" '{}' 'null'
+sleep 0.5
+run_test "Cookie survives a reload" "/cookie/get" '{"name":"testcookie"}' '"testvalue"'
 run_test "Delete cookie by name" "/cookie/delete" '{"name":"testcookie"}' 'null'
 sleep 0.3
 run_test "Get cookie after delete" "/cookie/get" '{"name":"testcookie"}' 'null'
+# Cookies are kept by domain, path and name: two paths don't replace each other.
+run_test "Add cookie on /" "/cookie/add" '{"cookie":{"name":"shared","value":"root","path":"/"}}' 'null'
+run_test "Add same-named cookie on /sub" "/cookie/add" '{"cookie":{"name":"shared","value":"sub","path":"/sub"}}' 'null'
+run_test "Reload with both cookies set" "/navigate/refresh" '{}' 'null'
+sleep 0.5
+result=$(curl -s -m 5 -X POST "http://127.0.0.1:$PORT/v1/cookie/get-all" \
+  -H 'Content-Type: application/json' -d '{}' 2>&1)
+if echo "$result" | grep -q '"value":"root"' && echo "$result" | grep -q '"value":"sub"'; then
+  echo "PASS: Same-named cookies on two paths both survive a reload"
+  PASS=$((PASS + 1))
+else
+  echo "FAIL: Same-named cookies on two paths both survive a reload"
+  echo "      Got: $result"
+  FAIL=$((FAIL + 1))
+fi
+run_test "Delete both by name" "/cookie/delete" '{"name":"shared"}' 'null'
+run_test "Reload after deleting both" "/navigate/refresh" '{}' 'null'
+sleep 0.5
+run_test "Neither comes back" "/cookie/get" '{"name":"shared"}' '"cookie":null'
 run_test "Add cookie for delete-all" "/cookie/add" '{"cookie":{"name":"cookie1","value":"val1","path":"/"}}' 'null'
 sleep 0.3
 run_test "Delete all cookies" "/cookie/delete-all" "{}" 'null'
@@ -41,6 +41,19 @@ run_test() {
   echo "$result" > /tmp/tauri-webdriver-last-result
 }
 
+# Cover the page with a fixed, topmost div so clicks are intercepted. With a
+# number of ms as $2 the cover removes itself after that long; otherwise
+# uncover_page removes it.
+cover_page() {
+  local name="$1"
+  local ms="${2:-null}"
+  run_test "$name" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"var c=document.createElement(\"div\");c.id=\"wd-cover\";c.style.cssText=\"position:fixed;inset:0;z-index:99999\";document.body.append(c);if(arguments[0]!=null)setTimeout(function(){c.remove()},arguments[0]);return null","args":['"$ms"']}' 'null'
+}
+
+uncover_page() {
+  run_test "$1" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"document.getElementById(\"wd-cover\").remove();return null","args":[]}' 'null'
+}
+
 extract_session_id() {
   SESSION_ID=$(cat /tmp/tauri-webdriver-last-result | python3 -c "
 import json,sys
@@ -117,6 +130,7 @@ sleep 2
 echo ""
 echo "=== Server Status (busy) ==="
 run_test "GET /status (busy)" "GET" "/status" "" '"ready":false'
+run_test "GET /status (session health)" "GET" "/status" "" '"healthy":true'
 
 echo ""
 echo "=== Window Operations ==="
@@ -129,6 +143,17 @@ run_test "Minimize window" "POST" "/session/$SESSION_ID/window/minimize" "" '"wi
 sleep 0.5
 run_test "Fullscreen window" "POST" "/session/$SESSION_ID/window/fullscreen" "" '"width"'
 
+echo ""
+echo "=== Viewport Emulation ==="
+run_test "Set viewport" "POST" "/session/$SESSION_ID/tauri/viewport" '{"width":640,"height":480,"deviceScaleFactor":2}' '"deviceScaleFactor":2'
+run_test "Set viewport (invalid)" "POST" "/session/$SESSION_ID/tauri/viewport" '{"width":-1,"height":480}' '"invalid argument"'
+run_test "Reset viewport" "DELETE" "/session/$SESSION_ID/tauri/viewport" "" '"width"'
+run_test "Press shortcut" "POST" "/session/$SESSION_ID/tauri/shortcut" '{"accelerator":"CmdOrCtrl+Shift+F12"}' '"handled"'
+run_test "Press shortcut (no accelerator)" "POST" "/session/$SESSION_ID/tauri/shortcut" '{}' '"invalid argument"'
+run_test "Set basic auth" "POST" "/session/$SESSION_ID/tauri/basic-auth" '{"username":"u","password":"p","host":"localhost"}' 'null'
+run_test "Set basic auth (invalid)" "POST" "/session/$SESSION_ID/tauri/basic-auth" '{"username":"u"}' '"invalid argument"'
+run_test "Clear basic auth" "DELETE" "/session/$SESSION_ID/tauri/basic-auth" "" 'null'
+
 echo ""
 echo "=== Switch To Window ==="
 run_test "Switch to main window" "POST" "/session/$SESSION_ID/window" '{"handle":"main"}' 'null'
@@ -138,6 +163,7 @@ echo ""
 echo "=== Navigation ==="
 run_test "GET title" "GET" "/session/$SESSION_ID/title" "" '"WebDriver Test App"'
 run_test "GET url" "GET" "/session/$SESSION_ID/url" "" 'tauri'
+run_test "Navigate to relative URL without baseUrl" "POST" "/session/$SESSION_ID/url" '{"url":"/settings"}' '"invalid argument"'
 
 echo ""
 echo "=== Page Source ==="
@@ -153,6 +179,8 @@ extract_element_id FRAME_TITLE_EID
 echo "      Frame Title Element ID: $FRAME_TITLE_EID"
 if [ -n "$FRAME_TITLE_EID" ]; then
   run_test "Get text in frame" "GET" "/session/$SESSION_ID/element/$FRAME_TITLE_EID/text" "" '"Inside Frame"'
+  run_test "Release frame element" "POST" "/session/$SESSION_ID/tauri/elements/release" '{"elements":["'"$FRAME_TITLE_EID"'"]}' '"released":1'
+  run_test "Released element is gone" "GET" "/session/$SESSION_ID/element/$FRAME_TITLE_EID/text" "" '"no such element"'
 fi
 run_test "Switch to parent frame" "POST" "/session/$SESSION_ID/frame/parent" "" 'null'
 # Verify we're back at top level
@@ -167,6 +195,7 @@ echo "=== Find Elements ==="
 run_test "Find element (#title)" "POST" "/session/$SESSION_ID/element" '{"using":"css selector","value":"#title"}' '"element-6066-11e4-a52e-4f735466cecf"'
 extract_element_id TITLE_EID
 echo "      Element ID: $TITLE_EID"
+run_test "Find element again reuses its ID" "POST" "/session/$SESSION_ID/element" '{"using":"css selector","value":"#title"}' "\"$TITLE_EID\""
 
 run_test "Find element (#increment)" "POST" "/session/$SESSION_ID/element" '{"using":"css selector","value":"#increment"}' '"element-6066'
 extract_element_id BTN_EID
@@ -238,11 +267,26 @@ if [ -n "$BTN_EID" ] && [ -n "$CTR_EID" ]; then
   run_test "Click increment (3)" "POST" "/session/$SESSION_ID/element/$BTN_EID/click" "" 'null'
   sleep 0.3
   run_test "Counter is Count: 3" "GET" "/session/$SESSION_ID/element/$CTR_EID/text" "" '"Count: 3"'
+
+  run_test "Record click pointer events" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"var b=arguments[0];window.__clickEv=[];[\"pointerdown\",\"mousedown\",\"pointerup\",\"mouseup\",\"click\"].forEach(function(t){b.addEventListener(t,function(e){window.__clickEv.push(t+(t===\"click\"?e.detail:\"\"))})});return null","args":[{"element-6066-11e4-a52e-4f735466cecf":"'"$BTN_EID"'"}]}' 'null'
+  run_test "Click increment (4)" "POST" "/session/$SESSION_ID/element/$BTN_EID/click" "" 'null'
+  run_test "Click sent the pointer sequence" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return window.__clickEv.join()","args":[]}' '"pointerdown,mousedown,pointerup,mouseup,click1"'
+  cover_page "Cover the button"
+  run_test "Click covered button" "POST" "/session/$SESSION_ID/element/$BTN_EID/click" "" '"element click intercepted"'
+  uncover_page "Uncover the button"
 fi
 
 if [ -n "$INPUT_EID" ]; then
   run_test "Send keys to input" "POST" "/session/$SESSION_ID/element/$INPUT_EID/value" '{"text":"hello"}' 'null'
   sleep 0.2
+  run_test "Scroll input into view" "POST" "/session/$SESSION_ID/tauri/scroll-into-view" '{"element":{"element-6066-11e4-a52e-4f735466cecf":"'"$INPUT_EID"'"},"block":"nearest"}' '"width"'
+  run_test "Scroll into view (invalid block)" "POST" "/session/$SESSION_ID/tauri/scroll-into-view" '{"element":{"element-6066-11e4-a52e-4f735466cecf":"'"$INPUT_EID"'"},"block":"middle"}' '"invalid argument"'
+  run_test "Count keydowns" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__keys=0;arguments[0].addEventListener(\"keydown\",function(){window.__keys++});return null","args":[{"element-6066-11e4-a52e-4f735466cecf":"'"$INPUT_EID"'"}]}' 'null'
+  run_test "Send keys with backspace" "POST" "/session/$SESSION_ID/element/$INPUT_EID/value" '{"text":"abc\uE003"}' 'null'
+  run_test "Send keys typed key by key" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return window.__keys+\":\"+arguments[0].value","args":[{"element-6066-11e4-a52e-4f735466cecf":"'"$INPUT_EID"'"}]}' '"4:helloab"'
+  run_test "Blur input with the caret at the start" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"arguments[0].setSelectionRange(0,0);arguments[0].blur();return null","args":[{"element-6066-11e4-a52e-4f735466cecf":"'"$INPUT_EID"'"}]}' 'null'
+  run_test "Send keys to the unfocused input" "POST" "/session/$SESSION_ID/element/$INPUT_EID/value" '{"text":"c"}' 'null'
+  run_test "Send keys appended" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return arguments[0].value","args":[{"element-6066-11e4-a52e-4f735466cecf":"'"$INPUT_EID"'"}]}' '"helloabc"'
   run_test "Clear input" "POST" "/session/$SESSION_ID/element/$INPUT_EID/clear" "" 'null'
 fi
 
@@ -271,6 +315,38 @@ if [ -n "$BTN_EID" ] && [ -n "$TITLE_EID" ] && [ -n "$INPUT_EID" ]; then
   run_test "Computed label of text-input" "GET" "/session/$SESSION_ID/element/$INPUT_EID/computedlabel" "" '"Enter text"'
 fi
 
+echo ""
+echo "=== Wait For Element ==="
+run_test "Wait for displayed element" "POST" "/session/$SESSION_ID/tauri/wait/element" '{"using":"css selector","value":"#title","condition":"displayed","timeout":1000}' '"element-6066'
+run_test "Wait for missing element" "POST" "/session/$SESSION_ID/tauri/wait/element" '{"using":"css selector","value":"#wd-never","timeout":200}' '"timeout"'
+
+echo ""
+echo "=== Wait For Idle ==="
+run_test "Wait for idle page" "POST" "/session/$SESSION_ID/tauri/wait/idle" '{"quiet":100,"timeout":2000}' '"value":null'
+run_test "Wait for idle (bad quiet)" "POST" "/session/$SESSION_ID/tauri/wait/idle" '{"quiet":-1}' '"invalid argument"'
+
+echo ""
+echo "=== Wait For Script ==="
+run_test "Wait for script" "POST" "/session/$SESSION_ID/tauri/wait/script" '{"script":"return document.readyState===\"complete\"&&document.title","timeout":2000}' '"WebDriver Test App"'
+run_test "Wait for script (timeout)" "POST" "/session/$SESSION_ID/tauri/wait/script" '{"script":"return null","interval":50,"timeout":200}' '"timeout"'
+run_test "Wait for script (missing)" "POST" "/session/$SESSION_ID/tauri/wait/script" '{}' '"invalid argument"'
+
+echo ""
+echo "=== Batch ==="
+run_test "Batch of commands" "POST" "/session/$SESSION_ID/tauri/batch" '{"commands":[{"method":"GET","path":"/title"},{"method":"POST","path":"/execute/sync","body":{"script":"return 40+2","args":[]}}]}' '"value":42'
+run_test "Batch stops at a failure" "POST" "/session/$SESSION_ID/tauri/batch" '{"commands":[{"method":"GET","path":"/element/wd-missing/text"},{"method":"GET","path":"/title"}]}' '"value":[{"status":404'
+run_test "Batch continues past a failure" "POST" "/session/$SESSION_ID/tauri/batch" '{"commands":[{"method":"GET","path":"/element/wd-missing/text"},{"method":"GET","path":"/title"}],"stopOnError":false}' '"WebDriver Test App"'
+run_test "Nested batch" "POST" "/session/$SESSION_ID/tauri/batch" '{"commands":[{"method":"POST","path":"/tauri/batch","body":{"commands":[]}}]}' '"invalid argument"'
+
+echo ""
+echo "=== Selection ==="
+if [ -n "$TITLE_EID" ]; then
+  run_test "Select text in h1" "POST" "/session/$SESSION_ID/tauri/element/$TITLE_EID/selection" '{"start":0,"end":4}' '"text":"Test"'
+  run_test "Get h1 selection" "GET" "/session/$SESSION_ID/tauri/element/$TITLE_EID/selection" "" '"end":4'
+  run_test "Get page selection" "GET" "/session/$SESSION_ID/tauri/selection" "" '"text":"Test"'
+  run_test "Select without start" "POST" "/session/$SESSION_ID/tauri/element/$TITLE_EID/selection" '{"end":4}' '"invalid argument"'
+fi
+
 echo ""
 echo "=== Active Element ==="
 if [ -n "$INPUT_EID" ]; then
@@ -286,16 +362,87 @@ run_test "Execute sync (title)" "POST" "/session/$SESSION_ID/execute/sync" '{"sc
 run_test "Execute sync (with args)" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return arguments[0]+arguments[1]","args":[10,20]}' '"value":30'
 run_test "Execute async" "POST" "/session/$SESSION_ID/execute/async" '{"script":"var done=arguments[arguments.length-1];setTimeout(function(){done(99)},100)","args":[]}' '"value":99'
 run_test "Execute sync (error)" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"throw new Error(\"test error\")","args":[]}' '"javascript error"'
+result=$(curl -s -i -m 10 -X POST "$BASE/session/$SESSION_ID/execute/sync" \
+  -H 'Content-Type: application/json' -H 'X-Request-Id: wd-req-check' \
+  -d '{"script":"throw new Error(\"test error\")","args":[]}')
+if echo "$result" | grep -qi '^x-request-id: wd-req-check' \
+  && echo "$result" | grep -q '"requestId":"wd-req-check"'; then
+  echo "PASS: Execute sync (request id echoed)"
+  PASS=$((PASS + 1))
+else
+  echo "FAIL: Execute sync (request id echoed)"
+  echo "      Got: $(echo "$result" | head -c 300)"
+  FAIL=$((FAIL + 1))
+fi
+BIG_SCRIPT=$(mktemp)
+python3 -c "print('{\"script\":\"return 1//' + 'x' * (65 * 1024 * 1024) + '\",\"args\":[]}')" > "$BIG_SCRIPT"
+result=$(curl -s -m 30 -X POST "$BASE/session/$SESSION_ID/execute/sync" \
+  -H 'Content-Type: application/json' --data-binary "@$BIG_SCRIPT")
+rm -f "$BIG_SCRIPT"
+if echo "$result" | grep -q 'payload too large'; then
+  echo "PASS: Execute sync (over --body-limit)"
+  PASS=$((PASS + 1))
+else
+  echo "FAIL: Execute sync (over --body-limit)"
+  echo "      Got: $(echo "$result" | head -c 300)"
+  FAIL=$((FAIL + 1))
+fi
+
+echo ""
+echo "=== Browser Logs ==="
+run_test "GET log types" "GET" "/session/$SESSION_ID/se/log/types" "" '"browser"'
+run_test "Execute console.warn" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"console.warn(\"wd-log-check\");return null","args":[]}' 'null'
+run_test "GET browser log" "POST" "/session/$SESSION_ID/se/log" '{"type":"browser"}' '"WARNING"'
+run_test "GET browser log (drained)" "POST" "/session/$SESSION_ID/se/log" '{"type":"browser"}' '"value":\[\]'
+run_test "Throw async page error" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"setTimeout(function(){throw new Error(\"wd-error-check\")},0);return null","args":[]}' 'null'
+sleep 0.2
+run_test "GET page errors" "GET" "/session/$SESSION_ID/tauri/errors" "" '"wd-error-check"'
+run_test "DELETE page errors" "DELETE" "/session/$SESSION_ID/tauri/errors" "" 'null'
+run_test "GET page errors (cleared)" "GET" "/session/$SESSION_ID/tauri/errors" "" '"value":\[\]'
+run_test "Call window.print()" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.print();return document.title","args":[]}' '"value"'
+run_test "GET print calls" "GET" "/session/$SESSION_ID/tauri/prints" "" '"timestamp"'
+run_test "DELETE print calls" "DELETE" "/session/$SESSION_ID/tauri/prints" "" 'null'
+run_test "GET print calls (cleared)" "GET" "/session/$SESSION_ID/tauri/prints" "" '"value":\[\]'
+
+echo ""
+echo "=== Network Log ==="
+run_test "Fetch a URL" "POST" "/session/$SESSION_ID/execute/async" '{"script":"var done=arguments[arguments.length-1];fetch(location.href).then(function(r){done(r.status)},function(){done(0)})","args":[]}' '"value"'
+run_test "GET network log" "GET" "/session/$SESSION_ID/tauri/network/log" "" '"type":"fetch"'
+run_test "GET network HAR" "GET" "/session/$SESSION_ID/tauri/network/har" "" '"version":"1.2"'
+run_test "DELETE network log" "DELETE" "/session/$SESSION_ID/tauri/network/log" "" 'null'
+run_test "GET network log (cleared)" "GET" "/session/$SESSION_ID/tauri/network/log" "" '"value":\[\]'
+
+echo ""
+echo "=== Request Mocks ==="
+run_test "Add mock" "POST" "/session/$SESSION_ID/tauri/mocks" '{"urlPattern":"*/wd-mock","status":200,"body":{"mocked":true}}' '"id":"mock-'
+run_test "Add failing mock" "POST" "/session/$SESSION_ID/tauri/mocks" '{"urlPattern":"*/wd-fail","error":"Failed to fetch"}' '"id":"mock-'
+run_test "Add mock (bad status)" "POST" "/session/$SESSION_ID/tauri/mocks" '{"urlPattern":"*","status":42}' '"invalid argument"'
+run_test "GET mocks" "GET" "/session/$SESSION_ID/tauri/mocks" "" '"urlPattern":"\*/wd-mock"'
+run_test "Fetch mocked URL" "POST" "/session/$SESSION_ID/execute/async" '{"script":"var done=arguments[arguments.length-1];fetch(\"/wd-mock\").then(function(r){return r.json()}).then(done)","args":[]}' '"mocked":true'
+run_test "Fetch failing mock" "POST" "/session/$SESSION_ID/execute/async" '{"script":"var done=arguments[arguments.length-1];fetch(\"/wd-fail\").then(function(){done(\"ok\")},function(e){done(e.message)})","args":[]}' '"Failed to fetch"'
+run_test "DELETE mocks" "DELETE" "/session/$SESSION_ID/tauri/mocks" "" 'null'
+run_test "GET mocks (cleared)" "GET" "/session/$SESSION_ID/tauri/mocks" "" '"value":\[\]'
+
+echo ""
+echo "=== App Log ==="
+run_test "GET app log" "GET" "/session/$SESSION_ID/tauri/app-log?lines=50" "" '"path":"'
+run_test "GET app log (bad lines)" "GET" "/session/$SESSION_ID/tauri/app-log?lines=x" "" '"invalid argument"'
 
 echo ""
 echo "=== Timeouts ==="
 run_test "GET timeouts" "GET" "/session/$SESSION_ID/timeouts" "" '"script":30000'
 run_test "SET timeouts" "POST" "/session/$SESSION_ID/timeouts" '{"script":60000,"implicit":5000}' 'null'
 run_test "GET timeouts (updated)" "GET" "/session/$SESSION_ID/timeouts" "" '"script":60000'
+run_test "SET timeouts (wrong type)" "POST" "/session/$SESSION_ID/timeouts" '{"implicit":"5000"}' "'implicit': invalid type"
+run_test "SET timeouts (out of range)" "POST" "/session/$SESSION_ID/timeouts" '{"pageLoad":-1}' '"invalid argument"'
+run_test "Navigate (missing url)" "POST" "/session/$SESSION_ID/url" '{}' 'missing field `url`'
+run_test "Actions (wrong structure)" "POST" "/session/$SESSION_ID/actions" '{"actions":[{"type":"key","id":"k","actions":[{"type":"pointerDown","button":0}]}]}' "'actions\[0\].actions\[0\]'"
+run_test "Execute sync (not an object)" "POST" "/session/$SESSION_ID/execute/sync" '[1]' 'must be a JSON object'
 
 echo ""
 echo "=== Screenshots ==="
 run_test "Full page screenshot" "GET" "/session/$SESSION_ID/screenshot" "" '"value"'
+run_test "Screenshot is a base64 PNG" "GET" "/session/$SESSION_ID/screenshot" "" '"value":"iVBORw0KGgo'
 if [ -n "$TITLE_EID" ]; then
   run_test "Element screenshot (#title)" "GET" "/session/$SESSION_ID/element/$TITLE_EID/screenshot" "" '"value"'
 fi
@@ -336,6 +483,86 @@ if [ -n "$NEW_WINDOW_HANDLE" ]; then
   run_test "Switch back to main" "POST" "/session/$SESSION_ID/window" '{"handle":"main"}' 'null'
 fi
 
+# window.open() popups become windows with handles
+run_test "Open a popup" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__wdTestPopup=window.open(\"about:blank\",\"_blank\",\"width=400,height=300\");return typeof window.__wdTestPopup.close","args":[]}' '"function"'
+sleep 0.5
+POPUP_HANDLE=$(curl -s -m 10 "$BASE/session/$SESSION_ID/window/handles" | python3 -c "
+import json,sys
+print(next((h for h in json.load(sys.stdin).get('value',[]) if h.startswith('wd-popup-')),''))
+" 2>/dev/null)
+echo "      Popup handle: $POPUP_HANDLE"
+if [ -n "$POPUP_HANDLE" ]; then
+  run_test "Switch to popup" "POST" "/session/$SESSION_ID/window" "{\"handle\":\"$POPUP_HANDLE\"}" 'null'
+  run_test "Popup URL" "GET" "/session/$SESSION_ID/url" "" '"about:blank"'
+  run_test "Switch back from popup" "POST" "/session/$SESSION_ID/window" '{"handle":"main"}' 'null'
+  run_test "Close popup from the page" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__wdTestPopup.close();return window.__wdTestPopup.closed","args":[]}' 'true'
+  sleep 0.5
+  run_test "Closed popup has no handle" "GET" "/session/$SESSION_ID/window/handles" "" '"main"\]'
+  run_test "Click a target=_blank link" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"var a=document.createElement(\"a\");a.href=\"about:blank\";a.target=\"_blank\";document.body.appendChild(a);a.click();a.remove();return null","args":[]}' 'null'
+  sleep 0.5
+  run_test "The link opened a window" "GET" "/session/$SESSION_ID/window/handles" "" '"wd-popup-'
+  LINK_POPUP=$(curl -s -m 10 "$BASE/session/$SESSION_ID/window/handles" | python3 -c "
+import json,sys
+print(next((h for h in json.load(sys.stdin).get('value',[]) if h.startswith('wd-popup-')),''))
+" 2>/dev/null)
+  if [ -n "$LINK_POPUP" ]; then
+    run_test "Switch to link popup" "POST" "/session/$SESSION_ID/window" "{\"handle\":\"$LINK_POPUP\"}" 'null'
+    run_test "Close link popup" "DELETE" "/session/$SESSION_ID/window" "" '"value"'
+    run_test "Switch back after link popup" "POST" "/session/$SESSION_ID/window" '{"handle":"main"}' 'null'
+  fi
+else
+  echo "FAIL: window.open() did not create a window"
+  FAIL=$((FAIL + 1))
+fi
+
+# A popup is a window of the app, so only web URLs and the app's own get one
+run_test "Refuse a file: popup" "POST" "/session/$SESSION_ID/execute/async" '{"script":"var done=arguments[arguments.length-1];var p=window.open(\"file:///etc/hosts\");setTimeout(function(){done(p.closed)},500)","args":[]}' '"value":true'
+
+# Popups and their openers can post messages to each other
+run_test "Open a same-origin popup" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__wdMsgPopup=window.open(location.href,\"_blank\");window.addEventListener(\"message\",function(e){window.__wdFromPopup=e.data});return null","args":[]}' 'null'
+sleep 1
+MSG_POPUP=$(curl -s -m 10 "$BASE/session/$SESSION_ID/window/handles" | python3 -c "
+import json,sys
+print(next((h for h in json.load(sys.stdin).get('value',[]) if h.startswith('wd-popup-')),''))
+" 2>/dev/null)
+if [ -n "$MSG_POPUP" ]; then
+  run_test "Switch to the message popup" "POST" "/session/$SESSION_ID/window" "{\"handle\":\"$MSG_POPUP\"}" 'null'
+  run_test "Listen in the popup" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.addEventListener(\"message\",function(e){window.__wdFromOpener=e.data});return null","args":[]}' 'null'
+  run_test "Switch back to the opener" "POST" "/session/$SESSION_ID/window" '{"handle":"main"}' 'null'
+  run_test "Post to the popup" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__wdMsgPopup.postMessage({hi:1},\"*\");return null","args":[]}' 'null'
+  sleep 0.3
+  run_test "Switch to the popup again" "POST" "/session/$SESSION_ID/window" "{\"handle\":\"$MSG_POPUP\"}" 'null'
+  run_test "The popup got the message" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return window.__wdFromOpener","args":[]}' '"hi":1'
+  run_test "Post to the opener" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.opener.postMessage(\"back\",\"/\");return null","args":[]}' 'null'
+  run_test "Close the message popup" "DELETE" "/session/$SESSION_ID/window" "" '"value"'
+  run_test "Switch back after messaging" "POST" "/session/$SESSION_ID/window" '{"handle":"main"}' 'null'
+  sleep 0.3
+  run_test "The opener got the reply" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return window.__wdFromPopup","args":[]}' '"back"'
+else
+  echo "FAIL: the same-origin popup did not open"
+  FAIL=$((FAIL + 1))
+fi
+
+# Targets naming a frame the page has navigate it instead of opening a popup
+run_test "Click a link targeting a named frame" "POST" "/session/$SESSION_ID/execute/async" '{"script":"var done=arguments[arguments.length-1];var f=document.createElement(\"iframe\");f.name=\"wd-target\";document.body.appendChild(f);var a=document.createElement(\"a\");a.href=\"about:blank#wd-framed\";a.target=\"wd-target\";document.body.appendChild(a);a.click();a.remove();setTimeout(function(){var h=f.contentWindow.location.hash;f.remove();done(h)},500)","args":[]}' '"#wd-framed"'
+run_test "The framed link opened no window" "GET" "/session/$SESSION_ID/window/handles" "" '"main"\]'
+# Opening the same name twice reuses the popup
+run_test "Open a named popup twice" "POST" "/session/$SESSION_ID/execute/async" '{"script":"var done=arguments[arguments.length-1];window.open(\"about:blank\",\"wd-named\");setTimeout(function(){window.__wdNamed=window.open(\"about:blank#again\",\"wd-named\");setTimeout(function(){done(null)},500)},500)","args":[]}' 'null'
+NAMED_POPUPS=$(curl -s -m 10 "$BASE/session/$SESSION_ID/window/handles" | python3 -c "
+import json,sys
+print(sum(1 for h in json.load(sys.stdin).get('value',[]) if h.startswith('wd-popup-')))
+" 2>/dev/null)
+if [ "$NAMED_POPUPS" = "1" ]; then
+  echo "PASS: The named popup was reused"
+  PASS=$((PASS + 1))
+else
+  echo "FAIL: The named popup was reused"
+  echo "      Got $NAMED_POPUPS popup windows"
+  FAIL=$((FAIL + 1))
+fi
+run_test "Close the named popup" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__wdNamed.close();return null","args":[]}' 'null'
+sleep 0.5
+
 echo ""
 echo "=== Alert/Dialog Handling ==="
 # Find alert trigger buttons
@@ -374,9 +601,19 @@ if [ -n "$PROMPT_BTN_EID" ]; then
   run_test "Accept prompt" "POST" "/session/$SESSION_ID/alert/accept" "" 'null'
 fi
 
+# Dialogs opened back to back queue up and block other commands
+run_test "Open two dialogs" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"alert(\"first\");confirm(\"second\");return null","args":[]}' 'null'
+run_test "Command with a dialog open" "GET" "/session/$SESSION_ID/title" "" '"unexpected alert open"'
+run_test "GET first queued dialog" "GET" "/session/$SESSION_ID/alert/text" "" '"first"'
+run_test "Accept first dialog" "POST" "/session/$SESSION_ID/alert/accept" "" 'null'
+run_test "GET second queued dialog" "GET" "/session/$SESSION_ID/alert/text" "" '"second"'
+run_test "Dismiss second dialog" "POST" "/session/$SESSION_ID/alert/dismiss" "" 'null'
+run_test "Command after the queue empties" "GET" "/session/$SESSION_ID/title" "" '"value"'
+
 echo ""
 echo "=== Print to PDF ==="
 run_test "Print page" "POST" "/session/$SESSION_ID/print" '{}' '"value"'
+run_test "Print page is a base64 PDF" "POST" "/session/$SESSION_ID/print" '{}' '"value":"JVBERi0'
 
 echo ""
 echo "=== Perform Actions ==="
@@ -386,6 +623,39 @@ run_test "Key actions (type 'x')" "POST" "/session/$SESSION_ID/actions" '{"actio
 # Pointer action: click at position
 run_test "Pointer actions (click)" "POST" "/session/$SESSION_ID/actions" '{"actions":[{"type":"pointer","id":"m1","parameters":{"pointerType":"mouse"},"actions":[{"type":"pointerMove","x":100,"y":100,"origin":"viewport","duration":0},{"type":"pointerDown","button":0},{"type":"pointerUp","button":0}]}]}' 'null'
 
+# Double click and context click
+run_test "Record mouse events" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__ev=[];[\"dblclick\",\"contextmenu\"].forEach(function(t){document.addEventListener(t,function(e){window.__ev.push(t+e.detail)})});return null","args":[]}' 'null'
+run_test "Pointer actions (double click)" "POST" "/session/$SESSION_ID/actions" '{"actions":[{"type":"pointer","id":"m2","parameters":{"pointerType":"mouse"},"actions":[{"type":"pointerMove","x":100,"y":100,"origin":"viewport","duration":0},{"type":"pointerDown","button":0},{"type":"pointerUp","button":0},{"type":"pointerDown","button":0},{"type":"pointerUp","button":0},{"type":"pointerDown","button":2},{"type":"pointerUp","button":2}]}]}' 'null'
+run_test "Double click fired dblclick and contextmenu" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return window.__ev.join()","args":[]}' '"dblclick2,contextmenu1"'
+
+# Hover
+run_test "Add hover target" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"var h=document.createElement(\"div\");h.style.cssText=\"position:fixed;left:200px;top:0;width:40px;height:40px\";window.__hover=[];h.onmouseenter=function(){window.__hover.push(\"enter\")};h.onmouseleave=function(){window.__hover.push(\"leave\")};document.body.append(h);window.__hoverEl=h;return null","args":[]}' 'null'
+run_test "Pointer actions (hover in and out)" "POST" "/session/$SESSION_ID/actions" '{"actions":[{"type":"pointer","id":"m1","parameters":{"pointerType":"mouse"},"actions":[{"type":"pointerMove","x":220,"y":20,"origin":"viewport","duration":0},{"type":"pointerMove","x":300,"y":300,"origin":"viewport","duration":0}]}]}' 'null'
+run_test "Hover fired mouseenter and mouseleave" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__hoverEl.remove();return window.__hover.join()","args":[]}' '"enter,leave"'
+
+# HTML5 drag and drop
+run_test "Add drag source and drop zone" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"var d=document.createElement(\"div\");d.draggable=true;d.style.cssText=\"position:fixed;left:0;top:0;width:40px;height:40px\";d.ondragstart=function(e){e.dataTransfer.setData(\"text/plain\",\"wd-dnd\")};var z=document.createElement(\"div\");z.style.cssText=\"position:fixed;left:100px;top:0;width:40px;height:40px\";z.ondragover=function(e){e.preventDefault()};z.ondrop=function(e){window.__dropped=e.dataTransfer.getData(\"text/plain\")};document.body.append(d,z);window.__dnd=[d,z];return null","args":[]}' 'null'
+run_test "Pointer actions (drag and drop)" "POST" "/session/$SESSION_ID/actions" '{"actions":[{"type":"pointer","id":"m3","parameters":{"pointerType":"mouse"},"actions":[{"type":"pointerMove","x":20,"y":20,"origin":"viewport","duration":0},{"type":"pointerDown","button":0},{"type":"pointerMove","x":120,"y":20,"origin":"viewport","duration":0},{"type":"pointerUp","button":0}]}]}' 'null'
+run_test "Drop received the drag data" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__dnd.forEach(function(e){e.remove()});return window.__dropped","args":[]}' '"wd-dnd"'
+
+# Touch gestures
+run_test "Record pointer types" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__touch=[];document.addEventListener(\"pointerdown\",function(e){window.__touch.push(e.pointerType)});return null","args":[]}' 'null'
+run_test "Gesture: swipe" "POST" "/session/$SESSION_ID/tauri/gesture/swipe" '{"x":100,"y":100,"deltaX":-80,"duration":50}' 'null'
+run_test "Gesture: pinch" "POST" "/session/$SESSION_ID/tauri/gesture/pinch" '{"x":150,"y":150,"scale":2,"duration":50}' 'null'
+run_test "Gesture: long press" "POST" "/session/$SESSION_ID/tauri/gesture/longpress" '{"x":100,"y":100,"duration":100}' 'null'
+run_test "Gestures used touch pointers" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return window.__touch.join()","args":[]}' '"touch,touch,touch,touch"'
+run_test "Gesture: swipe (invalid)" "POST" "/session/$SESSION_ID/tauri/gesture/swipe" '{"x":100,"y":100}' '"invalid argument"'
+
+# Modifiers held by a key source apply to pointer events
+run_test "Record shift-clicks" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__shift=[];document.addEventListener(\"click\",function(e){window.__shift.push(e.shiftKey)});return null","args":[]}' 'null'
+run_test "Pointer actions (shift-click)" "POST" "/session/$SESSION_ID/actions" '{"actions":[{"type":"key","id":"k1","actions":[{"type":"keyDown","value":"\uE008"},{"type":"pause"},{"type":"pause"},{"type":"keyUp","value":"\uE008"},{"type":"pause"},{"type":"pause"}]},{"type":"pointer","id":"m4","parameters":{"pointerType":"mouse"},"actions":[{"type":"pointerMove","x":300,"y":300,"origin":"viewport","duration":0},{"type":"pointerDown","button":0},{"type":"pointerUp","button":0},{"type":"pause"},{"type":"pointerDown","button":0},{"type":"pointerUp","button":0}]}]}' 'null'
+run_test "Shift applied to the first click only" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return window.__shift.join()","args":[]}' '"true,false"'
+
+# A move with a duration dispatches intermediate moves over that time
+run_test "Count mouse moves" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"window.__moves=0;document.addEventListener(\"mousemove\",function(){window.__moves++});window.__moveStart=Date.now();return null","args":[]}' 'null'
+run_test "Pointer actions (timed move)" "POST" "/session/$SESSION_ID/actions" '{"actions":[{"type":"pointer","id":"m1","parameters":{"pointerType":"mouse"},"actions":[{"type":"pointerMove","x":10,"y":10,"origin":"viewport","duration":0},{"type":"pointerMove","x":210,"y":10,"origin":"viewport","duration":200}]}]}' 'null'
+run_test "Timed move took its duration in steps" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return window.__moves>5&&Date.now()-window.__moveStart>=200","args":[]}' '"value":true'
+
 # Release actions
 run_test "Release actions" "DELETE" "/session/$SESSION_ID/actions" "" 'null'
 
@@ -393,6 +663,10 @@ echo ""
 echo "=== File Upload ==="
 # Create a temporary test file
 echo "hello world" > /tmp/tauri-webdriver-test-upload.txt
+mkdir -p /tmp/tauri-webdriver-test-dir/sub
+echo a > /tmp/tauri-webdriver-test-dir/a.txt
+echo b > /tmp/tauri-webdriver-test-dir/sub/b.txt
+head -c 2000000 /dev/zero > /tmp/tauri-webdriver-test-large.bin
 # Find the file input element
 run_test "Find #file-input" "POST" "/session/$SESSION_ID/element" '{"using":"css selector","value":"#file-input"}' '"element-6066'
 extract_element_id FILE_INPUT_EID
@@ -406,8 +680,21 @@ if [ -n "$FILE_INPUT_EID" ]; then
   if [ -n "$FILE_STATUS_EID" ]; then
     run_test "Verify file upload status" "GET" "/session/$SESSION_ID/element/$FILE_STATUS_EID/text" "" '"File: tauri-webdriver-test-upload.txt'
   fi
+  run_test "Send two files to a single-file input" "POST" "/session/$SESSION_ID/element/$FILE_INPUT_EID/value" '{"text":"/tmp/tauri-webdriver-test-upload.txt\n/tmp/tauri-webdriver-test-upload.txt"}' '"invalid argument"'
+  run_test "Send a directory to a file input" "POST" "/session/$SESSION_ID/element/$FILE_INPUT_EID/value" '{"text":"/tmp/tauri-webdriver-test-dir"}' '"invalid argument"'
+  run_test "Send a file larger than one chunk" "POST" "/session/$SESSION_ID/element/$FILE_INPUT_EID/value" '{"text":"/tmp/tauri-webdriver-test-large.bin"}' 'null'
+  run_test "Chunked file arrives whole" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return arguments[0].files[0].size","args":[{"element-6066-11e4-a52e-4f735466cecf":"'"$FILE_INPUT_EID"'"}]}' '"value":2000000'
+  run_test "Make the input take directories" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"arguments[0].webkitdirectory=true;return null","args":[{"element-6066-11e4-a52e-4f735466cecf":"'"$FILE_INPUT_EID"'"}]}' 'null'
+  run_test "Send a directory" "POST" "/session/$SESSION_ID/element/$FILE_INPUT_EID/value" '{"text":"/tmp/tauri-webdriver-test-dir"}' 'null'
+  run_test "Directory files keep relative paths" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"var f=arguments[0].files;arguments[0].webkitdirectory=false;return Array.from(f).map(function(x){return x.webkitRelativePath}).join()","args":[{"element-6066-11e4-a52e-4f735466cecf":"'"$FILE_INPUT_EID"'"}]}' '"tauri-webdriver-test-dir/a.txt,tauri-webdriver-test-dir/sub/b.txt"'
 fi
-rm -f /tmp/tauri-webdriver-test-upload.txt
+# Remote upload: send the file as a base64 zip, get back a driver-side path
+(cd /tmp && zip -q -j tauri-webdriver-test-upload.zip tauri-webdriver-test-upload.txt)
+ZIP_B64=$(base64 < /tmp/tauri-webdriver-test-upload.zip | tr -d '\n')
+run_test "Upload zipped file" "POST" "/session/$SESSION_ID/se/file" "{\"file\":\"$ZIP_B64\"}" 'tauri-webdriver-test-upload.txt"'
+run_test "Upload invalid zip" "POST" "/session/$SESSION_ID/se/file" '{"file":"bm90IGEgemlw"}' '"invalid argument"'
+rm -f /tmp/tauri-webdriver-test-upload.txt /tmp/tauri-webdriver-test-upload.zip /tmp/tauri-webdriver-test-large.bin
+rm -rf /tmp/tauri-webdriver-test-dir
 
 echo ""
 echo "=== Session Cleanup ==="
@@ -415,6 +702,23 @@ run_test "DELETE session" "DELETE" "/session/$SESSION_ID" "" 'null'
 sleep 1
 run_test "GET /status (ready again)" "GET" "/status" "" '"ready":true'
 
+echo ""
+echo "=== Retry Policy ==="
+run_test "POST /session (bad retry)" "POST" "/session" "{\"capabilities\":{\"alwaysMatch\":{\"tauri:options\":{\"binary\":\"$APP_BIN\",\"retry\":{\"attempts\":0}}}}}" '"invalid argument"'
+run_test "POST /session (retry)" "POST" "/session" "{\"capabilities\":{\"alwaysMatch\":{\"tauri:options\":{\"binary\":\"$APP_BIN\",\"retry\":{\"attempts\":5,\"backoff\":100}}}}}" '"attempts":5'
+extract_session_id
+if [ -n "$SESSION_ID" ]; then
+  sleep 2
+  run_test "Find #increment (retry)" "POST" "/session/$SESSION_ID/element" '{"using":"css selector","value":"#increment"}' '"element-6066'
+  extract_element_id RETRY_BTN_EID
+  cover_page "Cover the button briefly" 250
+  run_test "Click is retried past the cover" "POST" "/session/$SESSION_ID/element/$RETRY_BTN_EID/click" "" '"value":null'
+  run_test "The retried click landed once" "POST" "/session/$SESSION_ID/execute/sync" '{"script":"return document.getElementById(\"counter\").textContent","args":[]}' '"Count: 1"'
+  run_test "DELETE session (retry)" "DELETE" "/session/$SESSION_ID" "" 'null'
+  sleep 1
+fi
+run_test "GET /metrics" "GET" "/metrics" "" 'tauri_wd_sessions_created_total [1-9]'
+
 echo ""
 echo "=================================="
 echo "W3C WebDriver Results: $PASS passed, $FAIL failed"
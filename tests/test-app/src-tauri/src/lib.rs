@@ -1,12 +1,39 @@
 pub fn run() {
     let mut builder = tauri::Builder::default();
 
-    #[cfg(debug_assertions)]
+    #[cfg(any(debug_assertions, feature = "e2e-release"))]
     {
-        builder = builder.plugin(tauri_plugin_webdriver_automation::init());
+        builder = builder.plugin(automation::plugin()).setup(|app| {
+            let _ = automation::APP.set(app.handle().clone());
+            Ok(())
+        });
     }
 
     builder
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(any(debug_assertions, feature = "e2e-release"))]
+mod automation {
+    use std::sync::OnceLock;
+
+    use tauri_plugin_webdriver_automation::{Automation, Builder};
+
+    pub static APP: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+    /// The plugin, with `/ext/automation-handle` answering the current window
+    /// as the in-process `Automation` API sees it.
+    pub fn plugin() -> tauri::plugin::TauriPlugin<tauri::Wry> {
+        Builder::new()
+            .command("automation-handle", |_| async {
+                let app = APP.get().ok_or("app not set up")?;
+                let handle = Automation::for_app(app)
+                    .window_handle()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok(serde_json::json!({ "handle": handle }))
+            })
+            .build()
+    }
+}